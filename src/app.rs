@@ -0,0 +1,645 @@
+use std::env;
+use std::fs;
+use std::error::Error;
+use std::path::{self, Path, PathBuf};
+use std::io::{self, IsTerminal, Write};
+
+use log::{debug, warn};
+
+use crate::bench;
+use crate::build;
+use crate::cache;
+use crate::cli::{Args, Command, ServeOn};
+use crate::completions;
+use crate::config::{self, Config};
+use crate::convert;
+#[cfg(unix)]
+use crate::daemon;
+use crate::database::{self, Database, DbBytes, ParseOptions};
+use crate::diff;
+use crate::edit::OwnedDatabase;
+use crate::error::{DbError, ErrorKind};
+use crate::export::{self, ExportFormat};
+use crate::fetch;
+use crate::httpd;
+use crate::logger;
+use crate::manconf;
+use crate::mandb;
+use crate::memory;
+use crate::merge;
+use crate::open::open_page;
+use crate::pager;
+use crate::output::{OutputOptions, OutputStyle};
+use crate::pages::Page;
+use crate::progress::Progress;
+use crate::querylog;
+use crate::repair;
+use crate::repl::{Repl, DEFAULT_FIELDS, DEFAULT_HISTORY_SIZE};
+use crate::term;
+use crate::tui::Tui;
+use crate::validate;
+use crate::search::{MatchMode, SearchOptions};
+use crate::serve;
+use crate::space;
+use crate::stream;
+use crate::swap;
+use crate::utils;
+
+// The database location used when neither a path argument, the MANDOC_DB
+// environment variable, the config file, nor the manpath names one.
+pub const DEFAULT_DB_PATH: &str = "/usr/share/man/mandoc.db";
+
+// Runs the program with already parsed arguments.
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    logger::init(args.verbosity);
+
+    if let Some(Command::Completions(shell)) = args.command {
+        completions::print(shell);
+        return Ok(());
+    }
+
+    if let Some(Command::Build { dir, output }) = &args.command {
+        return build::build(dir, output.as_deref());
+    }
+
+    if let Some(Command::Update { db, files }) = &args.command {
+        return build::update(db, files);
+    }
+
+    if let Some(Command::Merge { dbs, output }) = &args.command {
+        return merge::merge(dbs, output);
+    }
+
+    if let Some(Command::Convert { whatis, output }) = &args.command {
+        return convert::convert_whatis(whatis, output);
+    }
+
+    if let Some(Command::Diff { old, new }) = &args.command {
+        let old_bytes = read_db(old, &args)?;
+        let new_bytes = read_db(new, &args)?;
+        let old = Database::parse(&old_bytes).map_err(|e| format!("{old}: {e}"))?;
+        let new = Database::parse(&new_bytes).map_err(|e| format!("{new}: {e}"))?;
+
+        let mut text = Vec::new();
+        diff::write_diff(&mut text, &old, &new)?;
+        return pager::show(&text, args.pager).map_err(Into::into);
+    }
+
+    if let Some(Command::Repair { db, output }) = &args.command {
+        let bytes = read_db(db, &args)?;
+        let (repaired, problems) = repair::repair(&bytes);
+        validate::write_problems(&mut io::stdout().lock(), &problems)?;
+
+        utils::replace_file(Path::new(output), |out| repaired.write(out))
+            .map_err(|e| DbError::new(ErrorKind::Io, format!("{output}: {e}")))?;
+
+        let values = repaired.macros.get()?.tables.iter().map(|table| table.values.len()).sum::<usize>();
+        writeln!(io::stdout(), "Wrote {} pages and {values} macro values to {output}.",
+            repaired.pages.count)?;
+        return Ok(());
+    }
+
+    let config = Config::load(args.config_path.as_deref())?;
+
+    if let Some(path) = &args.log_file {
+        querylog::init(path)
+            .map_err(|e| DbError::new(ErrorKind::Io, format!("{path}: {e}")))?;
+    }
+
+    let opts = SearchOptions {
+        mode: args.match_mode.or(config.match_mode).unwrap_or_default(),
+        case_sensitive: false,
+        limit: args.limit.or(config.limit),
+        section: args.section.clone(),
+        arch: args.arch.clone(),
+    };
+
+    // Pipes and files get one line per result unless a style was chosen.
+    let style = args.style.clone().unwrap_or(if io::stdout().is_terminal() {
+        OutputStyle::Detailed
+    } else {
+        OutputStyle::Whatis
+    });
+    let color = args.color.or(config.color).unwrap_or_default();
+    let output = OutputOptions {
+        style,
+        color: color.enabled(),
+        width: io::stdout().is_terminal().then(term::width).flatten(),
+        fields: None,
+        label: None,
+        resolve_files: args.resolve_files,
+        man_dir: None,
+    };
+
+    // A daemon has the databases loaded already.
+    if let Some(path) = &args.connect {
+        if args.queries.is_empty() {
+            return Err("Missing queries to send with --connect.".into());
+        }
+        let queries = args.queries.iter().map(String::as_str).collect::<Vec<&str>>();
+        let mut text = Vec::new();
+        connect(&mut text, path, &queries, &opts, &output)?;
+        return pager::show(&text, args.pager).map_err(Into::into);
+    }
+
+    // Searches include every database; other commands use the first one.
+    // Translated pages are searched for the locale of --lang or the
+    // environment. The manpath directories the databases were found in, if
+    // any, tell the databases of translated pages apart.
+    let lang = args.lang.clone().or_else(manconf::env_lang);
+    let (mut db_paths, manpath) = if args.db_paths.is_empty() {
+        manpath_db_paths(&args, lang.as_deref())?
+            .unwrap_or_else(|| default_db_paths(&config, lang.as_deref()))
+    } else {
+        (args.db_paths.clone(), Vec::new())
+    };
+    let db_path = db_paths.remove(0);
+    let other_db_paths = db_paths;
+
+    // A damaged database cannot be parsed, so it is checked as it is.
+    if let Some(Command::Validate { roundtrip }) = args.command {
+        let bytes = read_db(&db_path, &args)?;
+        let mut problems = validate::validate(&bytes);
+        // Only a database without damage can be written back unchanged.
+        if roundtrip && problems.is_empty() {
+            problems.extend(validate::verify_roundtrip(&bytes));
+        }
+        validate::write_problems(&mut io::stdout().lock(), &problems)?;
+
+        let message = match problems.len() {
+            0 => return Ok(()),
+            1 => "1 problem found.".to_string(),
+            count => format!("{count} problems found."),
+        };
+        return Err(DbError::new(ErrorKind::Format, message).into());
+    }
+
+    // A cache stands in for the database it was made from.
+    let source = args.from_cache.as_ref().unwrap_or(&db_path);
+    // A streamed database is never read whole.
+    let bytes = if args.stream {
+        DbBytes::from(Vec::new())
+    } else {
+        debug!("Reading {source}");
+        read_db(source, &args)?
+    };
+    debug!("Read {} bytes", bytes.len());
+
+    let streamed;
+    let (db, db_path) = if args.from_cache.is_some() {
+        let (db, db_path) = cache::load_cache(&bytes)?;
+        (db, db_path.to_string())
+    } else if args.stream {
+        streamed = stream_db(&db_path, &config.limits)?;
+        (streamed.as_database(), db_path)
+    } else {
+        let progress = Progress::for_bytes(bytes.len());
+        let db = Database::parse_with_options(&bytes, &config.limits, &progress);
+        progress.finish();
+        (db?, db_path)
+    };
+    let db_dir = manconf::db_dir(&db_path);
+    if args.full_search {
+        db.index_descriptions();
+    }
+    if args.sorted_index {
+        db.index_sorted_names();
+    }
+
+    if args.tui {
+        let mut opts = opts;
+        // Narrowing the list as a name is typed needs partial matches.
+        if args.match_mode.or(config.match_mode).is_none() {
+            opts.mode = MatchMode::Substring;
+        }
+
+        return Tui::new(&db, opts)
+            .with_query(&args.queries.join(" "))
+            .with_db_dir(db_dir)
+            .run();
+    }
+
+    if args.pick {
+        let picked = Tui::new(&db, opts)
+            .with_query(&args.queries.join(" "))
+            .pick()?;
+
+        let Some(picked) = picked else {
+            return Err(DbError::new(ErrorKind::NotFound, "No page was picked.").into());
+        };
+        println!("{picked}");
+        return Ok(());
+    }
+
+    if let Some(Command::Complete(prefix)) = &args.command {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        for name in db.complete(prefix) {
+            writeln!(out, "{name}")?;
+        }
+        out.flush()?;
+        return Ok(());
+    }
+
+    if let Some(Command::Stats { space, memory }) = args.command {
+        let mut text = Vec::new();
+        if space {
+            // Parts of the file are measured where they are, so a cache,
+            // which has none of them, cannot be measured, nor a database
+            // that was not read whole.
+            if args.from_cache.is_some() {
+                return Err("--space needs a database, not a cache.".into());
+            }
+            if args.stream {
+                return Err("--space needs the whole database, which --stream does not read."
+                    .into());
+            }
+            space::write_space(&mut text, &bytes, &config.limits)?;
+        } else if memory {
+            // Everything is parsed and built to be measured.
+            db.macros.get()?;
+            db.indexes.build_all(&db.pages.table);
+            memory::write_usage(&mut text, &db.heap_usage())?;
+        } else {
+            db.write_summary(&mut text)?;
+        }
+        return pager::show(&text, args.pager).map_err(Into::into);
+    }
+
+    if let Some(Command::DumpMacros(key)) = &args.command {
+        let mut text = Vec::new();
+        db.write_macros(&mut text, key.as_deref(), &opts)?;
+        return pager::show(&text, args.pager).map_err(Into::into);
+    }
+
+    if let Some(Command::Check(check)) = &args.command {
+        let mut text = Vec::new();
+        let count = check.write(&mut text, &db, &opts, db_dir.unwrap_or(Path::new(".")))?;
+        if count == 0 {
+            writeln!(text, "{}", check.summary(count))?;
+        }
+        pager::show(&text, args.pager)?;
+
+        return match count {
+            0 => Ok(()),
+            count => Err(DbError::new(ErrorKind::Other, check.summary(count)).into()),
+        };
+    }
+
+    if let Some(Command::Filter { output }) = &args.command {
+        let mut subset = OwnedDatabase::from_database(&db)?;
+        let mut pages = db.pages.table.iter();
+        subset.retain_pages(|_| pages.next().is_some_and(|page| opts.filters_match(page)));
+
+        utils::replace_file(Path::new(output), |out| subset.write(out))
+            .map_err(|e| DbError::new(ErrorKind::Io, format!("{output}: {e}")))?;
+
+        writeln!(io::stdout(), "Wrote {} of {} pages to {output}.", subset.pages().len(),
+            db.pages.count)?;
+        return Ok(());
+    }
+
+    if let Some(Command::Export(format)) = &args.command {
+        // The section and architecture filters choose what to export. The
+        // exports with macro values identify pages by their positions.
+        let positions = db.pages
+            .table
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| opts.filters_match(page))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<usize>>();
+        let pages = positions.iter().map(|&idx| &db.pages.table[idx]).collect::<Vec<&Page<'_>>>();
+
+        match format {
+            ExportFormat::Sqlite(path) => return export::write_sqlite(&db, &positions, path),
+            // The cache holds the whole database, whatever the filters.
+            ExportFormat::Cache(path) => {
+                if db_path == database::STDIN_PATH {
+                    return Err("A cache needs a database file, not standard input.".into());
+                }
+                // A URL is kept as it is, since it names no local files.
+                if fetch::is_url(&db_path) {
+                    return cache::write_cache(&db, &db_path, path);
+                }
+                let db_path = path::absolute(&db_path)?;
+                return cache::write_cache(&db, &db_path.to_string_lossy(), path);
+            },
+            ExportFormat::Docset(path) => {
+                return export::write_docset(&pages, db_dir.unwrap_or(Path::new(".")), path);
+            },
+            ExportFormat::Html { dir, url } => {
+                return export::write_html(&pages, dir, url.as_ref());
+            },
+            ExportFormat::Csv | ExportFormat::Tsv | ExportFormat::Markdown
+                | ExportFormat::Dot | ExportFormat::Whatis | ExportFormat::Json { .. }
+                | ExportFormat::Xml => {},
+        }
+
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        match format {
+            ExportFormat::Markdown => export::write_markdown(&mut out, &pages)?,
+            ExportFormat::Dot => export::write_dot(&mut out, &db, &positions)?,
+            ExportFormat::Whatis => export::write_whatis(&mut out, &pages)?,
+            ExportFormat::Xml => export::write_xml(&mut out, &db, &positions)?,
+            ExportFormat::Json { fields, tokens } => {
+                export::write_json(&mut out, &pages, fields, *tokens)?;
+            },
+            _ => export::write_table(&mut out, &pages, format)?,
+        }
+        out.flush()?;
+        return Ok(());
+    }
+
+    // The other databases are only loaded for searches, which include them.
+    let (other_bytes, other_streamed) = if args.stream {
+        let streamed = other_db_paths
+            .iter()
+            .map(|path| stream_db(path, &config.limits))
+            .collect::<Result<Vec<OwnedDatabase>, DbError>>()?;
+        (Vec::new(), streamed)
+    } else {
+        let bytes = other_db_paths
+            .iter()
+            .map(|path| read_db(path, &args))
+            .collect::<Result<Vec<DbBytes>, Box<dyn Error>>>()?;
+        (bytes, Vec::new())
+    };
+    let others = if args.stream {
+        other_streamed.iter().map(OwnedDatabase::as_database).collect()
+    } else {
+        other_bytes
+            .iter()
+            .zip(&other_db_paths)
+            .map(|(bytes, path)| {
+                Database::parse_with_options(bytes, &config.limits, &Progress::hidden())
+                    .map_err(|e| format!("{path}: {e}"))
+            })
+            .collect::<Result<Vec<Database<'_>>, String>>()?
+    };
+    if args.full_search {
+        others.iter().for_each(Database::index_descriptions);
+    }
+    if args.sorted_index {
+        others.iter().for_each(Database::index_sorted_names);
+    }
+    // Most names are only in one of several databases, which exact searches
+    // then find without indexing the names of the others.
+    if !others.is_empty() {
+        db.index_name_filter();
+        others.iter().for_each(Database::index_name_filter);
+    }
+    debug!("Searching {} databases", others.len() + 1);
+
+    // Results are labeled with their database when there are several.
+    let labels = std::iter::once(&db_path)
+        .chain(&other_db_paths)
+        .map(|path| (!others.is_empty()).then(|| manconf::db_label(path, &manpath)))
+        .collect::<Vec<Option<String>>>();
+    let dbs = std::iter::once(&db)
+        .chain(&others)
+        .zip(&labels)
+        .zip(std::iter::once(&db_path).chain(&other_db_paths))
+        .map(|((db, label), path)| (db, label.as_deref(), manconf::db_dir(path)))
+        .collect::<Vec<(&Database<'_>, Option<&str>, Option<&Path>)>>();
+
+    if args.serve_stdio {
+        return serve::serve_stdio(&dbs, &opts);
+    }
+
+    if let Some(Command::Serve(serve_on)) = &args.command {
+        return match serve_on {
+            ServeOn::Http(addr) => httpd::serve(addr, &dbs, &opts),
+            ServeOn::Socket(path) => serve_socket(path, &dbs, &opts),
+        };
+    }
+
+    if let Some(runs) = args.bench {
+        return bench(&dbs, &args, runs, &opts);
+    }
+
+    if !args.queries.is_empty() {
+        let queries = args.queries.iter().map(String::as_str).collect::<Vec<&str>>();
+
+        if args.where_ {
+            return write_paths(&dbs, &queries, &opts);
+        }
+
+        if args.open {
+            // The first result of the first database that has one.
+            let (page, dir) = dbs
+                .iter()
+                .find_map(|(db, _, dir)| Some((*db.search(&queries, &opts).first()?, *dir)))
+                .ok_or_else(|| DbError::new(ErrorKind::NotFound, "No results."))?;
+            return open_page(page, dir).map_err(Into::into);
+        }
+
+        let mut text = Vec::new();
+        database::write_search_all(&mut text, &dbs, &queries, &opts, &output)?;
+        pager::show(&text, args.pager)?;
+
+        return Ok(());
+    }
+
+    // Like `man -w` without names, print the manpath directory.
+    if args.where_ {
+        let dir = path::absolute(db_dir.unwrap_or(Path::new(".")))?;
+        println!("{}", dir.display());
+        return Ok(());
+    }
+
+    if !args.search {
+        let mut text = Vec::new();
+        db.write_summary(&mut text)?;
+        return pager::show(&text, args.pager).map_err(Into::into);
+    }
+
+    let prompt = config.repl.prompt.as_deref().unwrap_or("SEARCH: ");
+    let mut repl = Repl::new(&db, opts, output)
+        .with_prompt(prompt)
+        .with_pager(args.pager)
+        .with_db_path(&db_path)
+        .with_dbs(&others, &other_db_paths)
+        .with_manpath(manpath)
+        .with_history(config::history_path(),
+            config.repl.history_size.unwrap_or(DEFAULT_HISTORY_SIZE))
+        .with_fields(config.repl.fields.clone().unwrap_or_else(|| DEFAULT_FIELDS.to_vec()))
+        .with_aliases(config.aliases.clone(),
+            args.config_path.as_ref().map(PathBuf::from).or_else(config::default_path));
+
+    if repl.is_interactive() && config.repl.summary.unwrap_or(true) {
+        db.write_summary(&mut io::stdout())?;
+    }
+
+    repl.run()
+}
+
+// Reads a database with `database::read_db`, swapping the numbers of one
+// written with the opposite endianness when --swap-bytes is given.
+fn read_db(path: &str, args: &Args) -> Result<DbBytes, Box<dyn Error>> {
+    let bytes = database::read_db(path)?;
+    if !swap::is_swapped(&bytes) {
+        return Ok(bytes);
+    }
+    if !args.swap_bytes {
+        warn!("{path} appears to be written with opposite endianness; use --swap-bytes to \
+            read it anyway");
+        return Ok(bytes);
+    }
+    debug!("Swapping the numbers of {path}");
+    swap::swap_bytes(&bytes).map(DbBytes::from).map_err(|e| format!("{path}: {e}").into())
+}
+
+// Reads the database file at `path` a part at a time with
+// `stream::read_file_with`.
+fn stream_db(path: &str, limits: &ParseOptions) -> Result<OwnedDatabase, DbError> {
+    debug!("Streaming {path}");
+    stream::read_file_with(path, limits).map_err(|e| DbError { message: format!("{path}: {}", e.message), ..e })
+}
+
+// Sends the searches to the daemon listening on the Unix socket at `path`.
+#[cfg(unix)]
+fn connect(
+    out: &mut dyn Write,
+    path: &str,
+    queries: &[&str],
+    opts: &SearchOptions,
+    output: &OutputOptions
+) -> Result<(), Box<dyn Error>> {
+    daemon::write_search(out, path, queries, opts, output)
+}
+
+#[cfg(not(unix))]
+fn connect(
+    _out: &mut dyn Write,
+    _path: &str,
+    _queries: &[&str],
+    _opts: &SearchOptions,
+    _output: &OutputOptions
+) -> Result<(), Box<dyn Error>> {
+    Err("This platform does not support Unix sockets, which --connect needs.".into())
+}
+
+// Answers requests on the Unix socket at `path` as a daemon.
+#[cfg(unix)]
+fn serve_socket(
+    path: &str,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    daemon::serve_socket(path, dbs, opts)
+}
+
+#[cfg(not(unix))]
+fn serve_socket(
+    _path: &str,
+    _dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    _opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    Err("This platform does not support Unix sockets, which serve --socket needs.".into())
+}
+
+// Measures the queries of --bench-queries, one per line with its terms
+// separated by spaces, or the NAMEs as one query, and prints the report.
+fn bench(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    args: &Args,
+    runs: usize,
+    opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    let queries = match &args.bench_queries {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| format!("{path}: {e}"))?
+            .lines()
+            .map(|line| line.split_whitespace().map(str::to_string).collect::<Vec<String>>())
+            .filter(|terms| !terms.is_empty())
+            .collect(),
+        None => vec![args.queries.clone()],
+    };
+
+    let dbs = dbs.iter().map(|(db, _, _)| *db).collect::<Vec<&Database<'_>>>();
+    let measurements = queries
+        .iter()
+        .map(|terms| {
+            let terms = terms.iter().map(String::as_str).collect::<Vec<&str>>();
+            bench::measure(&dbs, &terms, runs, opts)
+        })
+        .collect::<Vec<bench::Measurement>>();
+
+    let mut text = Vec::new();
+    bench::write_report(&mut text, &measurements)?;
+    pager::show(&text, args.pager).map_err(Into::into)
+}
+
+// Prints the absolute paths of the files of every matching page, like
+// `man -w`. Page files are found relative to the directory of their
+// database.
+fn write_paths(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    queries: &[&str],
+    opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    let mut found = false;
+    for (db, _, dir) in dbs {
+        for page in db.search(queries, opts) {
+            found = true;
+            for (path, _) in page.resolve_files(dir.unwrap_or(Path::new(".")))? {
+                writeln!(out, "{}", path.display())?;
+            }
+        }
+    }
+
+    if !found {
+        return Err(DbError::new(ErrorKind::NotFound, "No results.").into());
+    }
+    Ok(())
+}
+
+// The paths of the databases to search, and the manpath directories they
+// were found in, if any.
+type DbPaths = (Vec<String>, Vec<String>);
+
+// Returns every mandoc.db found in the directories selected with -M, -m, or
+// -C, in manpath order, if any were given, with those of `lang` first in each,
+// and the directories.
+fn manpath_db_paths(args: &Args, lang: Option<&str>) -> Result<Option<DbPaths>, Box<dyn Error>> {
+    let dirs = args.manpath_dirs()?;
+    if dirs.is_empty() {
+        return Ok(None);
+    }
+
+    let dbs = manconf::find_databases(&dirs, lang);
+    if dbs.is_empty() {
+        return Err("No mandoc.db found in the manpath.".into());
+    }
+    debug!("Found {} databases in the manpath", dbs.len());
+
+    let dbs = dbs.iter().map(|path| path.to_string_lossy().into_owned()).collect();
+    Ok(Some((dbs, dirs)))
+}
+
+// Returns the MANDOC_DB environment variable, the databases in the config
+// file, every database in the manpath of /etc/man.conf, man-db's index on
+// Linux, or the standard database location, in that order. The manpath
+// includes the databases of `lang`, and its directories are returned when
+// the databases were found in it.
+fn default_db_paths(config: &Config, lang: Option<&str>) -> DbPaths {
+    if let Some(path) = env::var("MANDOC_DB").ok().filter(|path| !path.is_empty()) {
+        return (vec![path], Vec::new());
+    }
+    if !config.databases.is_empty() {
+        return (config.databases.clone(), Vec::new());
+    }
+
+    let manpath = manconf::system_manpath();
+    let dbs = manconf::find_databases(&manpath, lang);
+    if !dbs.is_empty() {
+        debug!("Found {} databases in the system manpath", dbs.len());
+        let dbs = dbs.iter().map(|path| path.to_string_lossy().into_owned()).collect();
+        return (dbs, manpath);
+    }
+    if Path::new(mandb::INDEX_PATH).is_file() {
+        return (vec![mandb::INDEX_PATH.to_string()], Vec::new());
+    }
+    (vec![DEFAULT_DB_PATH.to_string()], Vec::new())
+}
@@ -0,0 +1,150 @@
+#[cfg(feature = "bench")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::error::Error;
+use std::io::Write;
+#[cfg(feature = "bench")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::database::Database;
+use crate::search::SearchOptions;
+
+#[cfg(feature = "bench")]
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature = "bench")]
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+// The system allocator, counting the allocations made through it. With the
+// `bench` feature the binary installs it as the global allocator so that
+// `--bench` can report the allocations of each search.
+#[cfg(feature = "bench")]
+pub struct CountingAlloc;
+
+#[cfg(feature = "bench")]
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        // SAFETY: the layout is passed on as it was given.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        // SAFETY: the layout is passed on as it was given.
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        // SAFETY: the pointer was allocated by `System` with `layout`.
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the pointer was allocated by `System` with `layout`.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+// Returns how many allocations have been made through `CountingAlloc`, and
+// how many bytes they asked for, since the program started. Without the
+// `bench` feature nothing counts them, and they are unavailable.
+#[cfg(feature = "bench")]
+pub fn allocations() -> Option<(usize, usize)> {
+    Some((ALLOCATIONS.load(Ordering::Relaxed), ALLOCATED_BYTES.load(Ordering::Relaxed)))
+}
+
+#[cfg(not(feature = "bench"))]
+pub const fn allocations() -> Option<(usize, usize)> {
+    None
+}
+
+// How long one query took to search every database over a number of runs,
+// and what it allocated.
+#[derive(Clone, Debug)]
+pub struct Measurement {
+    pub query: String,
+    pub runs: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub p99: Duration,
+    // The allocations and allocated bytes of an average run, if they were
+    // counted.
+    pub allocations: Option<usize>,
+    pub bytes: Option<usize>,
+    pub results: usize,
+}
+
+// Searches every database for `queries`, the terms of one query, `runs`
+// times and measures each run. The query is searched once first, unmeasured,
+// so that the indexes it needs are built and the runs only measure lookups.
+pub fn measure(
+    dbs: &[&Database<'_>],
+    queries: &[&str],
+    runs: usize,
+    opts: &SearchOptions
+) -> Measurement {
+    let search = || dbs.iter().map(|db| db.search(queries, opts).len()).sum::<usize>();
+    let results = search();
+
+    let mut times = Vec::with_capacity(runs);
+    let start_allocations = allocations();
+    for _ in 0..runs {
+        let start = Instant::now();
+        std::hint::black_box(search());
+        times.push(start.elapsed());
+    }
+    let end_allocations = allocations();
+    times.sort_unstable();
+
+    let runs = runs.max(1);
+    let allocated = start_allocations
+        .zip(end_allocations)
+        .map(|((start, start_bytes), (end, end_bytes))| {
+            ((end - start) / runs, (end_bytes - start_bytes) / runs)
+        });
+    Measurement {
+        query: queries.join(" "),
+        runs,
+        min: times.first().copied().unwrap_or_default(),
+        median: times.get(times.len() / 2).copied().unwrap_or_default(),
+        // The run that 99% of the runs were at least as fast as.
+        p99: times.get((times.len() * 99).div_ceil(100).saturating_sub(1))
+            .copied()
+            .unwrap_or_default(),
+        allocations: allocated.map(|(allocations, _)| allocations),
+        bytes: allocated.map(|(_, bytes)| bytes),
+        results,
+    }
+}
+
+// Writes a table of the measurements, one query per line.
+pub fn write_report(
+    out: &mut dyn Write,
+    measurements: &[Measurement]
+) -> Result<(), Box<dyn Error>> {
+    let query_width = measurements
+        .iter()
+        .map(|m| m.query.len())
+        .chain(std::iter::once("QUERY".len()))
+        .max()
+        .unwrap_or(0);
+
+    writeln!(out, "{:query_width$}  {:>6}  {:>10}  {:>10}  {:>10}  {:>8}  {:>10}  {:>7}",
+        "QUERY", "RUNS", "MIN", "MEDIAN", "P99", "ALLOCS", "BYTES", "RESULTS")?;
+    for m in measurements {
+        writeln!(out, "{:query_width$}  {:>6}  {:>10}  {:>10}  {:>10}  {:>8}  {:>10}  {:>7}",
+            m.query, m.runs, format!("{:.1?}", m.min), format!("{:.1?}", m.median),
+            format!("{:.1?}", m.p99), or_unavailable(m.allocations), or_unavailable(m.bytes),
+            m.results)?;
+    }
+    Ok(())
+}
+
+// Formats a count that was not made without the `bench` feature as "n/a".
+fn or_unavailable(count: Option<usize>) -> String {
+    count.map_or_else(|| "n/a".to_string(), |count| count.to_string())
+}
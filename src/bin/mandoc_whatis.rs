@@ -0,0 +1,32 @@
+use std::env;
+use std::process::ExitCode;
+
+use mandoc_db_search::app;
+use mandoc_db_search::cli::{Args, Program};
+use mandoc_db_search::error::{self, ErrorFormat};
+use mandoc_db_search::utils::print_help;
+
+// A whatis(1) frontend: exact name matches printed as one-line summaries.
+fn main() -> ExitCode {
+    let args = match Args::parse_from(env::args().skip(1), Program::Whatis) {
+        Ok(args) => args,
+        Err(e) => {
+            error::report(&*e, ErrorFormat::Text);
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let Some(args) = args else {
+        print_help(Program::Whatis);
+        return ExitCode::SUCCESS;
+    };
+
+    let errors = args.errors;
+    match app::run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error::report(&*e, errors);
+            ExitCode::FAILURE
+        },
+    }
+}
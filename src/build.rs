@@ -0,0 +1,637 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{self, Path, PathBuf};
+
+use log::{debug, warn};
+
+use crate::database::{Database, Version};
+use crate::error::{DbError, ErrorKind};
+use crate::index::Indexes;
+use crate::macros::{self, Macros, Table, Value};
+use crate::manconf;
+use crate::pages::{Name, Page, PageFormat, Pages};
+use crate::source;
+use crate::utils::{self, strip_overstrike};
+
+// The name sources bits, as described in `Page`.
+const SOURCE_SYNOPSIS: u8 = 0b0000_0001;
+const SOURCE_NAME: u8 = 0b0000_0010;
+const SOURCE_FIRST_NAME: u8 = 0b0000_0100;
+const SOURCE_HEADER: u8 = 0b0000_1000;
+const SOURCE_FILE: u8 = 0b0001_0000;
+
+// The index of the Xr table in `macros::KEYS`.
+const XR: usize = 0;
+
+// What was found in one man page file.
+#[derive(Debug, Default)]
+struct Scanned {
+    names: Vec<(String, u8)>,
+    sect: String,
+    arch: Option<String>,
+    desc: String,
+    // The macro values, as indexes into `macros::KEYS` and the value.
+    macros: Vec<(usize, String)>,
+}
+
+impl Scanned {
+    // Adds a name, or the sources to a name that was already found.
+    fn add_name(&mut self, name: &str, source: u8) {
+        if name.is_empty() {
+            return;
+        }
+        match self.names.iter_mut().find(|(n, _)| n == name) {
+            Some((_, sources)) => *sources |= source,
+            None => self.names.push((name.to_string(), source)),
+        }
+    }
+}
+
+// A page of the database being built. Files with the same names, sections,
+// and description, like the copies of a page for each architecture, are
+// one page.
+#[derive(Debug)]
+struct BuiltPage {
+    names: Vec<(String, u8)>,
+    sects: Vec<String>,
+    archs: Vec<String>,
+    // Whether any of the files is machine-independent.
+    any_arch: bool,
+    desc: String,
+    files: Vec<String>,
+    format: PageFormat,
+    macros: Vec<(usize, String)>,
+}
+
+// Scans the man page files under `man_dir` like makewhatis(8) and writes
+// the database to `output`, or to mandoc.db in `man_dir`.
+//
+// Pages are looked for in the "manSECT" directories (mdoc(7) and man(7)
+// sources) and the "catSECT" directories (preformatted pages), along with
+// their architecture subdirectories, e.g. "man8/amd64". Names, sections,
+// architectures, and descriptions come from the .Dt or .TH header, the NAME
+// section, and the file name. For mdoc(7) pages, the arguments of the
+// macros indexed by makewhatis(8) at the start of a line are added to the
+// macro tables.
+pub fn build(man_dir: &str, output: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let root = Path::new(man_dir);
+    let files = page_files(root)?;
+    debug!("Found {} files in {man_dir}", files.len());
+
+    let mut pages = Vec::new();
+    for path in files {
+        if let Some(page) = scan_file(root, &path) {
+            add_page(&mut pages, page);
+        }
+    }
+
+    let output = output.map_or_else(|| root.join(manconf::DB_FILE_NAME), PathBuf::from);
+    write_pages(&pages, &output)
+}
+
+// Returns the files in the "manSECT" and "catSECT" directories of `root` and
+// their architecture subdirectories, in name order.
+pub fn page_files(root: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for entry in read_dir(root)? {
+        if !entry.is_dir() || file_info(entry.strip_prefix(root)?).is_none() {
+            continue;
+        }
+
+        for path in read_dir(&entry)? {
+            if path.is_dir() {
+                files.extend(read_dir(&path)?.into_iter().filter(|file| file.is_file()));
+            } else if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+// Rescans the given man page files of the database at `db_path` and writes
+// the database again, so that installing or removing a few pages does not
+// need a full build.
+//
+// The files must be within the directory of the database. The pages of each
+// file are dropped, and the file is scanned again unless it no longer
+// exists.
+pub fn update(db_path: &str, files: &[String]) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(db_path)
+        .map_err(|e| DbError::new(ErrorKind::Io, format!("{db_path}: {e}")))?;
+    let db = Database::parse(&bytes)?;
+
+    let root = path::absolute(manconf::db_dir(db_path).unwrap_or(Path::new(".")))?;
+    let changed = files
+        .iter()
+        .map(|file| {
+            let path = path::absolute(file)?;
+            let rel = path
+                .strip_prefix(&root)
+                .map_err(|_| format!("{file} is not in {}.", root.display()))?;
+            if file_info(rel).is_none() {
+                return Err(format!("{file} is not in a manSECT or catSECT directory.").into());
+            }
+            Ok(rel.to_string_lossy().into_owned())
+        })
+        .collect::<Result<Vec<String>, Box<dyn Error>>>()?;
+
+    // The other files of a page with a changed file are scanned again too,
+    // since the page's architectures and macro values may have come from
+    // the changed file.
+    let mut rescan = changed.clone();
+    let mut pages = owned_pages(&db)?;
+    pages.retain(|page| {
+        if !page.files.iter().any(|file| changed.contains(file)) {
+            return true;
+        }
+        for file in &page.files {
+            if !rescan.contains(file) {
+                rescan.push(file.clone());
+            }
+        }
+        false
+    });
+
+    for file in &rescan {
+        let path = root.join(file);
+        if !path.exists() {
+            debug!("{file} was removed");
+            continue;
+        }
+        if let Some(page) = scan_file(&root, &path) {
+            add_page(&mut pages, page);
+        }
+    }
+
+    write_pages(&pages, Path::new(db_path))
+}
+
+// Returns the section, architecture, and format of a page file from its path
+// within the man directory, e.g. "man8/amd64/apm.8", or of a "manSECT" or
+// "catSECT" directory.
+fn file_info(path: &Path) -> Option<(String, Option<String>, PageFormat)> {
+    let mut dirs = path.iter().filter_map(|dir| dir.to_str());
+    let dir = dirs.next()?;
+    let (sect, format) = if let Some(sect) = dir.strip_prefix("man") {
+        (sect, PageFormat::MdocMan)
+    } else if let Some(sect) = dir.strip_prefix("cat") {
+        (sect, PageFormat::Preformatted)
+    } else {
+        return None;
+    };
+    if sect.is_empty() {
+        return None;
+    }
+
+    // The file itself comes last, after an architecture directory.
+    let rest = dirs.collect::<Vec<&str>>();
+    let arch = match rest.as_slice() {
+        [arch, _] => Some((*arch).to_string()),
+        [] | [_] => None,
+        _ => return None,
+    };
+    Some((sect.to_string(), arch, format))
+}
+
+// Scans one page file under `root`, or returns None for files that are not
+// pages or cannot be read.
+fn scan_file(root: &Path, path: &Path) -> Option<BuiltPage> {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    let (dir_sect, dir_arch, format) = file_info(rel)?;
+
+    // Compressed pages, e.g. "man1/ls.1.gz", are decompressed. Without the
+    // "gzip" feature, gzipped pages cannot be and are skipped.
+    let bytes = match source::read_source(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Skipping {}: {e}", path.display());
+            return None;
+        },
+    };
+    let text = String::from_utf8_lossy(&bytes);
+
+    let mut scanned = match format {
+        PageFormat::MdocMan if is_mdoc(&text) => scan_mdoc(&text),
+        PageFormat::MdocMan => scan_man(&text),
+        PageFormat::Preformatted => scan_cat(&text),
+        // Pages are only built from man and cat directories.
+        PageFormat::Unknown(_) => return None,
+    };
+
+    // The file name is a name, without the suffix of a compressed page, and
+    // its directory decides the section and architecture when the page does
+    // not.
+    let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let file_name = file_name
+        .strip_suffix(".gz")
+        .or_else(|| file_name.strip_suffix(".Z"))
+        .unwrap_or(file_name);
+    let stem = Path::new(file_name).file_stem().and_then(|stem| stem.to_str());
+    scanned.add_name(stem.unwrap_or_default(), SOURCE_FILE);
+    if scanned.sect.is_empty() {
+        scanned.sect = dir_sect;
+    }
+    if scanned.arch.is_none() {
+        scanned.arch = dir_arch;
+    }
+
+    Some(BuiltPage {
+        names: scanned.names,
+        sects: vec![scanned.sect],
+        any_arch: scanned.arch.is_none(),
+        archs: scanned.arch.into_iter().collect(),
+        desc: scanned.desc,
+        files: vec![rel.to_string_lossy().into_owned()],
+        format,
+        macros: scanned.macros,
+    })
+}
+
+// Adds a scanned page, or its file and architecture to the same page in
+// another file.
+fn add_page(pages: &mut Vec<BuiltPage>, page: BuiltPage) {
+    let same_page = pages.iter_mut().find(|other| {
+        other.names == page.names && other.sects == page.sects && other.desc == page.desc
+    });
+    let Some(other) = same_page else {
+        pages.push(page);
+        return;
+    };
+
+    other.files.extend(page.files);
+    other.any_arch |= page.any_arch;
+    for arch in page.archs {
+        if !other.archs.contains(&arch) {
+            other.archs.push(arch);
+        }
+    }
+    for value in page.macros {
+        if !other.macros.contains(&value) {
+            other.macros.push(value);
+        }
+    }
+}
+
+// Copies the pages of a database, along with the macro values that list
+// them.
+fn owned_pages(db: &Database<'_>) -> Result<Vec<BuiltPage>, DbError> {
+    let mut pages = db.pages
+        .table
+        .iter()
+        .map(|page| BuiltPage {
+            names: page.names.iter().map(|name| (name.value.to_string(), name.source)).collect(),
+            sects: page.sects.iter().map(ToString::to_string).collect(),
+            any_arch: page.archs.is_none(),
+            archs: page.archs.iter().flatten().map(ToString::to_string).collect(),
+            desc: page.desc.to_string(),
+            files: page.files.iter().map(ToString::to_string).collect(),
+            format: page.format.clone(),
+            macros: Vec::new(),
+        })
+        .collect::<Vec<BuiltPage>>();
+
+    for (key, table) in db.macros.get()?.tables.iter().enumerate().take(macros::KEYS.len()) {
+        for value in &table.values {
+            for &idx in &value.pages {
+                if let Some(page) = pages.get_mut(idx) {
+                    page.macros.push((key, value.str.to_string()));
+                }
+            }
+        }
+    }
+
+    Ok(pages)
+}
+
+// Writes the built pages as a mandoc.db at `output`.
+fn write_pages(pages: &[BuiltPage], output: &Path) -> Result<(), Box<dyn Error>> {
+    let table = pages
+        .iter()
+        .map(|page| Page {
+            names: page.names
+                .iter()
+                .map(|(value, source)| Name { value, source: *source })
+                .collect(),
+            sects: page.sects.iter().map(String::as_str).collect(),
+            // A page that also has a machine-independent file is
+            // machine-independent.
+            archs: (!page.any_arch).then(|| page.archs.iter().map(String::as_str).collect()),
+            desc: &page.desc,
+            files: page.files.iter().map(String::as_str).collect(),
+            format: page.format.clone(),
+        })
+        .collect::<Vec<Page<'_>>>();
+
+    // The values of each macro table, sorted, with the pages using them.
+    let mut values = vec![BTreeMap::new(); macros::KEYS.len()];
+    for (idx, page) in pages.iter().enumerate() {
+        for (key, value) in &page.macros {
+            let value_pages = values[*key].entry(value.as_str()).or_insert_with(Vec::new);
+            value_pages.push(idx);
+        }
+    }
+    let tables = values
+        .into_iter()
+        .map(|values| {
+            let values = values
+                .into_iter()
+                .map(|(str, idxs)| Value { str, pages: idxs })
+                .collect::<Vec<Value<'_>>>();
+            Table { count: values.len(), values }
+        })
+        .collect::<Vec<Table<'_>>>();
+
+    let db = Database {
+        version: Version::V1,
+        pages: Pages { count: table.len(), table },
+        macros: Macros { count: tables.len(), tables }.into(),
+        indexes: Indexes::default(),
+    };
+
+    utils::replace_file(output, |out| db.write(out))
+        .map_err(|e| format!("{}: {e}", output.display()))?;
+
+    debug!("Wrote {} pages to {}", db.pages.count, output.display());
+    Ok(())
+}
+
+// Lists a directory in name order, so that builds are reproducible.
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {e}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    Ok(paths)
+}
+
+// Whether a source uses mdoc(7) rather than man(7).
+pub fn is_mdoc(text: &str) -> bool {
+    text.lines().any(|line| line.starts_with(".Dd") || line.starts_with(".Dt"))
+}
+
+// Reads an mdoc(7) page: the .Dt header, the .Nm names in the NAME and
+// SYNOPSIS sections, the .Nd description, and the macro values.
+fn scan_mdoc(text: &str) -> Scanned {
+    let mut scanned = Scanned::default();
+    let mut section = String::new();
+    let mut header = None;
+
+    for line in text.lines() {
+        let Some((name, args)) = parse_macro(line) else {
+            continue;
+        };
+
+        match name.as_str() {
+            "Dt" => {
+                header = args.first().map(|title| title.to_lowercase());
+                scanned.sect = args.get(1).cloned().unwrap_or_default();
+                scanned.arch = args.get(2).cloned();
+            },
+            "Sh" => section = args.join(" "),
+            "Nm" if section == "NAME" => {
+                for arg in args.iter().filter(|arg| !is_punctuation(arg)) {
+                    let first = if scanned.names.iter().any(|(_, s)| s & SOURCE_NAME != 0) {
+                        0
+                    } else {
+                        SOURCE_FIRST_NAME
+                    };
+                    scanned.add_name(arg, SOURCE_NAME | first);
+                }
+            },
+            "Nm" if section == "SYNOPSIS" => {
+                if let Some(arg) = args.first().filter(|arg| !is_punctuation(arg)) {
+                    scanned.add_name(arg, SOURCE_SYNOPSIS);
+                }
+            },
+            "Nd" => scanned.desc = args.join(" "),
+            _ => {},
+        }
+
+        let Some(key) = macros::KEYS.iter().position(|key| *key == name) else {
+            continue;
+        };
+        let args = args
+            .into_iter()
+            .filter(|arg| !is_punctuation(arg))
+            .collect::<Vec<String>>();
+        let value = match key {
+            XR => match args.as_slice() {
+                [name, sect, ..] => format!("{name}({sect})"),
+                [name] => name.clone(),
+                [] => continue,
+            },
+            // Section titles are indexed whole.
+            _ if matches!(name.as_str(), "Sh" | "Ss") => args.join(" "),
+            _ => match args.into_iter().next() {
+                Some(arg) => arg,
+                None => continue,
+            },
+        };
+        if !scanned.macros.contains(&(key, value.clone())) {
+            scanned.macros.push((key, value));
+        }
+    }
+
+    if let Some(header) = header {
+        scanned.add_name(&header, SOURCE_HEADER);
+    }
+    scanned
+}
+
+// Reads a man(7) page: the .TH header and the "name, name \- description"
+// lines of the NAME section.
+fn scan_man(text: &str) -> Scanned {
+    let mut scanned = Scanned::default();
+    let mut in_name = false;
+    let mut name_text = Vec::new();
+    let mut header = None;
+
+    for line in text.lines() {
+        // Comments are not part of the NAME section's text.
+        if is_comment(line) {
+            continue;
+        }
+
+        match parse_macro(line) {
+            Some((name, args)) if name == "TH" => {
+                let mut args = args.into_iter();
+                header = args.next().map(|title| title.to_lowercase());
+                scanned.sect = args.next().unwrap_or_default();
+            },
+            Some((name, args)) if name == "SH" => {
+                in_name = args.join(" ").eq_ignore_ascii_case("NAME");
+            },
+            Some(_) => {},
+            None if in_name => name_text.push(plain_text(line)),
+            None => {},
+        }
+    }
+
+    add_name_line(&mut scanned, &name_text.join(" "));
+    if let Some(header) = header {
+        scanned.add_name(&header, SOURCE_HEADER);
+    }
+    scanned
+}
+
+// Reads a preformatted page, which only has the lines of its NAME section.
+fn scan_cat(text: &str) -> Scanned {
+    let mut scanned = Scanned::default();
+
+    // Bold and underlined text is written with backspaces.
+    let plain = strip_overstrike(text);
+    let name_text = plain
+        .lines()
+        .skip_while(|line| line.trim() != "NAME")
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect::<Vec<&str>>();
+
+    add_name_line(&mut scanned, &name_text.join(" "));
+    scanned
+}
+
+// Adds the names and description of a NAME section line such as
+// "cat, tac - concatenate files".
+fn add_name_line(scanned: &mut Scanned, line: &str) {
+    let Some((names, desc)) = line.split_once(" - ") else {
+        return;
+    };
+
+    for (idx, name) in names.split(',').map(str::trim).enumerate() {
+        let first = if idx == 0 { SOURCE_FIRST_NAME } else { 0 };
+        scanned.add_name(name, SOURCE_NAME | first);
+    }
+    scanned.desc = desc.trim().to_string();
+}
+
+// Splits a roff control line such as `.Xr ls 1 ,` into the macro name and
+// its arguments, with escapes turned into plain text.
+pub fn parse_macro(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.strip_prefix(['.', '\''])?.trim_start();
+    // Comments.
+    if line.starts_with("\\\"") {
+        return None;
+    }
+
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                arg.push('"');
+            },
+            '"' if quoted => quoted = false,
+            '"' if arg.is_empty() => quoted = true,
+            '\\' if chars.peek() == Some(&'"') => break,
+            c if c.is_whitespace() && !quoted => {
+                if !arg.is_empty() {
+                    args.push(plain_text(&arg));
+                    arg.clear();
+                }
+            },
+            c => arg.push(c),
+        }
+    }
+    if !arg.is_empty() {
+        args.push(plain_text(&arg));
+    }
+
+    let mut args = args.into_iter();
+    let name = args.next()?;
+    Some((name, args.collect()))
+}
+
+// Whether a line is a roff comment, such as `.\" text` or `'\" text`.
+fn is_comment(line: &str) -> bool {
+    let line = line.strip_prefix(['.', '\'']).map_or(line, str::trim_start);
+    line.starts_with("\\\"")
+}
+
+// Removes the roff escapes from text: font changes and zero-width escapes
+// are dropped, and "\-" and "\e" become "-" and "\".
+pub fn plain_text(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            plain.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('-') => plain.push('-'),
+            Some('e') => plain.push('\\'),
+            Some(' ' | '~') => plain.push(' '),
+            Some('&' | '|' | '^' | ',' | '/' | ':' | 'c') => {},
+            // A comment ends the text.
+            Some('"') => break,
+            // Fonts like \fB, \f(CW, and \f[B].
+            Some('f') => skip_escape_name(&mut chars),
+            // Special characters like \(em and \[em].
+            Some('(') => {
+                let name = chars.by_ref().take(2).collect::<String>();
+                plain.push_str(special_char(&name));
+            },
+            Some('[') => {
+                let name = chars.by_ref().take_while(|c| *c != ']').collect::<String>();
+                plain.push_str(special_char(&name));
+            },
+            // Strings like \*(Ba are left out.
+            Some('*') => skip_escape_name(&mut chars),
+            Some(c) => plain.push(c),
+            None => {},
+        }
+    }
+
+    plain
+}
+
+// Skips the name of an escape: one character, two after "(", or up to "]"
+// after "[".
+fn skip_escape_name(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    match chars.next() {
+        Some('(') => {
+            chars.next();
+            chars.next();
+        },
+        Some('[') => {
+            for c in chars.by_ref() {
+                if c == ']' {
+                    break;
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+// The text of the common roff special characters.
+fn special_char(name: &str) -> &'static str {
+    match name {
+        "em" => "\u{2014}",
+        "en" => "\u{2013}",
+        "hy" | "mi" => "-",
+        "aq" => "'",
+        "dq" => "\"",
+        "lq" | "rq" | "Lq" | "Rq" => "\"",
+        "oq" | "cq" => "'",
+        "ba" => "|",
+        "rs" => "\\",
+        _ => "",
+    }
+}
+
+// Whether an mdoc(7) argument is punctuation like "," or ")".
+pub fn is_punctuation(arg: &str) -> bool {
+    !arg.is_empty() && arg.chars().all(|c| ".,;:?!()[]|".contains(c))
+}
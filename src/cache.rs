@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::str;
+
+use log::debug;
+
+use crate::database::{Database, Version};
+use crate::error::{DbError, ErrorKind};
+use crate::index::Indexes;
+use crate::macros::{Macros, Table, Value};
+use crate::pages::{Name, NameList, Page, PageFormat, Pages};
+use crate::utils::StrList;
+
+// The first bytes of a cache file, followed by the cache version.
+const CACHE_MAGIC: &[u8; 8] = b"MDBCACHE";
+const CACHE_VERSION: u32 = 1;
+
+// The length of the header: the magic, the version, and the lengths of the
+// strings and numbers sections.
+const HEADER_LEN: usize = 20;
+
+// A cache file consists of (in order):
+// 1. The magic "MDBCACHE" and the cache version.
+// 2. The length of the strings section, then of the numbers section.
+// 3. The strings section: every distinct string of the database, as UTF-8
+//    with no separators.
+// 4. The numbers section: little endian 32-bit numbers. A string is written
+//    as its offset and length in the strings section, and a list as its
+//    length followed by its items.
+//
+// The numbers are, in order: the path of the database the cache was made
+// from, the pages, and the macro tables. Each page has its names (each a
+// string and its sources byte), sections, architectures (written with a
+// length of 0 for machine-independent pages and one more than the number of
+// architectures otherwise), description, files, and format byte. Each macro
+// table has its values, and each value its string followed by a list of the
+// indexes of its pages.
+//
+// Strings are only validated once for the whole strings section and pages
+// are referred to by index, which makes loading a cache much faster than
+// parsing mandoc.db.
+struct CacheWriter<'a> {
+    strings: Vec<u8>,
+    offsets: HashMap<&'a str, (u32, u32)>,
+    numbers: Vec<u8>,
+}
+
+impl<'a> CacheWriter<'a> {
+    fn num(&mut self, num: usize) -> Result<(), Box<dyn Error>> {
+        let num = u32::try_from(num).map_err(|_| "The database is too large to cache.")?;
+        self.numbers.extend_from_slice(&num.to_le_bytes());
+        Ok(())
+    }
+
+    fn str(&mut self, s: &'a str) -> Result<(), Box<dyn Error>> {
+        let (offset, len) = match self.offsets.get(s) {
+            Some(&range) => range,
+            None => {
+                let offset = u32::try_from(self.strings.len())
+                    .map_err(|_| "The database is too large to cache.")?;
+                let len = u32::try_from(s.len())?;
+                self.strings.extend_from_slice(s.as_bytes());
+                self.offsets.insert(s, (offset, len));
+                (offset, len)
+            },
+        };
+
+        self.numbers.extend_from_slice(&offset.to_le_bytes());
+        self.numbers.extend_from_slice(&len.to_le_bytes());
+        Ok(())
+    }
+
+    fn list(&mut self, items: &StrList<'a>) -> Result<(), Box<dyn Error>> {
+        self.num(items.len())?;
+        for item in items {
+            self.str(item)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes the whole database to a new cache file at `path`, recording
+// `db_path` so that page files can still be found next to the database.
+pub fn write_cache(
+    db: &Database<'_>,
+    db_path: &str,
+    path: &str
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = CacheWriter {
+        strings: Vec::new(),
+        offsets: HashMap::new(),
+        numbers: Vec::new(),
+    };
+
+    writer.str(db_path)?;
+    writer.num(db.pages.table.len())?;
+
+    for page in &db.pages.table {
+        writer.num(page.names.len())?;
+        for name in &page.names {
+            writer.str(name.value)?;
+            writer.num(usize::from(name.source))?;
+        }
+        writer.list(&page.sects)?;
+        match &page.archs {
+            Some(archs) => {
+                writer.num(archs.len() + 1)?;
+                for arch in archs {
+                    writer.str(arch)?;
+                }
+            },
+            None => writer.num(0)?,
+        }
+        writer.str(page.desc)?;
+        writer.list(&page.files)?;
+        writer.num(usize::from(page.format.byte()))?;
+    }
+
+    writer.num(db.macros.get()?.tables.len())?;
+    for table in &db.macros.get()?.tables {
+        writer.num(table.values.len())?;
+        for value in &table.values {
+            writer.str(value.str)?;
+            writer.num(value.pages.len())?;
+            for &idx in &value.pages {
+                writer.num(idx)?;
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(HEADER_LEN + writer.strings.len() + writer.numbers.len());
+    out.extend_from_slice(CACHE_MAGIC);
+    out.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+    out.extend_from_slice(&u32::try_from(writer.strings.len())?.to_le_bytes());
+    out.extend_from_slice(&u32::try_from(writer.numbers.len())?.to_le_bytes());
+    out.extend_from_slice(&writer.strings);
+    out.extend_from_slice(&writer.numbers);
+
+    fs::write(path, out).map_err(|e| format!("{path}: {e}"))?;
+    debug!("Cached {} strings in {} bytes", writer.offsets.len(), writer.strings.len());
+    Ok(())
+}
+
+// Reads the numbers section of a cache.
+struct CacheReader<'a> {
+    strings: &'a str,
+    numbers: &'a [u8],
+    // The offset of the numbers section in the cache file.
+    start: usize,
+    // The index of the next number in the numbers section.
+    idx: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    // The offset of the next number in the cache file, for errors.
+    const fn pos(&self) -> usize {
+        self.start + self.idx
+    }
+
+    fn num(&mut self) -> Result<usize, DbError> {
+        let bytes = self.numbers
+            .get(self.idx..self.idx + 4)
+            .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, self.pos(),
+                "The cache ends too early."))?;
+        self.idx += 4;
+
+        let mut num = [0u8; 4];
+        num.copy_from_slice(bytes);
+        usize::try_from(u32::from_le_bytes(num))
+            .map_err(|_| DbError::at(ErrorKind::OutOfBounds, self.pos(), "Number is too large."))
+    }
+
+    fn str(&mut self) -> Result<&'a str, DbError> {
+        let pos = self.pos();
+        let offset = self.num()?;
+        let len = self.num()?;
+        offset
+            .checked_add(len)
+            .and_then(|end| self.strings.get(offset..end))
+            .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, pos,
+                "String is outside of the strings section."))
+    }
+
+    fn list(&mut self) -> Result<StrList<'a>, DbError> {
+        let len = self.count()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    // Reads the length of a list, which cannot be longer than the numbers
+    // that are left.
+    fn count(&mut self) -> Result<usize, DbError> {
+        let pos = self.pos();
+        let count = self.num()?;
+        if count > self.numbers.len() / 4 {
+            return Err(DbError::at(ErrorKind::Count, pos, "List is too long."));
+        }
+        Ok(count)
+    }
+}
+
+// Loads a database from a cache written by `write_cache`, along with the
+// path of the database it was made from.
+pub fn load_cache(bytes: &[u8]) -> Result<(Database<'_>, &str), Box<dyn Error>> {
+    let header = |idx: usize| -> Result<usize, DbError> {
+        let mut num = [0u8; 4];
+        num.copy_from_slice(bytes.get(idx..idx + 4).ok_or_else(|| {
+            DbError::at(ErrorKind::Format, 0, "Invalid cache file.")
+        })?);
+        usize::try_from(u32::from_le_bytes(num))
+            .map_err(|_| DbError::at(ErrorKind::OutOfBounds, idx, "Number is too large."))
+    };
+
+    if !bytes.starts_with(CACHE_MAGIC) {
+        return Err(DbError::at(ErrorKind::Format, 0, "Invalid cache file.").into());
+    }
+    if header(8)? != CACHE_VERSION as usize {
+        return Err(DbError::at(ErrorKind::Version, 8,
+            "Unsupported cache version; export the cache again.").into());
+    }
+
+    let strings_len = header(12)?;
+    let numbers_len = header(16)?;
+    let strings = HEADER_LEN
+        .checked_add(strings_len)
+        .and_then(|end| bytes.get(HEADER_LEN..end))
+        .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, 12,
+            "Strings section is past the end of the file."))?;
+    let numbers = bytes
+        .get(HEADER_LEN + strings_len..)
+        .filter(|numbers| numbers.len() == numbers_len)
+        .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, 16,
+            "Numbers section does not match the file size."))?;
+    let strings = str::from_utf8(strings)
+        .map_err(|e| DbError::at(ErrorKind::Encoding, HEADER_LEN + e.valid_up_to(),
+            "String is not valid UTF-8."))?;
+
+    let mut reader = CacheReader { strings, numbers, start: HEADER_LEN + strings_len, idx: 0 };
+    let db_path = reader.str()?;
+
+    let count = reader.count()?;
+    let mut table = Vec::with_capacity(count);
+    for _ in 0..count {
+        let pos = reader.pos();
+        let names = (0..reader.count()?)
+            .map(|_| {
+                let value = reader.str()?;
+                let source = u8::try_from(reader.num()?)
+                    .map_err(|_| DbError::at(ErrorKind::Format, pos, "Invalid name source."))?;
+                Ok(Name { value, source })
+            })
+            .collect::<Result<NameList<'_>, DbError>>()?;
+        let sects = reader.list()?;
+        let archs = match reader.count()? {
+            0 => None,
+            len => Some((1..len).map(|_| reader.str()).collect::<Result<StrList<'_>, DbError>>()?),
+        };
+        let desc = reader.str()?;
+        let files = reader.list()?;
+        let format = u8::try_from(reader.num()?)
+            .ok()
+            .and_then(|byte| PageFormat::try_from(byte).ok())
+            .ok_or_else(|| DbError::at(ErrorKind::Format, pos, "Unknown page format."))?;
+
+        table.push(Page { names, sects, archs, desc, files, format });
+    }
+
+    let tables_count = reader.count()?;
+    let mut tables = Vec::with_capacity(tables_count);
+    for _ in 0..tables_count {
+        let values_count = reader.count()?;
+        let mut values = Vec::with_capacity(values_count);
+        for _ in 0..values_count {
+            let str = reader.str()?;
+            let pages = (0..reader.count()?)
+                .map(|_| {
+                    let pos = reader.pos();
+                    let idx = reader.num()?;
+                    if idx >= table.len() {
+                        return Err(DbError::at(ErrorKind::OutOfBounds, pos,
+                            "Page index is past the end of the pages."));
+                    }
+                    Ok(idx)
+                })
+                .collect::<Result<Vec<usize>, DbError>>()?;
+            values.push(Value { str, pages });
+        }
+        tables.push(Table { count: values_count, values });
+    }
+
+    let db = Database {
+        version: Version::V1,
+        pages: Pages { count, table },
+        macros: Macros { count: tables_count, tables }.into(),
+        indexes: Indexes::default(),
+    };
+    Ok((db, db_path))
+}
@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::build;
+use crate::database::Database;
+use crate::pages::Page;
+use crate::search::SearchOptions;
+
+// Descriptions that stand in for a real one, compared case-insensitively and
+// without a final period.
+const PLACEHOLDER_DESCS: &[&str] = &[
+    "-", "?", "...", "(null)", "description", "fixme", "n/a", "no description",
+    "none", "short description", "tbd", "todo", "undocumented", "unknown", "xxx",
+];
+
+// A report on the pages of a database that maintainers may need to fix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    // Cross-references to pages that are not in the database.
+    Xrefs,
+    // Names that several pages of a section claim, or that differ only by
+    // case.
+    Duplicates,
+    // Pages without a proper description.
+    Descriptions,
+    // Page files that no longer exist, and with `unindexed`, page files that
+    // are not in the database.
+    Stale { unindexed: bool },
+}
+
+impl Check {
+    pub const NAMES: &'static [&'static str] = &["xrefs", "duplicates", "descriptions", "stale"];
+
+    // Writes what the check found in the pages that pass the section and
+    // architecture filters, and returns how many problems there were. Page
+    // files are relative to `man_dir`, the directory of the database.
+    pub fn write(
+        self,
+        out: &mut dyn Write,
+        db: &Database<'_>,
+        opts: &SearchOptions,
+        man_dir: &Path
+    ) -> Result<usize, Box<dyn Error>> {
+        let count = match self {
+            Self::Xrefs => write_dangling_xrefs(out, db, opts)?,
+            Self::Duplicates => write_duplicate_names(out, db, opts)?,
+            Self::Descriptions => write_missing_descs(out, db, opts)?,
+            Self::Stale { unindexed } => write_stale_files(out, db, opts, man_dir, unindexed)?,
+        };
+        Ok(count)
+    }
+
+    // Describes how many problems the check found, e.g. "No dangling
+    // cross-references found."
+    pub fn summary(self, count: usize) -> String {
+        let (one, many) = match self {
+            Self::Xrefs => ("dangling cross-reference", "dangling cross-references"),
+            Self::Duplicates => ("duplicate name", "duplicate names"),
+            Self::Descriptions => ("missing description", "missing descriptions"),
+            Self::Stale { .. } => ("stale file", "stale files"),
+        };
+        match count {
+            0 => format!("No {many} found."),
+            1 => format!("1 {one} found."),
+            count => format!("{count} {many} found."),
+        }
+    }
+}
+
+impl FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xrefs" => Ok(Self::Xrefs),
+            "duplicates" => Ok(Self::Duplicates),
+            "descriptions" => Ok(Self::Descriptions),
+            "stale" => Ok(Self::Stale { unindexed: false }),
+            _ => Err(format!("Unknown check \"{s}\". The checks are: {}.",
+                Self::NAMES.join(", "))),
+        }
+    }
+}
+
+// Formats the first name and section of a page, e.g. "ls(1)".
+fn label(page: &Page<'_>) -> String {
+    let name = page.names.first().map_or("", |n| n.value);
+    let sect = page.sects.first().unwrap_or_default();
+    format!("{name}({sect})")
+}
+
+// Writes the Xr references that no page of the database resolves to, one
+// "target ← page, page" line each, sorted by target, so the SEE ALSO
+// sections that mention them can be fixed.
+fn write_dangling_xrefs(
+    out: &mut dyn Write,
+    db: &Database<'_>,
+    opts: &SearchOptions
+) -> io::Result<usize> {
+    let targets = db.xr_targets();
+
+    // The label of each page the filters keep, by its position.
+    let pages = db.pages
+        .table
+        .iter()
+        .map(|page| opts.filters_match(page).then(|| label(page)))
+        .collect::<Vec<Option<String>>>();
+
+    let mut dangling = BTreeMap::new();
+    let xrs = db.macros
+        .get()
+        .map_err(io::Error::other)?
+        .tables
+        .first()
+        .map(|table| &table.values[..])
+        .unwrap_or_default();
+    for value in xrs {
+        if targets.contains_key(&value.str.to_ascii_lowercase()) {
+            continue;
+        }
+
+        let referrers = value.pages
+            .iter()
+            .filter_map(|&idx| pages.get(idx)?.as_deref())
+            .collect::<Vec<&str>>();
+        if !referrers.is_empty() {
+            dangling.insert(value.str, referrers);
+        }
+    }
+
+    for (target, referrers) in &dangling {
+        writeln!(out, "{target} \u{2190} {}", referrers.join(", "))?;
+    }
+    Ok(dangling.len())
+}
+
+// Whether two pages can both be installed on one machine, i.e. they are not
+// for different architectures.
+fn archs_overlap(a: &Page<'_>, b: &Page<'_>) -> bool {
+    match (&a.archs, &b.archs) {
+        (Some(a), Some(b)) => a.iter().any(|arch| b.contains(arch)),
+        _ => true,
+    }
+}
+
+// Writes the names that more than one page of a section claims, and the names
+// of a section that differ only by case, which point to packaging conflicts
+// or makewhatis(8) bugs. Pages for different architectures may share names.
+// Each conflict is listed with the first file of its pages.
+fn write_duplicate_names(
+    out: &mut dyn Write,
+    db: &Database<'_>,
+    opts: &SearchOptions
+) -> io::Result<usize> {
+    // The pages claiming each name in each section, by the lowercase name
+    // and section, then by their spelling.
+    let mut claims = BTreeMap::<(String, String), BTreeMap<(&str, &str), Vec<&Page<'_>>>>::new();
+    for page in db.pages.table.iter().filter(|page| opts.filters_match(page)) {
+        for name in &page.names {
+            for sect in &page.sects {
+                let key = (name.value.to_lowercase(), sect.to_lowercase());
+                let pages = claims.entry(key).or_default().entry((name.value, sect)).or_default();
+                if !pages.iter().any(|other| std::ptr::eq(*other, page)) {
+                    pages.push(page);
+                }
+            }
+        }
+    }
+
+    let file = |page: &Page<'_>| page.files.first().unwrap_or("?").to_string();
+    let mut count = 0;
+    for spellings in claims.values() {
+        for ((name, sect), pages) in spellings {
+            let conflict = pages.iter().enumerate().any(|(idx, page)| {
+                pages[idx + 1..].iter().any(|other| archs_overlap(page, other))
+            });
+            if conflict {
+                let files = pages.iter().map(|page| file(page)).collect::<Vec<String>>();
+                writeln!(out, "{name}({sect}) is claimed by {} pages: {}", pages.len(),
+                    files.join(", "))?;
+                count += 1;
+            }
+        }
+
+        if spellings.len() > 1 {
+            let names = spellings
+                .keys()
+                .map(|(name, sect)| format!("{name}({sect})"))
+                .collect::<Vec<String>>();
+            let files = spellings
+                .values()
+                .flatten()
+                .map(|page| file(page))
+                .collect::<Vec<String>>();
+            writeln!(out, "{} differ only by case: {}", names.join(", "), files.join(", "))?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+// Writes the pages whose description is empty, a placeholder like "TODO",
+// or just one of their names, which usually means the page has no .Nd line.
+// The pages are sorted like apropos(1) and listed with their first file.
+fn write_missing_descs(
+    out: &mut dyn Write,
+    db: &Database<'_>,
+    opts: &SearchOptions
+) -> io::Result<usize> {
+    let mut pages = db.pages
+        .table
+        .iter()
+        .filter(|page| opts.filters_match(page))
+        .filter_map(|page| {
+            let desc = page.desc.trim();
+            let lower = desc.trim_end_matches('.').to_lowercase();
+            let problem = if desc.is_empty() {
+                "no description".to_string()
+            } else if PLACEHOLDER_DESCS.contains(&lower.as_str())
+                || page.names.iter().any(|name| name.value.eq_ignore_ascii_case(desc))
+            {
+                format!("placeholder description \"{desc}\"")
+            } else {
+                return None;
+            };
+            Some((page, problem))
+        })
+        .collect::<Vec<(&Page<'_>, String)>>();
+    pages.sort_by(|(a, _), (b, _)| a.apropos_cmp(b));
+
+    for (page, problem) in &pages {
+        let file = page.files.first().unwrap_or("?");
+        writeln!(out, "{}: {problem} [{file}]", label(page))?;
+    }
+    Ok(pages.len())
+}
+
+// Writes the files of the pages that no longer exist in `man_dir`, which
+// means the database needs to be built again. With `unindexed`, also writes
+// the page files in `man_dir` that are not in the database.
+fn write_stale_files(
+    out: &mut dyn Write,
+    db: &Database<'_>,
+    opts: &SearchOptions,
+    man_dir: &Path,
+    unindexed: bool
+) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+    for page in db.pages.table.iter().filter(|page| opts.filters_match(page)) {
+        for file in page.files.iter().filter(|file| !man_dir.join(file).exists()) {
+            writeln!(out, "missing: {file} ({})", label(page))?;
+            count += 1;
+        }
+    }
+
+    if unindexed {
+        // Every page's files, whatever the filters, since a file of a page
+        // that is filtered out is still in the database.
+        let indexed = db.pages
+            .table
+            .iter()
+            .flat_map(|page| &page.files)
+            .collect::<HashSet<&str>>();
+        for path in build::page_files(man_dir)? {
+            let file = path.strip_prefix(man_dir).unwrap_or(&path).to_string_lossy();
+            if !indexed.contains(file.as_ref()) {
+                writeln!(out, "unindexed: {file}")?;
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,703 @@
+use std::env;
+use std::error::Error;
+use std::path::Path;
+
+use crate::check::Check;
+use crate::database::STDIN_PATH;
+use crate::color::ColorChoice;
+use crate::completions::Shell;
+use crate::error::ErrorFormat;
+use crate::export::ExportFormat;
+use crate::fetch;
+use crate::httpd;
+use crate::macros;
+use crate::manconf::{read_manpaths, split_manpath, system_manpath, with_env_manpath};
+use crate::output::OutputStyle;
+use crate::search::MatchMode;
+
+// The kind of value an option takes, used for help and shell completions.
+#[derive(Clone, Copy, Debug)]
+pub enum ArgValue {
+    None,
+    Path,
+    Number,
+    Text,
+    Choice(&'static [&'static str]),
+}
+
+// Describes one command line option.
+#[derive(Clone, Copy, Debug)]
+pub struct OptSpec {
+    pub short: Option<char>,
+    pub long: &'static str,
+    pub value: ArgValue,
+    pub value_name: &'static str,
+    pub help: &'static str,
+}
+
+impl OptSpec {
+    const fn flag(short: char, long: &'static str, help: &'static str) -> Self {
+        Self { short: Some(short), long, value: ArgValue::None, value_name: "", help }
+    }
+
+    const fn short_value(
+        short: char,
+        long: &'static str,
+        value: ArgValue,
+        value_name: &'static str,
+        help: &'static str
+    ) -> Self {
+        Self { short: Some(short), long, value, value_name, help }
+    }
+
+    const fn long_flag(long: &'static str, help: &'static str) -> Self {
+        Self { short: None, long, value: ArgValue::None, value_name: "", help }
+    }
+
+    const fn with_value(
+        long: &'static str,
+        value: ArgValue,
+        value_name: &'static str,
+        help: &'static str
+    ) -> Self {
+        Self { short: None, long, value, value_name, help }
+    }
+}
+
+// Every option the binary understands.
+pub const OPTIONS: &[OptSpec] = &[
+    OptSpec::flag('h', "help", "Print this help message."),
+    OptSpec::flag('s', "search", "Search for a page entry by name."),
+    OptSpec::flag('v', "verbose",
+        "Log parse steps and timings to stderr (-vv for more)."),
+    OptSpec::with_value("config", ArgValue::Path, "PATH",
+        "Read defaults from PATH instead of the config file."),
+    OptSpec::with_value("match", ArgValue::Choice(MatchMode::NAMES), "MODE",
+        "Match names using MODE: exact (default), substring, prefix, regex, or fuzzy."),
+    OptSpec::with_value("limit", ArgValue::Number, "N",
+        "Show at most N results per search."),
+    OptSpec::with_value("style",
+        ArgValue::Choice(&["detailed", "whatis", "terse"]), "STYLE",
+        "Print results using STYLE: detailed (default), whatis, or terse."),
+    OptSpec::short_value('o', "output", ArgValue::Choice(OutputStyle::FIELDS), "FIELD",
+        "Print only FIELD for each result: name, path, section, desc, or json."),
+    OptSpec::with_value("format", ArgValue::Text, "TEMPLATE",
+        "Print each result using TEMPLATE, e.g. \"{name}({sect})\\t{desc}\"."),
+    OptSpec::long_flag("markdown", "Print results, or export pages, as Markdown tables."),
+    OptSpec::long_flag("roff", "Print results as an mdoc(7) document for mandoc(1)."),
+    OptSpec::flag('t', "terse", "Print results exactly like man -k (--style terse)."),
+    OptSpec::flag('w', "where", "Print the absolute paths of the matching pages' files."),
+    OptSpec::long_flag("resolve-files",
+        "Show the files of results as absolute paths, leaving out missing ones."),
+    OptSpec::long_flag("open", "Open the first result in man(1) instead of printing it."),
+    OptSpec::long_flag("tui", "Browse the database in a full-screen interface."),
+    OptSpec::long_flag("pick", "Pick a page by fuzzy matching its name and print it."),
+    OptSpec::with_value("connect", ArgValue::Path, "SOCKET",
+        "Send the searches to the daemon started with serve --socket SOCKET."),
+    OptSpec::long_flag("full-search",
+        "Index the words of the descriptions when loading, for faster Nd= searches."),
+    OptSpec::long_flag("sorted-index",
+        "Look names up in a sorted array built when loading, which uses less memory."),
+    OptSpec::with_value("bench", ArgValue::Number, "N",
+        "Search for the NAMEs N times and print how long the searches took."),
+    OptSpec::with_value("bench-queries", ArgValue::Path, "FILE",
+        "Benchmark the queries in FILE, one per line, instead of the NAMEs."),
+    OptSpec::long_flag("serve-stdio",
+        "Answer JSON search, complete, and get-page requests on stdin, one per line."),
+    OptSpec::with_value("color", ArgValue::Choice(ColorChoice::NAMES), "WHEN",
+        "Colorize output: auto (default), always, or never."),
+    OptSpec::long_flag("no-pager", "Never pipe long output through $PAGER."),
+    OptSpec::with_value("errors", ArgValue::Choice(ErrorFormat::NAMES), "FORMAT",
+        "Print errors on stderr as text (default) or json."),
+    OptSpec::with_value("log-file", ArgValue::Path, "PATH",
+        "Append a JSON line for every search to PATH."),
+    OptSpec::with_value("from-cache", ArgValue::Path, "FILE",
+        "Load the database from FILE, made by export --cache."),
+    OptSpec::long_flag("stream",
+        "Read database files a part at a time, keeping only their strings in memory."),
+    OptSpec::long_flag("swap-bytes",
+        "Read databases written with the opposite endianness by swapping their numbers."),
+    OptSpec::with_value("section", ArgValue::Text, "SECT",
+        "Only show pages in section SECT (-s as apropos or whatis)."),
+    OptSpec::short_value('S', "arch", ArgValue::Text, "ARCH",
+        "Only show pages for architecture ARCH or machine-independent ones."),
+    OptSpec::with_value("lang", ArgValue::Text, "LANG",
+        "Search the pages translated for locale LANG, e.g. de, and the untranslated ones."),
+    OptSpec::short_value('C', "man-conf", ArgValue::Path, "FILE",
+        "Read manpath directives from the man.conf(5) file FILE."),
+    OptSpec::short_value('M', "manpath", ArgValue::Path, "DIRS",
+        "Look for mandoc.db in the colon-separated DIRS instead."),
+    OptSpec::short_value('m', "add-manpath", ArgValue::Path, "DIRS",
+        "Look for mandoc.db in the colon-separated DIRS first."),
+];
+
+// Every subcommand the binary understands, along with its arguments.
+pub const SUBCOMMANDS: &[(&str, &str, &str)] = &[
+    ("build", "<MAN_DIR> [FILE]",
+        "Index the man pages in MAN_DIR into FILE (default: MAN_DIR/mandoc.db)."),
+    ("update", "<DB> <FILE>...",
+        "Index the added, changed, or removed man page FILEs again and rewrite DB."),
+    ("completions", "<bash|zsh|fish>", "Print a shell completion script."),
+    ("check", "<xrefs|duplicates|descriptions|stale [--unindexed]> [DB]",
+        "Report dangling cross-references, names claimed by several pages, missing \
+        descriptions, or page files that were removed (or never indexed)."),
+    ("complete", "<PREFIX> [DB]", "Print page names starting with PREFIX."),
+    ("dump-macros", "[KEY] [DB]",
+        "Print the values of the macro table for KEY (e.g. Xr), or of all 36, and their pages."),
+    ("stats", "[--space | --memory] [DB]",
+        "Print a summary of the database, or with --space, the bytes each part of it uses, or \
+        with --memory, the heap memory its pages, macros, and each index use."),
+    ("validate", "[--verify-roundtrip] [DB]",
+        "Check the database for damage and print each problem with its offset; with \
+        --verify-roundtrip, also check that rewriting it changes no bytes."),
+    ("repair", "<DB> <FILE>",
+        "Write the pages and macro values of a damaged DB that can be salvaged to FILE."),
+    ("merge", "<DB>... -o <FILE>",
+        "Merge the databases into FILE; pages in earlier databases win."),
+    ("convert", "--from-whatis <FILE> -o <DB>",
+        "Write the pages of a plain text whatis database FILE to the mandoc.db DB."),
+    ("filter", "[--section SECT] [--arch ARCH] -o <FILE> [DB]",
+        "Write the pages in section SECT or for architecture ARCH to FILE, with their macro \
+        values."),
+    ("diff", "<OLD_DB> <NEW_DB>",
+        "Print the pages added, removed, renamed, or changed between two databases."),
+    ("serve", "[--listen ADDR | --socket PATH] [DB]...",
+        "Serve searches as JSON over HTTP on ADDR (default: 127.0.0.1:8080), at /search?q=QUERY, \
+        /page/NAME, and /complete?q=PREFIX, or as a daemon on the Unix socket PATH."),
+    ("export",
+        "<FORMAT> [DB]",
+        "Write every page as --csv, --tsv, --markdown, --dot, --whatis, --json, --xml, --cache FILE, --sqlite FILE, \
+        --docset DIR, or --html DIR."),
+];
+
+// A subcommand to run instead of searching a database.
+#[derive(Clone, Debug)]
+pub enum Command {
+    // The man directory to index and the database to write.
+    Build { dir: String, output: Option<String> },
+    Completions(Shell),
+    Complete(String),
+    // The whatis text database to read and the database to write.
+    Convert { whatis: String, output: String },
+    // The database to write the pages that pass the filters to.
+    Filter { output: String },
+    Check(Check),
+    // The macro table to print, or all of them.
+    DumpMacros(Option<String>),
+    // The old and new databases to compare.
+    Diff { old: String, new: String },
+    Export(ExportFormat),
+    // The database to rewrite and the page files that changed.
+    Update { db: String, files: Vec<String> },
+    // Whether to print the bytes used by each part of the database, or the
+    // heap memory used by its parsed parts and indexes.
+    Stats { space: bool, memory: bool },
+    // Whether to also check that writing the database gives back its bytes.
+    Validate { roundtrip: bool },
+    // Where to serve searches.
+    Serve(ServeOn),
+    // The damaged database and the database to write.
+    Repair { db: String, output: String },
+    // The databases to merge and the database to write.
+    Merge { dbs: Vec<String>, output: String },
+}
+
+// Where `serve` answers requests.
+#[derive(Clone, Debug)]
+pub enum ServeOn {
+    // The address to answer HTTP requests on, e.g. "127.0.0.1:8080".
+    Http(String),
+    // The path of a Unix socket to answer requests on, one JSON object per
+    // line, as a daemon for `--connect`.
+    Socket(String),
+}
+
+// Which frontend the program is acting as. Besides its own options, the
+// program can stand in for apropos(1) and whatis(1), which is selected by the
+// name it is invoked as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Program {
+    #[default]
+    Search,
+    Apropos,
+    Whatis,
+}
+
+impl Program {
+    // Detects the frontend from the program name in `argv[0]`.
+    pub fn from_argv0(argv0: &str) -> Self {
+        let name = Path::new(argv0)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+
+        match name {
+            "apropos" => Self::Apropos,
+            "whatis" | "mandoc_whatis" => Self::Whatis,
+            _ => Self::Search,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Search => env!("CARGO_PKG_NAME"),
+            Self::Apropos => "apropos",
+            Self::Whatis => "whatis",
+        }
+    }
+
+    // Whether `-s` selects a section (as in apropos(1) and whatis(1)) rather
+    // than starting the search prompt.
+    const fn is_man_compat(self) -> bool {
+        matches!(self, Self::Apropos | Self::Whatis)
+    }
+}
+
+// The parsed command line arguments.
+#[derive(Clone, Debug, Default)]
+pub struct Args {
+    pub command: Option<Command>,
+    // The databases to load, of which searches include every one.
+    pub db_paths: Vec<String>,
+    // Names to look up without entering the interactive prompt.
+    pub queries: Vec<String>,
+    pub config_path: Option<String>,
+    pub search: bool,
+    pub match_mode: Option<MatchMode>,
+    pub limit: Option<usize>,
+    pub style: Option<OutputStyle>,
+    pub color: Option<ColorChoice>,
+    pub section: Option<String>,
+    pub arch: Option<String>,
+    pub man_conf: Option<String>,
+    // The locale whose translated pages are searched, instead of the one of
+    // the environment.
+    pub lang: Option<String>,
+    // Directories given with -M, replacing the default manpath.
+    pub manpath: Option<Vec<String>>,
+    // Directories given with -m, searched before the others.
+    pub add_manpath: Vec<String>,
+    // Whether to print file paths like `man -w`.
+    pub where_: bool,
+    // Whether to show the files of results as absolute paths.
+    pub resolve_files: bool,
+    // Whether to open the first result in man(1).
+    pub open: bool,
+    // Whether to start the full-screen browser.
+    pub tui: bool,
+    // Whether to pick a page interactively and print its section and name.
+    pub pick: bool,
+    // Whether to index the words of the descriptions as the databases are
+    // loaded.
+    pub full_search: bool,
+    // Whether to sort the names as the databases are loaded and binary
+    // search them, instead of building hash and prefix indexes.
+    pub sorted_index: bool,
+    // How many times to run each query to measure it, if at all.
+    pub bench: Option<usize>,
+    // A file of queries to measure, one per line.
+    pub bench_queries: Option<String>,
+    // Whether to answer JSON requests on standard input, for editors.
+    pub serve_stdio: bool,
+    // The socket of a daemon to send the searches to instead of reading the
+    // databases.
+    pub connect: Option<String>,
+    // Whether long output may be shown through $PAGER.
+    pub pager: bool,
+    // How failures are printed on stderr.
+    pub errors: ErrorFormat,
+    // Where to record every search, if anywhere.
+    pub log_file: Option<String>,
+    // A cache made by `export --cache` to load instead of the database.
+    pub from_cache: Option<String>,
+    // Whether to read database files a part at a time instead of whole.
+    pub stream: bool,
+    // Whether to read databases written with the opposite endianness.
+    pub swap_bytes: bool,
+    pub verbosity: u8,
+}
+
+impl Args {
+    // Parses the process arguments, detecting the frontend from `argv[0]`.
+    // The arguments are `None` when the help message should be printed.
+    pub fn parse() -> Result<(Program, Option<Self>), Box<dyn Error>> {
+        let mut args = env::args();
+        let program = args
+            .next()
+            .map_or_else(Program::default, |argv0| Program::from_argv0(&argv0));
+
+        Ok((program, Self::parse_from(args, program)?))
+    }
+
+    // Returns `None` when the help message should be printed instead.
+    pub fn parse_from<I>(
+        args: I,
+        program: Program
+    ) -> Result<Option<Self>, Box<dyn Error>>
+    where
+        I: Iterator<Item = String>,
+    {
+        let mut parsed = Self { pager: true, ..Self::default() };
+        let mut args = args.peekable();
+        let man_compat = program.is_man_compat();
+
+        match args.peek().map(String::as_str) {
+            Some("build") => {
+                args.next();
+                let dir = args.next().ok_or("Missing man directory for build.")?;
+                let output = args.next();
+                parsed.command = Some(Command::Build { dir, output });
+                return Ok(args.next().is_none().then_some(parsed));
+            },
+            Some("update") => {
+                args.next();
+                let db = args.next().ok_or("Missing database for update.")?;
+                // Other options, like -v, may follow the files.
+                let files = std::iter::from_fn(|| args.next_if(|arg| !arg.starts_with('-')))
+                    .collect::<Vec<String>>();
+                if files.is_empty() {
+                    return Err("Missing man page files to update.".into());
+                }
+                parsed.command = Some(Command::Update { db, files });
+            },
+            Some("completions") => {
+                args.next();
+                let shell = args.next().ok_or("Missing shell for completions.")?;
+                parsed.command = Some(Command::Completions(shell.parse()?));
+                return Ok(args.next().is_none().then_some(parsed));
+            },
+            Some("complete") => {
+                args.next();
+                // A missing prefix completes every name.
+                let prefix = args.next().unwrap_or_default();
+                parsed.command = Some(Command::Complete(prefix));
+            },
+            Some("check") => {
+                args.next();
+                let check = args.next().ok_or_else(|| {
+                    format!("Missing check; the checks are: {}.", Check::NAMES.join(", "))
+                })?;
+                let mut check = check.parse()?;
+                if let Check::Stale { unindexed } = &mut check {
+                    *unindexed = args.next_if(|arg| arg == "--unindexed").is_some();
+                }
+                parsed.command = Some(Command::Check(check));
+            },
+            Some("dump-macros") => {
+                args.next();
+                // A key comes before the database path.
+                let key = args.next_if(|arg| !arg.starts_with('-') && !is_db_path(arg));
+                if let Some(key) = &key {
+                    if !macros::KEYS.contains(&key.as_str()) {
+                        return Err(format!("Unknown macro key \"{key}\". The keys are: {}.",
+                            macros::KEYS.join(", ")).into());
+                    }
+                }
+                parsed.command = Some(Command::DumpMacros(key));
+            },
+            Some("stats") => {
+                args.next();
+                let space = args.next_if(|arg| arg == "--space").is_some();
+                let memory = !space && args.next_if(|arg| arg == "--memory").is_some();
+                parsed.command = Some(Command::Stats { space, memory });
+            },
+            Some("validate") => {
+                args.next();
+                let roundtrip = args.next_if(|arg| arg == "--verify-roundtrip").is_some();
+                parsed.command = Some(Command::Validate { roundtrip });
+            },
+            Some("repair") => {
+                args.next();
+                let db = args.next().ok_or("Missing database to repair.")?;
+                let output = args.next().ok_or("Missing file for the repaired database.")?;
+                parsed.command = Some(Command::Repair { db, output });
+            },
+            Some("merge") => {
+                args.next();
+                // Other options, like -v, may follow the databases and -o.
+                let mut dbs = Vec::new();
+                let mut output = None;
+                while let Some(arg) = args.next_if(|arg| {
+                    matches!(arg.as_str(), "-o" | "--output") || !arg.starts_with('-')
+                }) {
+                    match arg.as_str() {
+                        "-o" | "--output" => {
+                            output = Some(args.next().ok_or("Missing value for -o.")?);
+                        },
+                        _ => dbs.push(arg),
+                    }
+                }
+                if dbs.len() < 2 {
+                    return Err("Missing databases to merge; give at least two.".into());
+                }
+                let output = output.ok_or("Missing -o FILE for the merged database.")?;
+                parsed.command = Some(Command::Merge { dbs, output });
+            },
+            Some("convert") => {
+                args.next();
+                // Other options, like -v, may follow the input and -o.
+                let mut whatis = None;
+                let mut output = None;
+                while let Some(arg) = args.next_if(|arg| {
+                    matches!(arg.as_str(), "--from-whatis" | "-o" | "--output")
+                }) {
+                    let value = args.next().ok_or_else(|| format!("Missing value for {arg}."))?;
+                    if arg == "--from-whatis" {
+                        whatis = Some(value);
+                    } else {
+                        output = Some(value);
+                    }
+                }
+                let whatis = whatis.ok_or("Missing --from-whatis FILE to convert.")?;
+                let output = output.ok_or("Missing -o FILE for the converted database.")?;
+                parsed.command = Some(Command::Convert { whatis, output });
+            },
+            Some("filter") => {
+                args.next();
+                // -o names the file here rather than an output field. Other
+                // options, like the database, may follow.
+                let mut output = None;
+                while let Some(arg) = args.next_if(|arg| {
+                    matches!(arg.as_str(), "-o" | "--output" | "--section" | "-S" | "--arch")
+                }) {
+                    let value = args.next().ok_or_else(|| format!("Missing value for {arg}."))?;
+                    match arg.as_str() {
+                        "--section" => parsed.section = Some(value),
+                        "-S" | "--arch" => parsed.arch = Some(value),
+                        _ => output = Some(value),
+                    }
+                }
+                let output = output.ok_or("Missing -o FILE for the filtered database.")?;
+                parsed.command = Some(Command::Filter { output });
+            },
+            Some("diff") => {
+                args.next();
+                let old = args.next().ok_or("Missing old database for diff.")?;
+                let new = args.next().ok_or("Missing new database for diff.")?;
+                parsed.command = Some(Command::Diff { old, new });
+            },
+            Some("serve") => {
+                args.next();
+                // Other options, like the databases, may follow.
+                let mut serve_on = None;
+                while let Some(arg) = args.next_if(|arg| arg == "--listen" || arg == "--socket") {
+                    if serve_on.is_some() {
+                        return Err("Give serve either --listen or --socket, once.".into());
+                    }
+                    let value = args.next().ok_or_else(|| format!("Missing value for {arg}."))?;
+                    serve_on = Some(if arg == "--listen" {
+                        ServeOn::Http(value)
+                    } else {
+                        ServeOn::Socket(value)
+                    });
+                }
+                let serve_on = serve_on.unwrap_or_else(|| {
+                    ServeOn::Http(httpd::DEFAULT_LISTEN.to_string())
+                });
+                parsed.command = Some(Command::Serve(serve_on));
+            },
+            Some("export") => {
+                args.next();
+                parsed.command = Some(Command::Export(ExportFormat::from_args(&mut args)?));
+            },
+            _ => {},
+        }
+
+        while let Some(arg) = args.next() {
+            // Support "--flag value", "--flag=value", "-f value", and "-fvalue".
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                },
+                // Only flags are split, and both of their bytes are ASCII, so
+                // the value starts on a char boundary.
+                _ if arg.len() > 2
+                    && !arg.starts_with("--")
+                    && (matches!(arg.get(..2), Some("-S" | "-C" | "-M" | "-m" | "-o"))
+                        || (man_compat && arg.starts_with("-s"))) => {
+                    let (flag, value) = arg.split_at(2);
+                    (flag.to_string(), Some(value.to_string()))
+                },
+                _ => (arg, None),
+            };
+
+            let mut value = |name: &str| {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {name}."))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-s" if man_compat => parsed.section = Some(value("-s")?),
+                "--no-pager" => parsed.pager = false,
+                "--errors" => parsed.errors = value("--errors")?.parse()?,
+                "--log-file" => parsed.log_file = Some(value("--log-file")?),
+                "--from-cache" => parsed.from_cache = Some(value("--from-cache")?),
+                "--stream" => parsed.stream = true,
+                "--swap-bytes" => parsed.swap_bytes = true,
+                "--color" => parsed.color = Some(value("--color")?.parse()?),
+                "--open" => parsed.open = true,
+                "--tui" => parsed.tui = true,
+                "--pick" => parsed.pick = true,
+                "--full-search" => parsed.full_search = true,
+                "--sorted-index" => parsed.sorted_index = true,
+                "--serve-stdio" => parsed.serve_stdio = true,
+                "--connect" => parsed.connect = Some(value("--connect")?),
+                "-w" | "--where" => parsed.where_ = true,
+                "--resolve-files" => parsed.resolve_files = true,
+                "--section" => parsed.section = Some(value("--section")?),
+                "-s" | "--search" => parsed.search = true,
+                "-S" | "--arch" => parsed.arch = Some(value("--arch")?),
+                "--lang" => parsed.lang = Some(value("--lang")?),
+                "-C" | "--man-conf" => parsed.man_conf = Some(value("--man-conf")?),
+                "-M" | "--manpath" => {
+                    let dirs = value("--manpath")?;
+                    parsed.manpath = Some(split_manpath(&dirs).collect());
+                },
+                "-m" | "--add-manpath" => {
+                    let dirs = value("--add-manpath")?;
+                    parsed.add_manpath.extend(split_manpath(&dirs));
+                },
+                "-v" | "--verbose" => {
+                    parsed.verbosity = parsed.verbosity.saturating_add(1);
+                },
+                "--config" => parsed.config_path = Some(value("--config")?),
+                "--match" => {
+                    parsed.match_mode = Some(value("--match")?.parse()?);
+                },
+                "--bench" => {
+                    parsed.bench = Some(value("--bench")?
+                        .parse()
+                        .ok()
+                        .filter(|&runs| runs > 0)
+                        .ok_or("Invalid value for --bench.")?);
+                },
+                "--bench-queries" => parsed.bench_queries = Some(value("--bench-queries")?),
+                "--limit" => {
+                    parsed.limit = Some(value("--limit")?
+                        .parse()
+                        .map_err(|_| "Invalid value for --limit.")?);
+                },
+                "-t" | "--terse" => parsed.style = Some(OutputStyle::Terse),
+                "--markdown" => parsed.style = Some(OutputStyle::Markdown),
+                "--roff" => parsed.style = Some(OutputStyle::Roff),
+                "--style" => parsed.style = Some(value("--style")?.parse()?),
+                "--format" => {
+                    let template = value("--format")?.parse()?;
+                    parsed.style = Some(OutputStyle::Template(template));
+                },
+                "-o" | "--output" => {
+                    let field = value("--output")?;
+                    if !OutputStyle::FIELDS.contains(&field.as_str()) {
+                        return Err(format!("Unknown output field \"{field}\".").into());
+                    }
+                    parsed.style = Some(field.parse()?);
+                },
+                // Stacked verbosity flags (e.g. "-vv").
+                flag if flag.len() > 2
+                    && flag.starts_with('-')
+                    && flag[1..].bytes().all(|b| b == b'v') => {
+                    let count = u8::try_from(flag.len() - 1).unwrap_or(u8::MAX);
+                    parsed.verbosity = parsed.verbosity.saturating_add(count);
+                },
+                // Standard input is read once, for one database.
+                STDIN_PATH if parsed.db_paths.iter().any(|path| path == STDIN_PATH) => {
+                    return Err("Only one database can be read from standard input.".into());
+                },
+                STDIN_PATH => parsed.db_paths.push(flag),
+                flag if flag.starts_with('-') => return Ok(None),
+                _ => parsed.queries.push(flag),
+            }
+        }
+
+        // The first positional arguments are database paths as long as they
+        // look like one. The rest are queries. A "-" anywhere is a database
+        // read from standard input, searched after the files.
+        if !man_compat {
+            let count = parsed.queries.iter().take_while(|p| is_db_path(p)).count();
+            parsed.db_paths.splice(..0, parsed.queries.drain(..count));
+        }
+
+        // Requests are read from standard input, so the database cannot be.
+        if parsed.serve_stdio && parsed.db_paths.iter().any(|path| path == STDIN_PATH) {
+            return Err("--serve-stdio reads requests from standard input, so it cannot read \
+                the database from it.".into());
+        }
+
+        // Only files can be read a part at a time.
+        let unseekable = parsed.db_paths
+            .iter()
+            .any(|path| path == STDIN_PATH || fetch::is_url(path));
+        if parsed.stream && (unseekable || parsed.from_cache.is_some()) {
+            return Err("--stream reads database files, not standard input, URLs, or caches."
+                .into());
+        }
+
+        if parsed.bench_queries.is_some() && parsed.bench.is_none() {
+            return Err("--bench-queries needs --bench N, the number of runs.".into());
+        }
+        if parsed.bench.is_some() && parsed.bench_queries.is_none() && parsed.queries.is_empty() {
+            return Err("Missing queries to benchmark with --bench.".into());
+        }
+
+        if parsed.bench.is_some() && parsed.connect.is_some() {
+            return Err("--bench measures searches of the databases, not of a daemon.".into());
+        }
+
+        if parsed.connect.is_some() && parsed.command.is_some() {
+            return Err("--connect only sends searches, not subcommands.".into());
+        }
+
+        // Subcommands do not take queries.
+        if parsed.command.is_some() && !parsed.queries.is_empty() {
+            return Ok(None);
+        }
+
+        // Like apropos(1) and whatis(1), default to one line per result.
+        if man_compat && parsed.style.is_none() {
+            parsed.style = Some(OutputStyle::Terse);
+        }
+
+        // whatis(1) only matches complete names.
+        if program == Program::Whatis && parsed.match_mode.is_none() {
+            parsed.match_mode = Some(MatchMode::Exact);
+        }
+
+        Ok(Some(parsed))
+    }
+
+    // Returns the manpath directories selected by -M, -m, and -C, in the
+    // order they should be searched. Like man(1), the directories of -m come
+    // before the system manpath when neither -M nor -C replaces it.
+    pub fn manpath_dirs(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut dirs = self.add_manpath.clone();
+
+        if let Some(manpath) = &self.manpath {
+            dirs.extend(manpath.iter().cloned());
+        } else if let Some(man_conf) = &self.man_conf {
+            dirs.extend(with_env_manpath(read_manpaths(man_conf)?));
+        } else if !dirs.is_empty() {
+            for dir in system_manpath() {
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+
+        Ok(dirs)
+    }
+}
+
+// Whether a positional argument is a database path rather than a name to look
+// up: a path with a directory, a file named like a database, a URL, or "-".
+// Whether a file of that name exists does not matter, so a file named `ls` in
+// the current directory does not turn `ls` into a database.
+fn is_db_path(arg: &str) -> bool {
+    arg.contains('/')
+        || arg.ends_with(".db")
+        || arg.ends_with(".db.gz")
+        || fetch::is_url(arg)
+        || arg == STDIN_PATH
+}
@@ -0,0 +1,157 @@
+use std::env;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+// Highlights only change the foreground color so that they can be nested
+// inside bold or dim text.
+const HIGHLIGHT: &str = "\x1b[33m";
+const DEFAULT_FG: &str = "\x1b[39m";
+
+// When to colorize output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    // Only when stdout is a terminal and NO_COLOR is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl Display for ColorChoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Auto => f.write_str("auto"),
+            Self::Always => f.write_str("always"),
+            Self::Never => f.write_str("never"),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!("Unknown color choice \"{s}\".").into()),
+        }
+    }
+}
+
+impl ColorChoice {
+    pub const NAMES: &'static [&'static str] = &["auto", "always", "never"];
+
+    // Resolves the choice against the environment (see https://no-color.org).
+    pub fn enabled(self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => {
+                let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                !no_color && io::stdout().is_terminal()
+            },
+        }
+    }
+}
+
+// Wraps text in ANSI escape sequences when colors are enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    pub const fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    pub const fn plain() -> Self {
+        Self { enabled: false }
+    }
+
+    pub fn bold(self, text: &str) -> String {
+        self.wrap(BOLD, text, RESET)
+    }
+
+    pub fn dim(self, text: &str) -> String {
+        self.wrap(DIM, text, RESET)
+    }
+
+    // Bolds a name and highlights the parts of it that match the queries.
+    pub fn name(self, text: &str, queries: &[&str]) -> String {
+        self.bold(&self.highlight(text, queries))
+    }
+
+    // Highlights every case-insensitive occurrence of the queries in `text`.
+    pub fn highlight(self, text: &str, queries: &[&str]) -> String {
+        if !self.enabled {
+            return text.to_string();
+        }
+
+        // ASCII lowercasing keeps byte offsets identical to the original.
+        let lower = text.to_ascii_lowercase();
+        let mut marked = vec![false; text.len()];
+
+        for query in queries.iter().filter(|q| !q.is_empty()) {
+            let query = query.to_ascii_lowercase();
+            for (start, _) in lower.match_indices(&query) {
+                marked[start..start + query.len()].fill(true);
+            }
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut in_match = false;
+
+        for (idx, c) in text.char_indices() {
+            if marked[idx] != in_match {
+                in_match = marked[idx];
+                out.push_str(if in_match { HIGHLIGHT } else { DEFAULT_FG });
+            }
+            out.push(c);
+        }
+
+        if in_match {
+            out.push_str(DEFAULT_FG);
+        }
+
+        out
+    }
+
+    fn wrap(self, start: &str, text: &str, end: &str) -> String {
+        if self.enabled {
+            format!("{start}{text}{end}")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+// Removes the color escape sequences from `text`, e.g. before handing output
+// to a command that expects plain text.
+pub fn strip(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut idx = 0;
+
+    while idx < text.len() {
+        if text[idx..].starts_with(b"\x1b[") {
+            // A sequence ends with its first letter, like the "m" of "\x1b[1m".
+            match text[idx + 2..].iter().position(u8::is_ascii_alphabetic) {
+                Some(end) => idx += end + 3,
+                None => break,
+            }
+            continue;
+        }
+
+        out.push(text[idx]);
+        idx += 1;
+    }
+
+    out
+}
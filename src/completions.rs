@@ -0,0 +1,212 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::cli::{ArgValue, OptSpec, OPTIONS, SUBCOMMANDS};
+
+const BIN: &str = env!("CARGO_PKG_NAME");
+
+// The shells that completion scripts can be generated for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub const NAMES: &'static [&'static str] = &["bash", "zsh", "fish"];
+}
+
+impl FromStr for Shell {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => Err(format!("Unsupported shell \"{s}\".").into()),
+        }
+    }
+}
+
+pub fn print(shell: Shell) {
+    let script = match shell {
+        Shell::Bash => bash(),
+        Shell::Zsh => zsh(),
+        Shell::Fish => fish(),
+    };
+
+    print!("{script}");
+}
+
+fn bash() -> String {
+    let mut words = Vec::new();
+    for opt in OPTIONS {
+        if let Some(short) = opt.short {
+            words.push(format!("-{short}"));
+        }
+        words.push(format!("--{}", opt.long));
+    }
+
+    let commands = SUBCOMMANDS
+        .iter()
+        .map(|(cmd, _, _)| *cmd)
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    let mut s = String::new();
+    let _ = writeln!(s, "_{BIN}() {{");
+    let _ = writeln!(s, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"");
+    let _ = writeln!(s, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "    case \"$prev\" in");
+
+    for opt in OPTIONS {
+        let reply = match opt.value {
+            ArgValue::None => continue,
+            ArgValue::Path => "$(compgen -f -- \"$cur\")".to_string(),
+            ArgValue::Number | ArgValue::Text => String::new(),
+            ArgValue::Choice(choices) => {
+                format!("$(compgen -W \"{}\" -- \"$cur\")", choices.join(" "))
+            },
+        };
+        let pattern = opt.short.map_or_else(
+            || format!("--{}", opt.long),
+            |short| format!("-{short}|--{}", opt.long));
+        let _ = writeln!(s, "        {pattern})");
+        let _ = writeln!(s, "            COMPREPLY=({reply})");
+        let _ = writeln!(s, "            return;;");
+    }
+
+    let _ = writeln!(s, "        completions)");
+    let _ = writeln!(s, "            if [[ $COMP_CWORD -eq 2 ]]; then");
+    let _ = writeln!(s, "                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        Shell::NAMES.join(" "));
+    let _ = writeln!(s, "                return");
+    let _ = writeln!(s, "            fi;;");
+    let _ = writeln!(s, "    esac");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "    if [[ \"$cur\" == -* ]]; then");
+    let _ = writeln!(s, "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        words.join(" "));
+    let _ = writeln!(s, "    elif [[ $COMP_CWORD -eq 1 ]]; then");
+    let _ = writeln!(s, "        COMPREPLY=($(compgen -W \"{commands}\" -- \"$cur\") \
+        $(compgen -f -- \"$cur\"))");
+    let _ = writeln!(s, "    else");
+    let _ = writeln!(s, "        COMPREPLY=($(compgen -f -- \"$cur\"))");
+    let _ = writeln!(s, "    fi");
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "complete -o filenames -F _{BIN} {BIN}");
+    s
+}
+
+// Escapes text for use inside a single-quoted zsh `_arguments` spec.
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_value(opt: &OptSpec) -> String {
+    match opt.value {
+        ArgValue::None => String::new(),
+        ArgValue::Path => format!(":{}:_files", opt.value_name),
+        ArgValue::Number | ArgValue::Text => format!(":{}: ", opt.value_name),
+        ArgValue::Choice(choices) => {
+            format!(":{}:({})", opt.value_name, choices.join(" "))
+        },
+    }
+}
+
+fn zsh() -> String {
+    let mut s = String::new();
+    let _ = writeln!(s, "#compdef {BIN}");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "_{BIN}_first() {{");
+    let commands = SUBCOMMANDS
+        .iter()
+        .map(|(cmd, _, help)| format!("{cmd}\\:\"{}\"", zsh_escape(help)))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let _ = writeln!(s, "    _alternative 'commands:command:(({commands}))' \
+        'files:database:_files'");
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "_{BIN}() {{");
+    let _ = writeln!(s, "    if (( CURRENT == 3 )) && [[ ${{words[2]}} == completions ]]; then");
+    let _ = writeln!(s, "        _values 'shell' {}", Shell::NAMES.join(" "));
+    let _ = writeln!(s, "        return");
+    let _ = writeln!(s, "    fi");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "    _arguments -s -S \\");
+
+    for opt in OPTIONS {
+        let help = zsh_escape(opt.help);
+        let value = zsh_value(opt);
+        let repeat = if opt.long == "verbose" { "*" } else { "" };
+
+        match opt.short {
+            Some(short) => {
+                let exclusive = if repeat.is_empty() {
+                    format!("(-{short} --{})", opt.long)
+                } else {
+                    String::new()
+                };
+                let _ = writeln!(s, "        '{exclusive}{repeat}'{{-{short},--{}}}'[{help}]{value}' \\",
+                    opt.long);
+            },
+            None if matches!(opt.value, ArgValue::None) => {
+                let _ = writeln!(s, "        '--{}[{help}]' \\", opt.long);
+            },
+            None => {
+                let _ = writeln!(s, "        '--{}=[{help}]{value}' \\", opt.long);
+            },
+        }
+    }
+
+    let _ = writeln!(s, "        '1: :_{BIN}_first' \\");
+    let _ = writeln!(s, "        '*:name: '");
+    let _ = writeln!(s, "}}");
+    let _ = writeln!(s);
+    let _ = writeln!(s, "_{BIN} \"$@\"");
+    s
+}
+
+// Escapes text for use inside a single-quoted fish string.
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish() -> String {
+    let mut s = String::new();
+
+    for opt in OPTIONS {
+        let mut line = format!("complete -c {BIN}");
+        if let Some(short) = opt.short {
+            let _ = write!(line, " -s {short}");
+        }
+        let _ = write!(line, " -l {}", opt.long);
+        match opt.value {
+            ArgValue::None => {},
+            ArgValue::Path => line.push_str(" -r -F"),
+            ArgValue::Number | ArgValue::Text => line.push_str(" -x"),
+            ArgValue::Choice(choices) => {
+                let _ = write!(line, " -x -a '{}'", choices.join(" "));
+            },
+        }
+        let _ = writeln!(s, "{line} -d '{}'", fish_escape(opt.help));
+    }
+
+    for (cmd, _, help) in SUBCOMMANDS {
+        let _ = writeln!(s, "complete -c {BIN} -n '__fish_use_subcommand' \
+            -a {cmd} -d '{}'", fish_escape(help));
+    }
+
+    let _ = writeln!(s, "complete -c {BIN} -n '__fish_seen_subcommand_from completions' \
+        -x -a '{}'", Shell::NAMES.join(" "));
+    s
+}
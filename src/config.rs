@@ -0,0 +1,449 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+use crate::color::ColorChoice;
+use crate::database::ParseOptions;
+use crate::pages::PageField;
+use crate::search::MatchMode;
+
+// Default settings read from `~/.config/mandoc_db_search/config.toml`.
+//
+// Only a small subset of TOML is understood: `[table]` headers, comments,
+// and `key = value` pairs whose value is a string, integer, boolean, or an
+// array of those. For example:
+//
+//   databases = ["/usr/share/man/mandoc.db", "/usr/local/man/mandoc.db"]
+//   match = "substring"
+//   limit = 20
+//   color = "never"
+//
+//   [repl]
+//   prompt = "man> "
+//   summary = false
+//   history_size = 500
+//   fields = "names,sect,desc"
+//
+//   [aliases]
+//   net = "Xr=netstat OR Nd~network"
+//
+//   [limits]
+//   max_pages = 2000000
+//   max_list_len = 100000
+//   max_string_len = 100000
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub databases: Vec<String>,
+    pub match_mode: Option<MatchMode>,
+    pub limit: Option<usize>,
+    pub color: Option<ColorChoice>,
+    pub repl: ReplConfig,
+    // Words that stand for longer queries at the search prompt.
+    pub aliases: BTreeMap<String, String>,
+    // The largest databases that are read.
+    pub limits: ParseOptions,
+}
+
+// Settings for the interactive search prompt.
+#[derive(Clone, Debug, Default)]
+pub struct ReplConfig {
+    pub prompt: Option<String>,
+    pub summary: Option<bool>,
+    // How many queries to keep in the history file. Zero disables it.
+    pub history_size: Option<usize>,
+    // The fields shown for each result.
+    pub fields: Option<Vec<PageField>>,
+}
+
+impl Config {
+    // Loads the config file at `path`, or the default config file if no path
+    // is given. A missing default config file is not an error.
+    pub fn load(path: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let (path, required) = match path {
+            Some(path) => (PathBuf::from(path), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) if !required && !path.exists() => {
+                debug!("No config file at {}", path.display());
+                return Ok(Self::default());
+            },
+            Err(e) => {
+                return Err(format!("{}: {e}", path.display()).into());
+            },
+        };
+
+        debug!("Loading config file {}", path.display());
+        Self::parse(&text)
+            .map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut config = Self::default();
+
+        for (key, value) in parse_toml(text)? {
+            match (key.as_str(), value) {
+                ("databases", Value::Array(items)) => {
+                    config.databases = items
+                        .into_iter()
+                        .map(Value::into_string)
+                        .collect::<Result<_, _>>()?;
+                },
+                ("match", Value::Str(mode)) => {
+                    config.match_mode = Some(mode.parse()?);
+                },
+                ("limit", Value::Int(limit)) => {
+                    config.limit = Some(usize::try_from(limit)
+                        .map_err(|_| "\"limit\" must not be negative.")?);
+                },
+                ("color", Value::Str(color)) => {
+                    config.color = Some(color.parse()?);
+                },
+                ("repl.prompt", Value::Str(prompt)) => {
+                    config.repl.prompt = Some(prompt);
+                },
+                ("repl.summary", Value::Bool(summary)) => {
+                    config.repl.summary = Some(summary);
+                },
+                ("repl.history_size", Value::Int(size)) => {
+                    config.repl.history_size = Some(usize::try_from(size)
+                        .map_err(|_| "\"repl.history_size\" must not be negative.")?);
+                },
+                ("repl.fields", Value::Str(fields)) => {
+                    config.repl.fields = Some(PageField::parse_list(&fields)?);
+                },
+                ("limits.max_pages", Value::Int(max)) => {
+                    config.limits.max_pages = usize::try_from(max)
+                        .map_err(|_| "\"limits.max_pages\" must not be negative.")?;
+                },
+                ("limits.max_list_len", Value::Int(max)) => {
+                    config.limits.max_list_len = usize::try_from(max)
+                        .map_err(|_| "\"limits.max_list_len\" must not be negative.")?;
+                },
+                ("limits.max_string_len", Value::Int(max)) => {
+                    config.limits.max_str_len = usize::try_from(max)
+                        .map_err(|_| "\"limits.max_string_len\" must not be negative.")?;
+                },
+                (key, Value::Str(query)) if key.starts_with("aliases.") => {
+                    config.aliases.insert(key["aliases.".len()..].to_string(), query);
+                },
+                (key, _) if is_known_key(key) => {
+                    return Err(format!("Invalid value for \"{key}\".").into());
+                },
+                (key, _) => warn!("Ignoring unknown config key \"{key}\""),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn is_known_key(key: &str) -> bool {
+    matches!(key, "databases" | "match" | "limit" | "color" | "repl.prompt"
+        | "repl.summary" | "repl.history_size" | "repl.fields" | "limits.max_pages"
+        | "limits.max_list_len" | "limits.max_string_len")
+        || key.starts_with("aliases.")
+}
+
+// Returns `$XDG_CONFIG_HOME/mandoc_db_search/config.toml`, falling back to
+// `$HOME/.config` when XDG_CONFIG_HOME is unset.
+pub fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+        })?;
+
+    Some(base.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+}
+
+// Returns `$XDG_STATE_HOME/mandoc_db_search/history`, falling back to
+// `$HOME/.local/state` when XDG_STATE_HOME is unset.
+pub fn history_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })?;
+
+    Some(base.join(env!("CARGO_PKG_NAME")).join("history"))
+}
+
+// Returns `$XDG_CACHE_HOME/mandoc_db_search`, falling back to `$HOME/.cache`
+// when XDG_CACHE_HOME is unset.
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache"))
+        })?;
+
+    Some(base.join(env!("CARGO_PKG_NAME")))
+}
+
+// Sets the alias `name` to `query` in the `[aliases]` table of the config
+// file at `path`, or removes it when `query` is `None`. The rest of the file,
+// including comments, is left as it was.
+pub fn save_alias(path: &Path, name: &str, query: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) if !path.exists() => String::new(),
+        Err(e) => return Err(format!("{}: {e}", path.display()).into()),
+    };
+
+    let mut lines = text.lines().map(str::to_string).collect::<Vec<String>>();
+    let header = |line: &str| {
+        let line = strip_comment(line).trim();
+        line.starts_with('[').then(|| line.trim_matches(['[', ']']).trim().to_string())
+    };
+
+    // The aliases table runs from its header to the next header.
+    let start = lines.iter().position(|line| header(line).as_deref() == Some("aliases"));
+    let mut end = match start {
+        Some(start) => lines[start + 1..]
+            .iter()
+            .position(|line| header(line).is_some())
+            .map_or(lines.len(), |idx| start + 1 + idx),
+        None => lines.len(),
+    };
+
+    let mut entry = query.map(|query| {
+        format!("{name} = \"{}\"", query.replace('\\', "\\\\").replace('"', "\\\""))
+    });
+
+    // An existing alias is replaced where it is, and any repeats removed.
+    if let Some(start) = start {
+        let mut idx = start + 1;
+        while idx < end {
+            let key = strip_comment(&lines[idx])
+                .split_once('=')
+                .map(|(key, _)| key.trim().trim_matches('"').to_string());
+            if key.as_deref() != Some(name) {
+                idx += 1;
+            } else if let Some(entry) = entry.take() {
+                lines[idx] = entry;
+                idx += 1;
+            } else {
+                lines.remove(idx);
+                end -= 1;
+            }
+        }
+    }
+
+    if let Some(entry) = entry {
+        if start.is_some() {
+            // Keep blank lines between tables after the new entry.
+            while end > 0 && lines[end - 1].trim().is_empty() {
+                end -= 1;
+            }
+            lines.insert(end, entry);
+        } else {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push("[aliases]".to_string());
+            lines.push(entry);
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    fs::write(path, text).map_err(|e| format!("{}: {e}", path.display()).into())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn into_string(self) -> Result<String, Box<dyn Error>> {
+        match self {
+            Self::Str(s) => Ok(s),
+            _ => Err("Expected a string.".into()),
+        }
+    }
+}
+
+// Parses the supported TOML subset into a map of dotted keys to values.
+fn parse_toml(text: &str) -> Result<HashMap<String, Value>, Box<dyn Error>> {
+    let mut map = HashMap::new();
+    let mut table = String::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((idx, line)) = lines.next() {
+        let line_num = idx + 1;
+        let mut line = strip_comment(line).trim().to_string();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            table = name
+                .strip_suffix(']')
+                .map(|name| name.trim().to_string())
+                .ok_or_else(|| format!("line {line_num}: Invalid table header."))?;
+            continue;
+        }
+
+        // Arrays may span several lines.
+        while line.contains('[') && !brackets_balanced(&line) {
+            let (_, next) = lines
+                .next()
+                .ok_or_else(|| format!("line {line_num}: Unterminated array."))?;
+            line.push(' ');
+            line.push_str(strip_comment(next).trim());
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {line_num}: Expected \"key = value\"."))?;
+
+        let key = key.trim().trim_matches('"');
+        let key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{table}.{key}")
+        };
+
+        let (value, rest) = parse_value(value.trim())
+            .map_err(|e| format!("line {line_num}: {e}"))?;
+
+        if !rest.trim().is_empty() {
+            return Err(format!("line {line_num}: Unexpected trailing text.").into());
+        }
+
+        map.insert(key, value);
+    }
+
+    Ok(map)
+}
+
+// Removes a trailing `#` comment, ignoring `#` characters inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (idx, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            },
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..idx],
+            _ => {},
+        }
+
+        escaped = false;
+    }
+
+    line
+}
+
+fn brackets_balanced(line: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {},
+        }
+    }
+
+    depth <= 0
+}
+
+// Parses one value from the start of `s`, returning it and the unparsed rest.
+fn parse_value(s: &str) -> Result<(Value, &str), Box<dyn Error>> {
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::Str(value), &rest[idx + 1..])),
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('\\') => value.push('\\'),
+                    Some('"') => value.push('"'),
+                    _ => return Err("Invalid escape sequence.".into()),
+                },
+                c => value.push(c),
+            }
+        }
+
+        return Err("Unterminated string.".into());
+    }
+
+    if let Some(rest) = s.strip_prefix('\'') {
+        let (value, rest) = rest
+            .split_once('\'')
+            .ok_or("Unterminated string.")?;
+        return Ok((Value::Str(value.to_string()), rest));
+    }
+
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+
+        loop {
+            rest = rest.trim_start();
+
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(items), after));
+            }
+
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return Err("Expected \",\" or \"]\" in array.".into());
+            }
+        }
+    }
+
+    let end = s
+        .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+
+    let value = match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::Int(word
+            .replace('_', "")
+            .parse()
+            .map_err(|_| format!("Invalid value \"{word}\"."))?),
+    };
+
+    Ok((value, rest))
+}
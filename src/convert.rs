@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use log::{debug, warn};
+
+use crate::edit::{OwnedDatabase, OwnedPage};
+use crate::error::{DbError, ErrorKind};
+use crate::pages::PageFormat;
+
+// The name sources of the names in a whatis line, which come from the NAME
+// sections of the pages: the first name is also marked as such.
+const SOURCE_NAME: u8 = 0b0000_0010;
+const SOURCE_FIRST_NAME: u8 = 0b0000_0100;
+
+// Converts the plain text whatis database at `input` into a mandoc.db at
+// `output`, for systems that only have the old text format.
+//
+// Lines that are not whatis lines are skipped with a warning. The text
+// format has no files or macro tables, so each page gets the file
+// "manSECT/NAME.SECT" and the macro tables are left empty.
+pub fn convert_whatis(input: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(input)
+        .map_err(|e| DbError::new(ErrorKind::Io, format!("{input}: {e}")))?;
+
+    let mut db = OwnedDatabase::new();
+    let mut skipped = 0;
+    for (line_idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_whatis_line(line) {
+            Some(page) => {
+                db.add_page(page);
+            },
+            None => {
+                warn!("{input}:{}: Skipping a line that is not \"name(sect) - desc\"",
+                    line_idx + 1);
+                skipped += 1;
+            },
+        }
+    }
+    debug!("Read {} pages from {input}", db.pages().len());
+
+    let file = File::create(output)
+        .map_err(|e| DbError::new(ErrorKind::Io, format!("{output}: {e}")))?;
+    let mut out = BufWriter::new(file);
+    db.write(&mut out)?;
+    out.flush()?;
+
+    let mut stdout = io::stdout();
+    write!(stdout, "Wrote {} pages to {output}.", db.pages().len())?;
+    match skipped {
+        0 => writeln!(stdout)?,
+        1 => writeln!(stdout, " Skipped 1 line.")?,
+        skipped => writeln!(stdout, " Skipped {skipped} lines.")?,
+    }
+    Ok(())
+}
+
+// Parses a line of a whatis database, such as "ls(1) - list directory
+// contents". Besides the "name, name(sect) - desc" lines written by
+// makewhatis(8) and `export --whatis`, these are understood:
+// * "name (sect)   - desc", as written by man-db, with any spacing.
+// * "name(sect), name(sect) - desc", with the section after each name.
+// * "name(sect, sect) - desc", for pages in several sections.
+// * "name(sect/arch, arch) - desc", for machine-dependent pages.
+pub fn parse_whatis_line(line: &str) -> Option<OwnedPage> {
+    let (head, desc) = line.split_once(" - ").or_else(|| line.split_once("\t- "))?;
+
+    let mut names = Vec::new();
+    let mut sects = Vec::new();
+    let mut archs = Vec::new();
+    for item in split_names(head) {
+        // A name, with or without its sections.
+        let (name, list) = match item.split_once('(') {
+            Some((name, rest)) => (name.trim_end(), Some(rest.strip_suffix(')')?)),
+            None => (item, None),
+        };
+        if name.is_empty() {
+            return None;
+        }
+        if !names.iter().any(|(other, _)| other == name) {
+            let first = if names.is_empty() { SOURCE_FIRST_NAME } else { 0 };
+            names.push((name.to_string(), SOURCE_NAME | first));
+        }
+
+        // The sections, then the architectures after a slash.
+        let (sect_list, arch_list) = list.map_or(("", ""), |list| {
+            list.split_once('/').unwrap_or((list, ""))
+        });
+        for (list, items) in [(sect_list, &mut sects), (arch_list, &mut archs)] {
+            for item in list.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+                if !items.iter().any(|other| other == item) {
+                    items.push(item.to_string());
+                }
+            }
+        }
+    }
+    if names.is_empty() || sects.is_empty() {
+        return None;
+    }
+
+    let files = sects
+        .iter()
+        .map(|sect| format!("man{sect}/{}.{sect}", names[0].0))
+        .collect();
+    Some(OwnedPage {
+        names,
+        sects,
+        archs: (!archs.is_empty()).then_some(archs),
+        desc: desc.trim().to_string(),
+        files,
+        format: PageFormat::MdocMan,
+    })
+}
+
+// Splits the names of a whatis line at the commas between them, leaving
+// the commas between sections in parentheses alone.
+fn split_names(head: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for (idx, c) in head.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                items.push(head[start..idx].trim());
+                start = idx + 1;
+            },
+            _ => {},
+        }
+    }
+    items.push(head[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
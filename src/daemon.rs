@@ -0,0 +1,192 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+
+use log::{debug, warn};
+
+use crate::database::Database;
+use crate::edit::OwnedPage;
+use crate::error::{DbError, ErrorKind};
+use crate::json::{self, JsonValue};
+use crate::output::{self, json_string, OutputOptions, OutputStyle};
+use crate::pages::PageFormat;
+use crate::search::SearchOptions;
+use crate::serve;
+
+// Keeps the parsed databases in memory and answers requests on the Unix
+// socket at `path`, so that tools which search often do not parse the
+// databases each time. Each connection sends any number of requests, one
+// JSON object per line, and gets a response per line, as with
+// `--serve-stdio`. Connections are answered at the same time, each by a
+// thread of its own, and may stay open.
+//
+// A socket left behind by a daemon that is no longer running is replaced.
+pub fn serve_socket(
+    path: &str,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)
+        .map_err(|e| DbError::new(ErrorKind::Io, format!("{path}: {e}")))?;
+    let plural = if dbs.len() == 1 { "database" } else { "databases" };
+    eprintln!("Serving {} {plural} on {path}", dbs.len());
+
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    scope.spawn(move || {
+                        if let Err(e) = handle_connection(&stream, dbs, opts) {
+                            debug!("Connection closed: {e}");
+                        }
+                    });
+                },
+                Err(e) => warn!("Failed to accept a connection: {e}"),
+            }
+        }
+    });
+    Ok(())
+}
+
+// Removes the socket at `path` unless a daemon is still listening on it.
+fn remove_stale_socket(path: &str) -> Result<(), Box<dyn Error>> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(format!("{path} exists and is not a socket.").into());
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(format!("A daemon is already listening on {path}.").into());
+    }
+    debug!("Removing the stale socket {path}");
+    fs::remove_file(path).map_err(|e| format!("{path}: {e}").into())
+}
+
+// Answers the requests of one connection until it is closed.
+fn handle_connection(
+    stream: &UnixStream,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions
+) -> io::Result<()> {
+    let mut out = stream;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(out, "{}", serve::respond(dbs, opts, &line))?;
+    }
+    Ok(())
+}
+
+// Sends each query to the daemon listening on the socket at `path` and
+// writes its results as a search of the databases would, without reading
+// them. The search options are sent along with the queries.
+pub fn write_search(
+    out: &mut dyn Write,
+    path: &str,
+    queries: &[&str],
+    opts: &SearchOptions,
+    output: &OutputOptions
+) -> Result<(), Box<dyn Error>> {
+    let stream = UnixStream::connect(path)
+        .map_err(|e| DbError::new(ErrorKind::Io, format!("{path}: {e}")))?;
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+
+    if output.style == OutputStyle::Roff {
+        output::write_roff_header(out)?;
+    }
+    for query in queries {
+        writeln!(writer, "{}", search_request(query, opts))?;
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(format!("{path}: The daemon closed the connection.").into());
+        }
+
+        let response = json::parse(&line)?;
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(JsonValue::as_str).unwrap_or_default();
+            return Err(DbError::new(ErrorKind::Other, message).into());
+        }
+        let Some(JsonValue::Array(results)) = response.get("result") else {
+            return Err(format!("{path}: The daemon sent a response without results.").into());
+        };
+        let pages = results
+            .iter()
+            .map(|result| owned_page(result, output.resolve_files))
+            .collect::<Option<Vec<(OwnedPage, Option<&str>)>>>()
+            .ok_or_else(|| format!("{path}: The daemon sent a result that is not a page."))?;
+
+        if pages.is_empty() {
+            match output.style {
+                OutputStyle::Detailed => writeln!(out, "No results for \"{query}\".\n")?,
+                _ => eprintln!("{query}: nothing appropriate"),
+            }
+            continue;
+        }
+        match output.style {
+            OutputStyle::Markdown => output::write_markdown_header(out)?,
+            OutputStyle::Roff => writeln!(out, ".Bl -tag -width Ds")?,
+            _ => {},
+        }
+        for (page, label) in &pages {
+            let output = OutputOptions { label: label.map(str::to_string), ..output.clone() };
+            output::write_page(out, &page.as_page(), &output, &[query])?;
+        }
+        if output.style == OutputStyle::Roff {
+            writeln!(out, ".El")?;
+        }
+    }
+
+    Ok(())
+}
+
+// Formats the search request for a query, with the options of the command
+// line.
+fn search_request(query: &str, opts: &SearchOptions) -> String {
+    let mut params = format!("\"query\":{},\"match\":{}", json_string(query),
+        json_string(&opts.mode.to_string()));
+    if let Some(section) = &opts.section {
+        params.push_str(&format!(",\"section\":{}", json_string(section)));
+    }
+    if let Some(arch) = &opts.arch {
+        params.push_str(&format!(",\"arch\":{}", json_string(arch)));
+    }
+    if let Some(limit) = opts.limit {
+        params.push_str(&format!(",\"limit\":{limit}"));
+    }
+    format!("{{\"method\":\"search\",\"params\":{{{params}}}}}")
+}
+
+// Reads a page of a search result, along with the label of its database.
+// With `resolved`, its files are the absolute paths of those that exist.
+fn owned_page(result: &JsonValue, resolved: bool) -> Option<(OwnedPage, Option<&str>)> {
+    let strings = |key: &str| match result.get(key)? {
+        JsonValue::Array(items) => items.iter().map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>(),
+        _ => None,
+    };
+
+    let page = OwnedPage {
+        // The daemon does not send where the names come from.
+        names: strings("names")?.into_iter().map(|name| (name, 0)).collect(),
+        sects: strings("sections")?,
+        archs: strings("archs"),
+        desc: result.get("description")?.as_str()?.to_string(),
+        files: strings(if resolved { "paths" } else { "files" })?,
+        format: match result.get("format")?.as_str()? {
+            "source" => PageFormat::MdocMan,
+            "preformatted" => PageFormat::Preformatted,
+            _ => PageFormat::Unknown(0),
+        },
+    };
+    let label = result.get("database").and_then(JsonValue::as_str);
+    Some((page, label))
+}
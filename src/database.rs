@@ -0,0 +1,925 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::time::Instant;
+
+use log::{debug, trace, warn};
+
+use crate::error::{DbError, ErrorKind};
+use crate::fetch;
+use crate::index::Indexes;
+use crate::interrupt;
+use crate::macros::{self, LazyMacros};
+use crate::mandb;
+use crate::memory::{vec_size, HeapUsage};
+use crate::output::{self, OutputOptions, OutputStyle};
+use crate::pages::{Page, PageFormat, Pages};
+use crate::progress::Progress;
+use crate::querylog;
+use crate::search::{Matcher, MatchMode, SearchOptions};
+use crate::swap;
+use crate::utils::{parse_num, write_list, StrList};
+use crate::whatisdb;
+
+pub const DB_MAGIC_NUMBER: usize = 0x3a7d_0cdb;
+pub const DB_VERSION_NUMBER: usize = 0x1;
+
+// Limits on what parsing a database accepts. Counts and offsets come
+// straight from the file, so without them a damaged or crafted database could
+// claim millions of pages, or lists and strings of any length, and have room
+// allocated for them. Real databases stay far below the defaults.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub max_pages: usize,
+    // The most strings in a list, or pages in a macro value's pages list.
+    pub max_list_len: usize,
+    // The most bytes in a string, not counting its NUL byte.
+    pub max_str_len: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self { max_pages: 1 << 20, max_list_len: 1 << 16, max_str_len: 1 << 16 }
+    }
+}
+
+impl ParseOptions {
+    pub fn check_pages(&self, count: usize, idx: usize) -> Result<(), DbError> {
+        Self::check(count, self.max_pages, idx, "pages")
+    }
+
+    pub fn check_list_len(&self, len: usize, idx: usize) -> Result<(), DbError> {
+        Self::check(len, self.max_list_len, idx, "list items")
+    }
+
+    pub fn check_str_len(&self, len: usize, idx: usize) -> Result<(), DbError> {
+        Self::check(len, self.max_str_len, idx, "string bytes")
+    }
+
+    fn check(count: usize, max: usize, idx: usize, what: &str) -> Result<(), DbError> {
+        if count > max {
+            return Err(DbError::at(ErrorKind::Limit, idx,
+                format!("More than the limit of {max} {what}.")));
+        }
+        Ok(())
+    }
+}
+
+// The version of a mandoc.db, which selects how it is decoded.
+//
+// Only version 1 exists so far. A later version is decoded as version 1, with
+// a warning, since mandoc may only add to the format; a decoder of its own
+// can be added here once its layout is known.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    // A version newer than this crate, decoded as version 1.
+    Unknown(usize),
+}
+
+impl Version {
+    // Returns the version for the number in the header, or None for 0,
+    // which no mandoc.db has.
+    pub const fn from_number(number: usize) -> Option<Self> {
+        match number {
+            0 => None,
+            DB_VERSION_NUMBER => Some(Self::V1),
+            number => Some(Self::Unknown(number)),
+        }
+    }
+
+    pub const fn number(self) -> usize {
+        match self {
+            Self::V1 => DB_VERSION_NUMBER,
+            Self::Unknown(number) => number,
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.number())
+    }
+}
+
+// The database path that stands for standard input.
+pub const STDIN_PATH: &str = "-";
+
+// The first bytes of a gzip file.
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// The bytes of a database as `read_db` returns them: read into memory, or,
+// with the "mmap" feature, mapped from its file, so that a large database is
+// not copied before its pages borrow from it.
+pub enum DbBytes {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for DbBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Self::Mapped(map) => map,
+        }
+    }
+}
+
+impl From<Vec<u8>> for DbBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Owned(bytes)
+    }
+}
+
+// Reads the bytes of the database at `path`, of standard input when the
+// path is "-", e.g. for a database piped from another system, or of the
+// download when it is a URL. Parsing only needs the bytes, however they were
+// read.
+//
+// A compressed database, such as a mandoc.db.gz, is recognized by the gzip
+// magic number and decompressed. man-db's index.db and the btree(3)
+// whatis.db of older BSD releases are recognized the same way and converted
+// into a mandoc.db.
+pub fn read_db(path: &str) -> Result<DbBytes, DbError> {
+    let bytes = if fetch::is_url(path) {
+        fetch::fetch_db(path)
+            .map(DbBytes::from)
+            .map_err(|e| DbError::new(ErrorKind::Io, format!("{path}: {e}")))?
+    } else if path == STDIN_PATH {
+        let mut bytes = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .map_err(|e| DbError::new(ErrorKind::Io, format!("{path}: {e}")))?;
+        bytes.into()
+    } else {
+        read_file(path).map_err(|e| DbError::new(ErrorKind::Io, format!("{path}: {e}")))?
+    };
+
+    let bytes = if bytes.starts_with(&GZIP_MAGIC) {
+        debug!("Decompressing {path}");
+        gunzip(&bytes)
+            .map(DbBytes::from)
+            .map_err(|e| DbError::new(ErrorKind::Format, format!("{path}: {e}")))?
+    } else {
+        bytes
+    };
+
+    if mandb::is_index(&bytes) {
+        debug!("Converting the man-db index {path}");
+        return mandb::index_to_mandoc_db(&bytes)
+            .map(DbBytes::from)
+            .map_err(|e| DbError::new(ErrorKind::Format, format!("{path}: {e}")));
+    }
+    if whatisdb::is_whatis_db(&bytes) {
+        debug!("Converting the whatis.db {path}");
+        return whatisdb::whatis_db_to_mandoc_db(&bytes)
+            .map(DbBytes::from)
+            .map_err(|e| DbError::new(ErrorKind::Format, format!("{path}: {e}")));
+    }
+    Ok(bytes)
+}
+
+// Maps a database file into memory. Files that cannot be mapped, like pipes,
+// are read instead.
+#[cfg(feature = "mmap")]
+fn read_file(path: &str) -> io::Result<DbBytes> {
+    let mut file = fs::File::open(path)?;
+    // SAFETY: the map is only read. Another process that truncates the file
+    // while it is mapped would make reading it fault, so the commands that
+    // write databases replace them with new files instead of rewriting them.
+    match unsafe { memmap2::Mmap::map(&file) } {
+        Ok(map) => {
+            debug!("Mapped {path} into memory");
+            Ok(DbBytes::Mapped(map))
+        },
+        Err(e) => {
+            debug!("Reading {path}, which cannot be mapped: {e}");
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Ok(bytes.into())
+        },
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_file(path: &str) -> io::Result<DbBytes> {
+    fs::read(path).map(DbBytes::from)
+}
+
+// Decompresses gzip data, including files of several gzip members.
+#[cfg(feature = "gzip")]
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut decompressed = Vec::new();
+    flate2::read::MultiGzDecoder::new(bytes)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| format!("Invalid gzip data: {e}"))?;
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "gzip"))]
+pub fn gunzip(_bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err("This build does not support gzip; rebuild it with the \"gzip\" feature.".into())
+}
+
+// Database data types:
+// * Number: a 32-bit signed integer with big endian byte order.
+// * String: a NUL-terminated array of bytes.
+// * Strings list: An array of strings that is terminated by a second NUL
+//   following the final entry.
+//
+// A mandoc.db file consists of (in order):
+// 1. The "magic number" (i.e. 0x3a7d0cdb).
+// 2. The version number (currently 1).
+// 3. The index of the MACROS TABLE.
+// 4. The index of the "magic number" located at the end of the file.
+// 5. The PAGES TABLE.
+// 6. The MACROS TABLE.
+// 7. The "magic number", again.
+#[derive(Debug, Clone)]
+pub struct Database<'a> {
+    // The version the database was read from. Databases are always written
+    // as version 1.
+    pub version: Version,
+    pub pages: Pages<'a>,
+    // Parsed when first needed.
+    pub macros: LazyMacros<'a>,
+    // Built from the pages as searches need them.
+    pub indexes: Indexes,
+}
+
+impl<'a> Database<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        Self::parse_with_progress(bytes, &Progress::hidden())
+    }
+
+    // Parses the database, reporting how far along parsing is.
+    pub fn parse_with_progress(
+        bytes: &'a [u8],
+        progress: &Progress
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::parse_with_options(bytes, &ParseOptions::default(), progress)
+    }
+
+    // Parses the database within the limits of `options`, reporting how far
+    // along parsing is.
+    pub fn parse_with_options(
+        bytes: &'a [u8],
+        options: &ParseOptions,
+        progress: &Progress
+    ) -> Result<Self, Box<dyn Error>> {
+        // The first 4 bytes and last 4 bytes should be the magic number.
+        let first_four = parse_num(bytes, 0)
+            .map_err(|_| DbError::at(ErrorKind::Format, 0, "Invalid file format."))?;
+        if first_four == swap::SWAPPED_MAGIC_NUMBER {
+            return Err(DbError::at(ErrorKind::Format, 0,
+                "The database appears to be written with opposite endianness.").into());
+        }
+        if first_four != DB_MAGIC_NUMBER {
+            return Err(DbError::at(ErrorKind::Format, 0, "Invalid file format.").into());
+        }
+
+        // The second 4 bytes should be the version number.
+        let second_four = parse_num(bytes, 4)?;
+        let version = Version::from_number(second_four)
+            .ok_or_else(|| DbError::at(ErrorKind::Version, 4, "Invalid version number."))?;
+        trace!("Header: magic {first_four:#x}, version {second_four}");
+
+        match version {
+            Version::V1 => Self::decode_v1(bytes, version, options, progress),
+            Version::Unknown(number) => {
+                warn!("Version {number} is newer than the supported version \
+                    {DB_VERSION_NUMBER}; reading it as version {DB_VERSION_NUMBER}");
+                Self::decode_v1(bytes, version, options, progress).map_err(|e| {
+                    DbError::new(ErrorKind::Version, format!("Version {number} is not \
+                        supported and could not be read as version {DB_VERSION_NUMBER}: {e}"))
+                        .into()
+                })
+            },
+        }
+    }
+
+    // Decodes the layout of version 1, described above, after the magic
+    // number and version.
+    fn decode_v1(
+        bytes: &'a [u8],
+        version: Version,
+        options: &ParseOptions,
+        progress: &Progress
+    ) -> Result<Self, Box<dyn Error>> {
+        let final_four_idx = parse_num(bytes, 12)?;
+        let final_four = parse_num(bytes, final_four_idx)?;
+        trace!("Final magic at offset {final_four_idx}");
+        if final_four != DB_MAGIC_NUMBER {
+            return Err(DbError::at(ErrorKind::Format, final_four_idx,
+                "Invalid file format.").into());
+        }
+
+        debug!("Parsing the pages table");
+        let pages = Pages::parse_with_options(bytes, options, progress)?;
+
+        let macros_idx = parse_num(bytes, 8)?;
+        let macros = LazyMacros::with_options(bytes, macros_idx, *options)?;
+        progress.finish();
+
+        Ok(Self { version, pages, macros, indexes: Indexes::default() })
+    }
+
+    // Writes the database as a version 1 mandoc.db, laid out as described
+    // above and in `Pages`, `Page`, `Macros`, and `Table`. Macro values refer
+    // to pages by their positions in the pages table; positions past its end
+    // are left out.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut buf = DbBuffer(Vec::new());
+
+        // The header: the macros table and final magic offsets are filled
+        // in once they are known.
+        buf.num(DB_MAGIC_NUMBER)?;
+        buf.num(DB_VERSION_NUMBER)?;
+        buf.num(0)?;
+        buf.num(0)?;
+        buf.num(self.pages.table.len())?;
+
+        // The page records, whose list offsets are filled in as the lists
+        // are written after them.
+        let records = buf.0.len();
+        buf.0.resize(records + self.pages.table.len() * 20, 0);
+
+        // As in makewhatis(8), each kind of list is written for every page
+        // before the next kind, so that a parsed database is written back
+        // byte for byte.
+        let pages = self.pages
+            .table
+            .iter()
+            .enumerate()
+            .map(|(idx, page)| (records + idx * 20, page));
+        for (record, page) in pages.clone() {
+            buf.set(record, buf.0.len())?;
+            for name in &page.names {
+                buf.0.push(name.source);
+                buf.str(name.value);
+            }
+            buf.0.push(0);
+        }
+        for (record, page) in pages.clone() {
+            buf.set(record + 4, buf.0.len())?;
+            buf.list(&page.sects);
+        }
+        for (record, page) in pages.clone() {
+            if let Some(archs) = &page.archs {
+                buf.set(record + 8, buf.0.len())?;
+                buf.list(archs);
+            }
+        }
+        for (record, page) in pages.clone() {
+            buf.set(record + 12, buf.0.len())?;
+            buf.str(page.desc);
+        }
+        for (record, page) in pages {
+            buf.set(record + 16, buf.0.len())?;
+            buf.0.push(page.format.byte());
+            buf.list(&page.files);
+        }
+        buf.align();
+
+        // The macros table: the number of tables and their offsets.
+        let macros = buf.0.len();
+        buf.set(8, macros)?;
+        let tables = &self.macros.get().map_err(io::Error::other)?.tables;
+        buf.num(tables.len())?;
+        buf.0.resize(macros + 4 + tables.len() * 4, 0);
+
+        for (idx, table) in tables.iter().enumerate() {
+            let start = buf.0.len();
+            buf.set(macros + 4 + idx * 4, start)?;
+
+            // The value records, followed by each value's string and list
+            // of page records.
+            buf.num(table.values.len())?;
+            buf.0.resize(start + 4 + table.values.len() * 8, 0);
+
+            for (value_idx, value) in table.values.iter().enumerate() {
+                let record = start + 4 + value_idx * 8;
+                buf.set(record, buf.0.len())?;
+                buf.str(value.str);
+                buf.align();
+
+                buf.set(record + 4, buf.0.len())?;
+                let pages = value.pages.iter().filter(|&&idx| idx < self.pages.table.len());
+                for idx in pages {
+                    buf.num(records + idx * 20)?;
+                }
+                buf.num(0)?;
+            }
+        }
+
+        let end = buf.0.len();
+        buf.set(12, end)?;
+        buf.num(DB_MAGIC_NUMBER)?;
+
+        out.write_all(&buf.0)
+    }
+
+    // Returns the pages matching any of the queries, in database order.
+    pub fn search(&self, queries: &[&str], opts: &SearchOptions) -> Vec<&Page<'a>> {
+        let start = Instant::now();
+
+        // A query that cannot be used, like an invalid regular expression,
+        // matches nothing.
+        let matchers = queries
+            .iter()
+            .filter_map(|query| self
+                .matchers(query, opts)
+                .inspect_err(|e| warn!("{e}"))
+                .ok())
+            .flatten()
+            .collect::<Vec<Matcher>>();
+
+        let results = self.search_matchers(&matchers, opts);
+
+        debug!("Search for {queries:?} ({} mode) found {} results in {:?}",
+            opts.mode, results.len(), start.elapsed());
+        querylog::record(queries, opts, results.len());
+
+        results
+    }
+
+    // Returns the pages any of the matchers match, in database order, like
+    // `search` but without logging the search.
+    pub fn search_matchers(&self, matchers: &[Matcher], opts: &SearchOptions) -> Vec<&Page<'a>> {
+        // Queries the indexes can answer only compare the pages the indexes
+        // have for them: exact names and name prefixes, description words if
+        // the word index was built, and the text that substrings and regular
+        // expressions need.
+        let candidates = self.indexed_candidates(matchers);
+
+        #[cfg(feature = "parallel")]
+        if candidates.is_none()
+            && self.pages.table.len() >= crate::pages::PARALLEL_MIN_PAGES
+            && rayon::current_num_threads() > 1
+        {
+            return self.scan_parallel(matchers, opts);
+        }
+
+        let pages: Box<dyn Iterator<Item = (usize, &Page<'a>)>> = match &candidates {
+            Some(idxs) => Box::new(idxs.iter().map(|&idx| (idx, &self.pages.table[idx]))),
+            None => Box::new(self.pages.table.iter().enumerate()),
+        };
+
+        // Ctrl-C at the search prompt stops a search early.
+        pages
+            .take_while(|_| !interrupt::is_requested())
+            .filter(|(idx, page)| matchers.iter().any(|matcher| opts.matches(*idx, page, matcher)))
+            .map(|(_, page)| page)
+            .take(opts.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    // Compares every page on every CPU, for queries that no index narrows
+    // down, like fuzzy matches. Each thread takes a share of the pages, and
+    // the results are put back in database order before the limit applies.
+    #[cfg(feature = "parallel")]
+    fn scan_parallel(&self, matchers: &[Matcher], opts: &SearchOptions) -> Vec<&Page<'a>> {
+        use rayon::prelude::*;
+
+        let mut results = self.pages
+            .table
+            .par_iter()
+            .enumerate()
+            .filter(|(idx, page)| {
+                !interrupt::is_requested()
+                    && matchers.iter().any(|matcher| opts.matches(*idx, page, matcher))
+            })
+            .map(|(_, page)| page)
+            .collect::<Vec<&Page<'a>>>();
+        results.truncate(opts.limit.unwrap_or(usize::MAX));
+        results
+    }
+
+    // Returns the positions of the pages that can match when the indexes
+    // narrow down the pages of every matcher, in database order, or `None`
+    // when some matcher needs every page compared.
+    fn indexed_candidates(&self, matchers: &[Matcher]) -> Option<Vec<usize>> {
+        let table = &self.pages.table;
+        let mut idxs = Vec::new();
+        for matcher in matchers {
+            let words = matcher.desc_query().and_then(|text| self.indexes.pages_with_words(text));
+            if let Some(found) = words {
+                idxs.extend(found);
+                continue;
+            }
+            match matcher.name_query() {
+                Some((MatchMode::Exact, name)) => {
+                    idxs.extend(self.indexes.pages_named(table, name));
+                },
+                Some((MatchMode::Prefix, prefix)) => {
+                    idxs.extend(self.indexes.pages_with_prefix(table, prefix));
+                },
+                _ => {
+                    let (field, texts) = matcher.required_texts()?;
+                    idxs.extend(self.indexes.pages_containing(table, field, &texts)?);
+                },
+            }
+        }
+        idxs.sort_unstable();
+        idxs.dedup();
+        Some(idxs)
+    }
+
+    // Builds the word index of the descriptions, so that "Nd=VALUE" searches
+    // only compare the pages with its words. Names are indexed as searches
+    // need them, but descriptions only when asked, as with --full-search.
+    pub fn index_descriptions(&self) {
+        self.indexes.index_descriptions(&self.pages.table);
+    }
+
+    // Builds the sorted array of the page names, which exact and prefix
+    // searches and completion then binary search instead of building the
+    // name hash map and prefix trie, as with --sorted-index.
+    pub fn index_sorted_names(&self) {
+        self.indexes.index_sorted_names(&self.pages.table);
+    }
+
+    // Builds the Bloom filter of the page names, with which exact searches
+    // rule this database out without building its name index. It is worth
+    // building when several databases are searched and most lack each name.
+    pub fn index_name_filter(&self) {
+        self.indexes.index_name_filter(&self.pages.table);
+    }
+
+    // Returns the heap memory used by the parsed pages and macros and by
+    // the indexes built so far.
+    pub fn heap_usage(&self) -> HeapUsage {
+        let pages = self.pages.table.iter().map(Page::heap_size).sum::<usize>();
+        HeapUsage {
+            pages: vec_size(&self.pages.table) + pages,
+            macros: self.macros.heap_size(),
+            indexes: self.indexes.heap_usage(),
+        }
+    }
+
+    // Prepares the terms of a query for searching this database.
+    pub fn matchers(
+        &self,
+        query: &str,
+        opts: &SearchOptions
+    ) -> Result<Vec<Matcher>, Box<dyn Error>> {
+        let mut matchers = opts.matchers(query)?;
+        // Only searches by macro key parse the macros.
+        for matcher in matchers.iter_mut().filter(|matcher| matcher.needs_macros()) {
+            matcher.resolve(self.macros.get()?);
+        }
+        Ok(matchers)
+    }
+
+    // Returns the sorted, deduplicated page names that start with `prefix`.
+    // The prefix index narrows the pages down to those with such a name in
+    // any case.
+    pub fn complete(&self, prefix: &str) -> Vec<&'a str> {
+        let mut names = self.indexes
+            .pages_with_prefix(&self.pages.table, prefix)
+            .into_iter()
+            .flat_map(|idx| self.pages.table[idx].names.iter().map(|n| n.value))
+            .filter(|name| name.starts_with(prefix))
+            .collect::<Vec<&str>>();
+
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    pub fn write_search(
+        &self,
+        out: &mut dyn Write,
+        queries: &[&str],
+        opts: &SearchOptions,
+        output: &OutputOptions
+    ) -> io::Result<()> {
+        write_search_all(out, &[(self, None, None)], queries, opts, output)
+    }
+
+    pub fn write_query(
+        &self,
+        out: &mut dyn Write,
+        query: &str,
+        opts: &SearchOptions,
+        output: &OutputOptions
+    ) -> io::Result<()> {
+        write_query_all(out, &[(self, None, None)], query, opts, output)
+    }
+
+    // Returns every section along with the number of pages in it.
+    pub fn section_counts(&self) -> Vec<(&'a str, usize)> {
+        let mut counts = BTreeMap::new();
+        for sect in self.pages.table.iter().flat_map(|page| &page.sects) {
+            *counts.entry(sect).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+
+    // Returns every architecture along with the number of pages for it.
+    // Machine-independent pages are not counted.
+    pub fn arch_counts(&self) -> Vec<(&'a str, usize)> {
+        let mut counts = BTreeMap::new();
+        for arch in self.pages.table.iter().filter_map(|page| page.archs.as_ref()).flatten() {
+            *counts.entry(arch).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+
+    // Returns the page each cross-reference resolves to, keyed by the
+    // lowercase "name(sect)" for each of a page's names and sections, and by
+    // the lowercase name alone. The first page wins when several match.
+    pub fn xr_targets(&self) -> HashMap<String, &Page<'a>> {
+        let mut targets = HashMap::new();
+        for page in &self.pages.table {
+            for name in &page.names {
+                let name = name.value.to_ascii_lowercase();
+                for sect in &page.sects {
+                    targets.entry(format!("{name}({})", sect.to_ascii_lowercase()))
+                        .or_insert(page);
+                }
+                targets.entry(name).or_insert(page);
+            }
+        }
+        targets
+    }
+
+    // Returns the pages in the section, compared case-insensitively.
+    pub fn pages_in_section(&self, sect: &str) -> Vec<&Page<'a>> {
+        self.pages
+            .table
+            .iter()
+            .filter(|page| page.sects.iter().any(|s| s.eq_ignore_ascii_case(sect)))
+            .collect()
+    }
+
+    pub const fn num_pages(&self) -> usize {
+        self.pages.count
+    }
+
+    pub fn num_files(&self) -> usize {
+        self.pages.table.iter().map(|p| p.files.len()).sum()
+    }
+
+    pub const fn num_macros(&self) -> usize {
+        self.macros.count
+    }
+
+    pub fn write_summary(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "\
+            [MANDOC.DB]\n\
+            * Contains {} macro {}.\n\
+            * Contains {} man page {} generated from {} man page {}.",
+            self.num_macros(),
+            if self.num_macros() == 1 { "entry" } else { "entries" },
+            self.num_pages(),
+            if self.num_pages() == 1 { "entry" } else { "entries" },
+            self.num_files(),
+            if self.num_files() == 1 { "file" } else { "files" }
+        )?;
+        if let Version::Unknown(number) = self.version {
+            writeln!(out, "* Uses version {number}, read as version {DB_VERSION_NUMBER}.")?;
+        }
+
+        let page_idx_vec = self.pages
+            .table
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, page)| match page.format {
+                PageFormat::MdocMan => None,
+                PageFormat::Preformatted | PageFormat::Unknown(_) => Some(idx),
+            })
+            .collect::<Vec<usize>>();
+
+        if page_idx_vec.is_empty() {
+            return writeln!(out, "* All pages use man(7) or mdoc(7).");
+        } else if page_idx_vec.len() == 1 {
+            write!(out, "* One page does not use man(7) or mdoc(7): ")?;
+        } else {
+            let num = page_idx_vec.len();
+            write!(out, "* {num} pages do not use man(7) or mdoc(7): ")?;
+        }
+
+        let names = page_idx_vec
+            .into_iter()
+            .flat_map(|idx| {
+                self.pages.table[idx].names.iter().map(|n| n.value)
+            })
+            .collect::<Vec<&str>>();
+
+        write_list(out, &names[..])
+    }
+    // Writes the values of the macro table for `key`, or of every table, as
+    // "value → [name(sect), ...]". Only pages that pass the section and
+    // architecture filters are listed, and values left without pages are
+    // skipped.
+    pub fn write_macros(
+        &self,
+        out: &mut dyn Write,
+        key: Option<&str>,
+        opts: &SearchOptions
+    ) -> io::Result<()> {
+        // The label of each page the filters keep, by its position.
+        let pages = self.pages
+            .table
+            .iter()
+            .map(|page| {
+                let name = page.names.first().filter(|_| opts.filters_match(page))?;
+                let sect = page.sects.first().unwrap_or_default();
+                Some(format!("{}({sect})", name.value))
+            })
+            .collect::<Vec<Option<String>>>();
+
+        let tables = macros::KEYS
+            .iter()
+            .zip(&self.macros.get().map_err(io::Error::other)?.tables)
+            .filter(|(k, _)| key.is_none_or(|key| **k == key));
+
+        for (key, table) in tables {
+            let values = table.values
+                .iter()
+                .filter_map(|value| {
+                    let refs = value.pages
+                        .iter()
+                        .filter_map(|&idx| pages.get(idx)?.as_deref())
+                        .collect::<Vec<&str>>();
+                    (!refs.is_empty()).then(|| format!("  {} \u{2192} [{}]", value.str,
+                        refs.join(", ")))
+                })
+                .collect::<Vec<String>>();
+
+            let plural = if values.len() == 1 { "value" } else { "values" };
+            writeln!(out, "{key} ({} {plural}):", values.len())?;
+            for value in values {
+                writeln!(out, "{value}")?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Searches several databases in order, keeping the label and the directory
+// of the database each result came from. The limit applies to the results
+// of all of them.
+pub fn search_all<'db, 'a>(
+    dbs: &[(&'db Database<'a>, Option<&'db str>, Option<&'db Path>)],
+    queries: &[&str],
+    opts: &SearchOptions
+) -> Vec<(&'db Page<'a>, Option<&'db str>, Option<&'db Path>)> {
+    dbs.iter()
+        .flat_map(|(db, label, dir)| {
+            db.search(queries, opts).into_iter().map(|page| (page, *label, *dir))
+        })
+        .take(opts.limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+// Like `Database::write_search`, for several databases searched in order.
+// Each result is shown with the label of its database, if it has one, and
+// its files are resolved against the database's directory.
+pub fn write_search_all(
+    out: &mut dyn Write,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    queries: &[&str],
+    opts: &SearchOptions,
+    output: &OutputOptions
+) -> io::Result<()> {
+    // Like apropos(1), terse output merges the results of every query
+    // into one sorted list.
+    if output.style == OutputStyle::Terse {
+        let mut results = search_all(dbs, queries, opts);
+
+        if results.is_empty() {
+            eprintln!("{}: nothing appropriate", env!("CARGO_PKG_NAME"));
+            return Ok(());
+        }
+
+        results.sort_by(|(a, _, _), (b, _, _)| a.apropos_cmp(b));
+        for (page, _, _) in results {
+            output::write_page(out, page, output, queries)?;
+        }
+        return Ok(());
+    }
+
+    if output.style == OutputStyle::Roff {
+        output::write_roff_header(out)?;
+    }
+
+    for query in queries {
+        // Tables of several queries are told apart by headings.
+        if output.style == OutputStyle::Markdown && queries.len() > 1 {
+            writeln!(out, "### {}\n", output::markdown_code(query))?;
+        }
+        if output.style == OutputStyle::Roff && queries.len() > 1 {
+            writeln!(out, ".Ss {}", output::roff_arg(query))?;
+        }
+        write_query_all(out, dbs, query, opts, output)?;
+    }
+
+    Ok(())
+}
+
+// Like `Database::write_query`, for several databases searched in order.
+pub fn write_query_all(
+    out: &mut dyn Write,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    query: &str,
+    opts: &SearchOptions,
+    output: &OutputOptions
+) -> io::Result<()> {
+    let results = search_all(dbs, &[query], opts);
+    let write_results = |out: &mut dyn Write| {
+        for (page, label, dir) in &results {
+            let output = OutputOptions {
+                label: label.map(str::to_string),
+                man_dir: dir.map(Path::to_path_buf),
+                ..output.clone()
+            };
+            output::write_page(out, page, &output, &[query])?;
+        }
+        Ok::<(), io::Error>(())
+    };
+
+    match output.style {
+        OutputStyle::Detailed if results.is_empty() => {
+            writeln!(out, "No results for \"{query}\".\n")?;
+        },
+        OutputStyle::Markdown if results.is_empty() => {
+            writeln!(out, "No results for {}.\n", output::markdown_code(query))?;
+        },
+        OutputStyle::Roff if results.is_empty() => {
+            writeln!(out, "No results for\n.Dq {} .", output::roff_arg(query))?;
+        },
+        _ if results.is_empty() => {
+            eprintln!("{query}: nothing appropriate");
+        },
+        OutputStyle::Roff => {
+            writeln!(out, ".Bl -tag -width Ds")?;
+            write_results(out)?;
+            writeln!(out, ".El")?;
+        },
+        OutputStyle::Markdown => {
+            output::write_markdown_header(out)?;
+            write_results(out)?;
+            writeln!(out)?;
+        },
+        _ => write_results(out)?,
+    }
+
+    Ok(())
+}
+
+// The bytes of a mandoc.db being written by `Database::write`.
+struct DbBuffer(Vec<u8>);
+
+impl DbBuffer {
+    // Converts a number or offset to the 32-bit signed integer it is
+    // stored as.
+    fn encode(num: usize) -> io::Result<[u8; 4]> {
+        i32::try_from(num)
+            .map(i32::to_be_bytes)
+            .map_err(|_| io::Error::other("The database is too large for mandoc.db."))
+    }
+
+    fn num(&mut self, num: usize) -> io::Result<()> {
+        self.0.extend_from_slice(&Self::encode(num)?);
+        Ok(())
+    }
+
+    // Overwrites the number at `idx`, which was reserved earlier.
+    fn set(&mut self, idx: usize, num: usize) -> io::Result<()> {
+        self.0[idx..idx + 4].copy_from_slice(&Self::encode(num)?);
+        Ok(())
+    }
+
+    fn str(&mut self, s: &str) {
+        self.0.extend_from_slice(s.as_bytes());
+        self.0.push(0);
+    }
+
+    // Writes a strings list, which ends with an empty string.
+    fn list(&mut self, items: &StrList<'_>) {
+        for item in items {
+            self.str(item);
+        }
+        self.0.push(0);
+    }
+
+    // Pads with NUL bytes so that the next number starts at a multiple of 4.
+    fn align(&mut self) {
+        while !self.0.len().is_multiple_of(4) {
+            self.0.push(0);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::database::Database;
+use crate::pages::Page;
+
+// The pages of two databases, paired up as the same page.
+struct Pairs<'p, 'a> {
+    // The pages with the same names, and the pages with other names that
+    // share a file.
+    same: Vec<(&'p Page<'a>, &'p Page<'a>)>,
+    renamed: Vec<(&'p Page<'a>, &'p Page<'a>)>,
+    removed: Vec<&'p Page<'a>>,
+    added: Vec<&'p Page<'a>>,
+}
+
+// Pairs the pages of the old database with those of the new one. Pages are
+// first paired by their names, in table order when several pages have the
+// same names, and then pages that are left are paired with a page that has
+// one of their files, which means they were renamed.
+fn pair<'p, 'a>(old: &'p [Page<'a>], new: &'p [Page<'a>]) -> Pairs<'p, 'a> {
+    let mut by_names = HashMap::<Vec<&str>, Vec<&Page<'a>>>::new();
+    for page in new.iter().rev() {
+        by_names.entry(names(page)).or_default().push(page);
+    }
+
+    let mut same = Vec::new();
+    let mut removed = Vec::new();
+    for page in old {
+        match by_names.get_mut(&names(page)).and_then(Vec::pop) {
+            Some(new_page) => same.push((page, new_page)),
+            None => removed.push(page),
+        }
+    }
+
+    // The new pages that are left, by their files.
+    let mut added = new
+        .iter()
+        .filter(|page| by_names.get(&names(page)).is_some_and(|left| {
+            left.iter().any(|left| std::ptr::eq(*left, *page))
+        }))
+        .collect::<Vec<&Page<'a>>>();
+
+    let mut renamed = Vec::new();
+    removed.retain(|page| {
+        let Some(idx) = added.iter().position(|new_page| {
+            new_page.files.iter().any(|file| page.files.contains(file))
+        }) else {
+            return true;
+        };
+        renamed.push((*page, added.remove(idx)));
+        false
+    });
+
+    Pairs { same, renamed, removed, added }
+}
+
+// The names of a page, which are what pairs it with a page of the other
+// database.
+fn names<'a>(page: &Page<'a>) -> Vec<&'a str> {
+    page.names.iter().map(|name| name.value).collect()
+}
+
+// Formats the names and sections of a page, e.g. "cat, tac(1)".
+fn label(page: &Page<'_>) -> String {
+    format!("{}({})", names(page).join(", "), page.sects.join(", "))
+}
+
+// Describes how a page changed, as one "field: old → new" line per field.
+fn changes(old: &Page<'_>, new: &Page<'_>) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if old.sects != new.sects {
+        changes.push(format!("sections: {} \u{2192} {}", old.sects.join(", "),
+            new.sects.join(", ")));
+    }
+    if old.archs != new.archs {
+        let archs = |page: &Page<'_>| page.archs.as_ref().map_or_else(
+            || "any".to_string(), |archs| archs.join(", "));
+        changes.push(format!("architectures: {} \u{2192} {}", archs(old), archs(new)));
+    }
+    if old.desc != new.desc {
+        changes.push(format!("description: \"{}\" \u{2192} \"{}\"", old.desc, new.desc));
+    }
+    if old.files != new.files {
+        let files = old.files
+            .iter()
+            .filter(|file| !new.files.contains(file))
+            .map(|file| format!("-{file}"))
+            .chain(new.files
+                .iter()
+                .filter(|file| !old.files.contains(file))
+                .map(|file| format!("+{file}")))
+            .collect::<Vec<String>>();
+        // Files that were only reordered are not a change worth showing.
+        if !files.is_empty() {
+            changes.push(format!("files: {}", files.join(", ")));
+        }
+    }
+    if old.format != new.format {
+        changes.push(format!("format: {} \u{2192} {}", old.format, new.format));
+    }
+
+    changes
+}
+
+// Writes the differences between the pages of two databases: the pages that
+// were added, removed, and renamed, and how the other pages changed.
+pub fn write_diff(
+    out: &mut dyn Write,
+    old: &Database<'_>,
+    new: &Database<'_>
+) -> io::Result<()> {
+    let pairs = pair(&old.pages.table, &new.pages.table);
+
+    let changed = pairs.same
+        .iter()
+        .filter_map(|(old, new)| {
+            let changes = changes(old, new);
+            (!changes.is_empty()).then(|| (label(new), changes))
+        })
+        .collect::<Vec<(String, Vec<String>)>>();
+
+    if !pairs.added.is_empty() {
+        writeln!(out, "Added:")?;
+        for page in &pairs.added {
+            writeln!(out, "  + {}", page.whatis())?;
+        }
+        writeln!(out)?;
+    }
+    if !pairs.removed.is_empty() {
+        writeln!(out, "Removed:")?;
+        for page in &pairs.removed {
+            writeln!(out, "  - {}", page.whatis())?;
+        }
+        writeln!(out)?;
+    }
+    if !pairs.renamed.is_empty() {
+        writeln!(out, "Renamed:")?;
+        for (old, new) in &pairs.renamed {
+            writeln!(out, "  {} \u{2192} {}", label(old), label(new))?;
+            for change in changes(old, new) {
+                writeln!(out, "    {change}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+    if !changed.is_empty() {
+        writeln!(out, "Changed:")?;
+        for (label, changes) in &changed {
+            writeln!(out, "  {label}")?;
+            for change in changes {
+                writeln!(out, "    {change}")?;
+            }
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "{} added, {} removed, {} renamed, {} changed.", pairs.added.len(),
+        pairs.removed.len(), pairs.renamed.len(), changed.len())
+}
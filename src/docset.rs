@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+
+use crate::export::html_escape;
+use crate::pages::{Page, PageFormat};
+use crate::progress::Progress;
+use crate::source;
+use crate::utils::strip_overstrike;
+
+// The schema that Dash and Zeal look up entries in.
+const INDEX_SCHEMA: &str = "
+    CREATE TABLE searchIndex (
+        id INTEGER PRIMARY KEY,
+        name TEXT,
+        type TEXT,
+        path TEXT
+    );
+    CREATE UNIQUE INDEX anchor ON searchIndex (name, type, path);
+";
+
+// Builds a Dash/Zeal docset at `path`, e.g. "OpenBSD.docset", named after
+// its file stem. Every page is rendered to HTML from the man tree in
+// `man_dir`, with mandoc(1) for mdoc(7) and man(7) sources, and each of its
+// names is added to the search index under every section it is in.
+//
+// The docset looks like this:
+// * Contents/Info.plist: the name of the docset.
+// * Contents/Resources/docSet.dsidx: the SQLite search index.
+// * Contents/Resources/Documents/SECT/NAME.SECT.html: the pages.
+pub fn write_docset(
+    pages: &[&Page<'_>],
+    man_dir: &Path,
+    path: &str
+) -> Result<(), Box<dyn Error>> {
+    let root = Path::new(path);
+    if root.exists() {
+        return Err(format!("{path} already exists.").into());
+    }
+
+    let name = root
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("Invalid docset path \"{path}\"."))?;
+
+    let resources = root.join("Contents").join("Resources");
+    let documents = resources.join("Documents");
+    fs::create_dir_all(&documents).map_err(|e| format!("{}: {e}", documents.display()))?;
+    fs::write(root.join("Contents").join("Info.plist"), info_plist(name))?;
+
+    let mut conn = Connection::open(resources.join("docSet.dsidx"))?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(INDEX_SCHEMA)?;
+
+    let progress = Progress::visible();
+    let mut written = HashSet::new();
+    {
+        let mut insert = tx.prepare(
+            "INSERT OR IGNORE INTO searchIndex (name, type, path) VALUES (?1, ?2, ?3)")?;
+
+        for (idx, page) in pages.iter().enumerate() {
+            progress.update("Rendering pages", idx + 1, pages.len());
+
+            let html = match render(page, man_dir) {
+                Ok(html) => html,
+                // Without mandoc(1), no source page can be rendered, so the
+                // docset would be next to empty.
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    progress.finish();
+                    let _ = fs::remove_dir_all(root);
+                    return Err("mandoc(1) is needed to render the pages.".into());
+                },
+                Err(e) => {
+                    let name = page.names.first().map_or("", |n| n.value);
+                    warn!("Skipping {name}: {e}");
+                    continue;
+                },
+            };
+
+            // Each name gets a copy so that mandoc's links, which are made
+            // from the name and section of the reference, lead somewhere.
+            for sect in &page.sects {
+                for name in &page.names {
+                    let doc = document_path(name.value, sect);
+                    if !written.insert(doc.clone()) {
+                        continue;
+                    }
+
+                    let file = documents.join(&doc);
+                    if let Some(dir) = file.parent() {
+                        fs::create_dir_all(dir)?;
+                    }
+                    fs::write(&file, &html).map_err(|e| format!("{}: {e}", file.display()))?;
+                    insert.execute(params![name.value, entry_type(sect), doc.to_str()])?;
+                }
+            }
+        }
+    }
+    progress.finish();
+
+    tx.commit()?;
+    debug!("Wrote {} documents", written.len());
+    Ok(())
+}
+
+// Renders the first of the page's files that exists. Sources are formatted
+// by mandoc(1), with references linking to the other documents, while
+// preformatted pages are shown as they are. Compressed files are
+// decompressed first.
+fn render(page: &Page<'_>, man_dir: &Path) -> io::Result<Vec<u8>> {
+    let (file, bytes) = source::read_page_source(page, man_dir)?;
+
+    if let PageFormat::Preformatted = page.format {
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        let title = page.names.first().map_or("", |n| n.value);
+        return Ok(format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>{}</title>\n</head>\n<body>\n<pre>{}</pre>\n</body>\n</html>\n",
+            html_escape(title), html_escape(&strip_overstrike(&text))).into_bytes());
+    }
+
+    // The source is given on standard input, since mandoc(1) cannot read
+    // every compressed format.
+    let mut cmd = Command::new("mandoc");
+    cmd.args(["-T", "html", "-O", "man=../%S/%N.%S.html"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    debug!("Running {cmd:?} on {}", file.display());
+
+    let mut child = cmd.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(&bytes)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("mandoc(1) exited with {}", output.status)));
+    }
+    Ok(output.stdout)
+}
+
+// Where a page's document is stored, relative to the Documents directory.
+fn document_path(name: &str, sect: &str) -> PathBuf {
+    // Names and sections become file names, so they cannot leave their
+    // directory.
+    let clean = |s: &str| s.replace(['/', '\\'], "_");
+    let sect = clean(sect);
+    Path::new(&sect).join(format!("{}.{sect}.html", clean(name)))
+}
+
+// The docset entry type that suits the pages of a section.
+fn entry_type(sect: &str) -> &'static str {
+    match sect.as_bytes().first() {
+        Some(b'1' | b'6' | b'8') => "Command",
+        Some(b'2' | b'3' | b'9') => "Function",
+        Some(b'4') => "Interface",
+        Some(b'5') => "File",
+        Some(b'7') => "Guide",
+        _ => "Entry",
+    }
+}
+
+fn info_plist(name: &str) -> String {
+    let name = html_escape(name);
+    format!("\
+        <?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+        \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+        <plist version=\"1.0\">\n\
+        <dict>\n\
+        \t<key>CFBundleIdentifier</key>\n\t<string>{name}</string>\n\
+        \t<key>CFBundleName</key>\n\t<string>{name}</string>\n\
+        \t<key>DocSetPlatformFamily</key>\n\t<string>{}</string>\n\
+        \t<key>isDashDocset</key>\n\t<true/>\n\
+        </dict>\n\
+        </plist>\n", name.to_lowercase())
+}
@@ -0,0 +1,306 @@
+use std::io::{self, Write};
+
+use crate::database::{Database, Version};
+use crate::error::DbError;
+use crate::index::Indexes;
+use crate::macros::{self, Macros, Table, Value};
+use crate::pages::{Name, Page, PageFormat, Pages};
+
+// A page that owns its strings, so that it can be changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OwnedPage {
+    // Each name with its name sources byte.
+    pub names: Vec<(String, u8)>,
+    pub sects: Vec<String>,
+    // None for a machine-independent page.
+    pub archs: Option<Vec<String>>,
+    pub desc: String,
+    pub files: Vec<String>,
+    pub format: PageFormat,
+}
+
+impl OwnedPage {
+    // A machine-independent mdoc(7) or man(7) page with one name, section,
+    // and file. The name is marked as coming from the NAME section.
+    pub fn new(name: &str, sect: &str, desc: &str, file: &str) -> Self {
+        Self {
+            names: vec![(name.to_string(), 0b0000_0010)],
+            sects: vec![sect.to_string()],
+            archs: None,
+            desc: desc.to_string(),
+            files: vec![file.to_string()],
+            format: PageFormat::MdocMan,
+        }
+    }
+
+    fn from_page(page: &Page<'_>) -> Self {
+        Self {
+            names: page.names.iter().map(|name| (name.value.to_string(), name.source)).collect(),
+            sects: page.sects.iter().map(ToString::to_string).collect(),
+            archs: page.archs
+                .as_ref()
+                .map(|archs| archs.iter().map(ToString::to_string).collect()),
+            desc: page.desc.to_string(),
+            files: page.files.iter().map(ToString::to_string).collect(),
+            format: page.format.clone(),
+        }
+    }
+
+    // Returns the page as a parsed page that borrows its strings, e.g. to
+    // print it.
+    pub fn as_page(&self) -> Page<'_> {
+        Page {
+            names: self.names
+                .iter()
+                .map(|(value, source)| Name { value, source: *source })
+                .collect(),
+            sects: self.sects.iter().map(String::as_str).collect(),
+            archs: self.archs.as_ref().map(|archs| archs.iter().map(String::as_str).collect()),
+            desc: &self.desc,
+            files: self.files.iter().map(String::as_str).collect(),
+            format: self.format.clone(),
+        }
+    }
+}
+
+// A macro value and the indexes of the pages that use it, in the order they
+// are listed in the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct OwnedValue {
+    str: String,
+    pages: Vec<usize>,
+}
+
+// A copy of a database that owns its strings, so that pages can be added,
+// removed, and changed before it is written again, e.g. to correct one
+// description without building the database again.
+//
+// Macro values keep the pages that use them by index, so removing a page
+// also removes it from every macro value, and values left without pages are
+// dropped. A copy that is not changed is written back byte for byte.
+#[derive(Clone, Debug)]
+pub struct OwnedDatabase {
+    pages: Vec<OwnedPage>,
+    // The values of each of the 36 macro tables, in database order.
+    tables: Vec<Vec<OwnedValue>>,
+}
+
+impl Default for OwnedDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OwnedDatabase {
+    // A database without pages.
+    pub fn new() -> Self {
+        Self { pages: Vec::new(), tables: vec![Vec::new(); macros::KEYS.len()] }
+    }
+
+    // Copies the pages and macro values of a parsed database.
+    pub fn from_database(db: &Database<'_>) -> Result<Self, DbError> {
+        let pages = db.pages.table.iter().map(OwnedPage::from_page).collect::<Vec<OwnedPage>>();
+
+        let mut tables = db.macros
+            .get()?
+            .tables
+            .iter()
+            .map(|table| {
+                table.values
+                    .iter()
+                    .map(|value| OwnedValue {
+                        str: value.str.to_string(),
+                        pages: value.pages
+                            .iter()
+                            .copied()
+                            .filter(|&idx| idx < pages.len())
+                            .collect(),
+                    })
+                    .collect::<Vec<OwnedValue>>()
+            })
+            .collect::<Vec<Vec<OwnedValue>>>();
+        tables.resize(macros::KEYS.len(), Vec::new());
+
+        Ok(Self { pages, tables })
+    }
+
+    pub fn pages(&self) -> &[OwnedPage] {
+        &self.pages
+    }
+
+    pub fn page(&self, idx: usize) -> Option<&OwnedPage> {
+        self.pages.get(idx)
+    }
+
+    // The page at `idx`, for changing its names, sections, architectures,
+    // description, files, or format. Its macro values stay with it.
+    pub fn page_mut(&mut self, idx: usize) -> Option<&mut OwnedPage> {
+        self.pages.get_mut(idx)
+    }
+
+    // Finds the first page with the name, in the section if one is given.
+    // Names and sections are compared ignoring case, as in searches.
+    pub fn find_page(&self, name: &str, sect: Option<&str>) -> Option<usize> {
+        self.pages.iter().position(|page| {
+            page.names.iter().any(|(value, _)| value.eq_ignore_ascii_case(name))
+                && sect.is_none_or(|sect| {
+                    page.sects.iter().any(|other| other.eq_ignore_ascii_case(sect))
+                })
+        })
+    }
+
+    // Adds a page after the others and returns its index.
+    pub fn add_page(&mut self, page: OwnedPage) -> usize {
+        self.pages.push(page);
+        self.pages.len() - 1
+    }
+
+    // Removes the page at `idx` and its macro values, and returns it. The
+    // pages after it move down by one.
+    pub fn remove_page(&mut self, idx: usize) -> Option<OwnedPage> {
+        if idx >= self.pages.len() {
+            return None;
+        }
+
+        for values in &mut self.tables {
+            for value in values.iter_mut() {
+                value.pages.retain(|page| *page != idx);
+                for page in &mut value.pages {
+                    if *page > idx {
+                        *page -= 1;
+                    }
+                }
+            }
+            values.retain(|value| !value.pages.is_empty());
+        }
+        Some(self.pages.remove(idx))
+    }
+
+    // Keeps only the pages for which `keep` returns true, visiting them in
+    // order, along with their macro values. Values left without pages are
+    // dropped, as in `remove_page`.
+    pub fn retain_pages(&mut self, mut keep: impl FnMut(&OwnedPage) -> bool) {
+        // The new index of each page that is kept.
+        let mut kept = 0;
+        let new_idxs = self.pages
+            .iter()
+            .map(|page| {
+                keep(page).then(|| {
+                    kept += 1;
+                    kept - 1
+                })
+            })
+            .collect::<Vec<Option<usize>>>();
+
+        for values in &mut self.tables {
+            for value in values.iter_mut() {
+                value.pages = value.pages.iter().filter_map(|idx| new_idxs[*idx]).collect();
+            }
+            values.retain(|value| !value.pages.is_empty());
+        }
+
+        let mut idxs = new_idxs.iter();
+        self.pages.retain(|_| idxs.next().is_some_and(Option::is_some));
+    }
+
+    // The macro values of the page at `idx`, as the index of their macro
+    // table in `macros::KEYS` and their value.
+    pub fn macros(&self, idx: usize) -> Vec<(usize, &str)> {
+        self.tables
+            .iter()
+            .enumerate()
+            .flat_map(|(key, values)| {
+                values
+                    .iter()
+                    .filter(move |value| value.pages.contains(&idx))
+                    .map(move |value| (key, value.str.as_str()))
+            })
+            .collect()
+    }
+
+    // Adds `value` to the macro table for `key` of the page at `idx`, e.g.
+    // an Xr reference. A new value is kept in sorted order. Returns false if
+    // there is no such page or table, or the page already has the value.
+    pub fn add_macro(&mut self, idx: usize, key: usize, value: &str) -> bool {
+        let Some(values) = self.tables.get_mut(key).filter(|_| idx < self.pages.len()) else {
+            return false;
+        };
+
+        match values.iter().position(|other| other.str == value) {
+            Some(pos) if values[pos].pages.contains(&idx) => false,
+            Some(pos) => {
+                values[pos].pages.push(idx);
+                true
+            },
+            None => {
+                let pos = values.partition_point(|other| other.str.as_str() < value);
+                values.insert(pos, OwnedValue { str: value.to_string(), pages: vec![idx] });
+                true
+            },
+        }
+    }
+
+    // Appends `value` to the macro table for `key` with the pages at `idxs`,
+    // as a database's values are read in order. Unlike `add_macro`, the
+    // value is neither sorted nor merged with an equal one, so that the
+    // database is written back as it was read.
+    pub fn push_macro(&mut self, key: usize, value: String, idxs: Vec<usize>) -> bool {
+        let Some(values) = self.tables.get_mut(key) else {
+            return false;
+        };
+        if idxs.iter().any(|idx| *idx >= self.pages.len()) {
+            return false;
+        }
+        values.push(OwnedValue { str: value, pages: idxs });
+        true
+    }
+
+    // Removes `value` from the macro table for `key` of the page at `idx`,
+    // dropping the value if no other page uses it. Returns whether the page
+    // had the value.
+    pub fn remove_macro(&mut self, idx: usize, key: usize, value: &str) -> bool {
+        let Some(values) = self.tables.get_mut(key) else {
+            return false;
+        };
+        let Some(pos) = values.iter().position(|other| other.str == value) else {
+            return false;
+        };
+
+        let len = values[pos].pages.len();
+        values[pos].pages.retain(|page| *page != idx);
+        let removed = values[pos].pages.len() != len;
+        if values[pos].pages.is_empty() {
+            values.remove(pos);
+        }
+        removed
+    }
+
+    // Borrows the pages and macro values as a `Database`, e.g. to search
+    // the changed database or write it.
+    pub fn as_database(&self) -> Database<'_> {
+        let table = self.pages.iter().map(OwnedPage::as_page).collect::<Vec<Page<'_>>>();
+
+        let tables = self.tables
+            .iter()
+            .map(|values| {
+                let values = values
+                    .iter()
+                    .map(|value| Value { str: &value.str, pages: value.pages.clone() })
+                    .collect::<Vec<Value<'_>>>();
+                Table { count: values.len(), values }
+            })
+            .collect::<Vec<Table<'_>>>();
+
+        Database {
+            version: Version::V1,
+            pages: Pages { count: table.len(), table },
+            macros: Macros { count: tables.len(), tables }.into(),
+            indexes: Indexes::default(),
+        }
+    }
+
+    // Writes the database as a version 1 mandoc.db.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        self.as_database().write(out)
+    }
+}
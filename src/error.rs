@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::str::FromStr;
+
+use crate::output::json_string;
+
+// The broad category of a failure, reported as the "kind" of a JSON error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    // The database file could not be read.
+    Io,
+    // The file is not a mandoc.db database.
+    Format,
+    // The database uses an unsupported version.
+    Version,
+    // An offset points outside of the file.
+    OutOfBounds,
+    // A string is not valid UTF-8 or is not terminated.
+    Encoding,
+    // A table has an unexpected number of entries.
+    Count,
+    // A count or length is more than the `ParseOptions` allow.
+    Limit,
+    // A search found nothing.
+    NotFound,
+    // Anything else, like invalid arguments or config files.
+    Other,
+}
+
+impl ErrorKind {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Io => "io",
+            Self::Format => "format",
+            Self::Version => "version",
+            Self::OutOfBounds => "out_of_bounds",
+            Self::Encoding => "encoding",
+            Self::Count => "count",
+            Self::Limit => "limit",
+            Self::NotFound => "not_found",
+            Self::Other => "other",
+        }
+    }
+}
+
+// An error with a kind and, for parse failures, the byte offset in the
+// database where it was found.
+#[derive(Clone, Debug)]
+pub struct DbError {
+    pub kind: ErrorKind,
+    pub offset: Option<usize>,
+    pub message: String,
+}
+
+impl DbError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, offset: None, message: message.into() }
+    }
+
+    pub fn at(kind: ErrorKind, offset: usize, message: impl Into<String>) -> Self {
+        Self { kind, offset: Some(offset), message: message.into() }
+    }
+}
+
+impl Display for DbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} (at byte offset {offset})", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Error for DbError {}
+
+// How failures are printed on stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    pub const NAMES: &'static [&'static str] = &["text", "json"];
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown error format \"{s}\".")),
+        }
+    }
+}
+
+// Prints the error on stderr in the given format. JSON errors are a single
+// line: {"kind":"...","offset":N or null,"message":"..."}.
+pub fn report(err: &(dyn Error + 'static), format: ErrorFormat) {
+    match format {
+        ErrorFormat::Text => eprintln!("Error: {err}"),
+        ErrorFormat::Json => eprintln!("{}", to_json(err)),
+    }
+}
+
+// Formats the error as the JSON object that `report` prints.
+pub fn to_json(err: &(dyn Error + 'static)) -> String {
+    let (kind, offset, message) = if let Some(db_err) = err.downcast_ref::<DbError>() {
+        (db_err.kind, db_err.offset, db_err.message.clone())
+    } else if err.is::<io::Error>() {
+        (ErrorKind::Io, None, err.to_string())
+    } else {
+        (ErrorKind::Other, None, err.to_string())
+    };
+
+    let offset = offset.map_or_else(|| "null".to_string(), |o| o.to_string());
+    format!("{{\"kind\":{},\"offset\":{offset},\"message\":{}}}",
+        json_string(kind.name()), json_string(&message))
+}
@@ -0,0 +1,729 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::iter::Peekable;
+use std::path::Path;
+
+use crate::database::Database;
+#[cfg(any(feature = "sqlite", feature = "xml"))]
+use crate::macros;
+use crate::output;
+use crate::pages::{Page, PageField};
+use crate::sections;
+use crate::template::Template;
+
+// What the export subcommand writes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    // Comma-separated values, quoted as described in RFC 4180.
+    Csv,
+    // Tab-separated values, with tabs, newlines, and backslashes escaped.
+    Tsv,
+    // One Markdown table per section.
+    Markdown,
+    // A Graphviz digraph of the Xr cross-references between pages.
+    Dot,
+    // The lines of a traditional whatis(1) text database.
+    Whatis,
+    // A compact JSON search index of the chosen fields, optionally with the
+    // pages' description words already split out.
+    Json { fields: Vec<PageField>, tokens: bool },
+    // The whole database, for loading quickly with --from-cache.
+    Cache(String),
+    // An XML document of the pages and macros.
+    Xml,
+    // A new SQLite database at the given path.
+    Sqlite(String),
+    // A Dash/Zeal docset at the given path.
+    Docset(String),
+    // A directory of HTML index pages, one per section. Names link to the
+    // URL made from the template, if one is given.
+    Html { dir: String, url: Option<Template> },
+}
+
+impl ExportFormat {
+    // The flags that choose a format, e.g. "export --csv".
+    pub const FLAGS: &'static [&'static str] = &[
+        "--csv", "--tsv", "--markdown", "--dot", "--whatis",
+        "--json [--fields LIST] [--tokens]", "--xml", "--cache FILE",
+        "--sqlite FILE", "--docset DIR", "--html DIR [--url TEMPLATE]",
+    ];
+
+    // Parses the arguments that follow "export", e.g. "--sqlite out.db".
+    pub fn from_args<I>(args: &mut Peekable<I>) -> Result<Self, Box<dyn Error>>
+    where
+        I: Iterator<Item = String>,
+    {
+        let flag = args.next().ok_or("Missing format for export.")?;
+
+        match flag.as_str() {
+            "--csv" => Ok(Self::Csv),
+            "--tsv" => Ok(Self::Tsv),
+            "--markdown" => Ok(Self::Markdown),
+            "--dot" => Ok(Self::Dot),
+            "--whatis" => Ok(Self::Whatis),
+            "--xml" => Ok(Self::Xml),
+            "--json" => {
+                let mut fields = JSON_FIELDS.to_vec();
+                let mut tokens = false;
+                let is_option = |arg: &String| arg == "--fields" || arg == "--tokens";
+                while let Some(option) = args.next_if(is_option) {
+                    if option == "--tokens" {
+                        tokens = true;
+                    } else {
+                        let list = args.next().ok_or("Missing value for --fields.")?;
+                        fields = PageField::parse_list(&list)?;
+                    }
+                }
+                Ok(Self::Json { fields, tokens })
+            },
+            "--sqlite" => {
+                let path = args.next().ok_or("Missing output file for --sqlite.")?;
+                Ok(Self::Sqlite(path))
+            },
+            "--cache" => {
+                let path = args.next().ok_or("Missing output file for --cache.")?;
+                Ok(Self::Cache(path))
+            },
+            "--docset" => {
+                let path = args.next().ok_or("Missing output directory for --docset.")?;
+                Ok(Self::Docset(path))
+            },
+            "--html" => {
+                let dir = args.next().ok_or("Missing output directory for --html.")?;
+                let url = match args.next_if(|arg| arg == "--url") {
+                    Some(_) => Some(args
+                        .next()
+                        .ok_or("Missing value for --url.")?
+                        .parse()?),
+                    None => None,
+                };
+                Ok(Self::Html { dir, url })
+            },
+            _ => Err(format!("Unknown export format \"{flag}\". Use one of: {}.",
+                Self::FLAGS.join(", ")).into()),
+        }
+    }
+}
+
+// The columns of a CSV or TSV export.
+const COLUMNS: [&str; 6] = ["name", "section", "arch", "description", "file", "format"];
+
+// Writes a header and then one row per page. Pages with several names,
+// sections, architectures, or files list them in one cell separated by
+// commas; machine-independent pages have an empty arch cell.
+pub fn write_table(
+    out: &mut dyn Write,
+    pages: &[&Page<'_>],
+    format: &ExportFormat
+) -> io::Result<()> {
+    let separator = if *format == ExportFormat::Tsv { "\t" } else { "," };
+    let cell = |value: &str| match format {
+        ExportFormat::Tsv => tsv_cell(value),
+        _ => csv_cell(value),
+    };
+
+    writeln!(out, "{}", COLUMNS.join(separator))?;
+
+    for page in pages {
+        let names = page.names.iter().map(|n| n.value).collect::<Vec<&str>>();
+        let row = [
+            names.join(","),
+            page.sects.join(","),
+            page.archs.as_ref().map(|archs| archs.join(",")).unwrap_or_default(),
+            page.desc.to_string(),
+            page.files.join(","),
+            page.format.name().to_string(),
+        ];
+
+        let row = row.iter().map(|value| cell(value)).collect::<Vec<String>>();
+        writeln!(out, "{}", row.join(separator))?;
+    }
+
+    Ok(())
+}
+
+// Writes a heading and a table of pages, sorted like apropos(1), for each
+// section. Pages in several sections are listed in each of them.
+pub fn write_markdown(out: &mut dyn Write, pages: &[&Page<'_>]) -> io::Result<()> {
+    for (sect, pages) in &mut by_section(pages) {
+        pages.sort_by(|a, b| a.apropos_cmp(b));
+
+        writeln!(out, "## Section {}\n", sections::describe(sect))?;
+        output::write_markdown_header(out)?;
+        for page in pages.iter() {
+            writeln!(out, "{}", output::markdown_row(page))?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+// Writes a Graphviz digraph with a node for every page, named like "ls(1)",
+// and an edge for every Xr reference from one of them. References to pages
+// missing from the database are drawn as dashed nodes, and pages nobody
+// refers to that refer to nothing are left unconnected.
+pub fn write_dot(
+    out: &mut dyn Write,
+    db: &Database<'_>,
+    positions: &[usize]
+) -> io::Result<()> {
+    let node = |page: &Page<'_>| {
+        let name = page.names.first().map_or("", |n| n.value);
+        let sect = page.sects.first().unwrap_or_default();
+        format!("{name}({sect})")
+    };
+
+    // References are resolved against the whole database, so a page outside
+    // the exported sections is not mistaken for a missing one.
+    let targets = db.xr_targets();
+
+    // Only the exported pages' references are drawn.
+    let sources = positions
+        .iter()
+        .filter_map(|&idx| Some((idx, node(db.pages.table.get(idx)?))))
+        .collect::<HashMap<usize, String>>();
+
+    let mut edges = BTreeSet::new();
+    let mut missing = BTreeSet::new();
+    let xrs = db.macros
+        .get()
+        .map_err(io::Error::other)?
+        .tables
+        .first()
+        .map(|table| &table.values[..])
+        .unwrap_or_default();
+    for value in xrs {
+        let target = targets.get(&value.str.to_ascii_lowercase()).map(|page| node(page));
+        let referrers = value.pages.iter().filter_map(|idx| sources.get(idx));
+
+        for source in referrers {
+            let target = target.clone().unwrap_or_else(|| {
+                missing.insert(value.str.to_string());
+                value.str.to_string()
+            });
+            if *source != target {
+                edges.insert((source.clone(), target));
+            }
+        }
+    }
+
+    writeln!(out, "digraph xr {{")?;
+    for node in sources.values().collect::<BTreeSet<&String>>() {
+        writeln!(out, "    {};", dot_id(node))?;
+    }
+    for node in &missing {
+        writeln!(out, "    {} [style=dashed];", dot_id(node))?;
+    }
+    for (source, target) in &edges {
+        writeln!(out, "    {} -> {};", dot_id(source), dot_id(target))?;
+    }
+    writeln!(out, "}}")
+}
+
+// Quotes a node name as a DOT string.
+fn dot_id(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Writes the pages the way makewhatis(8) used to write the plain text
+// whatis database, one "name, name(sect) - description" line per page,
+// sorted like apropos(1). Unlike `Page::whatis`, the line has no
+// architectures, which the old format did not have.
+pub fn write_whatis(out: &mut dyn Write, pages: &[&Page<'_>]) -> io::Result<()> {
+    let mut pages = pages.to_vec();
+    pages.sort_by(|a, b| a.apropos_cmp(b));
+
+    for page in pages {
+        let names = page.names.iter().map(|n| n.value).collect::<Vec<&str>>();
+        writeln!(out, "{}({}) - {}", names.join(", "), page.sects.join(", "), page.desc)?;
+    }
+
+    Ok(())
+}
+
+// The fields of `export --json` unless others are chosen.
+const JSON_FIELDS: [PageField; 4] = [
+    PageField::Names, PageField::Sections, PageField::Desc, PageField::Files,
+];
+
+// Words too common to be worth searching for.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "by", "for", "from", "in", "of", "on", "or", "the",
+    "to", "with",
+];
+
+// Writes a search index for client-side search on one line, e.g.
+//
+// {"fields":["names","sect","desc","files"],
+//  "pages":[[["ls"],["1"],"list directory contents",["man1/ls.1"]]],
+//  "tokens":{"contents":[0],"directory":[0],"list":[0]}}
+//
+// Each page is an array of its fields, in the order given by "fields", so
+// the names of the fields are not repeated. With `tokens`, the words of the
+// descriptions are lowercased and mapped to the indexes of their pages.
+pub fn write_json(
+    out: &mut dyn Write,
+    pages: &[&Page<'_>],
+    fields: &[PageField],
+    tokens: bool
+) -> io::Result<()> {
+    let names = fields.iter().map(ToString::to_string).collect::<Vec<String>>();
+    let names = names.iter().map(String::as_str).collect::<Vec<&str>>();
+    write!(out, "{{\"fields\":{},\"pages\":[", output::json_array(&names))?;
+
+    let mut index = BTreeMap::new();
+    for (idx, page) in pages.iter().enumerate() {
+        let values = fields
+            .iter()
+            .map(|field| match field {
+                PageField::Names => {
+                    let names = page.names.iter().map(|n| n.value).collect::<Vec<&str>>();
+                    output::json_array(&names)
+                },
+                PageField::Sections => output::json_array(&page.sects.to_vec()),
+                PageField::Archs => page.archs
+                    .as_ref()
+                    .map_or_else(|| "null".to_string(), |archs| {
+                        output::json_array(&archs.to_vec())
+                    }),
+                PageField::Desc => output::json_string(page.desc),
+                PageField::Files => output::json_array(&page.files.to_vec()),
+                PageField::Format => output::json_string(page.format.name()),
+            })
+            .collect::<Vec<String>>();
+
+        let separator = if idx == 0 { "" } else { "," };
+        write!(out, "{separator}[{}]", values.join(","))?;
+
+        if tokens {
+            for token in tokenize(page.desc) {
+                let pages = index.entry(token).or_insert_with(Vec::new);
+                if pages.last() != Some(&idx) {
+                    pages.push(idx);
+                }
+            }
+        }
+    }
+    write!(out, "]")?;
+
+    if tokens {
+        let index = index
+            .iter()
+            .map(|(token, pages)| {
+                let pages = pages.iter().map(ToString::to_string).collect::<Vec<String>>();
+                format!("{}:[{}]", output::json_string(token), pages.join(","))
+            })
+            .collect::<Vec<String>>();
+        write!(out, ",\"tokens\":{{{}}}", index.join(","))?;
+    }
+
+    writeln!(out, "}}")
+}
+
+// Splits a description into lowercase words, leaving out stop words.
+fn tokenize(desc: &str) -> impl Iterator<Item = String> + '_ {
+    desc.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+}
+
+// The schema of `export --sqlite`. Every table refers to a page by its index
+// in the mandoc.db pages table.
+#[cfg(feature = "sqlite")]
+const SQLITE_SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE pages (
+        id INTEGER PRIMARY KEY,
+        description TEXT NOT NULL,
+        format TEXT NOT NULL
+    );
+    CREATE TABLE names (
+        page_id INTEGER NOT NULL REFERENCES pages(id),
+        name TEXT NOT NULL,
+        -- The mandoc.db source bits: 1 SYNOPSIS, 2 NAME, 4 first NAME,
+        -- 8 header, 16 file name.
+        sources INTEGER NOT NULL
+    );
+    CREATE TABLE sections (
+        page_id INTEGER NOT NULL REFERENCES pages(id),
+        section TEXT NOT NULL
+    );
+    CREATE TABLE archs (
+        page_id INTEGER NOT NULL REFERENCES pages(id),
+        arch TEXT NOT NULL
+    );
+    CREATE TABLE files (
+        page_id INTEGER NOT NULL REFERENCES pages(id),
+        file TEXT NOT NULL
+    );
+    CREATE TABLE macros (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE macro_values (
+        id INTEGER PRIMARY KEY,
+        macro_id INTEGER NOT NULL REFERENCES macros(id),
+        value TEXT NOT NULL
+    );
+    CREATE TABLE macro_value_pages (
+        value_id INTEGER NOT NULL REFERENCES macro_values(id),
+        page_id INTEGER NOT NULL REFERENCES pages(id)
+    );
+    CREATE INDEX names_name ON names(name);
+    CREATE INDEX macro_values_value ON macro_values(value);
+";
+
+// Writes the pages at `positions` in the pages table, along with the macro
+// values that refer to them, to a new SQLite database at `path`.
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(
+    db: &Database<'_>,
+    positions: &[usize],
+    path: &str
+) -> Result<(), Box<dyn Error>> {
+    use rusqlite::{params, Connection};
+
+    // Adding to an existing database would mix two exports.
+    if Path::new(path).exists() {
+        return Err(format!("{path} already exists.").into());
+    }
+
+    let mut conn = Connection::open(path).map_err(|e| format!("{path}: {e}"))?;
+    let tx = conn.transaction()?;
+    tx.execute_batch(SQLITE_SCHEMA)?;
+
+    // Pages are numbered by their position in the database.
+    let ids = positions.iter().copied().collect::<HashSet<usize>>();
+    {
+        let mut insert_page = tx.prepare("INSERT INTO pages VALUES (?1, ?2, ?3)")?;
+        let mut insert_name = tx.prepare("INSERT INTO names VALUES (?1, ?2, ?3)")?;
+        let mut insert_sect = tx.prepare("INSERT INTO sections VALUES (?1, ?2)")?;
+        let mut insert_arch = tx.prepare("INSERT INTO archs VALUES (?1, ?2)")?;
+        let mut insert_file = tx.prepare("INSERT INTO files VALUES (?1, ?2)")?;
+
+        for &idx in positions {
+            let Some(page) = db.pages.table.get(idx) else {
+                continue;
+            };
+            let id = i64::try_from(idx)?;
+
+            insert_page.execute(params![id, page.desc, page.format.name()])?;
+            for name in &page.names {
+                insert_name.execute(params![id, name.value, name.source])?;
+            }
+            for sect in &page.sects {
+                insert_sect.execute(params![id, sect])?;
+            }
+            for arch in page.archs.iter().flatten() {
+                insert_arch.execute(params![id, arch])?;
+            }
+            for file in &page.files {
+                insert_file.execute(params![id, file])?;
+            }
+        }
+
+        let mut insert_macro = tx.prepare("INSERT INTO macros VALUES (?1, ?2)")?;
+        let mut insert_value = tx.prepare(
+            "INSERT INTO macro_values (macro_id, value) VALUES (?1, ?2)")?;
+        let mut insert_value_page = tx.prepare("INSERT INTO macro_value_pages VALUES (?1, ?2)")?;
+
+        let tables = macros::KEYS.iter().zip(&db.macros.get()?.tables);
+        for (macro_id, (key, table)) in (0_i64..).zip(tables) {
+            insert_macro.execute(params![macro_id, key])?;
+
+            for value in &table.values {
+                let page_ids = value.pages
+                    .iter()
+                    .filter(|idx| ids.contains(idx))
+                    .map(|&idx| i64::try_from(idx))
+                    .collect::<Result<Vec<i64>, _>>()?;
+                // Values only used by pages left out of the export are
+                // left out too.
+                if page_ids.is_empty() {
+                    continue;
+                }
+
+                insert_value.execute(params![macro_id, value.str])?;
+                let value_id = tx.last_insert_rowid();
+                for page_id in page_ids {
+                    insert_value_page.execute(params![value_id, page_id])?;
+                }
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+// Writes an XML document with the pages at `positions` in the pages table
+// and the macro values that refer to them. Pages keep their index in the
+// database as their id, and the document looks like this:
+//
+// <mandoc-db version="1">
+//   <pages>
+//     <page id="0" format="source">
+//       <name sources="6">ls</name>
+//       <section>1</section>
+//       <arch>amd64</arch>
+//       <description>list directory contents</description>
+//       <file>man1/ls.1</file>
+//     </page>
+//   </pages>
+//   <macros>
+//     <macro key="Xr">
+//       <value text="cat(1)"><page ref="0"/></value>
+//     </macro>
+//   </macros>
+// </mandoc-db>
+//
+// Machine-independent pages have no arch elements, and macro values used by
+// no exported page are left out.
+#[cfg(feature = "xml")]
+pub fn write_xml(
+    out: &mut dyn Write,
+    db: &Database<'_>,
+    positions: &[usize]
+) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(out, "<mandoc-db version=\"1\">\n  <pages>")?;
+
+    let ids = positions.iter().copied().collect::<HashSet<usize>>();
+    for &id in positions {
+        let Some(page) = db.pages.table.get(id) else {
+            continue;
+        };
+
+        writeln!(out, "    <page id=\"{id}\" format=\"{}\">", page.format.name())?;
+        for name in &page.names {
+            writeln!(out, "      <name sources=\"{}\">{}</name>", name.source,
+                xml_escape(name.value))?;
+        }
+        for sect in &page.sects {
+            writeln!(out, "      <section>{}</section>", xml_escape(sect))?;
+        }
+        for arch in page.archs.iter().flatten() {
+            writeln!(out, "      <arch>{}</arch>", xml_escape(arch))?;
+        }
+        writeln!(out, "      <description>{}</description>", xml_escape(page.desc))?;
+        for file in &page.files {
+            writeln!(out, "      <file>{}</file>", xml_escape(file))?;
+        }
+        writeln!(out, "    </page>")?;
+    }
+
+    writeln!(out, "  </pages>\n  <macros>")?;
+    for (key, table) in macros::KEYS.iter().zip(&db.macros.get()?.tables) {
+        writeln!(out, "    <macro key=\"{key}\">")?;
+
+        for value in &table.values {
+            let refs = value.pages
+                .iter()
+                .filter(|id| ids.contains(id))
+                .map(|id| format!("<page ref=\"{id}\"/>"))
+                .collect::<Vec<String>>();
+            if refs.is_empty() {
+                continue;
+            }
+
+            writeln!(out, "      <value text=\"{}\">{}</value>", xml_escape(value.str),
+                refs.concat())?;
+        }
+
+        writeln!(out, "    </macro>")?;
+    }
+    writeln!(out, "  </macros>\n</mandoc-db>")?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "xml"))]
+pub fn write_xml(
+    _out: &mut dyn Write,
+    _db: &Database<'_>,
+    _positions: &[usize]
+) -> Result<(), Box<dyn Error>> {
+    Err("This build does not support XML; rebuild it with the \"xml\" feature.".into())
+}
+
+// Escapes text for XML elements and attributes. Control characters that XML
+// 1.0 does not allow are dropped.
+#[cfg(feature = "xml")]
+fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if u32::from(c) < 0x20 => {},
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write_sqlite(
+    _db: &Database<'_>,
+    _positions: &[usize],
+    _path: &str
+) -> Result<(), Box<dyn Error>> {
+    Err("This build does not support SQLite; rebuild it with the \"sqlite\" feature.".into())
+}
+
+// Writes `index.html`, which lists the sections, and one
+// `section-SECT.html` per section into `dir`, creating it if needed. Every
+// name is an anchor, so `section-1.html#ls` links to ls(1), and with a URL
+// template such as "https://man.openbsd.org/{name}.{sect}" names also link
+// to a man.cgi(8) server.
+pub fn write_html(
+    pages: &[&Page<'_>],
+    dir: &str,
+    url: Option<&Template>
+) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+
+    let mut by_sect = by_section(pages);
+    let create = |name: &str| -> Result<BufWriter<File>, Box<dyn Error>> {
+        let path = dir.join(name);
+        let file = File::create(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(BufWriter::new(file))
+    };
+
+    let mut index = create("index.html")?;
+    write_html_head(&mut index, "Manual page index")?;
+    writeln!(index, "<ul>")?;
+    for (sect, pages) in &by_sect {
+        writeln!(index, "<li><a href=\"{}\">{}</a> ({} pages)</li>",
+            html_escape(&section_file(sect)), html_escape(&sections::describe(sect)),
+            pages.len())?;
+    }
+    writeln!(index, "</ul>\n</body>\n</html>")?;
+    index.flush()?;
+
+    for (sect, pages) in &mut by_sect {
+        pages.sort_by(|a, b| a.apropos_cmp(b));
+
+        let mut out = create(&section_file(sect))?;
+        write_html_head(&mut out, &format!("Section {}", sections::describe(sect)))?;
+        writeln!(out, "<p><a href=\"index.html\">All sections</a></p>\n<dl>")?;
+
+        // A name can only be the anchor of its first page in the section.
+        let mut ids = HashSet::new();
+        for page in pages.iter() {
+            let names = page.names
+                .iter()
+                .map(|name| {
+                    let id = name.value.replace(char::is_whitespace, "_");
+                    let href = url.map_or_else(|| format!("#{id}"), |url| {
+                        // Each name links to its own page in this section.
+                        let mut link = (*page).clone();
+                        link.names = [name].into_iter().collect();
+                        link.sects = [*sect].into_iter().collect();
+                        url.render(&link)
+                    });
+                    let id = if ids.insert(id.clone()) {
+                        format!(" id=\"{}\"", html_escape(&id))
+                    } else {
+                        String::new()
+                    };
+                    format!("<a{id} href=\"{}\">{}</a>", html_escape(&href),
+                        html_escape(name.value))
+                })
+                .collect::<Vec<String>>();
+
+            writeln!(out, "<dt>{}({})</dt>\n<dd>{}</dd>", names.join(", "),
+                html_escape(sect), html_escape(page.desc))?;
+        }
+
+        writeln!(out, "</dl>\n</body>\n</html>")?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+// Groups the pages by section, in section order.
+fn by_section<'a, 'p>(pages: &[&'p Page<'a>]) -> BTreeMap<&'a str, Vec<&'p Page<'a>>> {
+    let mut by_sect = BTreeMap::new();
+    for page in pages {
+        for sect in &page.sects {
+            by_sect.entry(sect).or_insert_with(Vec::new).push(*page);
+        }
+    }
+    by_sect
+}
+
+fn write_html_head(out: &mut dyn Write, title: &str) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>", html_escape(title))
+}
+
+// The file name of a section's page. Sections are short words like "3p",
+// but anything unusual is replaced so it cannot leave the directory.
+fn section_file(sect: &str) -> String {
+    let sect = sect
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    format!("section-{sect}.html")
+}
+
+pub fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(feature = "sqlite")]
+pub use crate::docset::write_docset;
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write_docset(
+    _pages: &[&Page<'_>],
+    _man_dir: &Path,
+    _path: &str
+) -> Result<(), Box<dyn Error>> {
+    Err("This build does not support SQLite; rebuild it with the \"sqlite\" feature.".into())
+}
+
+// Quotes a CSV cell when it contains a separator, quote, or line break.
+fn csv_cell(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// TSV cannot quote, so the characters it uses are escaped instead.
+fn tsv_cell(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
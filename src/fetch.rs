@@ -0,0 +1,100 @@
+use std::error::Error;
+#[cfg(feature = "http")]
+use std::fs;
+#[cfg(feature = "http")]
+use std::path::PathBuf;
+
+#[cfg(feature = "http")]
+use log::{debug, warn};
+
+#[cfg(feature = "http")]
+use crate::config;
+
+// Returns whether a database path is an http:// or https:// URL.
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+// Downloads the database at `url`, e.g. to search the pages of another
+// system without copying its database first.
+//
+// The download is kept in the cache directory along with its ETag, so the
+// database is only downloaded again when the server has a newer one. When
+// the server cannot be reached, the cached copy is used instead.
+#[cfg(feature = "http")]
+pub fn fetch_db(url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let cached = cache_path(url);
+    let etag_path = cached.as_ref().map(|path| {
+        let mut etag_path = path.clone().into_os_string();
+        etag_path.push(".etag");
+        PathBuf::from(etag_path)
+    });
+
+    // The ETag is only sent when the database it belongs to is still there.
+    let etag = etag_path
+        .as_ref()
+        .filter(|_| cached.as_ref().is_some_and(|path| path.is_file()))
+        .and_then(|path| fs::read_to_string(path).ok());
+
+    let mut request = ureq::get(url);
+    if let Some(etag) = &etag {
+        request = request.header("If-None-Match", etag.trim());
+    }
+    debug!("Downloading {url}");
+
+    let mut response = match request.call() {
+        Ok(response) => response,
+        Err(e) => {
+            let Some(bytes) = cached.as_ref().and_then(|path| fs::read(path).ok()) else {
+                return Err(e.into());
+            };
+            warn!("{url}: {e}; using the cached copy");
+            return Ok(bytes);
+        },
+    };
+
+    if response.status().as_u16() == 304 {
+        if let Some(bytes) = cached.as_ref().and_then(|path| fs::read(path).ok()) {
+            debug!("The cached copy of {url} is current");
+            return Ok(bytes);
+        }
+    }
+
+    // mandoc.db files are often larger than the default body limit.
+    let bytes = response.body_mut().with_config().limit(u64::MAX).read_to_vec()?;
+    debug!("Downloaded {} bytes", bytes.len());
+
+    // A download that cannot be cached is still searched.
+    let new_etag = response.headers().get("etag").and_then(|etag| etag.to_str().ok());
+    if let (Some(cached), Some(etag_path)) = (&cached, &etag_path) {
+        let saved = cached.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(cached, &bytes))
+            .and_then(|()| match new_etag {
+                Some(etag) => fs::write(etag_path, etag),
+                None => fs::remove_file(etag_path).or(Ok(())),
+            });
+        if let Err(e) = saved {
+            warn!("{}: {e}", cached.display());
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "http"))]
+pub fn fetch_db(_url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    Err("This build does not support URLs; rebuild it with the \"http\" feature.".into())
+}
+
+// Returns where the download of `url` is cached, named after the URL with
+// every character that is not a letter, digit, '.', or '-' replaced by '_'.
+#[cfg(feature = "http")]
+fn cache_path(url: &str) -> Option<PathBuf> {
+    let name = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect::<String>();
+    Some(config::cache_dir()?.join("downloads").join(name))
+}
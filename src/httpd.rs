@@ -0,0 +1,223 @@
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::num::NonZero;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+
+use crate::database::Database;
+use crate::error::{self, DbError, ErrorKind};
+use crate::json::JsonValue;
+use crate::search::SearchOptions;
+use crate::serve;
+
+// The address that `serve` listens on unless --listen gives another.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+// The most bytes of a request's line and headers that are read, and how long
+// a client may take to send all of them.
+const MAX_REQUEST_LEN: u64 = 16 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Serves searches of the databases over HTTP on `addr`, so that one process
+// can answer the searches of a whole team. Every endpoint answers a GET
+// request with JSON:
+//
+//   /search?q=QUERY    the results of a search, like `--serve-stdio`'s
+//                      search; match, section, arch, and limit may be given
+//   /page/NAME         the page NAME with its path and source, like
+//                      get-page; section may be given
+//   /complete?q=PREFIX the page names starting with PREFIX; limit may be
+//                      given
+//
+// A failed request gets an error like those of `--errors json`, with a 404
+// status for pages that are not found and a 400 status for bad requests.
+// Connections are answered by a thread for each CPU, one request each.
+pub fn serve(
+    addr: &str,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| DbError::new(ErrorKind::Io, format!("{addr}: {e}")))?;
+    let plural = if dbs.len() == 1 { "database" } else { "databases" };
+    eprintln!("Serving {} {plural} on http://{}", dbs.len(), listener.local_addr()?);
+
+    let workers = thread::available_parallelism().map_or(4, NonZero::get);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        if let Err(e) = handle_connection(&stream, dbs, opts) {
+                            debug!("{peer}: {e}");
+                        }
+                    },
+                    Err(e) => warn!("Failed to accept a connection: {e}"),
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// Reads one request from a connection and writes its response.
+fn handle_connection(
+    stream: &TcpStream,
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions
+) -> io::Result<()> {
+    let request = Deadline { stream, end: Instant::now() + READ_TIMEOUT };
+    let mut reader = BufReader::new(request.take(MAX_REQUEST_LEN));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are read up to the blank line that ends them, and ignored.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let (status, body) = respond(dbs, opts, request_line.trim_end());
+    debug!("{} {status}", request_line.trim_end());
+
+    let mut out = stream;
+    write!(out, "HTTP/1.1 {status} {}\r\nContent-Type: application/json\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n{body}", reason(status), body.len())?;
+    out.flush()
+}
+
+// A connection that is read until a deadline. Every read waits only as long
+// as is left until then, so that a client sending a byte at a time cannot
+// keep a worker busy past it.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    end: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "The request took too long."));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+// Answers a request, given its request line, e.g. "GET /search?q=ls HTTP/1.1",
+// with a status code and a JSON body.
+fn respond(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions,
+    request_line: &str
+) -> (u16, String) {
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return error_response(400, "Malformed request line.".into());
+    };
+    if method != "GET" {
+        return error_response(405, format!("Method {method} is not allowed; use GET.").into());
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = percent_decode(path, false);
+    let result = match path.as_str() {
+        "/search" => {
+            let params = query_params(query, "query");
+            if params.get("query").is_none() {
+                return error_response(400, "Missing q= to search for.".into());
+            }
+            serve::call(dbs, opts, "search", &params)
+        },
+        "/complete" => serve::call(dbs, opts, "complete", &query_params(query, "prefix")),
+        _ => match path.strip_prefix("/page/").filter(|name| !name.is_empty()) {
+            Some(name) => {
+                let mut params = query_params(query, "name");
+                if let JsonValue::Object(members) = &mut params {
+                    members.retain(|(key, _)| key != "name");
+                    members.push(("name".to_string(), JsonValue::Str(name.to_string())));
+                }
+                serve::call(dbs, opts, "get-page", &params)
+            },
+            None => Err(DbError::new(ErrorKind::NotFound, format!("Unknown path {path}; the \
+                endpoints are /search?q=QUERY, /page/NAME, and /complete?q=PREFIX.")).into()),
+        },
+    };
+
+    match result {
+        Ok(body) => (200, body),
+        Err(e) => {
+            let status = match e.downcast_ref::<DbError>() {
+                Some(e) if e.kind == ErrorKind::NotFound => 404,
+                _ if e.is::<io::Error>() => 500,
+                _ => 400,
+            };
+            error_response(status, e)
+        },
+    }
+}
+
+fn error_response(status: u16, err: Box<dyn Error>) -> (u16, String) {
+    (status, error::to_json(err.as_ref()))
+}
+
+const fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+// Reads the parameters of a query string, e.g. "q=ls&limit=5", into the
+// parameters of a `serve::call`. The query, `q`, is named `q_name`, and
+// limits are numbers.
+fn query_params(query: &str, q_name: &str) -> JsonValue {
+    let members = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let (key, value) = (percent_decode(key, true), percent_decode(value, true));
+            let value = match value.parse::<u32>() {
+                Ok(limit) if key == "limit" => JsonValue::Number(limit.into()),
+                _ => JsonValue::Str(value),
+            };
+            let key = if key == "q" { q_name.to_string() } else { key };
+            (key, value)
+        })
+        .collect();
+    JsonValue::Object(members)
+}
+
+// Decodes the %XX escapes of a URL path or query, and in a query, the "+"
+// that stands for a space. Bytes that are not UTF-8 are replaced.
+fn percent_decode(s: &str, query: bool) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let hex = after
+            .get(..2)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, hex) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &after[2..];
+                continue;
+            },
+            (b'+', _) if query => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+        rest = after;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use log::debug;
+
+use crate::memory::{map_size, vec_size};
+use crate::pages::Page;
+use crate::search::Field;
+
+// Indexes over the pages of a database that let searches find their results
+// without comparing the query against every page. Each one is built the
+// first time a search needs it and kept for the searches after it, so that
+// the search prompt, `serve`, and the query daemon pay for it once, while a
+// database searched only for other kinds of queries never builds it.
+//
+// The word index of the descriptions is the exception: it is only built when
+// asked for with `index_descriptions`, as `--full-search` does when the
+// databases are loaded, since most searches only compare names.
+//
+// Names can instead be looked up in a sorted array of them, built with
+// `index_sorted_names` when the databases are loaded, as `--sorted-index`
+// does. It takes less memory and time to build than the hash map and trie
+// together, at the cost of a binary search per lookup. Once it is built,
+// exact and prefix lookups use it instead of building the others.
+//
+// When several databases are searched, each also gets a Bloom filter of its
+// names with `index_name_filter`, so that an exact search skips the databases
+// that cannot have the name without building their name index.
+//
+// The indexes refer to pages by their position in the pages table, so they
+// must only be given the pages of the database they belong to.
+#[derive(Clone, Debug, Default)]
+pub struct Indexes {
+    // The positions of the pages with each name, keyed by the lowercase name.
+    names: OnceLock<HashMap<String, Vec<usize>>>,
+    // The lowercase names, for prefix searches and completion.
+    prefixes: OnceLock<Trie>,
+    // The lowercase names and the positions of their pages, sorted.
+    sorted: OnceLock<SortedNames>,
+    // The lowercase names, for ruling names out.
+    name_filter: OnceLock<NameFilter>,
+    // The positions of the pages with each word in their description, keyed
+    // by the lowercase word.
+    words: OnceLock<HashMap<String, Vec<usize>>>,
+    // The trigrams of the lowercase names and descriptions, for substring
+    // and regular expression searches.
+    name_trigrams: OnceLock<Trigrams>,
+    desc_trigrams: OnceLock<Trigrams>,
+}
+
+impl Indexes {
+    // Returns the positions of the pages with a name equal to `name`, ignoring
+    // ASCII case, in database order.
+    pub fn pages_named(&self, pages: &[Page<'_>], name: &str) -> Vec<usize> {
+        if self.name_filter.get().is_some_and(|filter| !filter.may_contain(name)) {
+            return Vec::new();
+        }
+        let name = name.to_ascii_lowercase();
+        if let Some(sorted) = self.sorted.get() {
+            return sorted.pages_with(&name, |indexed| indexed == name);
+        }
+        self.names
+            .get_or_init(|| name_index(pages))
+            .get(&name)
+            .map_or_else(Vec::new, Vec::clone)
+    }
+
+    // Returns the positions of the pages with a name that starts with
+    // `prefix`, ignoring ASCII case, in database order.
+    pub fn pages_with_prefix(&self, pages: &[Page<'_>], prefix: &str) -> Vec<usize> {
+        let prefix = prefix.to_ascii_lowercase();
+        if let Some(sorted) = self.sorted.get() {
+            return sorted.pages_with(&prefix, |indexed| indexed.starts_with(&prefix));
+        }
+        self.prefixes
+            .get_or_init(|| Trie::build(pages))
+            .pages_with_prefix(&prefix)
+    }
+
+    // Builds the sorted array of the names, unless it has been built.
+    pub fn index_sorted_names(&self, pages: &[Page<'_>]) {
+        self.sorted.get_or_init(|| SortedNames::build(pages));
+    }
+
+    // Builds the word index of the descriptions, unless it has been built.
+    pub fn index_descriptions(&self, pages: &[Page<'_>]) {
+        self.words.get_or_init(|| word_index(pages));
+    }
+
+    // Builds the Bloom filter of the names, unless it has been built.
+    pub fn index_name_filter(&self, pages: &[Page<'_>]) {
+        self.name_filter.get_or_init(|| NameFilter::build(pages));
+    }
+
+    // Builds every index, as the searches of every kind would, so that their
+    // sizes can be compared.
+    pub fn build_all(&self, pages: &[Page<'_>]) {
+        self.names.get_or_init(|| name_index(pages));
+        self.prefixes.get_or_init(|| Trie::build(pages));
+        self.index_sorted_names(pages);
+        self.index_name_filter(pages);
+        self.index_descriptions(pages);
+        for field in [Field::Name, Field::Desc] {
+            // The text is long enough to look up, so the field is indexed.
+            self.pages_containing(pages, field, &["   ".to_string()]);
+        }
+    }
+
+    // Returns the name and the bytes of heap memory used by each index that
+    // has been built.
+    pub fn heap_usage(&self) -> Vec<(&'static str, usize)> {
+        let keyed_size = |map: &HashMap<String, Vec<usize>>| {
+            map_size(map) + map.iter().map(|(k, v)| k.capacity() + vec_size(v)).sum::<usize>()
+        };
+        let trigrams_size = |trigrams: &Trigrams| {
+            map_size(&trigrams.0) + trigrams.0.values().map(vec_size).sum::<usize>()
+        };
+
+        let mut usage = Vec::new();
+        if let Some(names) = self.names.get() {
+            usage.push(("name index", keyed_size(names)));
+        }
+        if let Some(trie) = self.prefixes.get() {
+            let nodes = trie.nodes
+                .iter()
+                .map(|node| vec_size(&node.children) + vec_size(&node.pages))
+                .sum::<usize>();
+            usage.push(("prefix trie", vec_size(&trie.nodes) + nodes));
+        }
+        if let Some(sorted) = self.sorted.get() {
+            let names = sorted.0.iter().map(|(name, _)| name.capacity()).sum::<usize>();
+            usage.push(("sorted names", vec_size(&sorted.0) + names));
+        }
+        if let Some(filter) = self.name_filter.get() {
+            usage.push(("name filter", vec_size(&filter.bits)));
+        }
+        if let Some(words) = self.words.get() {
+            usage.push(("description words", keyed_size(words)));
+        }
+        if let Some(trigrams) = self.name_trigrams.get() {
+            usage.push(("name trigrams", trigrams_size(trigrams)));
+        }
+        if let Some(trigrams) = self.desc_trigrams.get() {
+            usage.push(("description trigrams", trigrams_size(trigrams)));
+        }
+        usage
+    }
+
+    // Returns the positions of the pages whose description may contain
+    // `text`, in database order, or `None` when the word index has not been
+    // built or `text` has no words to look up. A description that contains
+    // the text has a word containing each of its words, so only the words of
+    // the index are compared rather than every description.
+    pub fn pages_with_words(&self, text: &str) -> Option<Vec<usize>> {
+        let index = self.words.get()?;
+        let mut found: Option<Vec<usize>> = None;
+        for word in words(text) {
+            let mut idxs = index
+                .iter()
+                .filter(|(indexed, _)| indexed.contains(word.as_str()))
+                .flat_map(|(_, idxs)| idxs.iter().copied())
+                .collect::<Vec<usize>>();
+            idxs.sort_unstable();
+            idxs.dedup();
+
+            // Every word must be found in the same description.
+            found = Some(match found {
+                Some(prev) => {
+                    prev.into_iter().filter(|idx| idxs.binary_search(idx).is_ok()).collect()
+                },
+                None => idxs,
+            });
+        }
+        found
+    }
+
+    // Returns the positions of the pages whose `field` may contain one of
+    // `texts`, ignoring ASCII case, in database order, or `None` when the
+    // field is not indexed or a text is too short to look up.
+    pub fn pages_containing(
+        &self,
+        pages: &[Page<'_>],
+        field: Field,
+        texts: &[String]
+    ) -> Option<Vec<usize>> {
+        if texts.iter().any(|text| text.len() < 3) {
+            return None;
+        }
+        let trigrams = match field {
+            Field::Name => self.name_trigrams.get_or_init(|| {
+                Trigrams::build(pages, |page| page.names.iter().map(|n| n.value).collect())
+            }),
+            Field::Desc => self.desc_trigrams.get_or_init(|| {
+                Trigrams::build(pages, |page| vec![page.desc])
+            }),
+            _ => return None,
+        };
+
+        let mut found = Vec::new();
+        for text in texts {
+            found.extend(trigrams.pages_containing(text)?);
+        }
+        found.sort_unstable();
+        found.dedup();
+        Some(found)
+    }
+}
+
+// Splits text into lowercase words the way descriptions are indexed.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+fn word_index(pages: &[Page<'_>]) -> HashMap<String, Vec<usize>> {
+    let start = Instant::now();
+    let mut index = HashMap::<String, Vec<usize>>::new();
+    for (idx, page) in pages.iter().enumerate() {
+        for word in words(page.desc) {
+            let positions = index.entry(word).or_default();
+            if positions.last() != Some(&idx) {
+                positions.push(idx);
+            }
+        }
+    }
+    debug!("Indexed {} description words in {:?}", index.len(), start.elapsed());
+    index
+}
+
+fn name_index(pages: &[Page<'_>]) -> HashMap<String, Vec<usize>> {
+    let start = Instant::now();
+    let mut index = HashMap::<String, Vec<usize>>::new();
+    for (idx, page) in pages.iter().enumerate() {
+        for name in &page.names {
+            let positions = index.entry(name.value.to_ascii_lowercase()).or_default();
+            // A page may have the same name in different cases, e.g. "LS".
+            if positions.last() != Some(&idx) {
+                positions.push(idx);
+            }
+        }
+    }
+    debug!("Indexed {} names in {:?}", index.len(), start.elapsed());
+    index
+}
+
+// The pages with each sequence of three bytes in their lowercase names or
+// descriptions. A page that contains a text has every trigram of the text, so
+// the pages that have all of them are the only ones that need comparing.
+#[derive(Clone, Debug)]
+struct Trigrams(HashMap<[u8; 3], Vec<usize>>);
+
+impl Trigrams {
+    fn build<'a>(pages: &[Page<'a>], texts: impl Fn(&Page<'a>) -> Vec<&'a str>) -> Self {
+        let start = Instant::now();
+        let mut index = HashMap::<[u8; 3], Vec<usize>>::new();
+        for (idx, page) in pages.iter().enumerate() {
+            for text in texts(page) {
+                for trigram in trigrams(text) {
+                    let positions = index.entry(trigram).or_default();
+                    if positions.last() != Some(&idx) {
+                        positions.push(idx);
+                    }
+                }
+            }
+        }
+        debug!("Indexed {} trigrams in {:?}", index.len(), start.elapsed());
+        Self(index)
+    }
+
+    // Returns `None` for a text shorter than a trigram, which every page
+    // may contain.
+    fn pages_containing(&self, text: &str) -> Option<Vec<usize>> {
+        let mut found: Option<Vec<usize>> = None;
+        for trigram in trigrams(text) {
+            let Some(idxs) = self.0.get(&trigram) else {
+                return Some(Vec::new());
+            };
+            found = Some(match found {
+                Some(prev) => {
+                    prev.into_iter().filter(|idx| idxs.binary_search(idx).is_ok()).collect()
+                },
+                None => idxs.clone(),
+            });
+        }
+        found
+    }
+}
+
+fn trigrams(text: &str) -> impl Iterator<Item = [u8; 3]> + '_ {
+    text.as_bytes()
+        .windows(3)
+        .map(|w| [w[0].to_ascii_lowercase(), w[1].to_ascii_lowercase(), w[2].to_ascii_lowercase()])
+}
+
+// The lowercase page names paired with the positions of their pages, sorted
+// by name and then position. The names equal to, or starting with, a text
+// are next to each other from the first name not less than it.
+#[derive(Clone, Debug)]
+struct SortedNames(Vec<(String, usize)>);
+
+impl SortedNames {
+    fn build(pages: &[Page<'_>]) -> Self {
+        let start = Instant::now();
+        let mut names = pages
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, page)| {
+                page.names.iter().map(move |name| (name.value.to_ascii_lowercase(), idx))
+            })
+            .collect::<Vec<(String, usize)>>();
+        names.sort_unstable();
+        // A page may have the same name in different cases, e.g. "LS".
+        names.dedup();
+        debug!("Sorted {} names in {:?}", names.len(), start.elapsed());
+        Self(names)
+    }
+
+    // Returns the positions of the pages with the names from the first one
+    // not less than `start` for as long as `matches` holds, in database
+    // order.
+    fn pages_with(&self, start: &str, matches: impl Fn(&str) -> bool) -> Vec<usize> {
+        let first = self.0.partition_point(|(name, _)| name.as_str() < start);
+        let mut found = self.0[first..]
+            .iter()
+            .take_while(|(name, _)| matches(name))
+            .map(|(_, idx)| *idx)
+            .collect::<Vec<usize>>();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+// A Bloom filter of the lowercase page names. A name that was added always
+// sets the bits it hashes to, so a name with any of its bits clear is in no
+// page, while one with all of them set is in a page about 1% of the time
+// that it was never added.
+#[derive(Clone, Debug)]
+struct NameFilter {
+    bits: Vec<u64>,
+}
+
+impl NameFilter {
+    // About 1% false positives takes ten bits and seven hashes per name.
+    const BITS_PER_NAME: usize = 10;
+    const HASHES: u64 = 7;
+
+    fn build(pages: &[Page<'_>]) -> Self {
+        let start = Instant::now();
+        let count = pages.iter().map(|page| page.names.len()).sum::<usize>();
+        let words = (count * Self::BITS_PER_NAME).div_ceil(64).max(1);
+        let mut filter = Self { bits: vec![0; words] };
+        for name in pages.iter().flat_map(|page| &page.names) {
+            for bit in filter.bits_of(name.value) {
+                filter.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        debug!("Built a filter of {count} names in {:?}", start.elapsed());
+        filter
+    }
+
+    fn may_contain(&self, name: &str) -> bool {
+        self.bits_of(name).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // Returns the bits of the lowercase `name`, each derived from the two
+    // halves of its FNV-1a hash.
+    fn bits_of(&self, name: &str) -> impl Iterator<Item = usize> {
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte.to_ascii_lowercase())).wrapping_mul(0x0100_0000_01b3)
+        });
+        let (low, high) = (hash & 0xffff_ffff, hash >> 32);
+        let len = self.bits.len() as u64 * 64;
+        (0..Self::HASHES).map(move |i| {
+            usize::try_from(low.wrapping_add(i.wrapping_mul(high)) % len).unwrap_or(0)
+        })
+    }
+}
+
+// A trie over the lowercase page names, a byte per level. Finding the names
+// with a prefix takes a step per byte of the prefix and then visits only the
+// names below it.
+#[derive(Clone, Debug)]
+struct Trie {
+    // The root is the first node.
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct TrieNode {
+    // The next byte of a name and the node it leads to, sorted by byte.
+    children: Vec<(u8, usize)>,
+    // The positions of the pages with a name that ends here.
+    pages: Vec<usize>,
+}
+
+impl Trie {
+    fn build(pages: &[Page<'_>]) -> Self {
+        let start = Instant::now();
+        let mut trie = Self { nodes: vec![TrieNode::default()] };
+        for (idx, page) in pages.iter().enumerate() {
+            for name in &page.names {
+                trie.insert(&name.value.to_ascii_lowercase(), idx);
+            }
+        }
+        debug!("Built a trie of {} nodes in {:?}", trie.nodes.len(), start.elapsed());
+        trie
+    }
+
+    fn insert(&mut self, name: &str, page: usize) {
+        let mut node = 0;
+        for byte in name.bytes() {
+            node = match self.nodes[node].children.binary_search_by_key(&byte, |(b, _)| *b) {
+                Ok(pos) => self.nodes[node].children[pos].1,
+                Err(pos) => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TrieNode::default());
+                    self.nodes[node].children.insert(pos, (byte, child));
+                    child
+                },
+            };
+        }
+
+        let pages = &mut self.nodes[node].pages;
+        if pages.last() != Some(&page) {
+            pages.push(page);
+        }
+    }
+
+    fn pages_with_prefix(&self, prefix: &str) -> Vec<usize> {
+        let mut node = 0;
+        for byte in prefix.bytes() {
+            let children = &self.nodes[node].children;
+            match children.binary_search_by_key(&byte, |(b, _)| *b) {
+                Ok(pos) => node = children[pos].1,
+                Err(_) => return Vec::new(),
+            }
+        }
+
+        let mut found = Vec::new();
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            found.extend_from_slice(&self.nodes[node].pages);
+            stack.extend(self.nodes[node].children.iter().map(|(_, child)| *child));
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set by the SIGINT handler and cleared once the interrupted work has
+// stopped.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// Makes Ctrl-C interrupt the current search instead of ending the process.
+// Long running work checks `is_requested` and stops early.
+pub fn catch() {
+    install_handler();
+}
+
+// Whether Ctrl-C was pressed since the last call to `take`.
+pub fn is_requested() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+// Returns whether Ctrl-C was pressed and resets the flag.
+pub fn take() -> bool {
+    INTERRUPTED.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+#[cfg(unix)]
+fn install_handler() {
+    // SAFETY: the handler only stores to an atomic, which is
+    // async-signal-safe.
+    unsafe {
+        libc::signal(libc::SIGINT, on_sigint as *const () as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+const fn install_handler() {}
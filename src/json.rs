@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::output::json_string;
+
+// How deeply arrays and objects may nest, so that a hostile request cannot
+// exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+// A JSON value, as read from the requests of `--serve-stdio`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<JsonValue>),
+    // The members of an object, in the order they were written.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    // Returns the member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Object(members) => {
+                members.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    // Returns a number that is a whole, non-negative count.
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Self::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX.into() => {
+                Some(*n as usize)
+            },
+            _ => None,
+        }
+    }
+}
+
+// Writes the value back as compact JSON.
+impl Display for JsonValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Str(s) => f.write_str(&json_string(s)),
+            Self::Array(items) => {
+                f.write_str("[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            },
+            Self::Object(members) => {
+                f.write_str("{")?;
+                for (idx, (name, value)) in members.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}:{value}", json_string(name))?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+// Parses a JSON document, such as one line of requests.
+pub fn parse(text: &str) -> Result<JsonValue, Box<dyn Error>> {
+    let (value, rest) = parse_value(text.trim_start(), 0)?;
+    if !rest.trim().is_empty() {
+        return Err("Unexpected text after the JSON value.".into());
+    }
+    Ok(value)
+}
+
+// Parses one value from the start of `s`, returning it and the unparsed rest.
+fn parse_value(s: &str, depth: usize) -> Result<(JsonValue, &str), Box<dyn Error>> {
+    if depth > MAX_DEPTH {
+        return Err("The JSON value is nested too deeply.".into());
+    }
+
+    if let Some(rest) = s.strip_prefix('"') {
+        let (value, rest) = parse_string(rest)?;
+        return Ok((JsonValue::Str(value), rest));
+    }
+
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut items = Vec::new();
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(']') {
+            return Ok((JsonValue::Array(items), after));
+        }
+
+        loop {
+            let (item, after) = parse_value(rest, depth + 1)?;
+            items.push(item);
+            rest = after.trim_start();
+
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((JsonValue::Array(items), after));
+            }
+            rest = rest
+                .strip_prefix(',')
+                .ok_or("Expected \",\" or \"]\" in array.")?
+                .trim_start();
+        }
+    }
+
+    if let Some(mut rest) = s.strip_prefix('{') {
+        let mut members = Vec::new();
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((JsonValue::Object(members), after));
+        }
+
+        loop {
+            let after = rest.strip_prefix('"').ok_or("Expected a member name in object.")?;
+            let (name, after) = parse_string(after)?;
+            let after = after
+                .trim_start()
+                .strip_prefix(':')
+                .ok_or("Expected \":\" after a member name.")?;
+            let (value, after) = parse_value(after.trim_start(), depth + 1)?;
+            members.push((name, value));
+            rest = after.trim_start();
+
+            if let Some(after) = rest.strip_prefix('}') {
+                return Ok((JsonValue::Object(members), after));
+            }
+            rest = rest
+                .strip_prefix(',')
+                .ok_or("Expected \",\" or \"}\" in object.")?
+                .trim_start();
+        }
+    }
+
+    let end = s
+        .find(|c: char| matches!(c, ',' | ']' | '}') || c.is_whitespace())
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+
+    let value = match word {
+        "null" => JsonValue::Null,
+        "true" => JsonValue::Bool(true),
+        "false" => JsonValue::Bool(false),
+        // Rust also reads forms that JSON does not allow, like "inf".
+        _ if word.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => {
+            JsonValue::Number(word
+                .parse()
+                .ok()
+                .filter(|n: &f64| n.is_finite())
+                .ok_or_else(|| format!("Invalid number \"{word}\"."))?)
+        },
+        "" => return Err("Expected a JSON value.".into()),
+        _ => return Err(format!("Invalid value \"{word}\".").into()),
+    };
+
+    Ok((value, rest))
+}
+
+// Parses the rest of a string after its opening quote, returning it and the
+// text after its closing quote.
+fn parse_string(s: &str) -> Result<(String, &str), Box<dyn Error>> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &s[idx + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some('/') => value.push('/'),
+                Some('b') => value.push('\u{8}'),
+                Some('f') => value.push('\u{c}'),
+                Some('n') => value.push('\n'),
+                Some('r') => value.push('\r'),
+                Some('t') => value.push('\t'),
+                Some('u') => {
+                    let mut code = hex4(&mut chars)?;
+                    // Characters outside the Basic Multilingual Plane are
+                    // written as a pair of surrogates.
+                    if (0xd800..0xdc00).contains(&code) {
+                        let low = match (chars.next(), chars.next()) {
+                            (Some((_, '\\')), Some((_, 'u'))) => hex4(&mut chars)?,
+                            _ => return Err("Unpaired surrogate in string.".into()),
+                        };
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return Err("Unpaired surrogate in string.".into());
+                        }
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+                    value.push(char::from_u32(code).ok_or("Unpaired surrogate in string.")?);
+                },
+                _ => return Err("Invalid escape sequence.".into()),
+            },
+            c if u32::from(c) < 0x20 => return Err("Control character in string.".into()),
+            c => value.push(c),
+        }
+    }
+
+    Err("Unterminated string.".into())
+}
+
+// Reads the four hex digits of a `\u` escape.
+fn hex4(chars: &mut std::str::CharIndices<'_>) -> Result<u32, Box<dyn Error>> {
+    (0..4).try_fold(0, |code, _| {
+        chars
+            .next()
+            .and_then(|(_, c)| c.to_digit(16))
+            .map(|digit| code * 16 + digit)
+            .ok_or_else(|| "Invalid \\u escape.".into())
+    })
+}
@@ -0,0 +1,60 @@
+// A library for querying the man page entries in mandoc.db database files.
+pub mod app;
+pub mod bench;
+pub mod build;
+pub mod cache;
+pub mod check;
+pub mod cli;
+pub mod color;
+pub mod completions;
+pub mod config;
+pub mod convert;
+// The query daemon and its clients, which talk over a Unix socket.
+#[cfg(unix)]
+pub mod daemon;
+pub mod database;
+pub mod diff;
+// Docsets are indexed with SQLite.
+#[cfg(feature = "sqlite")]
+pub mod docset;
+pub mod edit;
+pub mod error;
+pub mod export;
+pub mod fetch;
+pub mod httpd;
+pub mod index;
+pub mod interrupt;
+pub mod json;
+pub mod logger;
+pub mod macros;
+pub mod manconf;
+pub mod mandb;
+pub mod memory;
+pub mod merge;
+pub mod open;
+pub mod output;
+pub mod pager;
+pub mod pages;
+// Previews of page sources, rendered without mandoc(1).
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod progress;
+pub mod querylog;
+pub mod repair;
+pub mod repl;
+pub mod search;
+pub mod sections;
+pub mod serve;
+pub mod source;
+pub mod space;
+pub mod stream;
+pub mod swap;
+pub mod template;
+pub mod term;
+// Builds mandoc.db files in memory for tests.
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod tui;
+pub mod utils;
+pub mod validate;
+pub mod whatisdb;
@@ -0,0 +1,46 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// A minimal `log` backend that writes every enabled record to stderr so that
+// diagnostics never get mixed into the search output on stdout.
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let level = match record.level() {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+
+        eprintln!("[{level} {}] {}", record.target(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+// Installs the stderr logger. Verbosity 0 only shows warnings and errors,
+// `-v` adds parse steps and search timings, and `-vv` adds every offset that
+// is visited while parsing.
+pub fn init(verbosity: u8) {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
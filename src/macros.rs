@@ -1,8 +1,20 @@
-use std::error::Error;
-use std::str;
+use std::sync::OnceLock;
 
-use crate::pages::Name;
-use crate::utils::parse_num;
+use log::{debug, trace};
+
+use crate::database::ParseOptions;
+use crate::error::{DbError, ErrorKind};
+use crate::memory::vec_size;
+use crate::progress::Progress;
+use crate::utils::{parse_num, parse_str};
+
+// The macro whose arguments each MACRO TABLE holds, in order, named as in
+// apropos(1) search keys.
+pub const KEYS: [&str; 36] = [
+    "Xr", "Ar", "Fa", "Fl", "Dv", "Fn", "Ic", "Pa", "Cm", "Li", "Em", "Cd",
+    "Va", "Ft", "Tn", "Er", "Ev", "Sy", "Sh", "In", "Ss", "Ox", "An", "Mt",
+    "St", "Bx", "At", "Nx", "Fx", "Lk", "Ms", "Bsx", "Dx", "Rs", "Vt", "Lb",
+];
 
 // The MACROS TABLE consists of (in order):
 // 1. The total number of MACRO TABLEs (currently 36).
@@ -14,9 +26,35 @@ pub struct Macros<'a> {
 }
 
 impl<'a> Macros<'a> {
-    pub fn parse(bytes: &'a [u8], start: usize) -> Result<Self, Box<dyn Error>> {
+    pub fn parse(bytes: &'a [u8], start: usize) -> Result<Self, DbError> {
+        Self::parse_with_progress(bytes, start, &Progress::hidden())
+    }
+
+    pub fn parse_with_progress(
+        bytes: &'a [u8],
+        start: usize,
+        progress: &Progress
+    ) -> Result<Self, DbError> {
+        Self::parse_with_options(bytes, start, &ParseOptions::default(), progress)
+    }
+
+    pub fn parse_with_options(
+        bytes: &'a [u8],
+        start: usize,
+        options: &ParseOptions,
+        progress: &Progress
+    ) -> Result<Self, DbError> {
+        // Macro values refer to pages by the offset of their record, so the
+        // number of page records decides which offsets are valid.
+        let page_count = parse_num(bytes, 16)?;
+
         // Number of macro entries.
         let count = parse_num(bytes, start)?;
+        debug!("Macros table contains {count} tables");
+        if count != KEYS.len() {
+            return Err(DbError::at(ErrorKind::Count, start,
+                format!("Expected 36 macro tables but found {count}.")));
+        }
         let mut tables = Vec::with_capacity(count);
 
         let macro_keys_start = start + 4;
@@ -24,17 +62,88 @@ impl<'a> Macros<'a> {
         // Iterate over macro entries.
         for i in 0..count {
             let macro_table_idx = parse_num(bytes, macro_keys_start + (i * 4))?;
-            let macro_table = Table::parse(bytes, macro_table_idx)?;
+            trace!("Macro table {i} at offset {macro_table_idx}");
+            let macro_table = Table::parse(bytes, macro_table_idx, page_count, options)?;
             tables.push(macro_table);
-        }
-
-        // Ensure the expected number of macros are present.
-        if count != 36 || tables.len() != 36 {
-            return Err("Macros parsing failed.".into());
+            progress.update("Parsing macros", i + 1, count);
         }
 
         Ok(Self { count, tables })
     }
+
+    // Returns the bytes of heap memory used by the tables and the pages of
+    // their values, not counting the strings, which are borrowed.
+    pub fn heap_size(&self) -> usize {
+        let tables = self.tables.iter().map(|table| vec_size(&table.values)).sum::<usize>();
+        let values = self.tables
+            .iter()
+            .flat_map(|table| &table.values)
+            .map(|value| vec_size(&value.pages))
+            .sum::<usize>();
+        vec_size(&self.tables) + tables + values
+    }
+}
+
+// The macros of a database, parsed from its bytes the first time a search by
+// macro key, a dump, or an export needs them. Searches by name never do, and
+// the macro tables take about as long to parse and as much memory as the
+// pages, so a database that is only searched by name never parses them.
+#[derive(Clone, Debug)]
+pub struct LazyMacros<'a> {
+    // The number of macro tables, read from the header of the table.
+    pub count: usize,
+    // The bytes of the database and the offset of the macros table.
+    source: (&'a [u8], usize),
+    options: ParseOptions,
+    parsed: OnceLock<Result<Macros<'a>, DbError>>,
+}
+
+impl<'a> LazyMacros<'a> {
+    pub fn new(bytes: &'a [u8], start: usize) -> Result<Self, DbError> {
+        Self::with_options(bytes, start, ParseOptions::default())
+    }
+
+    // Like `new`, parsing the macros within the limits of `options` once
+    // they are needed.
+    pub fn with_options(
+        bytes: &'a [u8],
+        start: usize,
+        options: ParseOptions
+    ) -> Result<Self, DbError> {
+        let count = parse_num(bytes, start)?;
+        Ok(Self { count, source: (bytes, start), options, parsed: OnceLock::new() })
+    }
+
+    // Returns the bytes of heap memory used by the macros, or zero if they
+    // have not been parsed.
+    pub fn heap_size(&self) -> usize {
+        self.parsed.get().and_then(|parsed| parsed.as_ref().ok()).map_or(0, Macros::heap_size)
+    }
+
+    // Returns the macros, parsing them if they have not been parsed. A
+    // damaged macros table fails every time it is needed.
+    pub fn get(&self) -> Result<&Macros<'a>, DbError> {
+        self.parsed
+            .get_or_init(|| {
+                let (bytes, start) = self.source;
+                debug!("Parsing the macros table at offset {start}");
+                Macros::parse_with_options(bytes, start, &self.options, &Progress::hidden())
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+}
+
+// Macros that were built rather than read are already parsed.
+impl<'a> From<Macros<'a>> for LazyMacros<'a> {
+    fn from(macros: Macros<'a>) -> Self {
+        Self {
+            count: macros.count,
+            source: (&[], 0),
+            options: ParseOptions::default(),
+            parsed: OnceLock::from(Ok(macros)),
+        }
+    }
 }
 
 // Each MACRO TABLE consists of (in order):
@@ -47,27 +156,39 @@ pub struct Table<'a> {
 }
 
 impl<'a> Table<'a> {
-    fn parse(bytes: &'a [u8], start: usize) -> Result<Self, Box<dyn Error>> {
+    fn parse(
+        bytes: &'a [u8],
+        start: usize,
+        page_count: usize,
+        options: &ParseOptions
+    ) -> Result<Self, DbError> {
         // Number of macro value entries.
         let count = parse_num(bytes, start)?;
+        trace!("Macro table at offset {start} contains {count} values");
         if count == 0 {
             return Ok(Self { count, values: Vec::new() });
         }
 
+        // Room is only made for as many records as the file holds.
         let values_start = start + 4;
+        if values_start + count * 8 > bytes.len() {
+            return Err(DbError::at(ErrorKind::OutOfBounds, start,
+                format!("The {count} macro value records are past the end of the file.")));
+        }
         let mut values = Vec::with_capacity(count);
 
         // Iterate over macro value entries.
         for i in 0..count {
             let value_idx = values_start + (i * 8);
             let pages_list_idx = value_idx + 4;
-            let value = Value::parse(bytes, value_idx, pages_list_idx)?;
+            let value = Value::parse(bytes, value_idx, pages_list_idx, page_count, options)?;
             values.push(value);
         }
 
         // Ensure the expected number of values are present.
         if values.len() != count {
-            return Err("Macro values parsing failed.".into());
+            return Err(DbError::at(ErrorKind::Count, start,
+                "Macro values parsing failed."));
         }
 
         Ok(Self { count, values })
@@ -80,43 +201,61 @@ impl<'a> Table<'a> {
 // 3. The macro string value.
 // 4. Zero to three NUL bytes for padding.
 // 5. A list of index values for the list of names for the pages in the list
-//    pointed to by #2 of this table.
+//    pointed to by #2 of this table. Each index is the offset of one of the
+//    20-byte records in the PAGES TABLE, whose first field is the index of
+//    the page's names list.
 #[derive(Clone, Debug)]
 pub struct Value<'a> {
     pub str: &'a str,
-    pub page_names: Vec<Vec<Name<'a>>>,
+    // The positions in the pages table of the pages that use the value.
+    pub pages: Vec<usize>,
 }
 
 impl<'a> Value<'a> {
     fn parse(
         bytes: &'a [u8],
         value_idx: usize,
-        pages_list_idx: usize
-    ) -> Result<Self, Box<dyn Error>> {
+        pages_list_idx: usize,
+        page_count: usize,
+        options: &ParseOptions
+    ) -> Result<Self, DbError> {
         let str_idx = parse_num(bytes, value_idx)?;
-        let str = bytes[str_idx..]
-            .split(|b| *b == 0)
-            .next()
-            .and_then(|str_bytes| str::from_utf8(str_bytes).ok())
-            .ok_or("Macro value parsing failed.")?;
+        let str = parse_str(bytes, str_idx)?;
+        options.check_str_len(str.len(), str_idx)?;
 
-        let mut page_names = Vec::with_capacity(20);
+        let mut pages = Vec::new();
         let pages_list = parse_num(bytes, pages_list_idx)?;
 
-        // Iterate over each page in the pages list.
-        for p in 0..=20 {
-            let page_idx = parse_num(bytes, pages_list + (p * 4))?;
+        // Iterate over each page in the pages list, however many pages share
+        // the value, up to the longest list allowed.
+        for item_idx in (pages_list..).step_by(4) {
+            let page_idx = parse_num(bytes, item_idx)?;
 
             // Zero marks the end of the pages list.
             if page_idx == 0 {
                 break;
             }
 
-            let names_list = parse_num(bytes, page_idx)?;
-            let names_vec = Name::parse_names(bytes, names_list)?;
-            page_names.push(names_vec);
+            options.check_list_len(pages.len() + 1, pages_list)?;
+            if !is_page_record(page_idx, page_count) {
+                return Err(DbError::at(ErrorKind::Format, item_idx,
+                    format!("Page offset {page_idx} is not a page record.")));
+            }
+
+            pages.push(page_position(page_idx));
         }
 
-        Ok(Self { str, page_names })
+        Ok(Self { str, pages })
     }
 }
+
+// Whether `offset` is the start of one of the `page_count` page records,
+// which begin at offset 20 and are 20 bytes each.
+pub const fn is_page_record(offset: usize, page_count: usize) -> bool {
+    offset >= 20 && (offset - 20).is_multiple_of(20) && (offset - 20) / 20 < page_count
+}
+
+// The position in the pages table of the page record at `offset`.
+pub const fn page_position(offset: usize) -> usize {
+    (offset - 20) / 20
+}
@@ -0,0 +1,194 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::database::STDIN_PATH;
+use crate::fetch;
+
+// The name of the database file inside each manpath directory.
+pub const DB_FILE_NAME: &str = "mandoc.db";
+
+// The man.conf(5) file that apropos(1) reads when no manpath is given.
+pub const MAN_CONF_PATH: &str = "/etc/man.conf";
+
+// The manpath apropos(1) uses when man.conf(5) has no manpath directives.
+pub const DEFAULT_MANPATH: [&str; 3] = ["/usr/share/man", "/usr/X11R6/man", "/usr/local/man"];
+
+// Reads the `manpath` directives from a man.conf(5) file. Other directives
+// (e.g. `output`) are ignored.
+pub fn read_manpaths(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+
+    let manpaths = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (directive, value) = line.split_once(char::is_whitespace)?;
+            (directive == "manpath").then(|| value.trim().to_string())
+        })
+        .filter(|dir| !dir.is_empty())
+        .collect();
+
+    Ok(manpaths)
+}
+
+// Returns the manpath directories of /etc/man.conf, or the default manpath
+// when the file is missing or has no manpath directives, like apropos(1).
+// The MANPATH environment variable changes them as in `with_env_manpath`.
+pub fn system_manpath() -> Vec<String> {
+    let dirs = match read_manpaths(MAN_CONF_PATH) {
+        Ok(dirs) if !dirs.is_empty() => dirs,
+        _ => DEFAULT_MANPATH.iter().map(ToString::to_string).collect(),
+    };
+    with_env_manpath(dirs)
+}
+
+// Changes the `standard` manpath with the MANPATH environment variable, if it
+// is set, as described in `combine_manpath`.
+pub fn with_env_manpath(standard: Vec<String>) -> Vec<String> {
+    match env::var("MANPATH") {
+        Ok(manpath) if !manpath.is_empty() => combine_manpath(&manpath, &standard),
+        _ => standard,
+    }
+}
+
+// Combines a MANPATH value with the `standard` manpath like man(1): a
+// leading colon appends MANPATH to the standard manpath, a trailing colon
+// prepends it, and two adjacent colons insert the standard manpath between
+// them. Otherwise MANPATH replaces the standard manpath.
+pub fn combine_manpath(manpath: &str, standard: &[String]) -> Vec<String> {
+    let (before, after) = if let Some((before, after)) = manpath.split_once("::") {
+        (before, after)
+    } else if let Some(after) = manpath.strip_prefix(':') {
+        ("", after)
+    } else if let Some(before) = manpath.strip_suffix(':') {
+        (before, "")
+    } else {
+        return split_manpath(manpath).collect();
+    };
+
+    split_manpath(before)
+        .chain(standard.iter().cloned())
+        .chain(split_manpath(after))
+        .collect()
+}
+
+// Splits a colon-separated list of directories, skipping empty entries.
+pub fn split_manpath(manpath: &str) -> impl Iterator<Item = String> + '_ {
+    manpath
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(str::to_string)
+}
+
+// Returns the manpath directory containing the database at `db_path`, which
+// the page file names in the database are relative to. A database read from
+// standard input or downloaded has none.
+pub fn db_dir(db_path: &str) -> Option<&Path> {
+    if db_path == STDIN_PATH || fetch::is_url(db_path) {
+        return None;
+    }
+    Path::new(db_path)
+        .parent()
+        .map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+}
+
+// Returns how search results from the database at `db_path` are labeled when
+// several databases are searched: its manpath directory, e.g.
+// "/usr/X11R6/man", the path itself for a database in the current
+// directory, "stdin", or the URL up to the database file name. Translated
+// pages found in one of the `manpath` directories are labeled with that
+// directory and their locale, e.g. "/usr/share/man (de)".
+pub fn db_label(db_path: &str, manpath: &[String]) -> String {
+    if db_path == STDIN_PATH {
+        return "stdin".to_string();
+    }
+    if fetch::is_url(db_path) {
+        return db_path.strip_suffix(&format!("/{DB_FILE_NAME}")).unwrap_or(db_path).to_string();
+    }
+    let dir = db_dir(db_path).filter(|dir| *dir != Path::new("."));
+    let man_dir = dir.and_then(Path::parent).filter(|dir| !dir.as_os_str().is_empty());
+    if let (Some(lang), Some(man_dir)) = (db_lang(db_path, manpath), man_dir) {
+        return format!("{} ({lang})", man_dir.display());
+    }
+    dir.map_or_else(|| db_path.to_string(), |dir| dir.display().to_string())
+}
+
+// Returns the database files that exist within the given directories. With
+// a locale, the databases of its translated pages in each directory, e.g.
+// "/usr/share/man/de/mandoc.db", come before the untranslated ones they fall
+// back to.
+pub fn find_databases(dirs: &[String], lang: Option<&str>) -> Vec<PathBuf> {
+    let lang_dirs = lang.map(lang_dirs).unwrap_or_default();
+    dirs.iter()
+        .flat_map(|dir| {
+            lang_dirs
+                .iter()
+                .map(move |lang| PathBuf::from(dir).join(lang).join(DB_FILE_NAME))
+                .chain(std::iter::once(PathBuf::from(dir).join(DB_FILE_NAME)))
+        })
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+// Returns the locale whose translated pages are wanted: the first of the
+// LC_ALL, LC_MESSAGES, and LANG environment variables that is set, unless it
+// is the C or POSIX locale, which only has the untranslated pages.
+pub fn env_lang() -> Option<String> {
+    let lang = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))?;
+    (!matches!(lang.as_str(), "C" | "POSIX") && !lang.starts_with("C.")).then_some(lang)
+}
+
+// Returns the directories that may hold the translated pages of `lang`, most
+// specific first, like man(1): "de_DE.UTF-8@euro" may be in "de_DE.UTF-8",
+// "de_DE", or "de", after the modifier, then the codeset, then the territory
+// is dropped.
+pub fn lang_dirs(lang: &str) -> Vec<String> {
+    let mut dirs = vec![lang.to_string()];
+    let mut rest = lang;
+    for sep in ['@', '.', '_'] {
+        if let Some((shorter, _)) = rest.split_once(sep) {
+            rest = shorter;
+            dirs.push(rest.to_string());
+        }
+    }
+    dirs.dedup();
+    dirs.retain(|dir| !dir.is_empty());
+    dirs
+}
+
+// Returns the locale of the translated pages in the database at `db_path`,
+// named by its directory, e.g. "de" for "/usr/share/man/de/mandoc.db". Only a
+// database in a directory of one of the `manpath` directories searched by
+// `find_databases` holds translated pages, and only when its directory is a
+// two-letter language code, or a two- or three-letter one with a territory,
+// such as "pt_BR", optionally followed by a codeset and modifier.
+pub fn db_lang(db_path: &str, manpath: &[String]) -> Option<String> {
+    let dir = db_dir(db_path)?;
+    let man_dir = dir.parent()?;
+    if !manpath.iter().any(|searched| Path::new(searched) == man_dir) {
+        return None;
+    }
+
+    let name = dir.file_name()?.to_str()?;
+    let base = name.split(['.', '@']).next()?;
+    let (language, territory) = match base.split_once('_') {
+        Some((language, territory)) => (language, Some(territory)),
+        None => (base, None),
+    };
+
+    let is_language = language.bytes().all(|b| b.is_ascii_lowercase())
+        && match territory {
+            Some(territory) => {
+                (2..=3).contains(&language.len())
+                    && territory.len() == 2
+                    && territory.bytes().all(|b| b.is_ascii_uppercase())
+            },
+            None => language.len() == 2,
+        };
+    is_language.then(|| name.to_string())
+}
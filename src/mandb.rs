@@ -0,0 +1,279 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::str;
+
+use log::{debug, warn};
+
+use crate::edit::{OwnedDatabase, OwnedPage};
+use crate::error::{DbError, ErrorKind};
+use crate::pages::PageFormat;
+
+// Where man-db keeps the index of the pages in /usr/share/man.
+pub const INDEX_PATH: &str = "/var/cache/man/index.db";
+
+// The GDBM magic numbers for 32-bit and 64-bit file offsets, and the same
+// with the numsync header extension of GDBM 1.21 and later.
+const GDBM_MAGIC32: u32 = 0x1357_9acd;
+const GDBM_MAGIC64: u32 = 0x1357_9acf;
+const GDBM_NUMSYNC_MAGIC32: u32 = 0x1357_9ad0;
+const GDBM_NUMSYNC_MAGIC64: u32 = 0x1357_9ad1;
+
+// The number of free space records at the start of each GDBM bucket.
+const BUCKET_AVAIL: usize = 6;
+
+// The name sources of man-db entries: a page's own name is from its NAME
+// section, as are the other names in it, while a .so link is only a file.
+const SOURCE_NAME: u8 = 0b0000_0010;
+const SOURCE_FIRST_NAME: u8 = 0b0000_0100;
+const SOURCE_FILE: u8 = 0b0001_0000;
+
+// Returns whether `bytes` start with a GDBM magic number, in either byte
+// order, as man-db's index.db does on most Linux systems.
+pub fn is_index(bytes: &[u8]) -> bool {
+    Gdbm::new(bytes).is_ok()
+}
+
+// Returns the length of the file offsets for a GDBM magic number.
+const fn gdbm_offset_len(magic: u32) -> Option<usize> {
+    match magic {
+        GDBM_MAGIC32 | GDBM_NUMSYNC_MAGIC32 => Some(4),
+        GDBM_MAGIC64 | GDBM_NUMSYNC_MAGIC64 => Some(8),
+        _ => None,
+    }
+}
+
+// A key and its value.
+type Pair<'a> = (&'a [u8], &'a [u8]);
+
+// Reads a GDBM file, written in the byte order and offset length of the
+// machine that made it.
+struct Gdbm<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+    offset_len: usize,
+}
+
+impl<'a> Gdbm<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, DbError> {
+        let magic = bytes
+            .get(..4)
+            .map(|magic| [magic[0], magic[1], magic[2], magic[3]])
+            .ok_or_else(|| DbError::at(ErrorKind::Format, 0, "The file is too short for GDBM."))?;
+
+        if let Some(offset_len) = gdbm_offset_len(u32::from_le_bytes(magic)) {
+            return Ok(Self { bytes, big_endian: false, offset_len });
+        }
+        if let Some(offset_len) = gdbm_offset_len(u32::from_be_bytes(magic)) {
+            return Ok(Self { bytes, big_endian: true, offset_len });
+        }
+        Err(DbError::at(ErrorKind::Format, 0, "The file is not a GDBM database."))
+    }
+
+    fn get(&self, idx: usize, len: usize) -> Result<&'a [u8], DbError> {
+        idx.checked_add(len)
+            .and_then(|end| self.bytes.get(idx..end))
+            .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, idx, "GDBM data is out of bounds."))
+    }
+
+    fn int(&self, idx: usize) -> Result<i32, DbError> {
+        let bytes = self.get(idx, 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big_endian { i32::from_be_bytes(bytes) } else { i32::from_le_bytes(bytes) })
+    }
+
+    // Reads a non-negative int, such as a size or a count.
+    fn len(&self, idx: usize) -> Result<usize, DbError> {
+        usize::try_from(self.int(idx)?)
+            .map_err(|_| DbError::at(ErrorKind::Format, idx, "A GDBM size is negative."))
+    }
+
+    // Reads a file offset.
+    fn offset(&self, idx: usize) -> Result<usize, DbError> {
+        let bytes = self.get(idx, self.offset_len)?;
+        let mut value = 0_u64;
+        for idx in 0..bytes.len() {
+            let byte = if self.big_endian { bytes[idx] } else { bytes[bytes.len() - 1 - idx] };
+            value = value << 8 | u64::from(byte);
+        }
+        usize::try_from(value)
+            .map_err(|_| DbError::at(ErrorKind::OutOfBounds, idx, "A GDBM offset is too large."))
+    }
+
+    // Returns every key and value, bucket by bucket.
+    //
+    // The header gives the directory of buckets and the number of elements
+    // in each one. The offsets in the header and in the elements are aligned
+    // like the C structures, so they move with the offset length.
+    fn pairs(&self) -> Result<Vec<Pair<'a>>, DbError> {
+        let off = self.offset_len;
+        let dir = self.offset(8)?;
+        let dir_size = self.len(8 + off)?;
+        let bucket_elems = self.len(8 + off + 12)?;
+
+        // The directory lists a bucket once for each hash prefix it holds.
+        let mut buckets = Vec::new();
+        let mut seen = HashSet::new();
+        for idx in 0..dir_size / off {
+            let bucket = self.offset(dir + idx * off)?;
+            if seen.insert(bucket) {
+                buckets.push(bucket);
+            }
+        }
+
+        // Each free space record is an int and an offset.
+        let avail_start = off;
+        let avail_len = 2 * off;
+        let table_start = avail_start + BUCKET_AVAIL * avail_len + 8;
+        let elem_len = 16 + off;
+
+        let mut pairs = Vec::new();
+        for bucket in buckets {
+            for idx in 0..bucket_elems {
+                let elem = bucket + table_start + idx * elem_len;
+                // Unused elements have a hash value of -1.
+                if self.int(elem)? == -1 {
+                    continue;
+                }
+                let data = self.offset(elem + 8)?;
+                let key_size = self.len(elem + 8 + off)?;
+                let data_size = self.len(elem + 12 + off)?;
+                pairs.push((self.get(data, key_size)?, self.get(data + key_size, data_size)?));
+            }
+        }
+        Ok(pairs)
+    }
+}
+
+// One page as man-db records it: a name and section extension, with the
+// fields of its content.
+struct Entry<'a> {
+    name: &'a str,
+    ext: &'a str,
+    sec: &'a str,
+    // 'A' for a page, 'B' for a .so link, 'C' for a name in another page's
+    // NAME section, and 'D' and 'E' for the same two kinds of preformatted
+    // pages.
+    id: char,
+    // The page that a link or name belongs to, or "-".
+    pointer: &'a str,
+    // The compression extension of the file, or "-".
+    comp: &'a str,
+    whatis: &'a str,
+}
+
+impl<'a> Entry<'a> {
+    // Parses the tab-separated content of an entry:
+    // "name ext sec mtime [mtime_nsec] id pointer filter comp whatis", where
+    // the nanoseconds were added in man-db 2.8.
+    fn parse(key: &'a str, content: &'a str) -> Option<Self> {
+        let id_idx = if content.split('\t').nth(4)?.parse::<u64>().is_ok() { 5 } else { 4 };
+        // The description may hold tabs of its own.
+        let fields = content.splitn(id_idx + 5, '\t').collect::<Vec<&str>>();
+        let name = fields[0];
+
+        Some(Self {
+            // Old databases leave the name to the key.
+            name: if name == "-" { key.split('\t').next()? } else { name },
+            ext: fields.get(1)?,
+            sec: fields.get(2)?,
+            id: fields.get(id_idx)?.chars().next()?,
+            pointer: fields.get(id_idx + 1)?,
+            comp: fields.get(id_idx + 3)?,
+            whatis: fields.get(id_idx + 4).copied().unwrap_or(""),
+        })
+    }
+
+    // The file, relative to the manpath directory, e.g. "man1/ls.1.gz".
+    fn file(&self) -> String {
+        let dir = if self.id >= 'D' { "cat" } else { "man" };
+        match self.comp {
+            "-" | "" => format!("{dir}{}/{}.{}", self.sec, self.name, self.ext),
+            comp => format!("{dir}{}/{}.{}.{comp}", self.sec, self.name, self.ext),
+        }
+    }
+}
+
+// Reads man-db's index.db, a GDBM database with one entry per name, into
+// the pages of a mandoc.db.
+//
+// Each page of its own becomes a page with its section and description.
+// Links and the other names in a page's NAME section are added to the page
+// they point to, so they are found and printed along with it, as in a
+// mandoc.db. Since man-db does not keep the names of the files, they are
+// made from the name, section, extension, and compression of each page.
+pub fn read_index(bytes: &[u8]) -> Result<OwnedDatabase, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for (key, content) in Gdbm::new(bytes)?.pairs()? {
+        // Keys and contents end with a NUL byte.
+        let key = str::from_utf8(key.strip_suffix(&[0]).unwrap_or(key));
+        let content = str::from_utf8(content.strip_suffix(&[0]).unwrap_or(content));
+        let (Ok(key), Ok(content)) = (key, content) else {
+            skipped += 1;
+            continue;
+        };
+
+        // Keys like "$version$" hold details of the database, and a content
+        // starting with a tab lists the sections of a name that has several,
+        // each of which has an entry of its own.
+        if key.starts_with('$') || content.starts_with('\t') {
+            continue;
+        }
+        match Entry::parse(key, content) {
+            Some(entry) => entries.push(entry),
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        warn!("Skipped {skipped} man-db entries that could not be read");
+    }
+
+    // GDBM keeps entries in hash order.
+    entries.sort_by(|a, b| a.name.cmp(b.name).then_with(|| a.ext.cmp(b.ext)));
+
+    let mut db = OwnedDatabase::new();
+    let mut pages = HashMap::new();
+    for entry in entries.iter().filter(|entry| entry.pointer == "-") {
+        let mut page = OwnedPage::new(entry.name, entry.sec, entry.whatis, &entry.file());
+        page.names[0].1 = SOURCE_NAME | SOURCE_FIRST_NAME;
+        if entry.id >= 'D' {
+            page.format = PageFormat::Preformatted;
+        }
+        pages.insert((entry.name, entry.ext), db.add_page(page));
+    }
+
+    for entry in entries.iter().filter(|entry| entry.pointer != "-") {
+        let source = if entry.id == 'B' { SOURCE_FILE } else { SOURCE_NAME };
+        let target = pages.get(&(entry.pointer, entry.ext)).copied().or_else(|| {
+            db.find_page(entry.pointer, Some(entry.sec))
+        });
+        match target.and_then(|idx| db.page_mut(idx)) {
+            Some(page) => {
+                if !page.names.iter().any(|(name, _)| name == entry.name) {
+                    page.names.push((entry.name.to_string(), source));
+                }
+                if entry.id == 'B' {
+                    page.files.push(entry.file());
+                }
+            },
+            // A name whose page is missing is still found.
+            None => {
+                let mut page = OwnedPage::new(entry.name, entry.sec, entry.whatis,
+                    &entry.file());
+                page.names[0].1 = source;
+                db.add_page(page);
+            },
+        }
+    }
+    debug!("Read {} man-db entries into {} pages", entries.len(), db.pages().len());
+
+    Ok(db)
+}
+
+// Converts man-db's index.db into the bytes of a mandoc.db, so that it can
+// be parsed and searched like one.
+pub fn index_to_mandoc_db(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut converted = Vec::new();
+    read_index(bytes)?.write(&mut converted)?;
+    Ok(converted)
+}
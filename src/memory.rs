@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::mem::size_of;
+
+// The heap memory used by the parsed pages and macros of a database and by
+// each of its indexes that has been built, in bytes. The strings of the pages
+// and macros are borrowed from the database's bytes, so only the tables and
+// lists that refer to them are counted, not the file itself.
+//
+// Hash maps are estimated from their capacity, since their layout is not
+// public, so the sizes are close rather than exact.
+#[derive(Clone, Debug, Default)]
+pub struct HeapUsage {
+    pub pages: usize,
+    // Zero until the macros are parsed.
+    pub macros: usize,
+    // The name and size of each index that has been built.
+    pub indexes: Vec<(&'static str, usize)>,
+}
+
+impl HeapUsage {
+    pub fn total(&self) -> usize {
+        self.pages + self.macros + self.indexes.iter().map(|(_, bytes)| bytes).sum::<usize>()
+    }
+}
+
+// Returns the bytes allocated for the items of `vec`, used or not.
+pub fn vec_size<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+// Returns an estimate of the bytes allocated for the entries of `map`: an
+// entry and a control byte for each one it has room for.
+pub fn map_size<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+// Writes how many bytes of heap memory the pages, the macros, and each index
+// use, with their share of the total.
+pub fn write_usage(out: &mut dyn Write, usage: &HeapUsage) -> Result<(), Box<dyn Error>> {
+    let mut parts = vec![("pages", usage.pages), ("macros", usage.macros)];
+    parts.extend(usage.indexes.iter().copied());
+
+    let total = usage.total();
+    let name_width = parts.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, bytes) in &parts {
+        // Tenths of a percent.
+        let share = bytes * 1000 / total.max(1);
+        writeln!(out, "{name:name_width$}  {bytes:>10}  {:>3}.{}%", share / 10, share % 10)?;
+    }
+    writeln!(out, "{:name_width$}  {total:>10}  100.0%", "total")?;
+
+    Ok(())
+}
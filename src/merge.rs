@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::{self, Path};
+
+use log::{debug, info};
+
+use crate::database::{self, Database, DbBytes, Version};
+use crate::error::DbError;
+use crate::index::Indexes;
+use crate::macros::{self, Macros, Table, Value};
+use crate::manconf;
+use crate::pages::{Page, Pages};
+use crate::utils::{self, StrList};
+
+// Merges the databases at `db_paths` into a new database at `output`.
+//
+// Pages keep the order of the databases and of their tables. A page with the
+// same names, sections, and architectures as an earlier page is a duplicate
+// and is left out, so the first database given wins, as in a manpath. Macro
+// values are the union of those of every database, with their pages.
+//
+// Page files are relative to the directory of their database, so they are
+// made relative to the directory of `output` instead, or absolute when they
+// are not within it.
+pub fn merge(db_paths: &[String], output: &str) -> Result<(), Box<dyn Error>> {
+    let files = db_paths
+        .iter()
+        .map(|path| database::read_db(path))
+        .collect::<Result<Vec<DbBytes>, DbError>>()?;
+    let dbs = files
+        .iter()
+        .zip(db_paths)
+        .map(|(bytes, path)| Database::parse(bytes).map_err(|e| format!("{path}: {e}")))
+        .collect::<Result<Vec<Database<'_>>, String>>()?;
+
+    let out_dir = path::absolute(manconf::db_dir(output).unwrap_or(Path::new(".")))?;
+
+    // The pages that are kept, as the index of their database and page.
+    let mut kept = Vec::new();
+    // The index in `kept` of each page, by its database and its position in
+    // that database's pages table. The values of duplicates go to the page
+    // that is kept instead.
+    let mut indexes = HashMap::new();
+    let mut seen = HashMap::new();
+    for (db_idx, db) in dbs.iter().enumerate() {
+        for (page_idx, page) in db.pages.table.iter().enumerate() {
+            let names = page.names.iter().map(|name| name.value).collect::<Vec<&str>>();
+            let sects = page.sects.to_vec();
+            let archs = page.archs.as_ref().map(StrList::to_vec);
+            let idx = *seen.entry((names, sects, archs)).or_insert_with(|| {
+                kept.push((db_idx, page_idx));
+                kept.len() - 1
+            });
+            if kept[idx] != (db_idx, page_idx) {
+                info!("Leaving out {} from {}, which duplicates an earlier page",
+                    page.whatis(), db_paths[db_idx]);
+            }
+            indexes.insert((db_idx, page_idx), idx);
+        }
+    }
+
+    // The files of the kept pages, relative to the new database.
+    let mut page_files = Vec::with_capacity(kept.len());
+    for &(db_idx, page_idx) in &kept {
+        let db_dir = manconf::db_dir(&db_paths[db_idx]).unwrap_or(Path::new("."));
+        let db_dir = path::absolute(db_dir)?;
+        let files = dbs[db_idx].pages.table[page_idx]
+            .files
+            .iter()
+            .map(|file| {
+                let file = db_dir.join(file);
+                file.strip_prefix(&out_dir)
+                    .unwrap_or(&file)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<String>>();
+        page_files.push(files);
+    }
+
+    let table = kept
+        .iter()
+        .zip(&page_files)
+        .map(|(&(db_idx, page_idx), files)| Page {
+            files: files.iter().map(String::as_str).collect(),
+            ..dbs[db_idx].pages.table[page_idx].clone()
+        })
+        .collect::<Vec<Page<'_>>>();
+
+    let mut values = vec![BTreeMap::new(); macros::KEYS.len()];
+    for (db_idx, db) in dbs.iter().enumerate() {
+        for (key, table) in db.macros.get()?.tables.iter().enumerate().take(values.len()) {
+            for value in &table.values {
+                let value_pages = values[key].entry(value.str).or_insert_with(Vec::new);
+                let idxs = value.pages.iter().filter_map(|&page| indexes.get(&(db_idx, page)));
+                for &idx in idxs {
+                    if !value_pages.contains(&idx) {
+                        value_pages.push(idx);
+                    }
+                }
+            }
+        }
+    }
+
+    let tables = values
+        .into_iter()
+        .map(|values| {
+            let values = values
+                .into_iter()
+                .filter(|(_, idxs)| !idxs.is_empty())
+                .map(|(str, idxs)| Value { str, pages: idxs })
+                .collect::<Vec<Value<'_>>>();
+            Table { count: values.len(), values }
+        })
+        .collect::<Vec<Table<'_>>>();
+
+    let db = Database {
+        version: Version::V1,
+        pages: Pages { count: table.len(), table },
+        macros: Macros { count: tables.len(), tables }.into(),
+        indexes: Indexes::default(),
+    };
+
+    // The output may be one of the databases that were merged.
+    utils::replace_file(Path::new(output), |out| db.write(out))
+        .map_err(|e| format!("{output}: {e}"))?;
+
+    debug!("Merged {} databases into {} pages", dbs.len(), db.pages.count);
+    Ok(())
+}
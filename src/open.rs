@@ -0,0 +1,42 @@
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use log::debug;
+
+use crate::pages::Page;
+
+// Displays a page with man(1). The page's source file is opened directly
+// with `man -l` when it can be found next to the database, otherwise man(1)
+// looks the page up by section and name.
+pub fn open_page(page: &Page<'_>, db_dir: Option<&Path>) -> io::Result<()> {
+    let file = db_dir.and_then(|dir| {
+        page.files
+            .iter()
+            .map(|file| dir.join(file))
+            .find(|path| path.is_file())
+    });
+
+    let mut cmd = Command::new("man");
+
+    if let Some(file) = file {
+        cmd.arg("-l").arg(file);
+    } else {
+        let name = page.names
+            .first()
+            .ok_or_else(|| io::Error::other("The page has no names."))?;
+        if let Some(sect) = page.sects.first() {
+            cmd.arg(sect);
+        }
+        cmd.arg(name.value);
+    }
+
+    debug!("Running {cmd:?}");
+    let status = cmd.status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("man(1) exited with {status}.")))
+    }
+}
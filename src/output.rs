@@ -0,0 +1,359 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter, Result as FmtResult};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use log::warn;
+
+use crate::color::Painter;
+use crate::pages::{Page, PageField};
+use crate::template::Template;
+
+// How search results are displayed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputStyle {
+    // One labeled line per page field.
+    #[default]
+    Detailed,
+    // whatis(1) one-liners: "name, name(sect) - description".
+    Whatis,
+    // The same one-liners, with the results of all queries merged and
+    // sorted exactly as `man -k` and apropos(1) print them.
+    Terse,
+    // Only the first name of each page.
+    Name,
+    // Only the file paths of each page, one per line.
+    Path,
+    // Only the sections of each page.
+    Section,
+    // Only the one-line description of each page.
+    Desc,
+    // One JSON object per page, one per line.
+    Json,
+    // A Markdown table with one row per page (see `write_markdown_header`).
+    Markdown,
+    // An mdoc(7) list item per page, in a document that mandoc(1) can format
+    // (see `write_roff_header`).
+    Roff,
+    // A user supplied template (see `--format`).
+    Template(Template),
+}
+
+// Settings that control how search results are rendered.
+#[derive(Clone, Debug, Default)]
+pub struct OutputOptions {
+    pub style: OutputStyle,
+    // Whether to use ANSI colors in the detailed and one-line styles.
+    pub color: bool,
+    // The terminal width that the detailed style wraps long lines to.
+    pub width: Option<usize>,
+    // The fields the detailed style shows, or `None` for all of them.
+    pub fields: Option<Vec<PageField>>,
+    // The database the results come from, shown with each result when
+    // several databases are searched.
+    pub label: Option<String>,
+    // Whether page files are shown as absolute paths, resolved against
+    // `man_dir`, leaving out the ones that do not exist.
+    pub resolve_files: bool,
+    // The directory containing the results' database, which their files are
+    // relative to.
+    pub man_dir: Option<PathBuf>,
+}
+
+impl OutputOptions {
+    pub const fn painter(&self) -> Painter {
+        Painter::new(self.color)
+    }
+}
+
+impl OutputStyle {
+    // The values accepted by `-o`.
+    pub const FIELDS: &'static [&'static str] = &[
+        "name", "path", "section", "desc", "json",
+    ];
+}
+
+impl Display for OutputStyle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Detailed => f.write_str("detailed"),
+            Self::Whatis => f.write_str("whatis"),
+            Self::Terse => f.write_str("terse"),
+            Self::Name => f.write_str("name"),
+            Self::Path => f.write_str("path"),
+            Self::Section => f.write_str("section"),
+            Self::Desc => f.write_str("desc"),
+            Self::Json => f.write_str("json"),
+            Self::Markdown => f.write_str("markdown"),
+            Self::Roff => f.write_str("roff"),
+            Self::Template(_) => f.write_str("template"),
+        }
+    }
+}
+
+impl FromStr for OutputStyle {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "detailed" => Ok(Self::Detailed),
+            "whatis" => Ok(Self::Whatis),
+            "terse" => Ok(Self::Terse),
+            "name" => Ok(Self::Name),
+            "path" => Ok(Self::Path),
+            "section" => Ok(Self::Section),
+            "desc" => Ok(Self::Desc),
+            "json" => Ok(Self::Json),
+            "markdown" => Ok(Self::Markdown),
+            "roff" => Ok(Self::Roff),
+            _ => Err(format!("Unknown output style \"{s}\".").into()),
+        }
+    }
+}
+
+// Writes a single search result. The parts of the names that match the
+// queries are highlighted when colors are enabled.
+pub fn write_page(
+    out: &mut dyn Write,
+    page: &Page<'_>,
+    output: &OutputOptions,
+    queries: &[&str]
+) -> io::Result<()> {
+    let painter = output.painter();
+
+    // Resolved files are owned, so the page is shown with a copy that
+    // borrows them.
+    let files;
+    let resolved;
+    let page = match (&output.man_dir, output.resolve_files) {
+        (Some(man_dir), true) => {
+            files = existing_files(page, man_dir)?;
+            resolved = Page { files: files.iter().map(String::as_str).collect(), ..page.clone() };
+            &resolved
+        },
+        _ => page,
+    };
+
+    // Like grep(1) with several files, one-line styles start each line with
+    // the result's database. Documents and `man -k` output are not labeled.
+    let prefix = match (&output.label, &output.style) {
+        (Some(label), OutputStyle::Whatis | OutputStyle::Name | OutputStyle::Path
+            | OutputStyle::Section | OutputStyle::Desc | OutputStyle::Template(_)) => {
+            format!("{label}: ")
+        },
+        _ => String::new(),
+    };
+
+    match &output.style {
+        OutputStyle::Detailed => {
+            let fields = output.fields.as_deref().unwrap_or(PageField::ALL);
+            page.write_fields(out, painter, queries, output.width, fields)?;
+            if let Some(label) = &output.label {
+                writeln!(out, "* Database: {label}")?;
+            }
+            writeln!(out)
+        },
+        OutputStyle::Whatis => writeln!(out, "{prefix}{}", page.whatis_with(painter, queries)),
+        OutputStyle::Terse => writeln!(out, "{}", page.whatis_with(painter, queries)),
+        OutputStyle::Name => match page.names.first() {
+            Some(name) => writeln!(out, "{prefix}{name}"),
+            None => Ok(()),
+        },
+        OutputStyle::Path => {
+            for file in &page.files {
+                writeln!(out, "{prefix}{file}")?;
+            }
+            Ok(())
+        },
+        OutputStyle::Section => writeln!(out, "{prefix}{}", page.sects.join(", ")),
+        OutputStyle::Desc => writeln!(out, "{prefix}{}", page.desc),
+        OutputStyle::Json => match &output.label {
+            Some(label) => {
+                let json = page_json(page);
+                let fields = json.strip_suffix('}').unwrap_or(&json);
+                writeln!(out, "{fields},\"database\":{}}}", json_string(label))
+            },
+            None => writeln!(out, "{}", page_json(page)),
+        },
+        OutputStyle::Markdown => writeln!(out, "{}", markdown_row(page)),
+        OutputStyle::Roff => {
+            let sect = page.sects.first().unwrap_or_default();
+            let names = page.names
+                .iter()
+                .map(|n| format!("Xr {} {}", roff_arg(n.value), roff_arg(sect)))
+                .collect::<Vec<String>>();
+            writeln!(out, ".It {}", names.join(" , "))?;
+            if !page.desc.is_empty() {
+                writeln!(out, "{}", roff_text(page.desc))?;
+            }
+            Ok(())
+        },
+        OutputStyle::Template(template) => {
+            writeln!(out, "{prefix}{}", template.render(page))
+        },
+    }
+}
+
+// Returns the absolute paths of the page's files that exist, warning about
+// the others, which a stale database may still list.
+fn existing_files(page: &Page<'_>, man_dir: &Path) -> io::Result<Vec<String>> {
+    let files = page
+        .resolve_files(man_dir)?
+        .into_iter()
+        .filter_map(|(path, exists)| {
+            if !exists {
+                warn!("{} does not exist", path.display());
+            }
+            exists.then(|| path.display().to_string())
+        })
+        .collect();
+    Ok(files)
+}
+
+// Writes the header of the table that the Markdown style's rows belong to.
+pub fn write_markdown_header(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "| Name | Section | Description |\n|------|---------|-------------|")
+}
+
+// Formats a page as a row of a Markdown table, with the names as code spans
+// and the sections written as in whatis(1), e.g. "3/amd64".
+pub fn markdown_row(page: &Page<'_>) -> String {
+    let names = page.names
+        .iter()
+        .map(|n| markdown_code(n.value))
+        .collect::<Vec<String>>();
+    let mut sects = page.sects.join(", ");
+    if let Some(archs) = &page.archs {
+        sects.push('/');
+        sects.push_str(&archs.join(", "));
+    }
+
+    format!("| {} | {} | {} |", names.join(", "), markdown_text(&sects),
+        markdown_text(page.desc))
+}
+
+// Escapes the characters that Markdown would treat as formatting, along with
+// the pipes that would end a table cell.
+fn markdown_text(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Formats a name as a code span. Only pipes can be escaped inside one, so a
+// name with backticks gets a longer fence instead.
+pub fn markdown_code(s: &str) -> String {
+    let s = s.replace('|', "\\|");
+    if s.contains('`') {
+        format!("`` {s} ``")
+    } else {
+        format!("`{s}`")
+    }
+}
+
+// Writes the prologue and NAME section of an mdoc(7) document, followed by
+// the heading of the section holding the results. mandoc(1) fills in the
+// date.
+pub fn write_roff_header(out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "\
+        .Dd $Mdocdate$\n\
+        .Dt {} 7\n\
+        .Os\n\
+        .Sh NAME\n\
+        .Nm {}\n\
+        .Nd search results\n\
+        .Sh RESULTS", env!("CARGO_PKG_NAME").to_ascii_uppercase(), env!("CARGO_PKG_NAME"))
+}
+
+// Escapes a line of roff text, which cannot start with a control character.
+fn roff_text(s: &str) -> String {
+    let text = s.replace('\\', "\\e").replace('\n', " ");
+    if text.starts_with(['.', '\'']) {
+        format!("\\&{text}")
+    } else {
+        text
+    }
+}
+
+// Escapes an argument of an mdoc(7) macro. Arguments with spaces are
+// quoted, and ones that mdoc(7) would read as a macro name or as punctuation
+// are protected with "\&".
+pub fn roff_arg(s: &str) -> String {
+    let arg = s.replace('\\', "\\e").replace('"', "\\(dq").replace('\n', " ");
+    let is_macro = arg.len() <= 3 && arg.starts_with(|c: char| c.is_ascii_uppercase());
+    let is_punct = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_punctuation());
+
+    if arg.is_empty() || arg.contains(char::is_whitespace) {
+        format!("\"{arg}\"")
+    } else if is_macro || is_punct {
+        format!("\\&{arg}")
+    } else {
+        arg
+    }
+}
+
+// Formats a page as a single-line JSON object.
+pub fn page_json(page: &Page<'_>) -> String {
+    let names = page.names.iter().map(|n| n.value).collect::<Vec<&str>>();
+    let format = page.format.name();
+
+    let mut json = String::from("{\"names\":");
+    json.push_str(&json_array(&names));
+    json.push_str(",\"sections\":");
+    json.push_str(&json_array(&page.sects.to_vec()));
+    json.push_str(",\"archs\":");
+    match &page.archs {
+        Some(archs) => json.push_str(&json_array(&archs.to_vec())),
+        None => json.push_str("null"),
+    }
+    json.push_str(",\"description\":");
+    json.push_str(&json_string(page.desc));
+    json.push_str(",\"files\":");
+    json.push_str(&json_array(&page.files.to_vec()));
+    json.push_str(",\"format\":");
+    json.push_str(&json_string(format));
+    json.push('}');
+    json
+}
+
+pub fn json_array(items: &[&str]) -> String {
+    let items = items
+        .iter()
+        .map(|item| json_string(item))
+        .collect::<Vec<String>>();
+
+    format!("[{}]", items.join(","))
+}
+
+// Quotes and escapes a string as a JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = fmt::Write::write_fmt(&mut quoted, format_args!( "\\u{:04x}", u32::from(c)));
+            },
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
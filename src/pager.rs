@@ -0,0 +1,213 @@
+use std::env;
+use std::io::{self, ErrorKind, IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+
+use log::{debug, warn};
+
+use crate::color;
+use crate::term::{self, Key, RawMode};
+
+// The pager used when PAGER is not set.
+const DEFAULT_PAGER: &str = "less";
+
+// Writes `text` to stdout, piping it through $PAGER instead when stdout is a
+// terminal and the text does not fit on one screen.
+pub fn show(text: &[u8], enabled: bool) -> io::Result<()> {
+    if enabled && io::stdout().is_terminal() && exceeds_screen(text) {
+        match run_pager(text) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {},
+            Err(e) => warn!("Pager failed: {e}"),
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    out.write_all(text)?;
+    out.flush()
+}
+
+fn exceeds_screen(text: &[u8]) -> bool {
+    let Some((cols, rows)) = term::size() else {
+        return false;
+    };
+
+    // Long lines wrap, so they take up more than one row.
+    let lines = text
+        .split(|b| *b == b'\n')
+        .map(|line| line.len().div_ceil(cols).max(1))
+        .sum::<usize>();
+
+    // Leave a row for the prompt that follows the output.
+    lines >= rows
+}
+
+// Returns `false` when paging is disabled by an empty PAGER.
+fn run_pager(text: &[u8]) -> io::Result<bool> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    if pager.trim().is_empty() {
+        return Ok(false);
+    }
+
+    debug!("Paging output through {pager:?}");
+
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(&pager).stdin(Stdio::piped());
+
+    // Like git and man(1), let less quit for short output and pass colors.
+    if env::var_os("LESS").is_none() {
+        cmd.env("LESS", "FRX");
+    }
+
+    let mut child = cmd.spawn()?;
+    write_input(&mut child, text)?;
+    child.wait()?;
+    Ok(true)
+}
+
+// Runs `command` with the shell, writing `text` to its stdin, as for
+// "QUERY | grep foo" at the search prompt.
+pub fn pipe(text: &[u8], command: &str) -> io::Result<()> {
+    debug!("Piping output through {command:?}");
+
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    write_input(&mut child, text)?;
+
+    // Commands like grep fail when nothing matches, which is not an error
+    // here.
+    child.wait()?;
+    Ok(())
+}
+
+fn write_input(child: &mut Child, text: &[u8]) -> io::Result<()> {
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command closes its input when it quits early, e.g. the pager
+        // when the user quits or head(1) after enough lines.
+        match stdin.write_all(text) {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e),
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+// Writes numbered entries a screenful at a time, like more(1). After each
+// screen, a "--More--" prompt waits for Space (the next screen), Enter (the
+// next entry), a number and Enter (continue from that entry), or q.
+pub fn more(entries: &[Vec<u8>]) -> io::Result<()> {
+    let Some((cols, rows)) = term::size() else {
+        return write_all(entries);
+    };
+    if rows < 3 || !io::stdin().is_terminal() {
+        return write_all(entries);
+    }
+
+    // Colors take no room on the screen.
+    let height = |entry: &Vec<u8>| {
+        color::strip(entry)
+            .split(|b| *b == b'\n')
+            .map(|line| line.len().div_ceil(cols).max(1))
+            .sum::<usize>()
+            .saturating_sub(1)
+            .max(1)
+    };
+
+    let mut out = io::stdout().lock();
+    let mut next = 0;
+    // Leave a row for the prompt.
+    let mut room = rows - 1;
+
+    while next < entries.len() {
+        // Always show at least one entry, even if it is taller than the
+        // screen.
+        let start = next;
+        while next < entries.len() && (next == start || height(&entries[next]) <= room) {
+            room = room.saturating_sub(height(&entries[next]));
+            out.write_all(&entries[next])?;
+            next += 1;
+        }
+
+        if next == entries.len() {
+            break;
+        }
+
+        match more_prompt(&mut out, next, entries.len(), cols)? {
+            More::Screen => room = rows - 1,
+            More::Entry => room = 1,
+            More::Jump(number) => {
+                next = number.clamp(1, entries.len()) - 1;
+                room = rows - 1;
+            },
+            More::Quit => break,
+        }
+    }
+
+    out.flush()
+}
+
+// What to show after the "--More--" prompt.
+enum More {
+    Screen,
+    Entry,
+    // A 1-based entry number.
+    Jump(usize),
+    Quit,
+}
+
+fn more_prompt(
+    out: &mut dyn Write,
+    shown: usize,
+    total: usize,
+    cols: usize
+) -> io::Result<More> {
+    let Ok(_raw) = RawMode::enable() else {
+        return Ok(More::Screen);
+    };
+
+    let mut number = String::new();
+    loop {
+        let status = if number.is_empty() {
+            format!("--More-- ({shown} of {total}) Space: next page, Enter: next, N Enter: go to N, q: quit")
+        } else {
+            format!("--More-- Go to: {number}")
+        };
+        // A prompt that wrapped could not be cleared afterwards.
+        let status = status.chars().take(cols.saturating_sub(1)).collect::<String>();
+        write!(out, "\r\x1b[7m{status}\x1b[0m\x1b[K")?;
+        out.flush()?;
+
+        let action = match term::read_key()? {
+            Key::Char(c) if c.is_ascii_digit() => {
+                number.push(c);
+                continue;
+            },
+            Key::Backspace => {
+                number.pop();
+                continue;
+            },
+            Key::Enter => match number.parse() {
+                Ok(number) => More::Jump(number),
+                Err(_) => More::Entry,
+            },
+            Key::Char(' ') | Key::PageDown => More::Screen,
+            Key::Down => More::Entry,
+            Key::Char('q' | 'Q') | Key::Esc | Key::Ctrl('c' | 'd') => More::Quit,
+            _ => continue,
+        };
+
+        // Clear the prompt so the next entry starts on its row.
+        write!(out, "\r\x1b[K")?;
+        return Ok(action);
+    }
+}
+
+fn write_all(entries: &[Vec<u8>]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    for entry in entries {
+        out.write_all(entry)?;
+    }
+    out.flush()
+}
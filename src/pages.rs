@@ -1,8 +1,33 @@
+use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
-use std::str;
+use std::io::{self, Write};
+use std::path::{self, Path, PathBuf};
+use std::slice;
+use std::str::FromStr;
 
-use crate::utils::{parse_list, parse_num, print_list};
+use log::{debug, trace};
+
+use crate::color::Painter;
+use crate::database::ParseOptions;
+use crate::error::{DbError, ErrorKind};
+use crate::memory::vec_size;
+use crate::progress::Progress;
+use crate::sections;
+use crate::utils::{parse_num, parse_str, write_list, write_wrapped, StrList, StrListIter};
+
+// The page entries begin at offset 20, and each one is 20 bytes.
+const TABLE_IDX: usize = 20;
+const PAGE_SIZE: usize = 20;
+
+// Tables with fewer pages than this are parsed, or searched, faster on one
+// thread than it takes to hand them out to others.
+#[cfg(feature = "parallel")]
+pub const PARALLEL_MIN_PAGES: usize = 4096;
+
+// How many pages are parsed in parallel between progress updates.
+#[cfg(feature = "parallel")]
+const PARALLEL_BATCH: usize = 16384;
 
 // The Pages table consists of (in order):
 // 1. The total number of Page entries.
@@ -15,32 +40,82 @@ pub struct Pages<'a> {
 
 impl<'a> Pages<'a> {
     pub fn parse(bytes: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        Self::parse_with_progress(bytes, &Progress::hidden())
+    }
+
+    pub fn parse_with_progress(
+        bytes: &'a [u8],
+        progress: &Progress
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::parse_with_options(bytes, &ParseOptions::default(), progress)
+    }
+
+    pub fn parse_with_options(
+        bytes: &'a [u8],
+        options: &ParseOptions,
+        progress: &Progress
+    ) -> Result<Self, Box<dyn Error>> {
         // The total number of pages is at offset 16.
         let count = parse_num(bytes, 16)?;
+        debug!("Pages table contains {count} entries");
+        options.check_pages(count, 16)?;
+        // Room is only made for as many records as the file holds.
+        if TABLE_IDX + PAGE_SIZE * count > bytes.len() {
+            return Err(DbError::at(ErrorKind::OutOfBounds, 16,
+                format!("The {count} page records are past the end of the file.")).into());
+        }
         let mut table = Vec::with_capacity(count);
 
-        // The page entries begin at offset 20.
-        let table_idx = 20;
-
-        // Each page entry is 20 bytes.
-        let page_size = 20;
+        #[cfg(feature = "parallel")]
+        if count >= PARALLEL_MIN_PAGES && rayon::current_num_threads() > 1 {
+            parse_parallel(bytes, count, options, &mut table, progress)?;
+        }
 
-        for page_idx in 0..count {
-            let offset = page_size * page_idx;
-            let page = Page::parse(bytes, table_idx + offset)?;
+        // Tables too small to parse in parallel are parsed here.
+        for page_idx in table.len()..count {
+            let offset = TABLE_IDX + PAGE_SIZE * page_idx;
+            trace!("Page {page_idx} record at offset {offset}");
+            let page = Page::parse_with(bytes, offset, options)?;
             table.push(page);
+            progress.update("Parsing pages", page_idx + 1, count);
         }
 
         // Ensure the expected number of pages are present.
         if table.len() != count {
-            return Err("Page entry parsing failed.".into());
+            return Err(DbError::at(ErrorKind::Count, 16, "Page entry parsing failed.").into());
         }
 
         Ok(Self { count, table })
     }
 }
 
-#[derive(Clone)]
+// Parses the page records of a large table on every CPU, a batch at a time so
+// that progress is still reported. The records are independent of each other,
+// and rayon hands the records of a batch to whichever threads are free.
+#[cfg(feature = "parallel")]
+fn parse_parallel<'a>(
+    bytes: &'a [u8],
+    count: usize,
+    options: &ParseOptions,
+    table: &mut Vec<Page<'a>>,
+    progress: &Progress
+) -> Result<(), DbError> {
+    use rayon::prelude::*;
+
+    debug!("Parsing {count} pages on {} threads", rayon::current_num_threads());
+    for start in (0..count).step_by(PARALLEL_BATCH) {
+        let end = count.min(start + PARALLEL_BATCH);
+        let pages = (start..end)
+            .into_par_iter()
+            .map(|page_idx| Page::parse_with(bytes, TABLE_IDX + PAGE_SIZE * page_idx, options))
+            .collect::<Result<Vec<Page<'a>>, DbError>>()?;
+        table.extend(pages);
+        progress.update("Parsing pages", end, count);
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
 pub struct Name<'a> {
     pub value: &'a str,
     pub source: u8,
@@ -59,43 +134,172 @@ impl<'a> Debug for Name<'a> {
 }
 
 impl<'a> Name<'a> {
+    // Describes the bits of the name sources byte, e.g. ["SYNOPSIS", "NAME"].
+    pub fn sources(&self) -> Vec<&'static str> {
+        const SOURCES: [&str; 5] = ["SYNOPSIS", "NAME", "first NAME", "header", "file name"];
+
+        SOURCES
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.source & (1 << bit) != 0)
+            .map(|(_, source)| *source)
+            .collect()
+    }
+
     pub fn parse_names(
         bytes: &'a [u8],
         start: usize
-    ) -> Result<Vec<Name<'a>>, Box<dyn Error>> {
-        let mut names = Vec::with_capacity(10);
-        let item_iter = bytes[start..].split_inclusive(|b| *b == 0);
-
-        for item_bytes in item_iter {
-            match item_bytes.len() {
-                0 => return Err("Parsed an unexpected empty string.".into()),
-                // A NUL byte marks the end of a list.
-                1 if item_bytes[0] == 0 => break,
-                _ if !matches!(item_bytes[0], 1..=31) => {
-                    return Err("Name source parsing failed.".into());
-                },
-                len => {
-                    // We know the slice is not empty so it is safe to unwrap.
-                    let (src, name_bytes) = item_bytes[..(len - 1)]
-                        .split_first()
-                        .ok_or("Names list parsing failed.")?;
-
-                    let name = str::from_utf8(name_bytes)?;
-                    names.push(Self { value: name, source: *src });
-                },
+    ) -> Result<Vec<Name<'a>>, DbError> {
+        NameList::parse(bytes, start).map(|names| names.to_vec())
+    }
+}
+
+// A names list read where it is in the database, like `StrList`, whose
+// strings each start with a name sources byte.
+#[derive(Clone, Debug)]
+pub struct NameList<'a>(NameItems<'a>);
+
+#[derive(Clone, Debug)]
+enum NameItems<'a> {
+    InPlace(StrList<'a>),
+    Collected(Vec<Name<'a>>),
+}
+
+impl<'a> NameList<'a> {
+    // Checks the names of the list at `start`, including their sources
+    // bytes.
+    pub fn parse(bytes: &'a [u8], start: usize) -> Result<Self, DbError> {
+        Self::parse_with(bytes, start, &ParseOptions::default())
+    }
+
+    pub fn parse_with(
+        bytes: &'a [u8],
+        start: usize,
+        options: &ParseOptions
+    ) -> Result<Self, DbError> {
+        let list = StrList::parse_with(bytes, start, options)?;
+
+        let mut item_idx = start;
+        for item in list.iter() {
+            if !matches!(item.as_bytes()[0], 1..=31) {
+                return Err(DbError::at(ErrorKind::Format, item_idx,
+                    "Name source parsing failed."));
             }
+            item_idx += item.len() + 1;
         }
 
-        Ok(names)
+        Ok(Self(NameItems::InPlace(list)))
+    }
+
+    pub fn iter(&self) -> NameListIter<'_, 'a> {
+        match &self.0 {
+            NameItems::InPlace(list) => NameListIter::InPlace(list.iter()),
+            NameItems::Collected(names) => NameListIter::Collected(names.iter()),
+        }
+    }
+
+    pub fn first(&self) -> Option<Name<'a>> {
+        self.iter().next()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            NameItems::InPlace(list) => list.len(),
+            NameItems::Collected(names) => names.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns the bytes of heap memory used by a collected list. A list read
+    // in place uses none.
+    pub fn heap_size(&self) -> usize {
+        match &self.0 {
+            NameItems::InPlace(_) => 0,
+            NameItems::Collected(names) => vec_size(names),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<Name<'a>> {
+        let mut names = Vec::with_capacity(self.len());
+        names.extend(self.iter());
+        names
     }
 }
 
-#[derive(Debug, Clone)]
+impl Default for NameList<'_> {
+    fn default() -> Self {
+        Self(NameItems::Collected(Vec::new()))
+    }
+}
+
+impl<'a> FromIterator<Name<'a>> for NameList<'a> {
+    fn from_iter<I: IntoIterator<Item = Name<'a>>>(iter: I) -> Self {
+        Self(NameItems::Collected(iter.into_iter().collect()))
+    }
+}
+
+impl<'l, 'a> IntoIterator for &'l NameList<'a> {
+    type Item = Name<'a>;
+    type IntoIter = NameListIter<'l, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// The names of a `NameList`, in order.
+#[derive(Clone, Debug)]
+pub enum NameListIter<'l, 'a> {
+    InPlace(StrListIter<'l, 'a>),
+    Collected(slice::Iter<'l, Name<'a>>),
+}
+
+impl<'a> Iterator for NameListIter<'_, 'a> {
+    type Item = Name<'a>;
+
+    fn next(&mut self) -> Option<Name<'a>> {
+        match self {
+            // The source byte is ASCII so the name starts on a char boundary.
+            Self::InPlace(items) => items
+                .next()
+                .map(|item| Name { value: &item[1..], source: item.as_bytes()[0] }),
+            Self::Collected(names) => names.next().copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PageFormat {
     // 0x01: The file format is mdoc(7) or man(7).
     MdocMan,
     // 0x02: The manual page is preformatted.
     Preformatted,
+    // Another control byte, such as a format added by a later mandoc. The
+    // page is still searched, and written back with the same byte.
+    Unknown(u8),
+}
+
+impl PageFormat {
+    // The byte that starts the page's files list.
+    pub const fn byte(&self) -> u8 {
+        match self {
+            Self::MdocMan => 1,
+            Self::Preformatted => 2,
+            Self::Unknown(byte) => *byte,
+        }
+    }
+
+    // How the format is named in JSON output, exports, and templates.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::MdocMan => "source",
+            Self::Preformatted => "preformatted",
+            Self::Unknown(_) => "unknown",
+        }
+    }
 }
 
 impl Display for PageFormat {
@@ -103,30 +307,120 @@ impl Display for PageFormat {
         match self {
             Self::MdocMan => f.write_str("man(7) or mdoc(7)"),
             Self::Preformatted => f.write_str("preformatted"),
+            Self::Unknown(byte) => write!(f, "unknown ({byte:#04x})"),
         }
     }
 }
 
-impl From<u8> for PageFormat {
-    fn from(byte: u8) -> Self {
+// Reads the format byte of a files list. A NUL or printable byte cannot be a
+// format: the list is missing its format byte.
+impl TryFrom<u8> for PageFormat {
+    type Error = DbError;
+
+    fn try_from(byte: u8) -> Result<Self, DbError> {
         match byte {
-            1 => Self::MdocMan,
-            2 => Self::Preformatted,
-            _ => unreachable!(),
+            1 => Ok(Self::MdocMan),
+            2 => Ok(Self::Preformatted),
+            3..=0x1f => Ok(Self::Unknown(byte)),
+            _ => Err(DbError::new(ErrorKind::Format,
+                format!("Byte {byte:#04x} is not a page format."))),
+        }
+    }
+}
+
+// A line of the detailed view of a page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageField {
+    Names,
+    Sections,
+    Archs,
+    Desc,
+    Files,
+    Format,
+}
+
+impl PageField {
+    pub const ALL: &'static [Self] = &[
+        Self::Names, Self::Sections, Self::Archs, Self::Desc, Self::Files, Self::Format,
+    ];
+
+    pub const NAMES: &'static [&'static str] = &[
+        "names", "sect", "arch", "desc", "files", "format",
+    ];
+
+    // Parses a comma-separated list such as "names,sect,desc", or "all".
+    // The fields are shown in the order given.
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, Box<dyn Error>> {
+        if s.trim() == "all" {
+            return Ok(Self::ALL.to_vec());
+        }
+
+        let mut fields = Vec::new();
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let field = field.parse()?;
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+
+        if fields.is_empty() {
+            return Err("Expected at least one field.".into());
+        }
+        Ok(fields)
+    }
+}
+
+impl Display for PageField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Names => f.write_str("names"),
+            Self::Sections => f.write_str("sect"),
+            Self::Archs => f.write_str("arch"),
+            Self::Desc => f.write_str("desc"),
+            Self::Files => f.write_str("files"),
+            Self::Format => f.write_str("format"),
+        }
+    }
+}
+
+impl FromStr for PageField {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "names" | "name" => Ok(Self::Names),
+            "sect" | "sects" | "sections" => Ok(Self::Sections),
+            "arch" | "archs" => Ok(Self::Archs),
+            "desc" => Ok(Self::Desc),
+            "files" | "file" => Ok(Self::Files),
+            "format" => Ok(Self::Format),
+            _ => Err(format!("Unknown field \"{s}\". The fields are {}, or all.",
+                Self::NAMES.join(", ")).into()),
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Page<'a> {
-    pub names: Vec<Name<'a>>,
-    pub sects: Vec<&'a str>,
-    pub archs: Option<Vec<&'a str>>,
+    pub names: NameList<'a>,
+    pub sects: StrList<'a>,
+    pub archs: Option<StrList<'a>>,
     pub desc: &'a str,
-    pub files: Vec<&'a str>,
+    pub files: StrList<'a>,
     pub format: PageFormat,
 }
 
+impl Page<'_> {
+    // Returns the bytes of heap memory used by the page's lists, not
+    // counting the strings, which are borrowed.
+    pub fn heap_size(&self) -> usize {
+        self.names.heap_size()
+            + self.sects.heap_size()
+            + self.archs.as_ref().map_or(0, StrList::heap_size)
+            + self.files.heap_size()
+    }
+}
+
 // Each PAGE entry consists of (in order):
 // 1. The index of the name strings list.
 //   a. Each name consists of (in order):
@@ -151,46 +445,157 @@ impl<'a> Page<'a> {
     pub fn parse(
         bytes: &'a [u8],
         start: usize
-    ) -> Result<Self, Box<dyn Error>> {
-        assert!(start + 19 < bytes.len());
+    ) -> Result<Self, DbError> {
+        Self::parse_with(bytes, start, &ParseOptions::default())
+    }
 
+    // Parses the page record at `start` within the limits of `options`.
+    pub fn parse_with(
+        bytes: &'a [u8],
+        start: usize,
+        options: &ParseOptions
+    ) -> Result<Self, DbError> {
         let names_start = parse_num(bytes, start)?;
         let sects_start = parse_num(bytes, start + 4)?;
         let archs_start = parse_num(bytes, start + 8)?;
         let desc_start = parse_num(bytes, start + 12)?;
         let files_start = parse_num(bytes, start + 16)?;
+        trace!("Page lists: names {names_start}, sects {sects_start}, \
+            archs {archs_start}, desc {desc_start}, files {files_start}");
 
-        let names = Name::parse_names(bytes, names_start)?;
-        let sects = parse_list(bytes, sects_start)?;
+        let names = NameList::parse_with(bytes, names_start, options)?;
+        let sects = StrList::parse_with(bytes, sects_start, options)?;
         let archs = if archs_start != 0 {
-            Some(parse_list(bytes, archs_start)?)
+            Some(StrList::parse_with(bytes, archs_start, options)?)
         } else {
             None
         };
-        let desc = bytes[desc_start..]
-            .split(|b| *b == 0)
-            .next()
-            .and_then(|desc_bytes| str::from_utf8(desc_bytes).ok())
-            .ok_or("Description string parsing failed.")?;
-        let files = parse_list(bytes, files_start + 1)?;
-        let format = PageFormat::from(bytes[files_start]);
+        let desc = parse_str(bytes, desc_start)?;
+        options.check_str_len(desc.len(), desc_start)?;
+        let format_byte = *bytes
+            .get(files_start)
+            .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, files_start,
+                "Files list is past the end of the file."))?;
+        let format = PageFormat::try_from(format_byte)
+            .map_err(|e| DbError::at(e.kind, files_start, e.message))?;
+        let files = StrList::parse_with(bytes, files_start + 1, options)?;
 
         Ok(Self { names, sects, archs, desc, files, format })
     }
 
-    pub fn print(&self) {
-        let names = self.names.iter().map(|n| n.value).collect::<Vec<&str>>();
-        print!("* Names: ");
-        print_list(&names[..]);
-        print!("* Sections: ");
-        print_list(&self.sects[..]);
-        print!("* Architectures: ");
-        self.archs.as_ref().map_or_else(
-            || println!("machine-independent"),
-            |archs| print_list(&archs[..]));
-        println!("* Description: {}", self.desc);
-        print!("* Files: ");
-        print_list(&self.files[..]);
-        println!("* Format: {}", self.format);
+    // Returns the page's files joined with `man_dir`, the directory that
+    // contains the database. Database entries are relative to it.
+    pub fn file_paths(&self, man_dir: &Path) -> Vec<PathBuf> {
+        self.files.iter().map(|file| man_dir.join(file)).collect()
+    }
+
+    // Like `file_paths`, with `man_dir` made absolute so that the paths can
+    // be used from any directory, and whether each file exists.
+    pub fn resolve_files(&self, man_dir: &Path) -> io::Result<Vec<(PathBuf, bool)>> {
+        let man_dir = path::absolute(man_dir)?;
+        let files = self
+            .file_paths(&man_dir)
+            .into_iter()
+            .map(|path| {
+                let exists = path.is_file();
+                (path, exists)
+            })
+            .collect();
+        Ok(files)
+    }
+
+    // Formats the page the way whatis(1) and apropos(1) do:
+    // "name, name(sect, sect/arch, arch) - description".
+    pub fn whatis(&self) -> String {
+        self.whatis_with(Painter::plain(), &[])
+    }
+
+    // Like `whatis`, with bold names, dim sections, and highlighted matches
+    // when the painter has colors enabled.
+    pub fn whatis_with(&self, painter: Painter, queries: &[&str]) -> String {
+        let names = self.names
+            .iter()
+            .map(|n| painter.name(n.value, queries))
+            .collect::<Vec<String>>();
+        let mut sects = self.sects.join(", ");
+        if let Some(archs) = &self.archs {
+            sects.push('/');
+            sects.push_str(&archs.join(", "));
+        }
+
+        format!("{}({}) - {}", names.join(", "), painter.dim(&sects), self.desc)
+    }
+
+    // Orders pages the way apropos(1) sorts its results: by the names
+    // (ignoring ASCII case), then by the sections.
+    pub fn apropos_cmp(&self, other: &Self) -> Ordering {
+        let lower = |page: &Self| {
+            page.names
+                .iter()
+                .map(|n| n.value.to_ascii_lowercase())
+                .collect::<Vec<String>>()
+        };
+
+        lower(self)
+            .cmp(&lower(other))
+            .then_with(|| self.sects.iter().cmp(other.sects.iter()))
+    }
+
+    // Writes the detailed view of the page. The description and file list
+    // are wrapped to fit in `width` columns, if given.
+    pub fn write(
+        &self,
+        out: &mut dyn Write,
+        painter: Painter,
+        queries: &[&str],
+        width: Option<usize>
+    ) -> io::Result<()> {
+        self.write_fields(out, painter, queries, width, PageField::ALL)
+    }
+
+    // Like `write`, showing only the given fields in the given order.
+    pub fn write_fields(
+        &self,
+        out: &mut dyn Write,
+        painter: Painter,
+        queries: &[&str],
+        width: Option<usize>,
+        fields: &[PageField]
+    ) -> io::Result<()> {
+        for field in fields {
+            match field {
+                PageField::Names => {
+                    let names = self.names
+                        .iter()
+                        .map(|n| painter.name(n.value, queries))
+                        .collect::<Vec<String>>();
+                    writeln!(out, "* Names: {}", names.join(", "))?;
+                },
+                PageField::Sections => {
+                    let sects = self.sects
+                        .iter()
+                        .map(|sect| painter.dim(&sections::describe(sect)))
+                        .collect::<Vec<String>>();
+                    writeln!(out, "* Sections: {}", sects.join(", "))?;
+                },
+                PageField::Archs => {
+                    write!(out, "* Architectures: ")?;
+                    match &self.archs {
+                        Some(archs) => write_list(out, &archs.to_vec())?,
+                        None => writeln!(out, "machine-independent")?,
+                    }
+                },
+                PageField::Desc => {
+                    let words = self.desc.split_whitespace().collect::<Vec<&str>>();
+                    write_wrapped(out, "* Description: ", &words, " ", width)?;
+                },
+                PageField::Files => {
+                    write_wrapped(out, "* Files: ", &self.files.to_vec(), ", ", width)?;
+                },
+                PageField::Format => writeln!(out, "* Format: {}", self.format)?,
+            }
+        }
+
+        Ok(())
     }
 }
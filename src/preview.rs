@@ -0,0 +1,394 @@
+use crate::build::{is_mdoc, is_punctuation, parse_macro, plain_text};
+use crate::pages::PageFormat;
+use crate::utils::strip_overstrike;
+
+// The sections that a preview shows.
+const SECTIONS: [&str; 2] = ["NAME", "SYNOPSIS"];
+const SYNOPSIS: usize = 1;
+
+// How far the text of a section is indented, as by mandoc(1).
+const INDENT: usize = 5;
+
+// The mdoc(7) macros that may be called from the arguments of another.
+const CALLABLE: &[&str] = &[
+    "Ad", "Ar", "Aq", "Brq", "Bq", "Cd", "Cm", "Dq", "Dv", "Em", "Er", "Ev", "Fa", "Fl", "Ic",
+    "Li", "Ms", "Nm", "No", "Ns", "Oc", "Oo", "Op", "Pa", "Pq", "Qq", "Sq", "Sx", "Sy", "Tn",
+    "Va", "Vt", "Xr",
+];
+
+// The sections of a page, each with its lines before they are wrapped.
+type Sections = Vec<(&'static str, Vec<String>)>;
+
+// Renders the NAME and SYNOPSIS sections of a man page source as plain text
+// wrapped to `width` columns, to preview a page without formatting it with
+// mandoc(1).
+//
+// Only the mdoc(7) and man(7) macros that these sections commonly use are
+// understood; the text of other macros is shown as it is, and their layout
+// is left out. Preformatted pages already have their layout, so their
+// sections are copied.
+pub fn render(text: &str, format: &PageFormat, width: usize) -> String {
+    let sections = match format {
+        PageFormat::Preformatted => cat_sections(&strip_overstrike(text)),
+        _ if is_mdoc(text) => mdoc_sections(text),
+        _ => man_sections(text),
+    };
+
+    let mut out = String::new();
+    for (idx, (title, lines)) in sections.iter().enumerate() {
+        if lines.is_empty() {
+            continue;
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(title);
+        out.push('\n');
+        for line in lines {
+            for wrapped in wrap(line, width, idx == SYNOPSIS) {
+                out.push_str(&wrapped);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+// A line of output being put together from words, which are separated by
+// spaces unless punctuation or `Ns` says otherwise.
+#[derive(Default)]
+struct Line {
+    text: String,
+    no_space: bool,
+}
+
+impl Line {
+    fn word(&mut self, word: &str) {
+        if word.is_empty() {
+            return;
+        }
+        let closing = word != "..." && word.chars().all(|c| ".,;:?!)]".contains(c));
+        if !self.text.is_empty() && !self.no_space && !closing {
+            self.text.push(' ');
+        }
+        self.text.push_str(word);
+        self.no_space = word.ends_with(['(', '[']);
+    }
+
+    // Adds text that the next word follows without a space, e.g. "[".
+    fn open(&mut self, text: &str) {
+        self.word(text);
+        self.no_space = true;
+    }
+
+    // Adds text right after the last word, e.g. "]".
+    fn close(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.no_space = false;
+    }
+
+    fn take(&mut self) -> Option<String> {
+        self.no_space = false;
+        let text = std::mem::take(&mut self.text);
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
+// Renders the sections of an mdoc(7) page.
+//
+// In the SYNOPSIS, each utility (`Nm`), include (`In`), and function
+// (`Ft`, `Fn`, `Fo`) starts a line of its own, as mandoc(1) lays them out.
+fn mdoc_sections(text: &str) -> Sections {
+    let mut sections = SECTIONS.map(|title| (title, Vec::new())).to_vec();
+    let mut current = None;
+    let mut line = Line::default();
+    // The page's name, which `Nm` without arguments stands for.
+    let mut name = None;
+    // The name and arguments of a function between `Fo` and `Fc`.
+    let mut function: Option<(String, Vec<String>)> = None;
+
+    for source in text.lines() {
+        let Some((macro_name, args)) = parse_macro(source) else {
+            if current.is_some() && !source.starts_with(['.', '\'']) {
+                line.word(&plain_text(source));
+            }
+            continue;
+        };
+
+        if macro_name == "Nm" && name.is_none() {
+            name = args.first().cloned();
+        }
+        if macro_name == "Sh" {
+            push_line(&mut sections, current, &mut line);
+            current = SECTIONS.iter().position(|title| *title == args.join(" "));
+            continue;
+        }
+        let Some(idx) = current else {
+            continue;
+        };
+        let synopsis = idx == SYNOPSIS;
+
+        match macro_name.as_str() {
+            "Pp" | "Lp" | "Bl" | "El" | "Bd" | "Ed" | "It" | "Ss" => {
+                push_line(&mut sections, current, &mut line);
+            },
+            // Macros that only change the layout, or set up the page.
+            "Bk" | "Ek" | "Dd" | "Dt" | "Os" | "Tg" => {},
+            "Nm" | "Ft" | "Fd" | "In" | "Fn" | "Vt" if synopsis => {
+                push_line(&mut sections, current, &mut line);
+                inline(&mut line, &macro_name, &args, name.as_deref());
+                if macro_name != "Nm" {
+                    if macro_name == "Fn" {
+                        line.close(";");
+                    }
+                    push_line(&mut sections, current, &mut line);
+                }
+            },
+            "Fo" => {
+                push_line(&mut sections, current, &mut line);
+                function = Some((args.first().cloned().unwrap_or_default(), Vec::new()));
+            },
+            "Fa" if function.is_some() => {
+                if let Some((_, fn_args)) = &mut function {
+                    fn_args.push(args.join(" "));
+                }
+            },
+            "Fc" => {
+                if let Some((fn_name, fn_args)) = function.take() {
+                    let end = if synopsis { ");" } else { ")" };
+                    line.word(&format!("{fn_name}({}{end}", fn_args.join(", ")));
+                    push_line(&mut sections, current, &mut line);
+                }
+            },
+            "Nd" => {
+                line.word("-");
+                for arg in &args {
+                    line.word(arg);
+                }
+            },
+            _ => inline(&mut line, &macro_name, &args, name.as_deref()),
+        }
+    }
+    push_line(&mut sections, current, &mut line);
+
+    sections
+}
+
+// Renders an mdoc(7) macro line along with the macros called from its
+// arguments, e.g. `.Op Fl a Ar file`.
+fn inline(line: &mut Line, macro_name: &str, args: &[String], name: Option<&str>) {
+    // The text closing the enclosures opened on this line, e.g. "]" for `Op`.
+    let mut closers = Vec::new();
+    // The text a macro without arguments stands for, e.g. "file ..." for `Ar`.
+    let mut default = None;
+    let mut mode = String::new();
+
+    let mut tokens = std::iter::once(macro_name.to_string())
+        .chain(args.iter().cloned())
+        .peekable();
+    while let Some(token) = tokens.next() {
+        // The line's own macro need not be callable, e.g. `Fn`.
+        let is_macro = mode.is_empty() || CALLABLE.contains(&token.as_str());
+        if is_macro || is_punctuation(&token) {
+            if let Some(default) = default.take() {
+                line.word(default);
+            }
+        }
+        if !is_macro {
+            match mode.as_str() {
+                "Fl" if !is_punctuation(&token) => line.word(&format!("-{token}")),
+                _ => line.word(&token),
+            }
+            default = None;
+            continue;
+        }
+
+        match token.as_str() {
+            "Op" => {
+                line.open("[");
+                closers.push("]");
+            },
+            "Pq" => {
+                line.open("(");
+                closers.push(")");
+            },
+            "Bq" => {
+                line.open("[");
+                closers.push("]");
+            },
+            "Brq" => {
+                line.open("{");
+                closers.push("}");
+            },
+            "Aq" => {
+                line.open("<");
+                closers.push(">");
+            },
+            "Dq" | "Qq" => {
+                line.open("\"");
+                closers.push("\"");
+            },
+            "Sq" => {
+                line.open("'");
+                closers.push("'");
+            },
+            "Oo" => line.open("["),
+            "Oc" => line.close("]"),
+            "Ns" => line.no_space = true,
+            "Fl" => default = Some("-"),
+            "Ar" => default = Some("file ..."),
+            "Nm" => default = name,
+            "Xr" => {
+                let xr_name = tokens.next_if(|arg| !CALLABLE.contains(&arg.as_str()));
+                let sect = tokens.next_if(|arg| !is_punctuation(arg));
+                match (xr_name, sect) {
+                    (Some(xr_name), Some(sect)) => line.word(&format!("{xr_name}({sect})")),
+                    (Some(xr_name), None) => line.word(&xr_name),
+                    _ => {},
+                }
+            },
+            "In" => {
+                if let Some(file) = tokens.next() {
+                    line.word(&format!("#include <{file}>"));
+                }
+            },
+            "Fn" => {
+                let fn_name = tokens.next().unwrap_or_default();
+                let mut fn_args = Vec::new();
+                while let Some(arg) = tokens.next_if(|arg| !is_punctuation(arg)) {
+                    fn_args.push(arg);
+                }
+                line.word(&format!("{fn_name}({})", fn_args.join(", ")));
+            },
+            _ => {},
+        }
+        mode = token;
+    }
+
+    if let Some(default) = default {
+        line.word(default);
+    }
+    for closer in closers.iter().rev() {
+        line.close(closer);
+    }
+}
+
+// Renders the sections of a man(7) page.
+fn man_sections(text: &str) -> Sections {
+    let mut sections = SECTIONS.map(|title| (title, Vec::new())).to_vec();
+    let mut current = None;
+    let mut line = Line::default();
+    // Whether each line of text is a line of output, as between `nf` and `fi`.
+    let mut no_fill = false;
+
+    for source in text.lines() {
+        let Some((macro_name, args)) = parse_macro(source) else {
+            if current.is_some() && !source.starts_with(['.', '\'']) {
+                line.word(&plain_text(source));
+                if no_fill {
+                    push_line(&mut sections, current, &mut line);
+                }
+            }
+            continue;
+        };
+
+        if macro_name == "SH" {
+            push_line(&mut sections, current, &mut line);
+            let title = args.join(" ").to_uppercase();
+            current = SECTIONS.iter().position(|section| *section == title);
+            continue;
+        }
+        if current.is_none() {
+            continue;
+        }
+
+        match macro_name.as_str() {
+            "br" | "sp" | "PP" | "LP" | "P" | "TP" | "IP" | "HP" | "SY" | "YS" => {
+                push_line(&mut sections, current, &mut line);
+                if macro_name == "SY" {
+                    line.word(&args.join(" "));
+                }
+            },
+            "nf" | "EX" => {
+                push_line(&mut sections, current, &mut line);
+                no_fill = true;
+            },
+            "fi" | "EE" => {
+                push_line(&mut sections, current, &mut line);
+                no_fill = false;
+            },
+            // Fonts that alternate between the arguments join them.
+            "BR" | "RB" | "BI" | "IB" | "IR" | "RI" => line.word(&args.concat()),
+            "B" | "I" | "SM" | "SB" => line.word(&args.join(" ")),
+            "OP" => line.word(&format!("[{}]", args.join(" "))),
+            _ => {},
+        }
+        if no_fill && matches!(macro_name.as_str(), "B" | "I" | "BR" | "RB" | "BI" | "IB"
+            | "IR" | "RI") {
+            push_line(&mut sections, current, &mut line);
+        }
+    }
+    push_line(&mut sections, current, &mut line);
+
+    sections
+}
+
+// Copies the sections of a preformatted page: the lines after a heading
+// such as "NAME", up to the next heading, which starts at the first column.
+fn cat_sections(text: &str) -> Sections {
+    let mut sections = SECTIONS.map(|title| (title, Vec::new())).to_vec();
+    let mut current = None;
+
+    for source in text.lines() {
+        if !source.starts_with(char::is_whitespace) && !source.trim().is_empty() {
+            current = SECTIONS.iter().position(|title| *title == source.trim());
+            continue;
+        }
+        if let Some(idx) = current {
+            if !source.trim().is_empty() {
+                sections[idx].1.push(source.trim().to_string());
+            }
+        }
+    }
+
+    sections
+}
+
+// Ends the line being put together, adding it to the current section.
+fn push_line(sections: &mut Sections, current: Option<usize>, line: &mut Line) {
+    if let (Some(idx), Some(text)) = (current, line.take()) {
+        sections[idx].1.push(text);
+    }
+}
+
+// Wraps a line to `width` columns, indented. A SYNOPSIS line continues
+// below the end of its first word, like the arguments of a utility.
+fn wrap(line: &str, width: usize, synopsis: bool) -> Vec<String> {
+    let first_len = line.split(' ').next().map_or(0, |word| word.chars().count());
+    let hang = if synopsis && first_len + 1 < width / 3 { first_len + 1 } else { 0 };
+
+    let mut lines = Vec::new();
+    let mut current = " ".repeat(INDENT);
+    let mut len = INDENT;
+    let mut empty = true;
+    for word in line.split(' ').filter(|word| !word.is_empty()) {
+        let word_len = word.chars().count();
+        if !empty && len + 1 + word_len > width {
+            lines.push(std::mem::replace(&mut current, " ".repeat(INDENT + hang)));
+            len = INDENT + hang;
+            empty = true;
+        }
+        if !empty {
+            current.push(' ');
+            len += 1;
+        }
+        current.push_str(word);
+        len += word_len;
+        empty = false;
+    }
+    if !empty {
+        lines.push(current);
+    }
+    lines
+}
@@ -0,0 +1,74 @@
+use std::cell::Cell;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+// Databases smaller than this parse too quickly for progress to be useful.
+const MIN_BYTES: usize = 1024 * 1024;
+
+// How often the progress line is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+// A single-line progress indicator drawn on stderr. It stays silent unless
+// stderr is a terminal, so redirected output is never cluttered.
+#[derive(Debug, Default)]
+pub struct Progress {
+    enabled: bool,
+    last_draw: Cell<Option<Instant>>,
+    drawn: Cell<bool>,
+}
+
+impl Progress {
+    // A progress indicator that never draws anything.
+    pub fn hidden() -> Self {
+        Self::default()
+    }
+
+    // A progress indicator for work that is always slow enough to show.
+    pub fn visible() -> Self {
+        Self {
+            enabled: io::stderr().is_terminal(),
+            ..Self::default()
+        }
+    }
+
+    // A progress indicator for parsing a database of `len` bytes.
+    pub fn for_bytes(len: usize) -> Self {
+        Self {
+            enabled: len >= MIN_BYTES && io::stderr().is_terminal(),
+            ..Self::default()
+        }
+    }
+
+    // Reports that `done` of `total` items of `stage` have been processed.
+    pub fn update(&self, stage: &str, done: usize, total: usize) {
+        if !self.enabled || total == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let due = self.last_draw
+            .get()
+            .is_none_or(|last| now.duration_since(last) >= REDRAW_INTERVAL);
+
+        if !due && done < total {
+            return;
+        }
+
+        self.last_draw.set(Some(now));
+        self.drawn.set(true);
+
+        let percent = done * 100 / total;
+        let mut err = io::stderr().lock();
+        let _ = write!(err, "\r\x1b[K{stage}: {percent:>3}% ({done}/{total})");
+        let _ = err.flush();
+    }
+
+    // Clears the progress line.
+    pub fn finish(&self) {
+        if self.drawn.replace(false) {
+            let mut err = io::stderr().lock();
+            let _ = write!(err, "\r\x1b[K");
+            let _ = err.flush();
+        }
+    }
+}
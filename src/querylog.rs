@@ -0,0 +1,73 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+
+use crate::output::{json_array, json_string};
+use crate::search::SearchOptions;
+
+// The file given with --log-file, if any. Like the stderr logger, it is set
+// up once at startup so every search can be recorded without threading it
+// through the callers.
+static QUERY_LOG: OnceLock<Mutex<File>> = OnceLock::new();
+
+// Opens `path` for appending and records every following search in it.
+pub fn init(path: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    // Only the first call installs a log.
+    let _ = QUERY_LOG.set(Mutex::new(file));
+    Ok(())
+}
+
+// Appends one JSON line describing a search, e.g.
+// {"time":"2024-05-01T12:00:00Z","queries":["ls"],"mode":"exact",
+//  "limit":null,"section":null,"arch":null,"results":1}
+pub fn record(queries: &[&str], opts: &SearchOptions, results: usize) {
+    let Some(log) = QUERY_LOG.get() else {
+        return;
+    };
+
+    let optional = |value: Option<&str>| value.map_or_else(|| "null".to_string(), json_string);
+    let line = format!(
+        "{{\"time\":{},\"queries\":{},\"mode\":{},\"limit\":{},\"section\":{},\"arch\":{},\"results\":{results}}}\n",
+        json_string(&timestamp()),
+        json_array(queries),
+        json_string(&opts.mode.to_string()),
+        opts.limit.map_or_else(|| "null".to_string(), |limit| limit.to_string()),
+        optional(opts.section.as_deref()),
+        optional(opts.arch.as_deref())
+    );
+
+    let Ok(mut file) = log.lock() else {
+        return;
+    };
+    if let Err(e) = file.write_all(line.as_bytes()) {
+        warn!("Could not write to the query log: {e}");
+    }
+}
+
+// Formats the current time as an RFC 3339 UTC timestamp.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let days = i64::try_from(secs / 86_400).unwrap_or_default();
+    let rem = secs % 86_400;
+
+    // Converts days since 1970-01-01 to a civil date (Howard Hinnant's
+    // days_from_civil algorithm, run in reverse).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600, rem % 3600 / 60, rem % 60)
+}
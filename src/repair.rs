@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use log::debug;
+
+use crate::database::{Database, Version};
+use crate::error::{DbError, ErrorKind};
+use crate::index::Indexes;
+use crate::macros::{self, Macros, Table, Value};
+use crate::pages::{Page, Pages};
+use crate::utils::{parse_num, parse_str};
+
+// The offset of the first page record and the size of each record.
+const PAGES_START: usize = 20;
+const PAGE_LEN: usize = 20;
+
+// Salvages what can be parsed from a damaged mandoc.db, along with the
+// problems that were worked around.
+//
+// Page records that fail to parse are dropped. Missing or broken macro
+// tables are left empty, values whose string cannot be read are dropped, and
+// so are references to page records that were dropped or do not exist.
+// Values left without pages are unreachable from any page and are dropped
+// too. Writing the result with `Database::write` gives a consistent
+// database.
+pub fn repair(bytes: &[u8]) -> (Database<'_>, Vec<DbError>) {
+    let mut problems = Vec::new();
+
+    // The page count says how many records to try, as long as they fit
+    // before the macros table and the end of the file.
+    let macros_idx = parse_num(bytes, 8).map_err(|e| problems.push(e)).ok();
+    let end = macros_idx.filter(|idx| *idx <= bytes.len()).unwrap_or(bytes.len());
+    let fits = end.saturating_sub(PAGES_START) / PAGE_LEN;
+    let count = parse_num(bytes, 16).map_err(|e| problems.push(e)).unwrap_or(fits);
+    if count > fits {
+        problems.push(DbError::at(ErrorKind::Count, 16,
+            format!("Only {fits} of {count} page records fit before offset {end}.")));
+    }
+
+    // The kept pages by the offset of their record, which is how macro
+    // values refer to them.
+    let mut table = Vec::new();
+    let mut records = HashMap::new();
+    for idx in 0..count.min(fits) {
+        let start = PAGES_START + idx * PAGE_LEN;
+        match Page::parse(bytes, start) {
+            Ok(page) => {
+                records.insert(start, table.len());
+                table.push(page);
+            },
+            Err(e) => problems.push(e),
+        }
+    }
+    debug!("Kept {} of {count} pages", table.len());
+
+    // The offsets of the macro tables that can be found.
+    let mut starts = vec![None; macros::KEYS.len()];
+    if let Some(macros_idx) = macros_idx {
+        match parse_num(bytes, macros_idx) {
+            Ok(count) => {
+                if count != starts.len() {
+                    problems.push(DbError::at(ErrorKind::Count, macros_idx,
+                        format!("Expected {} macro tables but found {count}.", starts.len())));
+                }
+                for (key, start) in starts.iter_mut().enumerate().take(count) {
+                    *start = parse_num(bytes, macros_idx + 4 + key * 4)
+                        .map_err(|e| problems.push(e))
+                        .ok();
+                }
+            },
+            Err(e) => problems.push(e),
+        }
+    }
+
+    let tables = starts
+        .into_iter()
+        .enumerate()
+        .map(|(key, start)| {
+            let values = start
+                .map(|start| {
+                    table_values(bytes, start, key, &records, count, &mut problems)
+                })
+                .unwrap_or_default();
+            Table { count: values.len(), values }
+        })
+        .collect::<Vec<Table<'_>>>();
+
+    let db = Database {
+        version: Version::V1,
+        pages: Pages { count: table.len(), table },
+        macros: Macros { count: tables.len(), tables }.into(),
+        indexes: Indexes::default(),
+    };
+    (db, problems)
+}
+
+// Salvages the values of the macro table for `key` at `start`.
+fn table_values<'a>(
+    bytes: &'a [u8],
+    start: usize,
+    key: usize,
+    records: &HashMap<usize, usize>,
+    page_count: usize,
+    problems: &mut Vec<DbError>
+) -> Vec<Value<'a>> {
+    let values_count = match parse_num(bytes, start) {
+        Ok(count) => count,
+        Err(e) => {
+            problems.push(e);
+            return Vec::new();
+        },
+    };
+
+    let mut values = Vec::new();
+    for idx in 0..values_count {
+        let value_idx = start + 4 + idx * 8;
+        if value_idx + 8 > bytes.len() {
+            problems.push(DbError::at(ErrorKind::Count, start,
+                format!("Only {idx} of {values_count} macro values fit in the file.")));
+            break;
+        }
+        let str = match parse_num(bytes, value_idx).and_then(|idx| parse_str(bytes, idx)) {
+            Ok(str) => str,
+            Err(e) => {
+                problems.push(e);
+                continue;
+            },
+        };
+        // The value record fits, so its pages list offset can be read.
+        let Ok(pages_list) = parse_num(bytes, value_idx + 4) else {
+            continue;
+        };
+
+        let mut value_pages = Vec::new();
+        let mut item_idx = pages_list;
+        loop {
+            let record = match parse_num(bytes, item_idx) {
+                Ok(0) => break,
+                Ok(record) => record,
+                Err(_) => {
+                    problems.push(DbError::at(ErrorKind::Encoding, pages_list,
+                        "Unterminated pages list."));
+                    break;
+                },
+            };
+            match records.get(&record) {
+                Some(&page) => value_pages.push(page),
+                None if !macros::is_page_record(record, page_count) => {
+                    problems.push(DbError::at(ErrorKind::Format, item_idx,
+                        format!("Page offset {record} is not a page record.")));
+                },
+                None => problems.push(DbError::at(ErrorKind::Format, item_idx,
+                    format!("The page record at {record} was dropped."))),
+            }
+            item_idx += 4;
+        }
+
+        if value_pages.is_empty() {
+            debug!("Dropping the {} value \"{str}\", which has no pages", macros::KEYS[key]);
+            continue;
+        }
+        values.push(Value { str, pages: value_pages });
+    }
+
+    values
+}
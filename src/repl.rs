@@ -0,0 +1,1008 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+use crate::color;
+use crate::config;
+use crate::database::{self, Database};
+use crate::interrupt;
+use crate::manconf;
+use crate::open::open_page;
+use crate::output::{self, OutputOptions, OutputStyle};
+use crate::pager;
+use crate::pages::{Page, PageField};
+use crate::progress::Progress;
+use crate::search::{MatchMode, SearchOptions};
+use crate::sections;
+use crate::source;
+
+// The number of queries remembered when the config file does not say.
+pub const DEFAULT_HISTORY_SIZE: usize = 1000;
+
+// The width of previews when output is not to a terminal.
+const DEFAULT_PREVIEW_WIDTH: usize = 80;
+
+// The fields shown for each result when the config file does not say. The
+// files and format are left for a result's full entry.
+pub const DEFAULT_FIELDS: &[PageField] = &[
+    PageField::Names, PageField::Sections, PageField::Archs, PageField::Desc,
+];
+
+// The colon commands understood by the search prompt, along with their
+// arguments and descriptions.
+const COMMANDS: &[(&str, &str, &str)] = &[
+    (":help", "", "Show this list of commands."),
+    (":stats", "", "Show a summary of the database."),
+    (":sections", "", "List every section and how many pages it has."),
+    (":archs", "", "List every architecture and how many pages it has."),
+    (":list", "<SECT>", "List the pages in section SECT."),
+    (":dump", "<NAME>", "Show the raw database fields of the pages named NAME."),
+    (":set", "[KEY [VALUE]]",
+        "Show the settings or change match, case, section, limit, or color."),
+    (":db", "[add|use PATH]",
+        "List the databases, or search PATH as well as or instead of them."),
+    (":fields", "[LIST|all]",
+        "Show or choose the fields shown per result, e.g. names,sect,desc."),
+    (":alias", "[NAME [QUERY]]", "List the aliases, or make NAME stand for QUERY."),
+    (":unalias", "<NAME>", "Remove the alias NAME."),
+];
+
+// A database searched at the prompt.
+struct Source<'db, 'a> {
+    db: &'db Database<'a>,
+    // The path the database was loaded from.
+    path: String,
+    // The directory containing the database, used to find page files.
+    dir: Option<PathBuf>,
+    // Whether searches include this database.
+    active: bool,
+}
+
+impl<'db, 'a> Source<'db, 'a> {
+    fn new(db: &'db Database<'a>, path: &str) -> Self {
+        Self {
+            db,
+            path: path.to_string(),
+            dir: manconf::db_dir(path).map(Path::to_path_buf),
+            active: true,
+        }
+    }
+}
+
+// A numbered result along with the index of the database it came from.
+#[derive(Clone, Copy)]
+struct Hit<'db, 'a> {
+    page: &'db Page<'a>,
+    source: usize,
+}
+
+// The search prompt. When stdin is not a terminal, queries are read one per
+// line without prompting, and the session ends at the end of the input.
+pub struct Repl<'db, 'a> {
+    // Every database loaded in the session, starting with the one given on
+    // the command line.
+    sources: Vec<Source<'db, 'a>>,
+    // The manpath directories the databases were found in, which results of
+    // translated pages are labeled with.
+    manpath: Vec<String>,
+    opts: SearchOptions,
+    output: OutputOptions,
+    prompt: String,
+    interactive: bool,
+    pager: bool,
+    // Where queries are saved between sessions, if anywhere.
+    history_path: Option<PathBuf>,
+    // The most queries to remember.
+    history_size: usize,
+    // Words that stand for longer queries, e.g. "net" for
+    // "Xr=netstat OR Nd~network".
+    aliases: BTreeMap<String, String>,
+    // The config file that aliases are saved to, if any.
+    config_path: Option<PathBuf>,
+    // The shell command the current line's output is piped to, if any.
+    pipe: Option<String>,
+}
+
+impl<'db, 'a> Repl<'db, 'a> {
+    pub fn new(
+        db: &'db Database<'a>,
+        opts: SearchOptions,
+        output: OutputOptions
+    ) -> Self {
+        // Prompts are only useful when a person is both typing the queries
+        // and reading the results.
+        let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+
+        Self {
+            sources: vec![Source::new(db, "")],
+            manpath: Vec::new(),
+            opts,
+            output: OutputOptions { fields: Some(DEFAULT_FIELDS.to_vec()), ..output },
+            prompt: "SEARCH: ".to_string(),
+            interactive,
+            pager: true,
+            history_path: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            aliases: BTreeMap::new(),
+            config_path: None,
+            pipe: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    #[must_use]
+    pub const fn with_pager(mut self, pager: bool) -> Self {
+        self.pager = pager;
+        self
+    }
+
+    // Sets the path of the database given to `new`, which page files are
+    // found relative to.
+    #[must_use]
+    pub fn with_db_path(mut self, path: &str) -> Self {
+        let db = self.sources[0].db;
+        self.sources[0] = Source::new(db, path);
+        self
+    }
+
+    // Adds more databases to search, along with the paths they were loaded
+    // from.
+    #[must_use]
+    pub fn with_dbs(mut self, dbs: &'db [Database<'a>], paths: &[String]) -> Self {
+        for (db, path) in dbs.iter().zip(paths) {
+            self.sources.push(Source::new(db, path));
+        }
+        self
+    }
+
+    // Sets the manpath directories the databases were found in.
+    #[must_use]
+    pub fn with_manpath(mut self, manpath: Vec<String>) -> Self {
+        self.manpath = manpath;
+        self
+    }
+
+    // Loads earlier queries from `path` and saves the session's queries
+    // there, keeping at most `size` of them. A size of zero keeps the
+    // history to this session.
+    #[must_use]
+    pub fn with_history(mut self, path: Option<PathBuf>, size: usize) -> Self {
+        self.history_path = path.filter(|_| size > 0);
+        self.history_size = size;
+        self
+    }
+
+    // Sets the fields shown for each result.
+    #[must_use]
+    pub fn with_fields(mut self, fields: Vec<PageField>) -> Self {
+        self.output.fields = Some(fields);
+        self
+    }
+
+    // Uses the aliases from the config file, saving the ones changed at the
+    // prompt to `config_path`.
+    #[must_use]
+    pub fn with_aliases(
+        mut self,
+        aliases: BTreeMap<String, String>,
+        config_path: Option<PathBuf>
+    ) -> Self {
+        self.aliases = aliases;
+        self.config_path = config_path;
+        self
+    }
+
+    pub const fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.interactive {
+            println!("* Type a result's number to show it, \"open N\" or \"open NAME\" \
+                to read a page,\n  \":help\" for more commands, or \"quit\" to exit.\n");
+        }
+
+        let mut input = Input::new(self.interactive, self.active_dbs(), self.history_size)?;
+        if let Some(path) = &self.history_path {
+            input.load_history(path);
+        }
+
+        // The numbered results of the last search, which "N" and "open N"
+        // refer to.
+        let mut results = Vec::new();
+
+        // Ctrl-C cancels the current line or search rather than the session.
+        if self.interactive {
+            interrupt::catch();
+        }
+
+        while let Some(line) = input.read_line(&self.prompt)? {
+            // Forget a Ctrl-C pressed while, for example, the pager was open.
+            interrupt::take();
+
+            let (query, pipe) = split_pipe(line.trim());
+            if pipe == Some("") {
+                println!("Missing a command after \"|\".\n");
+                continue;
+            }
+            self.pipe = pipe.map(str::to_string);
+
+            match query.len() {
+                0 => continue,
+                1 if query == "q" => break,
+                4 if query.eq_ignore_ascii_case("quit") => break,
+                _ if query.starts_with("open ") => self.open(query[5..].trim(), &results),
+                _ if query.starts_with("source ") => self.source(query[7..].trim(), &results)?,
+                _ if query.starts_with("preview ") => self.preview(query[8..].trim(), &results)?,
+                _ if query.parse::<usize>().is_ok() => self.show(query, &results)?,
+                _ if query.starts_with(':') => {
+                    self.command(query, &mut results)?;
+                    input.set_dbs(self.active_dbs());
+                },
+                _ if self.interactive => {
+                    let expanded = self.expand(query);
+                    results = self.search(&expanded);
+                    if results.is_empty() {
+                        self.display(format!("No results for \"{query}\".\n\n").as_bytes())?;
+                        continue;
+                    }
+
+                    let entries = self.numbered(&[&expanded], &results)?;
+                    if interrupt::take() {
+                        results.clear();
+                        println!("Interrupted.\n");
+                    } else {
+                        self.display_numbered(&entries)?;
+                    }
+                },
+                _ => {
+                    let expanded = self.expand(query);
+                    let labels = self.active_labels();
+                    let dbs = self.sources
+                        .iter()
+                        .filter(|source| source.active)
+                        .zip(&labels)
+                        .map(|(source, label)| (source.db, label.as_deref(), source.dir.as_deref()))
+                        .collect::<Vec<(&Database<'_>, Option<&str>, Option<&Path>)>>();
+                    let mut text = Vec::new();
+                    database::write_search_all(&mut text, &dbs, &[&expanded], &self.opts,
+                        &self.output)?;
+                    self.display(&text)?;
+                },
+            }
+        }
+
+        if let Some(path) = &self.history_path {
+            input.save_history(path);
+        }
+
+        Ok(())
+    }
+
+    // Shows the output of a line through the pager, or the command it was
+    // piped to. Commands get plain text, like when a shell pipes output.
+    fn display(&self, text: &[u8]) -> io::Result<()> {
+        match &self.pipe {
+            Some(command) => pager::pipe(&color::strip(text), command),
+            None => pager::show(text, self.pager && self.interactive),
+        }
+    }
+
+    // Returns how the results of each active database are labeled: by
+    // their database when there are several, and not at all otherwise.
+    fn active_labels(&self) -> Vec<Option<String>> {
+        let active = self.sources.iter().filter(|source| source.active);
+        let several = active.clone().count() > 1;
+        active.map(|source| several.then(|| manconf::db_label(&source.path, &self.manpath))).collect()
+    }
+
+    // Returns the databases that searches include.
+    fn active_dbs(&self) -> Vec<&'db Database<'a>> {
+        self.sources
+            .iter()
+            .filter(|source| source.active)
+            .map(|source| source.db)
+            .collect()
+    }
+
+    // Replaces the words of the query that are aliases with what they stand
+    // for. Aliases are not expanded again, so one alias may not refer to
+    // another.
+    fn expand(&self, query: &str) -> String {
+        let words = query.split_whitespace().collect::<Vec<&str>>();
+        if !words.iter().any(|word| self.aliases.contains_key(*word)) {
+            return query.to_string();
+        }
+
+        let expanded = words
+            .iter()
+            .map(|word| self.aliases.get(*word).map_or(*word, String::as_str))
+            .collect::<Vec<&str>>()
+            .join(" ");
+        debug!("Expanded \"{query}\" to \"{expanded}\"");
+        expanded
+    }
+
+    // Searches every active database, in the order they were loaded.
+    fn search(&self, query: &str) -> Vec<Hit<'db, 'a>> {
+        self.sources
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| source.active)
+            .flat_map(|(idx, source)| {
+                source.db
+                    .search(&[query], &self.opts)
+                    .into_iter()
+                    .map(move |page| Hit { page, source: idx })
+            })
+            .take(self.opts.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    // Runs a colon command such as ":list 3". Listed pages become the
+    // numbered results.
+    fn command(&mut self, line: &str, results: &mut Vec<Hit<'db, 'a>>) -> io::Result<()> {
+        let (name, arg) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, arg)| (name, arg.trim()));
+        let mut text = Vec::new();
+
+        match (name, arg) {
+            (":help", _) => write_help(&mut text)?,
+            (":stats", _) => {
+                let active = self.sources.iter().filter(|source| source.active);
+                let several = active.clone().count() > 1;
+                for source in active {
+                    if several {
+                        writeln!(&mut text, "{}:", source.path)?;
+                    }
+                    source.db.write_summary(&mut text)?;
+                }
+            },
+            (":sections", _) => {
+                let counts = self.merge_counts(Database::section_counts);
+                for (sect, count) in counts {
+                    writeln!(&mut text, "{:<36} {count}", sections::describe(sect))?;
+                }
+            },
+            (":archs", _) => {
+                for (arch, count) in self.merge_counts(Database::arch_counts) {
+                    writeln!(&mut text, "{arch:<36} {count}")?;
+                }
+                let independent = self.active_dbs()
+                    .iter()
+                    .flat_map(|db| &db.pages.table)
+                    .filter(|page| page.archs.is_none())
+                    .count();
+                writeln!(&mut text, "{:<36} {independent}", "(machine-independent)")?;
+            },
+            (":list" | ":dump", "") => writeln!(&mut text, "Usage: {name} {}", usage(name))?,
+            (":list", sect) => {
+                *results = self.sources
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, source)| source.active)
+                    .flat_map(|(idx, source)| {
+                        source.db
+                            .pages_in_section(sect)
+                            .into_iter()
+                            .map(move |page| Hit { page, source: idx })
+                    })
+                    .collect();
+                if results.is_empty() {
+                    writeln!(&mut text, "No pages in section {sect}.\n")?;
+                } else if self.interactive {
+                    let entries = self.numbered(&[], results)?;
+                    return self.display_numbered(&entries);
+                } else {
+                    for hit in results.iter() {
+                        output::write_page(&mut text, hit.page, &self.output, &[])?;
+                    }
+                }
+            },
+            (":dump", name) => self.write_dump(&mut text, name)?,
+            (":set", setting) => self.set(&mut text, setting)?,
+            (":db", "") => self.write_dbs(&mut text)?,
+            (":db", arg) => {
+                let (action, path) = arg
+                    .split_once(char::is_whitespace)
+                    .map_or((arg, ""), |(action, path)| (action, path.trim()));
+                match (action, path) {
+                    ("add" | "use", "") => writeln!(&mut text, "Usage: :db {action} PATH")?,
+                    ("add" | "use", path) => match self.load(path) {
+                        Ok(idx) => {
+                            if action == "use" {
+                                for source in &mut self.sources {
+                                    source.active = false;
+                                }
+                            }
+                            self.sources[idx].active = true;
+                            // The numbers may refer to a database no longer
+                            // searched.
+                            results.clear();
+                            self.write_dbs(&mut text)?;
+                        },
+                        Err(e) => writeln!(&mut text, "Error: {e}\n")?,
+                    },
+                    _ => writeln!(&mut text, "Usage: :db {}", usage(":db"))?,
+                }
+            },
+            (":fields", fields) => self.fields(&mut text, fields)?,
+            (":alias", "") => self.write_aliases(&mut text)?,
+            (":alias", arg) => self.alias(&mut text, arg)?,
+            (":unalias", "") => writeln!(&mut text, "Usage: :unalias {}", usage(":unalias"))?,
+            (":unalias", alias) => self.unalias(&mut text, alias)?,
+            _ => writeln!(&mut text, "Unknown command \"{name}\". Type \":help\" for a list.")?,
+        }
+
+        self.display(&text)
+    }
+
+    // Loads the database at `path` unless it already was, and returns its
+    // index in the sources.
+    fn load(&mut self, path: &str) -> Result<usize, Box<dyn Error>> {
+        if let Some(idx) = self.sources.iter().position(|source| source.path == path) {
+            return Ok(idx);
+        }
+
+        let bytes = database::read_db(path)?;
+
+        // Pages borrow from the file's bytes, so databases loaded at the
+        // prompt are kept until the program exits, just like the one given on
+        // the command line.
+        let bytes: &'static [u8] = Box::leak(Box::new(bytes));
+        let progress = Progress::for_bytes(bytes.len());
+        let db = Database::parse_with_progress(bytes, &progress);
+        progress.finish();
+        let db: &'db Database<'a> = Box::leak(Box::new(db?));
+
+        debug!("Loaded {path} with {} pages", db.num_pages());
+        self.sources.push(Source::new(db, path));
+        Ok(self.sources.len() - 1)
+    }
+
+    // Lists the loaded databases, marking the ones that searches include.
+    fn write_dbs(&self, out: &mut dyn Write) -> io::Result<()> {
+        for source in &self.sources {
+            writeln!(out, "{} {} ({} pages)",
+                if source.active { "*" } else { " " },
+                source.path,
+                source.db.num_pages())?;
+        }
+        writeln!(out)
+    }
+
+    // Adds up the per-database counts of the active databases.
+    fn merge_counts(
+        &self,
+        counts: fn(&Database<'a>) -> Vec<(&'a str, usize)>
+    ) -> BTreeMap<&'a str, usize> {
+        let mut merged = BTreeMap::new();
+        for db in self.active_dbs() {
+            for (key, count) in counts(db) {
+                *merged.entry(key).or_insert(0) += count;
+            }
+        }
+        merged
+    }
+
+    // Changes a setting, e.g. "match regex" or "limit 10". The settings are
+    // shown afterwards, or right away when `setting` is empty.
+    fn set(&mut self, out: &mut dyn Write, setting: &str) -> io::Result<()> {
+        let (key, value) = setting
+            .split_once(char::is_whitespace)
+            .map_or((setting, ""), |(key, value)| (key, value.trim()));
+
+        let changed = match (key, value) {
+            ("", _) => Ok(()),
+            ("match", mode) => mode
+                .parse()
+                .map(|mode| self.opts.mode = mode)
+                .map_err(|_| format!("The match mode is one of: {}.", MatchMode::NAMES.join(", "))),
+            ("case", "sensitive" | "on") => {
+                self.opts.case_sensitive = true;
+                Ok(())
+            },
+            ("case", "insensitive" | "off") => {
+                self.opts.case_sensitive = false;
+                Ok(())
+            },
+            ("case", _) => Err("The case is either sensitive or insensitive.".to_string()),
+            // An empty section, or "any", removes the filter.
+            ("section", "" | "any") => {
+                self.opts.section = None;
+                Ok(())
+            },
+            ("section", sect) => {
+                self.opts.section = Some(sect.to_string());
+                Ok(())
+            },
+            ("limit", "" | "none") => {
+                self.opts.limit = None;
+                Ok(())
+            },
+            ("limit", limit) => limit
+                .parse()
+                .map(|limit| self.opts.limit = Some(limit))
+                .map_err(|_| format!("Invalid limit \"{limit}\".")),
+            ("color", "on" | "always") => {
+                self.output.color = true;
+                Ok(())
+            },
+            ("color", "off" | "never") => {
+                self.output.color = false;
+                Ok(())
+            },
+            ("color", _) => Err("The color is either on or off.".to_string()),
+            _ => Err(format!("Unknown setting \"{key}\".")),
+        };
+
+        if let Err(e) = changed {
+            return writeln!(out, "{e}\n");
+        }
+
+        writeln!(out, "match    {}", self.opts.mode)?;
+        writeln!(out, "case     {}",
+            if self.opts.case_sensitive { "sensitive" } else { "insensitive" })?;
+        writeln!(out, "section  {}", self.opts.section.as_deref().unwrap_or("any"))?;
+        writeln!(out, "limit    {}",
+            self.opts.limit.map_or_else(|| "none".to_string(), |limit| limit.to_string()))?;
+        writeln!(out, "color    {}\n", if self.output.color { "on" } else { "off" })
+    }
+
+    // Changes the fields shown for each result, e.g. "names,sect,desc" or
+    // "all". The fields are shown afterwards, or right away when `fields` is
+    // empty.
+    fn fields(&mut self, out: &mut dyn Write, fields: &str) -> io::Result<()> {
+        if !fields.is_empty() {
+            match PageField::parse_list(fields) {
+                Ok(fields) => self.output.fields = Some(fields),
+                Err(e) => return writeln!(out, "{e}\n"),
+            }
+        }
+
+        let shown = self.output.fields.as_deref().unwrap_or(PageField::ALL);
+        let names = shown.iter().map(ToString::to_string).collect::<Vec<String>>();
+        writeln!(out, "Fields: {}\n", names.join(","))
+    }
+
+    fn write_aliases(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.aliases.is_empty() {
+            return writeln!(out, "No aliases. Add one with \":alias NAME QUERY\".\n");
+        }
+
+        let width = self.aliases.keys().map(String::len).max().unwrap_or(0);
+        for (alias, query) in &self.aliases {
+            writeln!(out, "{alias:<width$}  {query}")?;
+        }
+        writeln!(out)
+    }
+
+    // Shows an alias, or defines one and saves it to the config file, e.g.
+    // "net Xr=netstat OR Nd~network".
+    fn alias(&mut self, out: &mut dyn Write, arg: &str) -> io::Result<()> {
+        let (alias, query) = arg
+            .split_once(char::is_whitespace)
+            .map_or((arg, ""), |(alias, query)| (alias, query.trim()));
+
+        if query.is_empty() {
+            return match self.aliases.get(alias) {
+                Some(query) => writeln!(out, "{alias}  {query}\n"),
+                None => writeln!(out, "No alias named \"{alias}\".\n"),
+            };
+        }
+
+        // Alias names are bare TOML keys, and "OR" separates terms.
+        let valid = alias != "OR"
+            && alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return writeln!(out,
+                "An alias name may only contain letters, digits, \"_\", and \"-\".\n");
+        }
+
+        self.aliases.insert(alias.to_string(), query.to_string());
+        self.save_alias(out, alias, Some(query))
+    }
+
+    fn unalias(&mut self, out: &mut dyn Write, alias: &str) -> io::Result<()> {
+        if self.aliases.remove(alias).is_none() {
+            return writeln!(out, "No alias named \"{alias}\".\n");
+        }
+        self.save_alias(out, alias, None)
+    }
+
+    // Writes a changed alias to the config file. The change applies to the
+    // session even if it cannot be saved.
+    fn save_alias(
+        &self,
+        out: &mut dyn Write,
+        alias: &str,
+        query: Option<&str>
+    ) -> io::Result<()> {
+        let Some(path) = &self.config_path else {
+            return writeln!(out, "No config file to save the alias in; it lasts this session.\n");
+        };
+
+        match config::save_alias(path, alias, query) {
+            Ok(()) => writeln!(out, "Saved to {}.\n", path.display()),
+            Err(e) => writeln!(out, "Error: {e}\n"),
+        }
+    }
+
+    // Writes every field of the pages named `name` as stored in the
+    // database, including the name sources.
+    fn write_dump(&self, out: &mut dyn Write, name: &str) -> io::Result<()> {
+        let mut found = false;
+        let pages = self.sources
+            .iter()
+            .filter(|source| source.active)
+            .flat_map(|source| source.db.pages.table.iter().enumerate().map(move |p| (source, p)));
+
+        for (source, (idx, page)) in pages {
+            if !page.names.iter().any(|n| n.value.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+            found = true;
+
+            writeln!(out, "Page {idx} of {}:", source.path)?;
+            writeln!(out, "  names:")?;
+            for n in &page.names {
+                writeln!(out, "    {} ({:#04x}: {})", n.value, n.source, n.sources().join(", "))?;
+            }
+            writeln!(out, "  sections: {}", page.sects.join(", "))?;
+            match &page.archs {
+                Some(archs) => writeln!(out, "  archs: {}", archs.join(", "))?,
+                None => writeln!(out, "  archs: none (machine-independent)")?,
+            }
+            writeln!(out, "  desc: {:?}", page.desc)?;
+            writeln!(out, "  files: {}", page.files.join(", "))?;
+            writeln!(out, "  format: {}\n", page.format)?;
+        }
+
+        if !found {
+            writeln!(out, "No page named \"{name}\".\n")?;
+        }
+
+        Ok(())
+    }
+
+    // Renders the results with their numbers, e.g. "[2] ls(1) - list ...",
+    // one entry per result. Lines after the first are indented to line up
+    // with the first.
+    fn numbered(
+        &self,
+        queries: &[&str],
+        results: &[Hit<'_, '_>]
+    ) -> io::Result<Vec<Vec<u8>>> {
+        let indent = format!("[{}] ", results.len()).len();
+        let output = OutputOptions {
+            width: self.output.width.map(|width| width.saturating_sub(indent)),
+            ..self.output.clone()
+        };
+
+        // Results are labeled with their database when several are searched.
+        let several = self.sources.iter().filter(|source| source.active).count() > 1;
+
+        let mut entries = Vec::with_capacity(results.len());
+        for (idx, hit) in results.iter().enumerate() {
+            if interrupt::is_requested() {
+                break;
+            }
+
+            let source = &self.sources[hit.source];
+            let label = several.then(|| manconf::db_label(&source.path, &self.manpath));
+            let output = OutputOptions { label, man_dir: source.dir.clone(), ..output.clone() };
+            let mut page = Vec::new();
+            output::write_page(&mut page, hit.page, &output, queries)?;
+
+            let mut entry = Vec::new();
+            for (line_idx, line) in String::from_utf8_lossy(&page).lines().enumerate() {
+                if line_idx == 0 {
+                    write!(entry, "{:indent$}", format!("[{}]", idx + 1))?;
+                } else if !line.is_empty() {
+                    write!(entry, "{:indent$}", "")?;
+                }
+                writeln!(entry, "{line}")?;
+            }
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    // Shows numbered results a screenful at a time, unless the line was
+    // piped to a command or paging is disabled.
+    fn display_numbered(&self, entries: &[Vec<u8>]) -> io::Result<()> {
+        if self.pipe.is_none() && self.pager {
+            return pager::more(entries);
+        }
+        self.display(&entries.concat())
+    }
+
+    // Shows the full entry of the numbered result.
+    fn show(&self, number: &str, results: &[Hit<'_, '_>]) -> io::Result<()> {
+        let Some(hit) = select(number, results) else {
+            println!("No result {number}.\n");
+            return Ok(());
+        };
+
+        let output = OutputOptions {
+            style: OutputStyle::Detailed,
+            fields: None,
+            man_dir: self.sources[hit.source].dir.clone(),
+            ..self.output.clone()
+        };
+        let mut text = Vec::new();
+        output::write_page(&mut text, hit.page, &output, &[])?;
+        self.display(&text)
+    }
+
+    // Returns the numbered result, or the first result for `query`, telling
+    // the user when there is none.
+    fn find(&self, query: &str, results: &[Hit<'db, 'a>]) -> Option<Hit<'db, 'a>> {
+        if query.parse::<usize>().is_ok() {
+            let hit = select(query, results);
+            if hit.is_none() {
+                println!("No result {query}.\n");
+            }
+            return hit;
+        }
+
+        let hit = self.search(&self.expand(query)).first().copied();
+        if hit.is_none() {
+            println!("No results for \"{query}\".\n");
+        }
+        hit
+    }
+
+    // Opens the numbered result, or the first result for `query`, in man(1).
+    fn open(&self, query: &str, results: &[Hit<'db, 'a>]) {
+        let Some(hit) = self.find(query, results) else {
+            return;
+        };
+
+        if let Err(e) = open_page(hit.page, self.sources[hit.source].dir.as_deref()) {
+            eprintln!("Error: {e}");
+        }
+    }
+
+    // Shows the source file of the numbered result, or of the first result
+    // for `query`, decompressed if it has to be.
+    fn source(&self, query: &str, results: &[Hit<'db, 'a>]) -> io::Result<()> {
+        let Some(hit) = self.find(query, results) else {
+            return Ok(());
+        };
+
+        let man_dir = self.sources[hit.source].dir.as_deref().unwrap_or(Path::new("."));
+        match source::read_page_source(hit.page, man_dir) {
+            Ok((_, bytes)) => self.display(&bytes),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                Ok(())
+            },
+        }
+    }
+
+    // Shows the NAME and SYNOPSIS sections of the numbered result, or of the
+    // first result for `query`, rendered from its source file.
+    fn preview(&self, query: &str, results: &[Hit<'db, 'a>]) -> io::Result<()> {
+        let Some(hit) = self.find(query, results) else {
+            return Ok(());
+        };
+
+        let man_dir = self.sources[hit.source].dir.as_deref().unwrap_or(Path::new("."));
+        let width = self.output.width.unwrap_or(DEFAULT_PREVIEW_WIDTH);
+        match source::preview_page(hit.page, man_dir, width) {
+            Ok(text) => self.display(format!("{text}\n").as_bytes()),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                Ok(())
+            },
+        }
+    }
+}
+
+// Writes the colon commands along with their descriptions.
+fn write_help(out: &mut dyn Write) -> io::Result<()> {
+    let usages = COMMANDS
+        .iter()
+        .map(|(name, args, _)| format!("{name} {args}").trim_end().to_string())
+        .collect::<Vec<String>>();
+    let width = usages.iter().map(String::len).max().unwrap_or(0);
+
+    for (usage, (_, _, help)) in usages.iter().zip(COMMANDS) {
+        writeln!(out, "  {usage:width$}   {help}")?;
+    }
+    writeln!(out, "  {:width$}   Show the full entry of result N.", "N")?;
+    writeln!(out, "  {:width$}   Read result N or the first result for NAME.", "open N|NAME")?;
+    writeln!(out, "  {:width$}   Show the source file of result N or the first result for NAME.",
+        "source N|NAME")?;
+    writeln!(out, "  {:width$}   Show the NAME and SYNOPSIS of result N or the first result for \
+        NAME.", "preview N|NAME")?;
+    writeln!(out, "  {:width$}   Exit.\n", "quit")
+}
+
+// Returns the arguments of a colon command, e.g. "<SECT>" for ":list".
+fn usage(name: &str) -> &'static str {
+    COMMANDS
+        .iter()
+        .find(|(command, _, _)| *command == name)
+        .map_or("", |(_, args, _)| args)
+}
+
+// Splits "QUERY | COMMAND" into the query and the shell command. The "|"
+// must follow a space, so regular expressions like "ls|cat" are left alone.
+fn split_pipe(line: &str) -> (&str, Option<&str>) {
+    match line.find(" |") {
+        Some(idx) => (line[..idx].trim_end(), Some(line[idx + 2..].trim())),
+        None => (line, None),
+    }
+}
+
+// Returns the result with the given 1-based number, if there is one.
+fn select<'db, 'a>(number: &str, results: &[Hit<'db, 'a>]) -> Option<Hit<'db, 'a>> {
+    let idx = number.parse::<usize>().ok()?.checked_sub(1)?;
+    results.get(idx).copied()
+}
+
+// Completes the word before the cursor with the page names it starts, or
+// with a colon command at the start of the line.
+struct NameCompleter<'db, 'a> {
+    dbs: Vec<&'db Database<'a>>,
+}
+
+impl Completer for NameCompleter<'_, '_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        // A colon at the start of the line begins a command.
+        if line[..pos].starts_with(':') && !line[..pos].contains(char::is_whitespace) {
+            let commands = COMMANDS
+                .iter()
+                .map(|(name, _, _)| *name)
+                .filter(|name| name.starts_with(&line[..pos]))
+                .map(str::to_string)
+                .collect();
+            return Ok((0, commands));
+        }
+
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map_or(0, |idx| idx + 1);
+        let mut names = self.dbs
+            .iter()
+            .flat_map(|db| db.complete(&line[start..pos]))
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+        names.sort_unstable();
+        names.dedup();
+
+        Ok((start, names))
+    }
+}
+
+impl Hinter for NameCompleter<'_, '_> {
+    type Hint = String;
+}
+
+impl Highlighter for NameCompleter<'_, '_> {}
+
+impl Validator for NameCompleter<'_, '_> {}
+
+impl Helper for NameCompleter<'_, '_> {}
+
+// Where the search prompt reads its lines from. A terminal gets a line editor
+// with cursor movement, kill/yank, up/down history for the session, and tab
+// completion of page names.
+enum Input<'db, 'a> {
+    Editor(Box<Editor<NameCompleter<'db, 'a>, DefaultHistory>>),
+    Stdin,
+}
+
+impl<'db, 'a> Input<'db, 'a> {
+    fn new(
+        interactive: bool,
+        dbs: Vec<&'db Database<'a>>,
+        history_size: usize
+    ) -> Result<Self, Box<dyn Error>> {
+        if !interactive {
+            return Ok(Self::Stdin);
+        }
+
+        // Like a shell, list every candidate when a name is ambiguous.
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .max_history_size(history_size.max(1))?
+            .history_ignore_dups(true)?
+            .build();
+        let mut editor = Editor::with_config(config)?;
+        editor.set_helper(Some(NameCompleter { dbs }));
+
+        Ok(Self::Editor(Box::new(editor)))
+    }
+
+    // Completes names from these databases from now on.
+    fn set_dbs(&mut self, dbs: Vec<&'db Database<'a>>) {
+        if let Self::Editor(editor) = self {
+            editor.set_helper(Some(NameCompleter { dbs }));
+        }
+    }
+
+    // Adds the queries saved in the history file, if it exists.
+    fn load_history(&mut self, path: &Path) {
+        let Self::Editor(editor) = self else {
+            return;
+        };
+
+        match editor.load_history(path) {
+            Ok(()) => debug!("Loaded the history from {}", path.display()),
+            Err(ReadlineError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => warn!("Could not read the history file {}: {e}", path.display()),
+        }
+    }
+
+    // Writes the queries, including earlier sessions', to the history file.
+    fn save_history(&mut self, path: &Path) {
+        let Self::Editor(editor) = self else {
+            return;
+        };
+
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(ReadlineError::from)
+            .and_then(|()| editor.save_history(path));
+        if let Err(e) = saved {
+            warn!("Could not write the history file {}: {e}", path.display());
+        }
+    }
+
+    // Returns the next line, or `None` at the end of the input.
+    fn read_line(&mut self, prompt: &str) -> Result<Option<String>, Box<dyn Error>> {
+        match self {
+            Self::Editor(editor) => match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        editor.add_history_entry(line.as_str())?;
+                    }
+                    Ok(Some(line))
+                },
+                // Ctrl-C discards the line and Ctrl-D on an empty line ends
+                // the session like "quit".
+                Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+                Err(ReadlineError::Eof) => Ok(None),
+                Err(e) => Err(e.into()),
+            },
+            Self::Stdin => {
+                let mut line = String::with_capacity(50);
+
+                // Zero bytes read means the end of the input was reached.
+                if io::stdin().lock().read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                Ok(Some(line))
+            },
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+use regex_syntax::ParserBuilder;
+
+use crate::macros::{self, Macros};
+use crate::pages::Page;
+
+// How a query is compared against page names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    // The query must equal a name.
+    #[default]
+    Exact,
+    // The query may appear anywhere within a name.
+    Substring,
+    // A name must start with the query.
+    Prefix,
+    // The query is a regular expression that must match part of a name.
+    Regex,
+    // The characters of the query must appear in a name in order, though
+    // not necessarily next to each other (e.g. "pcr" matches
+    // "pthread_create").
+    Fuzzy,
+}
+
+impl MatchMode {
+    pub const NAMES: &'static [&'static str] = &[
+        "exact", "substring", "prefix", "regex", "fuzzy",
+    ];
+}
+
+impl Display for MatchMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Exact => f.write_str("exact"),
+            Self::Substring => f.write_str("substring"),
+            Self::Prefix => f.write_str("prefix"),
+            Self::Regex => f.write_str("regex"),
+            Self::Fuzzy => f.write_str("fuzzy"),
+        }
+    }
+}
+
+impl FromStr for MatchMode {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Self::Exact),
+            "substring" => Ok(Self::Substring),
+            "prefix" => Ok(Self::Prefix),
+            "regex" => Ok(Self::Regex),
+            "fuzzy" => Ok(Self::Fuzzy),
+            _ => Err(format!("Unknown match mode \"{s}\".").into()),
+        }
+    }
+}
+
+// The part of a page that a query is compared against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Desc,
+    Section,
+    Arch,
+    // The arguments of a macro, by its index in `macros::KEYS`.
+    Macro(usize),
+}
+
+impl Field {
+    // Looks up an apropos(1) search key such as "Nd" or "Xr".
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "Nm" => Some(Self::Name),
+            "Nd" => Some(Self::Desc),
+            "sec" => Some(Self::Section),
+            "arch" => Some(Self::Arch),
+            _ => macros::KEYS.iter().position(|k| *k == key).map(Self::Macro),
+        }
+    }
+}
+
+// A query prepared for comparing against pages, so that a regular
+// expression is compiled once per search rather than once per name.
+#[derive(Clone, Debug)]
+pub struct Matcher {
+    field: Field,
+    mode: MatchMode,
+    // The query, lowercased unless matching is case-sensitive.
+    query: String,
+    regex: Option<Regex>,
+    case_sensitive: bool,
+    // For macro fields, the positions of the pages with a matching argument.
+    // Filled in by `resolve`.
+    macro_pages: HashSet<usize>,
+}
+
+impl Matcher {
+    pub fn new(
+        query: &str,
+        mode: MatchMode,
+        case_sensitive: bool
+    ) -> Result<Self, Box<dyn Error>> {
+        let regex = match mode {
+            MatchMode::Regex => Some(RegexBuilder::new(query)
+                .case_insensitive(!case_sensitive)
+                .build()
+                .map_err(|e| format!("Invalid regular expression \"{query}\": {e}"))?),
+            _ => None,
+        };
+        let query = if case_sensitive {
+            query.to_string()
+        } else {
+            query.to_ascii_lowercase()
+        };
+
+        Ok(Self {
+            field: Field::Name,
+            mode,
+            query,
+            regex,
+            case_sensitive,
+            macro_pages: HashSet::new(),
+        })
+    }
+
+    // Parses a term of a query. Like apropos(1), "KEY=VALUE" matches pages
+    // whose KEY field contains VALUE and "KEY~VALUE" those where it matches
+    // the regular expression VALUE, e.g. "Xr=netstat" or "Nd~^network".
+    // Anything else is compared against page names using `mode`, unless it
+    // chooses a mode of its own: "/REGEX/", "=EXACT", "~FUZZY", or "^PREFIX".
+    pub fn parse(
+        term: &str,
+        mode: MatchMode,
+        case_sensitive: bool
+    ) -> Result<Self, Box<dyn Error>> {
+        let field_term = term
+            .find(['=', '~'])
+            .and_then(|idx| Some((Field::from_key(&term[..idx])?, idx)));
+
+        let Some((field, idx)) = field_term else {
+            let (query, mode) = split_mode(term).unwrap_or((term, mode));
+            return Self::new(query, mode, case_sensitive);
+        };
+
+        let mode = if term[idx..].starts_with('~') {
+            MatchMode::Regex
+        } else {
+            MatchMode::Substring
+        };
+        let mut matcher = Self::new(&term[idx + 1..], mode, case_sensitive)?;
+        matcher.field = field;
+        Ok(matcher)
+    }
+
+    // Whether the matcher's field is a macro, which `resolve` needs the
+    // macros for.
+    pub const fn needs_macros(&self) -> bool {
+        matches!(self.field, Field::Macro(_))
+    }
+
+    // Finds the pages a macro field matches. Other fields need no
+    // preparation.
+    pub fn resolve(&mut self, macros: &Macros<'_>) {
+        let Field::Macro(idx) = self.field else {
+            return;
+        };
+        let Some(table) = macros.tables.get(idx) else {
+            return;
+        };
+
+        self.macro_pages = table.values
+            .iter()
+            .filter(|value| self.is_match(value.str))
+            .flat_map(|value| value.pages.iter().copied())
+            .collect();
+    }
+
+    // Whether the matcher's field of the page at position `idx` in the pages
+    // table matches.
+    pub fn matches_page(&self, idx: usize, page: &Page<'_>) -> bool {
+        match self.field {
+            Field::Name => page.names.iter().any(|n| self.is_match(n.value)),
+            Field::Desc => self.is_match(page.desc),
+            Field::Section => page.sects.iter().any(|s| self.is_match(s)),
+            Field::Arch => page.archs.iter().flatten().any(|a| self.is_match(a)),
+            Field::Macro(_) => self.macro_pages.contains(&idx),
+        }
+    }
+
+    // Returns the match mode and the query when the matcher compares page
+    // names without a regular expression. The query is lowercased unless
+    // matching is case-sensitive.
+    pub fn name_query(&self) -> Option<(MatchMode, &str)> {
+        (self.field == Field::Name && self.regex.is_none()).then_some((self.mode, &self.query))
+    }
+
+    // Returns the query when the matcher looks for it within descriptions,
+    // as "Nd=VALUE" does. The query is lowercased unless matching is
+    // case-sensitive.
+    pub fn desc_query(&self) -> Option<&str> {
+        (self.field == Field::Desc && self.mode == MatchMode::Substring && self.regex.is_none())
+            .then_some(&self.query)
+    }
+
+    // Returns the field and the texts of which a matching page's field must
+    // contain one, ignoring ASCII case, when the matcher compares names or
+    // descriptions by substring or regular expression. For a regular
+    // expression these are the literals its matches start with, if it has
+    // few enough of them.
+    pub fn required_texts(&self) -> Option<(Field, Vec<String>)> {
+        if !matches!(self.field, Field::Name | Field::Desc) {
+            return None;
+        }
+        match (&self.regex, self.mode) {
+            (Some(regex), _) => {
+                Some((self.field, regex_prefixes(regex.as_str(), self.case_sensitive)?))
+            },
+            (None, MatchMode::Substring) => Some((self.field, vec![self.query.clone()])),
+            _ => None,
+        }
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        if let Some(regex) = &self.regex {
+            return regex.is_match(name);
+        }
+
+        if self.mode == MatchMode::Exact {
+            return if self.case_sensitive {
+                name == self.query
+            } else {
+                name.eq_ignore_ascii_case(&self.query)
+            };
+        }
+
+        let name = if self.case_sensitive {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(name.to_ascii_lowercase())
+        };
+
+        match self.mode {
+            MatchMode::Fuzzy => fuzzy_score(&name, &self.query, true).is_some(),
+            MatchMode::Prefix => name.starts_with(self.query.as_str()),
+            _ => name.contains(self.query.as_str()),
+        }
+    }
+}
+
+// Scores how well `name` matches `query` when the query's characters must
+// appear in the name in order, or returns `None` when they do not. Like fzf,
+// characters that follow each other or start a word (e.g. after "_") score
+// higher, while skipped characters count against the name.
+pub fn fuzzy_score(name: &str, query: &str, case_sensitive: bool) -> Option<i64> {
+    let fold = |c: char| if case_sensitive { c } else { c.to_ascii_lowercase() };
+    let name = name.chars().collect::<Vec<char>>();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut prev_match: Option<usize> = None;
+
+    for q in query.chars().map(fold) {
+        let idx = pos + name[pos..].iter().position(|c| fold(*c) == q)?;
+
+        score += 1;
+        if prev_match.is_some_and(|prev| prev + 1 == idx) {
+            score += 5;
+        }
+        let word_start = idx == 0
+            || matches!(name[idx - 1], '_' | '-' | '.' | ':' | '/')
+            || (name[idx - 1].is_ascii_lowercase() && name[idx].is_ascii_uppercase());
+        if word_start {
+            score += 8;
+        }
+        score -= i64::try_from(idx - pos).unwrap_or(i64::MAX).min(5);
+
+        prev_match = Some(idx);
+        pos = idx + 1;
+    }
+
+    Some(score)
+}
+
+// Returns the literals that every match of the regular expression starts
+// with one of, or `None` when there are too many of them to be useful, e.g.
+// for "[a-z]+". Ignoring case, the literals include every case of them that
+// the expression matches, such as the Kelvin sign for "k".
+fn regex_prefixes(pattern: &str, case_sensitive: bool) -> Option<Vec<String>> {
+    let hir = ParserBuilder::new()
+        .case_insensitive(!case_sensitive)
+        .build()
+        .parse(pattern)
+        .ok()?;
+    let seq = Extractor::new().kind(ExtractKind::Prefix).extract(&hir);
+    seq.literals()?
+        .iter()
+        .map(|literal| String::from_utf8(literal.as_bytes().to_vec()).ok())
+        .collect()
+}
+
+// Settings that control how a search is performed.
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    pub mode: MatchMode,
+    // Whether names must match the case of the query.
+    pub case_sensitive: bool,
+    // The maximum number of results to return, if any.
+    pub limit: Option<usize>,
+    // Only match pages in this section.
+    pub section: Option<String>,
+    // Only match pages for this architecture or machine-independent pages.
+    pub arch: Option<String>,
+}
+
+impl SearchOptions {
+    // Prepares a query for matching with these options.
+    pub fn matcher(&self, query: &str) -> Result<Matcher, Box<dyn Error>> {
+        Matcher::parse(query, self.mode, self.case_sensitive)
+    }
+
+    // Prepares a query made of terms separated by "OR", any of which may
+    // match, e.g. "Xr=netstat OR Nd~network".
+    pub fn matchers(&self, query: &str) -> Result<Vec<Matcher>, Box<dyn Error>> {
+        split_or(query)
+            .into_iter()
+            .map(|term| self.matcher(term))
+            .collect()
+    }
+
+    pub fn matches(&self, idx: usize, page: &Page<'_>, matcher: &Matcher) -> bool {
+        self.filters_match(page) && matcher.matches_page(idx, page)
+    }
+
+    // Like apropos(1), sections and architectures compare case-insensitively.
+    pub fn filters_match(&self, page: &Page<'_>) -> bool {
+        let section_ok = self.section.as_ref().is_none_or(|section| {
+            page.sects.iter().any(|s| s.eq_ignore_ascii_case(section))
+        });
+
+        let arch_ok = match (&self.arch, &page.archs) {
+            (Some(arch), Some(archs)) => {
+                archs.iter().any(|a| a.eq_ignore_ascii_case(arch))
+            },
+            _ => true,
+        };
+
+        section_ok && arch_ok
+    }
+}
+
+// Splits the match mode prefix off a name query, e.g. "^pthread" into
+// "pthread" and `MatchMode::Prefix`.
+fn split_mode(term: &str) -> Option<(&str, MatchMode)> {
+    if let Some(regex) = term.strip_prefix('/').and_then(|t| t.strip_suffix('/')) {
+        return Some((regex, MatchMode::Regex));
+    }
+
+    let mode = match term.chars().next()? {
+        '=' => MatchMode::Exact,
+        '~' => MatchMode::Fuzzy,
+        '^' => MatchMode::Prefix,
+        _ => return None,
+    };
+    Some((&term[1..], mode))
+}
+
+// Splits a query on the word "OR". A query without one is a single term,
+// even if it contains spaces.
+fn split_or(query: &str) -> Vec<&str> {
+    let words = query.split_whitespace().collect::<Vec<&str>>();
+    if !words.contains(&"OR") {
+        return vec![query.trim()];
+    }
+
+    let mut terms = Vec::new();
+    let mut rest = query;
+    while let Some(idx) = find_word(rest, "OR") {
+        terms.push(rest[..idx].trim());
+        rest = &rest[idx + 2..];
+    }
+    terms.push(rest.trim());
+    terms.retain(|term| !term.is_empty());
+    terms
+}
+
+// Returns the byte index of `word` where it appears as a whole word.
+fn find_word(text: &str, word: &str) -> Option<usize> {
+    text.match_indices(word).map(|(idx, _)| idx).find(|&idx| {
+        let before = text[..idx].chars().next_back();
+        let after = text[idx + word.len()..].chars().next();
+        before.is_none_or(char::is_whitespace) && after.is_none_or(char::is_whitespace)
+    })
+}
@@ -0,0 +1,34 @@
+// Returns a human-readable title for a manual section, following the titles
+// used by mandoc(1). Unknown suffixes such as "3ssl" or "1m" fall back to the
+// title of the numbered section they extend.
+pub fn section_title(sect: &str) -> Option<&'static str> {
+    let title = match sect.to_ascii_lowercase().as_str() {
+        "3p" => "Perl Library Functions",
+        "3f" => "Fortran Library Functions",
+        "n" => "Tcl/Tk Commands",
+        "l" => "Local Commands",
+        "o" => "Old Commands",
+        "x" => "X Window System",
+        "unass" | "draft" => "Unassigned",
+        sect => match sect.as_bytes().first()? {
+            b'1' => "General Commands",
+            b'2' => "System Calls",
+            b'3' => "Library Functions",
+            b'4' => "Device Drivers",
+            b'5' => "File Formats",
+            b'6' => "Games",
+            b'7' => "Miscellaneous Information",
+            b'8' => "System Manager's Manual",
+            b'9' => "Kernel Developer's Manual",
+            _ => return None,
+        },
+    };
+
+    Some(title)
+}
+
+// Formats a section along with its title, e.g. "3 (Library Functions)".
+pub fn describe(sect: &str) -> String {
+    section_title(sect)
+        .map_or_else(|| sect.to_string(), |title| format!("{sect} ({title})"))
+}
@@ -0,0 +1,237 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use log::debug;
+
+use crate::database::{self, Database};
+use crate::error::{self, DbError, ErrorKind};
+use crate::json::{self, JsonValue};
+use crate::output::{self, json_array, json_string};
+use crate::pages::Page;
+use crate::search::{MatchMode, SearchOptions};
+use crate::source;
+
+// The methods that requests can call.
+pub const METHODS: &[&str] = &["search", "complete", "get-page"];
+
+// Answers requests read from standard input, one JSON object per line, with
+// one JSON object per line on standard output, until standard input ends.
+// This lets an editor plugin keep the databases loaded and look up pages as
+// the user types.
+//
+// A request names its method and parameters, and may have an id that the
+// response repeats:
+//
+//   {"id":1,"method":"search","params":{"query":"ls","match":"prefix"}}
+//   {"id":1,"result":[{"names":["ls"],...,"paths":["/usr/share/man/man1/ls.1"]}]}
+//
+// A request that fails gets an error like those of `--errors json` instead
+// of a result:
+//
+//   {"id":2,"error":{"kind":"not_found","offset":null,"message":"..."}}
+pub fn serve_stdio(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions
+) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        writeln!(out, "{}", respond(dbs, opts, &line))?;
+        out.flush()?;
+    }
+    debug!("Standard input ended; no more requests to serve");
+    Ok(())
+}
+
+// Answers one request, returning the response without a newline. The
+// search options apply unless the request's parameters change them.
+pub fn respond(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions,
+    request: &str
+) -> String {
+    let (id, result) = match json::parse(request) {
+        Ok(request) => {
+            let id = request.get("id").cloned().unwrap_or(JsonValue::Null);
+            (id, handle(dbs, opts, &request))
+        },
+        Err(e) => (JsonValue::Null, Err(format!("Invalid request: {e}").into())),
+    };
+
+    match result {
+        Ok(result) => format!("{{\"id\":{id},\"result\":{result}}}"),
+        Err(e) => format!("{{\"id\":{id},\"error\":{}}}", error::to_json(e.as_ref())),
+    }
+}
+
+// Calls the method of a request, returning its result as JSON.
+fn handle(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions,
+    request: &JsonValue
+) -> Result<String, Box<dyn Error>> {
+    let method = request
+        .get("method")
+        .and_then(JsonValue::as_str)
+        .ok_or("The request has no \"method\".")?;
+    let params = request.get("params").unwrap_or(&JsonValue::Null);
+    call(dbs, opts, method, params)
+}
+
+// Calls one of `METHODS` with its parameters, an object, returning its
+// result as JSON. The `serve` HTTP server answers its requests with these
+// too.
+pub fn call(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions,
+    method: &str,
+    params: &JsonValue
+) -> Result<String, Box<dyn Error>> {
+    debug!("Serving a {method} request");
+
+    match method {
+        "search" => search(dbs, opts, params),
+        "complete" => complete(dbs, params),
+        "get-page" => get_page(dbs, opts, params),
+        _ => Err(format!("Unknown method \"{method}\". The methods are: {}.",
+            METHODS.join(", ")).into()),
+    }
+}
+
+// Searches every database like a query on the command line. The parameters
+// are the query and, optionally, "match", "section", "arch", and "limit",
+// which work like the options of the same names.
+fn search(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions,
+    params: &JsonValue
+) -> Result<String, Box<dyn Error>> {
+    let query = str_param(params, "query")?.ok_or("Missing \"query\" to search for.")?;
+
+    let mut opts = opts.clone();
+    if let Some(mode) = str_param(params, "match")? {
+        opts.mode = mode.parse()?;
+    }
+    if let Some(section) = str_param(params, "section")? {
+        opts.section = Some(section.to_string());
+    }
+    if let Some(arch) = str_param(params, "arch")? {
+        opts.arch = Some(arch.to_string());
+    }
+    if let Some(limit) = usize_param(params, "limit")? {
+        opts.limit = Some(limit);
+    }
+
+    let results = database::search_all(dbs, &[query], &opts)
+        .into_iter()
+        .map(|(page, label, dir)| page_result(page, label, dir))
+        .collect::<io::Result<Vec<String>>>()?;
+    Ok(format!("[{}]", results.join(",")))
+}
+
+// Returns the page names that start with "prefix", or every name without
+// one, sorted and at most "limit" of them.
+fn complete(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    params: &JsonValue
+) -> Result<String, Box<dyn Error>> {
+    let prefix = str_param(params, "prefix")?.unwrap_or_default();
+    let limit = usize_param(params, "limit")?.unwrap_or(usize::MAX);
+
+    let names = dbs
+        .iter()
+        .flat_map(|(db, _, _)| db.complete(prefix))
+        .collect::<BTreeSet<&str>>();
+    let names = names.into_iter().take(limit).collect::<Vec<&str>>();
+    Ok(json_array(&names))
+}
+
+// Looks up the page called "name", in "section" if it is given, and returns
+// it along with the path and source of its first file that exists, or nulls
+// when none does. Compressed files are decompressed.
+fn get_page(
+    dbs: &[(&Database<'_>, Option<&str>, Option<&Path>)],
+    opts: &SearchOptions,
+    params: &JsonValue
+) -> Result<String, Box<dyn Error>> {
+    let name = str_param(params, "name")?.ok_or("Missing \"name\" of the page.")?;
+    let section = str_param(params, "section")?;
+    let opts = SearchOptions {
+        mode: MatchMode::Exact,
+        limit: Some(1),
+        section: section.map(str::to_string),
+        ..opts.clone()
+    };
+
+    let (page, label, dir) = database::search_all(dbs, &[name], &opts)
+        .into_iter()
+        .next()
+        .ok_or_else(|| {
+            let in_section = section.map(|section| format!(" in section {section}"));
+            DbError::new(ErrorKind::NotFound,
+                format!("No page is named {name}{}.", in_section.unwrap_or_default()))
+        })?;
+
+    let source = match dir.map(|dir| source::read_page_source(page, dir)) {
+        Some(Ok((path, bytes))) => Some((path, bytes)),
+        Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+        _ => None,
+    };
+    let (path, text) = source.map_or_else(
+        || ("null".to_string(), "null".to_string()),
+        |(path, bytes)| {
+            (json_string(&path.to_string_lossy()), json_string(&String::from_utf8_lossy(&bytes)))
+        });
+
+    Ok(format!("{{\"page\":{},\"path\":{path},\"source\":{text}}}",
+        page_result(page, label, dir)?))
+}
+
+// Formats a page as `-o json` does, along with the absolute paths of its
+// files that exist, which an editor can open.
+fn page_result(page: &Page<'_>, label: Option<&str>, dir: Option<&Path>) -> io::Result<String> {
+    let paths = match dir {
+        Some(dir) => page
+            .resolve_files(dir)?
+            .into_iter()
+            .filter(|(_, exists)| *exists)
+            .map(|(path, _)| path.to_string_lossy().into_owned())
+            .collect(),
+        None => Vec::new(),
+    };
+    let paths = paths.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    let json = output::page_json(page);
+    let fields = json.strip_suffix('}').unwrap_or(&json);
+    Ok(match label {
+        Some(label) => format!("{fields},\"database\":{},\"paths\":{}}}", json_string(label),
+            json_array(&paths)),
+        None => format!("{fields},\"paths\":{}}}", json_array(&paths)),
+    })
+}
+
+// Returns the string parameter `name`, if it is given.
+fn str_param<'a>(params: &'a JsonValue, name: &str) -> Result<Option<&'a str>, Box<dyn Error>> {
+    match params.get(name) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(value) => {
+            value.as_str().map(Some).ok_or_else(|| format!("\"{name}\" must be a string.").into())
+        },
+    }
+}
+
+// Returns the count parameter `name`, if it is given.
+fn usize_param(params: &JsonValue, name: &str) -> Result<Option<usize>, Box<dyn Error>> {
+    match params.get(name) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(value) => value
+            .as_usize()
+            .map(Some)
+            .ok_or_else(|| format!("\"{name}\" must be a whole number.").into()),
+    }
+}
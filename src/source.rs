@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::debug;
+
+use crate::database::{self, GZIP_MAGIC};
+use crate::pages::Page;
+#[cfg(feature = "preview")]
+use crate::preview;
+
+// The first bytes of a file compressed with compress(1), e.g. "ls.1.Z".
+pub const COMPRESS_MAGIC: [u8; 2] = [0x1f, 0x9d];
+
+// The code that clears the table of a compress(1) file in block mode, and
+// the first code after it and the 256 byte values.
+const CLEAR_CODE: usize = 256;
+const FIRST_CODE: usize = 257;
+
+// The number of bits of the first codes, and the most bits any code can have.
+const INIT_BITS: u32 = 9;
+const MAX_BITS: u32 = 16;
+
+// Returns the path and contents of the first of the page's files that
+// exists in `man_dir`, the directory that contains its database.
+pub fn read_page_source(page: &Page<'_>, man_dir: &Path) -> io::Result<(PathBuf, Vec<u8>)> {
+    let path = page
+        .file_paths(man_dir)
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "None of its files exist."))?;
+    let bytes = read_source(&path)?;
+    Ok((path, bytes))
+}
+
+// Renders the NAME and SYNOPSIS sections of the page's source as plain text
+// wrapped to `width` columns, as described in `preview::render`.
+#[cfg(feature = "preview")]
+pub fn preview_page(page: &Page<'_>, man_dir: &Path, width: usize) -> io::Result<String> {
+    let (_, bytes) = read_page_source(page, man_dir)?;
+    Ok(preview::render(&String::from_utf8_lossy(&bytes), &page.format, width))
+}
+
+#[cfg(not(feature = "preview"))]
+pub fn preview_page(_page: &Page<'_>, _man_dir: &Path, _width: usize) -> io::Result<String> {
+    Err(io::Error::other(
+        "This build does not support previews; rebuild it with the \"preview\" feature."))
+}
+
+// Reads a man page file. Many systems keep their pages compressed, e.g.
+// "man1/ls.1.gz", so files compressed with gzip(1) or compress(1) are
+// recognized by their magic numbers and decompressed.
+pub fn read_source(path: &Path) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(&GZIP_MAGIC) {
+        debug!("Decompressing {}", path.display());
+        return database::gunzip(&bytes).map_err(|e| io::Error::other(e.to_string()));
+    }
+    if bytes.starts_with(&COMPRESS_MAGIC) {
+        debug!("Decompressing {}", path.display());
+        return uncompress(&bytes);
+    }
+    Ok(bytes)
+}
+
+// Decompresses the LZW data of a compress(1) file, like uncompress(1).
+//
+// After the magic number, one byte gives the most bits of a code and
+// whether the table can be cleared. Codes are packed from the lowest bit up
+// and start out 9 bits long. Whenever the codes grow by a bit or the table
+// is cleared, compress(1) has written out its whole buffer of 8 codes, so
+// the next code starts after the bits that were left over.
+pub fn uncompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let flags = *bytes.get(2).ok_or_else(|| invalid("The compressed file is too short."))?;
+    let max_bits = u32::from(flags & 0x1f);
+    let block_mode = flags & 0x80 != 0;
+    if !(INIT_BITS..=MAX_BITS).contains(&max_bits) {
+        return Err(invalid("The compressed file has an unsupported code size."));
+    }
+    let max_max_code = 1 << max_bits;
+
+    let data = &bytes[3..];
+    let total_bits = data.len() * 8;
+
+    // The table holds the code of the string that each code extends, and
+    // its last byte. The 256 byte values stand for themselves.
+    let mut prefixes = vec![0_usize; max_max_code];
+    let mut suffixes = (0..max_max_code).map(|code| code as u8).collect::<Vec<u8>>();
+
+    let mut n_bits = INIT_BITS;
+    let mut max_code = (1 << n_bits) - 1;
+    let mut free_code = if block_mode { FIRST_CODE } else { CLEAR_CODE };
+    // Where the current buffer of codes starts, in bits.
+    let mut group_start = 0;
+    let mut pos = 0;
+    let mut old_code = None;
+    let mut last_byte = 0;
+
+    let mut out = Vec::with_capacity(bytes.len() * 3);
+    let mut stack = Vec::new();
+    while pos + n_bits as usize <= total_bits {
+        if free_code > max_code && n_bits < max_bits {
+            pos = next_group(group_start, pos, n_bits);
+            group_start = pos;
+            n_bits += 1;
+            max_code = if n_bits == max_bits { max_max_code } else { (1 << n_bits) - 1 };
+            continue;
+        }
+
+        let code = read_code(data, pos, n_bits);
+        pos += n_bits as usize;
+
+        let Some(prev) = old_code else {
+            if code >= CLEAR_CODE {
+                return Err(invalid("The compressed data does not start with a byte."));
+            }
+            last_byte = code as u8;
+            out.push(last_byte);
+            old_code = Some(code);
+            continue;
+        };
+
+        if code == CLEAR_CODE && block_mode {
+            // The next code adds an entry of no use, so the ones that count
+            // start at FIRST_CODE again.
+            free_code = CLEAR_CODE;
+            pos = next_group(group_start, pos, n_bits);
+            group_start = pos;
+            n_bits = INIT_BITS;
+            max_code = (1 << n_bits) - 1;
+            continue;
+        }
+
+        // A code that is not in the table yet is the previous string
+        // followed by its own first byte.
+        let mut next = code;
+        if code >= free_code {
+            if code > free_code {
+                return Err(invalid("The compressed data has an unknown code."));
+            }
+            stack.push(last_byte);
+            next = prev;
+        }
+        while next >= CLEAR_CODE {
+            stack.push(suffixes[next]);
+            next = prefixes[next];
+        }
+        last_byte = suffixes[next];
+        stack.push(last_byte);
+        out.extend(stack.drain(..).rev());
+
+        if free_code < max_max_code {
+            prefixes[free_code] = prev;
+            suffixes[free_code] = last_byte;
+            free_code += 1;
+        }
+        old_code = Some(code);
+    }
+
+    Ok(out)
+}
+
+// Reads the code of `n_bits` bits at bit `pos`.
+fn read_code(data: &[u8], pos: usize, n_bits: u32) -> usize {
+    let idx = pos / 8;
+    let bits = (0..3)
+        .map(|offset| usize::from(data.get(idx + offset).copied().unwrap_or(0)) << (offset * 8))
+        .fold(0, |bits, byte| bits | byte);
+    (bits >> (pos % 8)) & ((1 << n_bits) - 1)
+}
+
+// Returns where the buffer after the one holding bit `pos` starts, for
+// buffers of 8 codes of `n_bits` bits from `group_start`.
+const fn next_group(group_start: usize, pos: usize, n_bits: u32) -> usize {
+    let group_bits = n_bits as usize * 8;
+    group_start + (pos - group_start).div_ceil(group_bits) * group_bits
+}
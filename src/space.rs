@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::Write;
+
+use crate::database::{ParseOptions, DB_MAGIC_NUMBER};
+use crate::error::{DbError, ErrorKind};
+use crate::macros;
+use crate::utils::{parse_num, parse_str, StrList};
+
+// The length of the header and of each page record and macro value record.
+const HEADER_LEN: usize = 20;
+const PAGE_LEN: usize = 20;
+const VALUE_LEN: usize = 8;
+
+// The bytes used by one part of a database.
+struct Region {
+    name: String,
+    bytes: usize,
+}
+
+// Adds the length of the string list at `idx` to `total` unless another page
+// shares it.
+fn add_list(
+    bytes: &[u8],
+    idx: usize,
+    seen: &mut HashSet<usize>,
+    total: &mut usize
+) -> Result<(), Box<dyn Error>> {
+    if seen.insert(idx) {
+        *total += StrList::parse(bytes, idx)?.byte_len();
+    }
+    Ok(())
+}
+
+// Writes how many bytes of a mandoc.db each part uses: the header, the page
+// records, each kind of page string, and each macro table with its value
+// strings and pages lists. Macro tables are sorted by size, and the bytes not
+// used by any part, such as alignment padding, are shown as "other".
+//
+// The counts are checked against `options` and the length of the file before
+// anything is made room for, as when parsing.
+pub fn write_space(
+    out: &mut dyn Write,
+    bytes: &[u8],
+    options: &ParseOptions
+) -> Result<(), Box<dyn Error>> {
+    if parse_num(bytes, 0).ok() != Some(DB_MAGIC_NUMBER) {
+        return Err(DbError::at(ErrorKind::Format, 0, "Invalid file format.").into());
+    }
+    let mut regions = vec![Region { name: "header".to_string(), bytes: HEADER_LEN }];
+
+    let page_count = parse_num(bytes, 16)?;
+    options.check_pages(page_count, 16)?;
+    if HEADER_LEN + page_count * PAGE_LEN > bytes.len() {
+        return Err(DbError::at(ErrorKind::OutOfBounds, 16,
+            format!("The {page_count} page records are past the end of the file.")).into());
+    }
+    regions.push(Region { name: "page records".to_string(), bytes: page_count * PAGE_LEN });
+
+    // The strings of each page field, where the files list starts with the
+    // page's format byte.
+    let fields = ["names", "sections", "architectures", "descriptions", "files"];
+    let mut seen = vec![HashSet::new(); fields.len()];
+    let mut totals = vec![0; fields.len()];
+    for idx in 0..page_count {
+        let start = HEADER_LEN + idx * PAGE_LEN;
+        for field in 0..fields.len() {
+            let offset = parse_num(bytes, start + field * 4)?;
+            match field {
+                // Machine-independent pages have no architectures list.
+                2 if offset == 0 => {},
+                // The description is one string.
+                3 => {
+                    if seen[field].insert(offset) {
+                        totals[field] += parse_str(bytes, offset)?.len() + 1;
+                    }
+                },
+                4 => {
+                    if seen[field].insert(offset) {
+                        totals[field] += 1 + StrList::parse(bytes, offset + 1)?.byte_len();
+                    }
+                },
+                _ => add_list(bytes, offset, &mut seen[field], &mut totals[field])?,
+            }
+        }
+    }
+    for (field, total) in fields.iter().zip(totals) {
+        regions.push(Region { name: format!("page {field}"), bytes: total });
+    }
+
+    let macros_idx = parse_num(bytes, 8)?;
+    let table_count = parse_num(bytes, macros_idx)?;
+    options.check_list_len(table_count, macros_idx)?;
+    if macros_idx + 4 + table_count * 4 > bytes.len() {
+        return Err(DbError::at(ErrorKind::OutOfBounds, macros_idx,
+            format!("The {table_count} macro table offsets are past the end of the file."))
+            .into());
+    }
+    regions.push(Region { name: "macro table offsets".to_string(), bytes: 4 + table_count * 4 });
+
+    let mut tables = Vec::with_capacity(table_count);
+    let mut empty = 0;
+    for key in 0..table_count {
+        let start = parse_num(bytes, macros_idx + 4 + key * 4)?;
+        let count = parse_num(bytes, start)?;
+        if count == 0 {
+            empty += 1;
+            continue;
+        }
+        options.check_list_len(count, start)?;
+        if start + 4 + count * VALUE_LEN > bytes.len() {
+            return Err(DbError::at(ErrorKind::OutOfBounds, start,
+                format!("The {count} macro value records are past the end of the file.")).into());
+        }
+
+        // The records, then each value's string, padded to 4 bytes, and its
+        // pages list, which ends with 0.
+        let mut total = 4 + count * VALUE_LEN;
+        for idx in 0..count {
+            let value = start + 4 + idx * VALUE_LEN;
+            let str = parse_str(bytes, parse_num(bytes, value)?)?;
+            total += (str.len() + 1).next_multiple_of(4);
+
+            let pages_list = parse_num(bytes, value + 4)?;
+            let mut item = pages_list;
+            while parse_num(bytes, item)? != 0 {
+                item += 4;
+                options.check_list_len((item - pages_list) / 4, pages_list)?;
+            }
+            total += item + 4 - pages_list;
+        }
+
+        let key = macros::KEYS.get(key).map_or_else(|| format!("table {key}"), |k| k.to_string());
+        let plural = if count == 1 { "value" } else { "values" };
+        let name = format!("{key} macro table ({count} {plural})");
+        tables.push(Region { name, bytes: total });
+    }
+    tables.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    regions.extend(tables);
+    if empty > 0 {
+        let plural = if empty == 1 { "table" } else { "tables" };
+        regions.push(Region { name: format!("{empty} empty macro {plural}"), bytes: empty * 4 });
+    }
+
+    regions.push(Region { name: "final magic number".to_string(), bytes: 4 });
+    let used = regions.iter().map(|region| region.bytes).sum::<usize>();
+    regions.push(Region { name: "other".to_string(), bytes: bytes.len().saturating_sub(used) });
+
+    let name_width = regions.iter().map(|region| region.name.len()).max().unwrap_or(0);
+    let total = bytes.len().max(1);
+    for region in &regions {
+        // Tenths of a percent.
+        let share = region.bytes * 1000 / total;
+        writeln!(out, "{:name_width$}  {:>10}  {:>3}.{}%", region.name, region.bytes,
+            share / 10, share % 10)?;
+    }
+    writeln!(out, "{:name_width$}  {:>10}  100.0%", "total", bytes.len())?;
+
+    Ok(())
+}
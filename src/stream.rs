@@ -0,0 +1,276 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+use log::{debug, warn};
+
+use crate::database::{ParseOptions, Version, DB_MAGIC_NUMBER, DB_VERSION_NUMBER, GZIP_MAGIC};
+use crate::edit::{OwnedDatabase, OwnedPage};
+use crate::error::{DbError, ErrorKind};
+use crate::macros;
+use crate::pages::PageFormat;
+use crate::swap;
+
+// Reads the mandoc.db at `path` with `read_database`.
+pub fn read_file(path: &str) -> Result<OwnedDatabase, DbError> {
+    read_file_with(path, &ParseOptions::default())
+}
+
+// Like `read_file`, within the limits of `options`.
+pub fn read_file_with(path: &str, options: &ParseOptions) -> Result<OwnedDatabase, DbError> {
+    let file = File::open(path).map_err(|e| DbError::new(ErrorKind::Io, e.to_string()))?;
+    read_database_with(file, options)
+}
+
+// Reads a mandoc.db from `reader` a part at a time, seeking to each table and
+// list instead of reading the whole file into memory first, so that only the
+// strings of the database are kept. The database is checked the way
+// `Database::parse` checks it, and the lists are read in the order they are
+// laid out, so that a buffered reader mostly moves forward.
+//
+// Any reader that can seek will do, e.g. a file or a `Cursor` over bytes
+// that were downloaded or read from standard input. Compressed databases
+// cannot be seeked in, and are refused.
+pub fn read_database(reader: impl Read + Seek) -> Result<OwnedDatabase, DbError> {
+    read_database_with(reader, &ParseOptions::default())
+}
+
+// Like `read_database`, failing once a count or length is more than
+// `options` allow, as `Database::parse_with_options` does.
+pub fn read_database_with(
+    mut reader: impl Read + Seek,
+    options: &ParseOptions
+) -> Result<OwnedDatabase, DbError> {
+    // Room is only made for as many records as the file holds.
+    let file_len = reader
+        .seek(SeekFrom::End(0))
+        .and_then(|len| reader.rewind().map(|()| len))
+        .map_err(|e| DbError::new(ErrorKind::Io, e.to_string()))?;
+    let file_len = usize::try_from(file_len).unwrap_or(usize::MAX);
+    let mut stream = Stream { reader: BufReader::new(reader), pos: 0, options: *options };
+
+    let magic = stream.num(0)
+        .map_err(|_| DbError::at(ErrorKind::Format, 0, "Invalid file format."))?;
+    if magic == swap::SWAPPED_MAGIC_NUMBER {
+        return Err(DbError::at(ErrorKind::Format, 0,
+            "The database appears to be written with opposite endianness."));
+    }
+    if magic >> 16 == usize::from(u16::from_be_bytes(GZIP_MAGIC)) {
+        return Err(DbError::at(ErrorKind::Format, 0, "The database is compressed with gzip, \
+            which cannot be read a part at a time; read it without --stream."));
+    }
+    if magic != DB_MAGIC_NUMBER {
+        return Err(DbError::at(ErrorKind::Format, 0, "Invalid file format."));
+    }
+    let version = Version::from_number(stream.num(4)?)
+        .ok_or_else(|| DbError::at(ErrorKind::Version, 4, "Invalid version number."))?;
+    if let Version::Unknown(number) = version {
+        warn!("Version {number} is newer than the supported version \
+            {DB_VERSION_NUMBER}; reading it as version {DB_VERSION_NUMBER}");
+    }
+    let macros_idx = stream.num(8)?;
+    let final_idx = stream.num(12)?;
+
+    // The page records, then each kind of list for every page in turn.
+    let count = stream.num(16)?;
+    debug!("Streaming {count} pages");
+    options.check_pages(count, 16)?;
+    if 20 + count * 20 > file_len {
+        return Err(DbError::at(ErrorKind::OutOfBounds, 16,
+            format!("The {count} page records are past the end of the file.")));
+    }
+    let mut records = Vec::with_capacity(count);
+    for idx in 0..count {
+        let start = 20 + idx * 20;
+        let mut record = [0; 5];
+        for (field, num) in record.iter_mut().enumerate() {
+            *num = stream.num(start + field * 4)?;
+        }
+        records.push(record);
+    }
+
+    let names = records
+        .iter()
+        .map(|record| stream.names(record[0]))
+        .collect::<Result<Vec<Vec<(String, u8)>>, DbError>>()?;
+    let sects = records
+        .iter()
+        .map(|record| stream.list(record[1]))
+        .collect::<Result<Vec<Vec<String>>, DbError>>()?;
+    // Machine-independent pages have no architectures list.
+    let archs = records
+        .iter()
+        .map(|record| (record[2] != 0).then(|| stream.list(record[2])).transpose())
+        .collect::<Result<Vec<Option<Vec<String>>>, DbError>>()?;
+    let descs = records
+        .iter()
+        .map(|record| stream.str(record[3]))
+        .collect::<Result<Vec<String>, DbError>>()?;
+    let files = records
+        .iter()
+        .map(|record| stream.files(record[4]))
+        .collect::<Result<Vec<(PageFormat, Vec<String>)>, DbError>>()?;
+
+    let mut db = OwnedDatabase::new();
+    let pages = names.into_iter().zip(sects).zip(archs).zip(descs).zip(files);
+    for ((((names, sects), archs), desc), (format, files)) in pages {
+        db.add_page(OwnedPage { names, sects, archs, desc, files, format });
+    }
+
+    // The macro tables, each a list of value records followed by each
+    // value's string and pages list.
+    let tables_count = stream.num(macros_idx)?;
+    if tables_count != macros::KEYS.len() {
+        return Err(DbError::at(ErrorKind::Count, macros_idx,
+            format!("Expected 36 macro tables but found {tables_count}.")));
+    }
+    let table_starts = (0..tables_count)
+        .map(|key| stream.num(macros_idx + 4 + key * 4))
+        .collect::<Result<Vec<usize>, DbError>>()?;
+
+    for (key, start) in table_starts.into_iter().enumerate() {
+        let values_count = stream.num(start)?;
+        let mut values = Vec::new();
+        for idx in 0..values_count {
+            let record = start + 4 + idx * 8;
+            values.push((stream.num(record)?, stream.num(record + 4)?));
+        }
+
+        for (str_idx, pages_idx) in values {
+            let value = stream.str(str_idx)?;
+            let mut value_pages = Vec::new();
+            for offset in (pages_idx..).step_by(4) {
+                // Zero marks the end of the pages list.
+                let page = stream.num(offset)?;
+                if page == 0 {
+                    break;
+                }
+                options.check_list_len(value_pages.len() + 1, pages_idx)?;
+                if !macros::is_page_record(page, count) {
+                    return Err(DbError::at(ErrorKind::Format, offset,
+                        format!("Page offset {page} is not a page record.")));
+                }
+                value_pages.push(macros::page_position(page));
+            }
+            db.push_macro(key, value, value_pages);
+        }
+    }
+
+    if stream.num(final_idx)? != DB_MAGIC_NUMBER {
+        return Err(DbError::at(ErrorKind::Format, final_idx, "Invalid file format."));
+    }
+
+    Ok(db)
+}
+
+// A reader that keeps track of where it is, so that moving to the next part
+// of the file keeps what is already buffered.
+struct Stream<R> {
+    reader: BufReader<R>,
+    pos: usize,
+    options: ParseOptions,
+}
+
+impl<R: Read + Seek> Stream<R> {
+    fn seek(&mut self, idx: usize) -> Result<(), DbError> {
+        if idx == self.pos {
+            return Ok(());
+        }
+        let delta = i64::try_from(idx)
+            .ok()
+            .zip(i64::try_from(self.pos).ok())
+            .map(|(idx, pos)| idx - pos)
+            .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, idx, "Offset is too large."))?;
+        self.reader.seek_relative(delta).map_err(|e| Self::io_error(idx, &e))?;
+        self.pos = idx;
+        Ok(())
+    }
+
+    fn io_error(idx: usize, e: &io::Error) -> DbError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            DbError::at(ErrorKind::OutOfBounds, idx, "Offset is past the end of the file.")
+        } else {
+            DbError::at(ErrorKind::Io, idx, e.to_string())
+        }
+    }
+
+    fn byte(&mut self, idx: usize) -> Result<u8, DbError> {
+        self.seek(idx)?;
+        let mut byte = [0; 1];
+        self.reader.read_exact(&mut byte).map_err(|e| Self::io_error(idx, &e))?;
+        self.pos += 1;
+        Ok(byte[0])
+    }
+
+    // Reads the big endian number at `idx`.
+    fn num(&mut self, idx: usize) -> Result<usize, DbError> {
+        self.seek(idx)?;
+        let mut bytes = [0; 4];
+        self.reader.read_exact(&mut bytes).map_err(|e| Self::io_error(idx, &e))?;
+        self.pos += 4;
+        usize::try_from(u32::from_be_bytes(bytes))
+            .map_err(|_| DbError::at(ErrorKind::OutOfBounds, idx, "Number is too large."))
+    }
+
+    // Reads the NUL-terminated string at `idx`, reading no further than the
+    // longest string allowed.
+    fn str(&mut self, idx: usize) -> Result<String, DbError> {
+        self.seek(idx)?;
+        let mut bytes = Vec::new();
+        let max = u64::try_from(self.options.max_str_len).unwrap_or(u64::MAX);
+        let len = self.reader
+            .by_ref()
+            .take(max.saturating_add(1))
+            .read_until(0, &mut bytes)
+            .map_err(|e| Self::io_error(idx, &e))?;
+        self.pos += len;
+        if bytes.pop() != Some(0) {
+            // The string went on past the limit.
+            self.options.check_str_len(len, idx)?;
+            return Err(DbError::at(ErrorKind::Encoding, idx, "Unterminated string."));
+        }
+        String::from_utf8(bytes)
+            .map_err(|e| DbError::at(ErrorKind::Encoding, idx + e.utf8_error().valid_up_to(),
+                "String is not valid UTF-8."))
+    }
+
+    // Reads the strings list at `idx`, which ends with an empty string.
+    fn list(&mut self, idx: usize) -> Result<Vec<String>, DbError> {
+        let mut list = Vec::new();
+        let mut item_idx = idx;
+        loop {
+            let item = self.str(item_idx)?;
+            if item.is_empty() {
+                return Ok(list);
+            }
+            item_idx += item.len() + 1;
+            list.push(item);
+            self.options.check_list_len(list.len(), idx)?;
+        }
+    }
+
+    // Reads the files list at `idx`, which starts with the page's format.
+    fn files(&mut self, idx: usize) -> Result<(PageFormat, Vec<String>), DbError> {
+        let byte = self.byte(idx)?;
+        let format = PageFormat::try_from(byte)
+            .map_err(|e| DbError::at(e.kind, idx, e.message))?;
+        Ok((format, self.list(idx + 1)?))
+    }
+
+    // Reads the names list at `idx`, whose names start with a name sources
+    // byte.
+    fn names(&mut self, idx: usize) -> Result<Vec<(String, u8)>, DbError> {
+        let mut names = Vec::new();
+        let mut item_idx = idx;
+        for mut item in self.list(idx)? {
+            let source = item.as_bytes()[0];
+            if !matches!(source, 1..=31) {
+                return Err(DbError::at(ErrorKind::Format, item_idx,
+                    "Name source parsing failed."));
+            }
+            item_idx += item.len() + 1;
+            item.remove(0);
+            names.push((item, source));
+        }
+        Ok(names)
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+
+use crate::error::{DbError, ErrorKind};
+use crate::utils::parse_num;
+
+// The magic number as it reads from a database whose numbers were written in
+// little endian byte order, e.g. by a port that left out the conversion.
+pub const SWAPPED_MAGIC_NUMBER: usize = 0xdb0c_7d3a;
+
+// Returns whether the database's numbers appear to be in little endian byte
+// order instead of big endian.
+pub fn is_swapped(bytes: &[u8]) -> bool {
+    parse_num(bytes, 0).is_ok_and(|magic| magic == SWAPPED_MAGIC_NUMBER)
+}
+
+// Swaps the numbers of a database in place, each one once, as their offsets
+// are found.
+struct Swapper {
+    bytes: Vec<u8>,
+    swapped: HashSet<usize>,
+}
+
+impl Swapper {
+    // Swaps the number at `idx` and returns it, as it reads once swapped.
+    fn swap(&mut self, idx: usize) -> Result<usize, DbError> {
+        if self.swapped.insert(idx) {
+            let num = idx
+                .checked_add(4)
+                .and_then(|end| self.bytes.get_mut(idx..end))
+                .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, idx,
+                    "Number is past the end of the file."))?;
+            num.reverse();
+        }
+        parse_num(&self.bytes, idx)
+    }
+}
+
+// Returns a copy of a database written in little endian byte order with
+// every number swapped, so that it can be parsed. Strings are bytes and stay
+// as they are, so only the header, the page records, and the macro tables,
+// values, and pages lists change.
+pub fn swap_bytes(bytes: &[u8]) -> Result<Vec<u8>, DbError> {
+    let mut swapper = Swapper { bytes: bytes.to_vec(), swapped: HashSet::new() };
+
+    // The magic number, version, macros table offset, final magic number
+    // offset, and page count.
+    for idx in [0, 4] {
+        swapper.swap(idx)?;
+    }
+    let macros_idx = swapper.swap(8)?;
+    let final_idx = swapper.swap(12)?;
+    let page_count = swapper.swap(16)?;
+    swapper.swap(final_idx)?;
+
+    // Each page record is five offsets.
+    for idx in 0..page_count {
+        for field in 0..5 {
+            swapper.swap(20 + idx * 20 + field * 4)?;
+        }
+    }
+
+    let table_count = swapper.swap(macros_idx)?;
+    for key in 0..table_count {
+        let table = swapper.swap(macros_idx + 4 + key * 4)?;
+        let value_count = swapper.swap(table)?;
+        // Each value is a string offset and a pages list offset, and the
+        // pages list ends with 0.
+        for value in 0..value_count {
+            swapper.swap(table + 4 + value * 8)?;
+            let mut page = swapper.swap(table + 8 + value * 8)?;
+            while swapper.swap(page)? != 0 {
+                page += 4;
+            }
+        }
+    }
+
+    Ok(swapper.bytes)
+}
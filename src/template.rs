@@ -0,0 +1,167 @@
+use std::error::Error;
+use std::mem;
+use std::str::FromStr;
+
+use crate::pages::Page;
+use crate::utils::StrList;
+
+// A page field that can be substituted into a template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Name,
+    Names,
+    Sect,
+    Sects,
+    Arch,
+    Archs,
+    Desc,
+    File,
+    Files,
+    Format,
+}
+
+impl Field {
+    const NAMES: &'static [&'static str] = &[
+        "name", "names", "sect", "sects", "arch", "archs", "desc", "file",
+        "files", "format",
+    ];
+}
+
+impl FromStr for Field {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "names" => Ok(Self::Names),
+            "sect" => Ok(Self::Sect),
+            "sects" => Ok(Self::Sects),
+            "arch" => Ok(Self::Arch),
+            "archs" => Ok(Self::Archs),
+            "desc" => Ok(Self::Desc),
+            "file" => Ok(Self::File),
+            "files" => Ok(Self::Files),
+            "format" => Ok(Self::Format),
+            _ => Err(format!("Unknown placeholder \"{{{s}}}\"; expected one of: {}.",
+                Field::NAMES.join(", ")).into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+// A user supplied output template such as "{name}({sect})\t{desc}".
+//
+// Placeholders:
+// * {name}, {sect}, {arch}, {file}: the first entry of the list.
+// * {names}, {sects}, {archs}, {files}: the whole list, comma-separated.
+// * {desc}: the one-line description.
+// * {format}: "source" or "preformatted".
+//
+// "{{" and "}}" produce literal braces, and the escapes "\t", "\n", and "\\"
+// are expanded so that templates can be written without shell quoting tricks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err("Unterminated placeholder in template.".into()),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(name.trim().parse()?));
+                },
+                '}' => return Err("Unmatched \"}\" in template.".into()),
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(c) => {
+                        literal.push('\\');
+                        literal.push(c);
+                    },
+                    None => literal.push('\\'),
+                },
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Template {
+    pub fn render(&self, page: &Page<'_>) -> String {
+        let mut out = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Field(field) => out.push_str(&field_value(page, *field)),
+            }
+        }
+
+        out
+    }
+}
+
+fn field_value(page: &Page<'_>, field: Field) -> String {
+    let first = |list: &StrList<'_>| list.first().unwrap_or_default().to_string();
+    let no_archs = StrList::default();
+    let archs = page.archs.as_ref().unwrap_or(&no_archs);
+
+    match field {
+        Field::Name => page.names
+            .first()
+            .map(|n| n.value.to_string())
+            .unwrap_or_default(),
+        Field::Names => page.names
+            .iter()
+            .map(|n| n.value)
+            .collect::<Vec<&str>>()
+            .join(", "),
+        Field::Sect => first(&page.sects),
+        Field::Sects => page.sects.join(", "),
+        Field::Arch => first(archs),
+        Field::Archs => archs.join(", "),
+        Field::Desc => page.desc.to_string(),
+        Field::File => first(&page.files),
+        Field::Files => page.files.join(", "),
+        Field::Format => page.format.name().to_string(),
+    }
+}
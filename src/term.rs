@@ -0,0 +1,228 @@
+use std::env;
+use std::io::{self, Read};
+use std::str;
+
+// Returns the size of the terminal attached to stdout, or to stderr when
+// stdout is redirected, as (columns, rows). The COLUMNS and LINES environment variables are used when the size cannot
+// be queried from the terminal itself.
+pub fn size() -> Option<(usize, usize)> {
+    query_size().or_else(|| {
+        let var = |name| env::var(name).ok()?.parse::<usize>().ok();
+        Some((var("COLUMNS")?, var("LINES")?))
+    })
+}
+
+// Returns the number of columns of the terminal attached to stdout.
+pub fn width() -> Option<usize> {
+    size().map(|(cols, _)| cols)
+}
+
+#[cfg(unix)]
+fn query_size() -> Option<(usize, usize)> {
+    // SAFETY: TIOCGWINSZ only writes a `winsize` struct into the pointer we
+    // provide, which is valid for the duration of the call.
+    let ws = unsafe {
+        let mut ws = std::mem::zeroed::<libc::winsize>();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) != 0
+            && libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut ws) != 0
+        {
+            return None;
+        }
+        ws
+    };
+
+    if ws.ws_col == 0 || ws.ws_row == 0 {
+        return None;
+    }
+
+    Some((usize::from(ws.ws_col), usize::from(ws.ws_row)))
+}
+
+#[cfg(not(unix))]
+const fn query_size() -> Option<(usize, usize)> {
+    None
+}
+
+// A key read from the terminal in raw mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    // A letter pressed along with Ctrl, e.g. `Ctrl('u')`.
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+}
+
+// Puts the terminal on stdin in raw mode, where keys are read one at a time
+// without being echoed. The previous mode is restored when this is dropped.
+#[cfg(unix)]
+pub struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        // SAFETY: tcgetattr and tcsetattr only read and write the termios
+        // structs we pass, which live for the duration of the calls.
+        unsafe {
+            let mut original = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self { original })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: see `RawMode::enable`.
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    pub fn enable() -> io::Result<Self> {
+        Err(io::Error::new(io::ErrorKind::Unsupported,
+            "Full-screen mode needs a Unix terminal."))
+    }
+}
+
+// Reads one key from stdin, which must be in raw mode.
+pub fn read_key() -> io::Result<Key> {
+    let mut stdin = UnbufferedStdin;
+    let byte = read_byte(&mut stdin)?;
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => read_escape(&mut stdin)?,
+        0x01..=0x1a => Key::Ctrl(char::from(b'a' + byte - 1)),
+        _ => Key::Char(read_char(&mut stdin, byte)?),
+    };
+
+    Ok(key)
+}
+
+// Reads stdin without the buffering of `io::Stdin`, so that polling the file
+// descriptor tells whether the rest of an escape sequence has arrived.
+struct UnbufferedStdin;
+
+#[cfg(unix)]
+impl Read for UnbufferedStdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: read writes at most `buf.len()` bytes into `buf`.
+        let len = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        usize::try_from(len).map_err(|_| io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+impl Read for UnbufferedStdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::stdin().read(buf)
+    }
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+// Reads the rest of a UTF-8 character starting with `first`.
+fn read_char(input: &mut impl Read, first: u8) -> io::Result<char> {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        bytes.push(read_byte(input)?);
+    }
+
+    Ok(str::from_utf8(&bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+// Reads an escape sequence such as "\x1b[A" for the up arrow. A lone escape
+// is the Esc key.
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    if !input_pending() {
+        return Ok(Key::Esc);
+    }
+
+    let key = match (read_byte(input)?, read_byte(input)?) {
+        (b'[' | b'O', b'A') => Key::Up,
+        (b'[' | b'O', b'B') => Key::Down,
+        (b'[' | b'O', b'C') => Key::Right,
+        (b'[' | b'O', b'D') => Key::Left,
+        (b'[' | b'O', b'H') => Key::Home,
+        (b'[' | b'O', b'F') => Key::End,
+        (b'[', digit @ b'0'..=b'9') => {
+            // Sequences like "\x1b[5~" end with a tilde.
+            let mut code = vec![digit];
+            loop {
+                match read_byte(input)? {
+                    b'~' => break,
+                    byte => code.push(byte),
+                }
+            }
+            match code.as_slice() {
+                b"1" | b"7" => Key::Home,
+                b"4" | b"8" => Key::End,
+                b"5" => Key::PageUp,
+                b"6" => Key::PageDown,
+                _ => Key::Esc,
+            }
+        },
+        _ => Key::Esc,
+    };
+
+    Ok(key)
+}
+
+// Whether more input arrives within a moment, which tells an escape sequence
+// apart from the Esc key.
+#[cfg(unix)]
+fn input_pending() -> bool {
+    let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    // SAFETY: poll only writes to the single pollfd we pass.
+    unsafe { libc::poll(&mut fd, 1, 50) > 0 }
+}
+
+#[cfg(not(unix))]
+const fn input_pending() -> bool {
+    true
+}
@@ -0,0 +1,134 @@
+use crate::edit::{OwnedDatabase, OwnedPage};
+use crate::macros;
+use crate::pages::PageFormat;
+
+// Builds small mandoc.db files in memory, so that tests can parse databases
+// with known contents instead of shipping binary fixtures:
+//
+//     let bytes = DbBuilder::new()
+//         .with_page("ls", "1", "list directory contents")
+//         .with_macro("Xr", "cat(1)")
+//         .with_pages(100)
+//         .build();
+//     let db = Database::parse(&bytes)?;
+//
+// The other options apply to the page added last. The same calls always
+// build the same bytes.
+#[derive(Clone, Debug, Default)]
+pub struct DbBuilder {
+    db: OwnedDatabase,
+    // The page the options apply to.
+    last: Option<usize>,
+}
+
+impl DbBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a machine-independent page with one name and section, and the
+    // file `manSECT/NAME.SECT`.
+    #[must_use]
+    pub fn with_page(mut self, name: &str, sect: &str, desc: &str) -> Self {
+        let file = format!("man{sect}/{name}.{sect}");
+        let idx = self.db.add_page(OwnedPage::new(name, sect, desc, &file));
+        self.last = Some(idx);
+        self
+    }
+
+    // Adds `count` pages named pageN, where N is the page's index in the
+    // database, in sections 1 to 9 in turn, each with the description
+    // "synthetic page N".
+    #[must_use]
+    pub fn with_pages(mut self, count: usize) -> Self {
+        let start = self.db.pages().len();
+        for num in start..start + count {
+            let sect = (num % 9 + 1).to_string();
+            self = self.with_page(&format!("page{num}"), &sect, &format!("synthetic page {num}"));
+        }
+        self
+    }
+
+    // Adds more names to the last page, e.g. the functions a page documents
+    // besides its first one.
+    #[must_use]
+    pub fn with_names(mut self, names: &[&str]) -> Self {
+        if let Some(page) = self.last_page() {
+            page.names.extend(names.iter().map(|name| (name.to_string(), 0b0000_0010)));
+        }
+        self
+    }
+
+    // Adds more sections to the last page.
+    #[must_use]
+    pub fn with_sects(mut self, sects: &[&str]) -> Self {
+        if let Some(page) = self.last_page() {
+            page.sects.extend(sects.iter().map(ToString::to_string));
+        }
+        self
+    }
+
+    // Makes the last page machine-dependent, for the architectures given.
+    #[must_use]
+    pub fn with_archs(mut self, archs: &[&str]) -> Self {
+        if let Some(page) = self.last_page() {
+            page.archs = Some(archs.iter().map(ToString::to_string).collect());
+        }
+        self
+    }
+
+    // Replaces the files of the last page.
+    #[must_use]
+    pub fn with_files(mut self, files: &[&str]) -> Self {
+        if let Some(page) = self.last_page() {
+            page.files = files.iter().map(ToString::to_string).collect();
+        }
+        self
+    }
+
+    // Changes the format of the last page, e.g. to a format byte this
+    // version does not know.
+    #[must_use]
+    pub fn with_format(mut self, format: PageFormat) -> Self {
+        if let Some(page) = self.last_page() {
+            page.format = format;
+        }
+        self
+    }
+
+    // Adds `value` to the macro table for `key`, one of `macros::KEYS` such
+    // as "Xr", for the last page.
+    //
+    // Panics if `key` is not a macro key.
+    #[must_use]
+    pub fn with_macro(mut self, key: &str, value: &str) -> Self {
+        let Some(key_idx) = macros::KEYS.iter().position(|other| *other == key) else {
+            panic!("Unknown macro key \"{key}\".");
+        };
+        if let Some(idx) = self.last {
+            self.db.add_macro(idx, key_idx, value);
+        }
+        self
+    }
+
+    fn last_page(&mut self) -> Option<&mut OwnedPage> {
+        self.db.page_mut(self.last?)
+    }
+
+    // The database built so far, e.g. to change it further.
+    pub const fn database(&self) -> &OwnedDatabase {
+        &self.db
+    }
+
+    // Writes the database as the bytes of a version 1 mandoc.db.
+    //
+    // Panics if the database is too large for the 32-bit offsets of the
+    // format.
+    pub fn build(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.db
+            .write(&mut bytes)
+            .expect("The database is too large for mandoc.db.");
+        bytes
+    }
+}
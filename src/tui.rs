@@ -0,0 +1,429 @@
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::color::Painter;
+use crate::database::Database;
+use crate::open::open_page;
+use crate::pages::Page;
+use crate::search::{fuzzy_score, SearchOptions};
+use crate::source;
+use crate::term::{self, Key, RawMode};
+
+// Terminals at least this wide show the details next to the result list
+// rather than below it.
+const SIDE_BY_SIDE_WIDTH: usize = 100;
+
+// A full-screen browser with a result list, a details pane for the selected
+// page, and a search box that filters the list as the query is typed.
+//
+// As a picker, it lists every page name instead, ranks them by how well they
+// fuzzy match the query, and returns the one selected, much like fzf(1).
+pub struct Tui<'db, 'a> {
+    db: &'db Database<'a>,
+    opts: SearchOptions,
+    picking: bool,
+    // The directory containing the database, used to find page files.
+    db_dir: Option<PathBuf>,
+    query: String,
+    results: Vec<Entry<'db, 'a>>,
+    selected: usize,
+    // The index of the first result shown in the list.
+    scroll: usize,
+    // Whether typed keys edit the query rather than move the selection.
+    editing: bool,
+    // Why the query could not be used, e.g. an invalid regular expression.
+    error: Option<String>,
+    // Whether the details pane previews the selected page's source rather
+    // than showing its fields.
+    preview: bool,
+}
+
+// A row in the list: a page, along with the name it matched when picking.
+#[derive(Clone, Copy)]
+struct Entry<'db, 'a> {
+    page: &'db Page<'a>,
+    name: &'a str,
+}
+
+// What to do after a key press.
+enum Action {
+    Continue,
+    Open,
+    Pick,
+    Quit,
+}
+
+impl<'db, 'a> Tui<'db, 'a> {
+    pub fn new(db: &'db Database<'a>, opts: SearchOptions) -> Self {
+        Self {
+            db,
+            opts,
+            picking: false,
+            db_dir: None,
+            query: String::new(),
+            results: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            editing: false,
+            error: None,
+            preview: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_query(mut self, query: &str) -> Self {
+        self.query = query.to_string();
+        self
+    }
+
+    #[must_use]
+    pub fn with_db_dir(mut self, db_dir: Option<&Path>) -> Self {
+        self.db_dir = db_dir.map(Path::to_path_buf);
+        self
+    }
+
+    // Browses the database until the user quits.
+    pub fn run(mut self) -> Result<(), Box<dyn Error>> {
+        self.event_loop()?;
+        Ok(())
+    }
+
+    // Lets the user pick a page by name. Returns the section and name of
+    // the selected page, e.g. "3 pthread_create", or `None` if the user
+    // cancelled.
+    pub fn pick(mut self) -> Result<Option<String>, Box<dyn Error>> {
+        self.picking = true;
+        self.editing = true;
+
+        let picked = self.event_loop()?.map(|entry| {
+            let sect = entry.page.sects.first().unwrap_or_default();
+            format!("{sect} {}", entry.name).trim_start().to_string()
+        });
+
+        Ok(picked)
+    }
+
+    // Handles key presses until the user quits or picks an entry. The
+    // screen is drawn on stderr so that stdout can be captured, as in
+    // `man $(mandoc_db_search --pick)`.
+    fn event_loop(&mut self) -> Result<Option<Entry<'db, 'a>>, Box<dyn Error>> {
+        if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+            return Err("Full-screen mode needs a terminal.".into());
+        }
+
+        self.update_results();
+        let mut screen = Screen::enter()?;
+
+        loop {
+            self.draw()?;
+
+            match self.handle(term::read_key()?) {
+                Action::Continue => {},
+                Action::Quit => return Ok(None),
+                Action::Pick => {
+                    if let Some(entry) = self.results.get(self.selected) {
+                        return Ok(Some(*entry));
+                    }
+                },
+                Action::Open => {
+                    let Some(entry) = self.results.get(self.selected) else {
+                        continue;
+                    };
+
+                    // man(1) needs the terminal back while it runs.
+                    drop(screen);
+                    if let Err(e) = open_page(entry.page, self.db_dir.as_deref()) {
+                        self.error = Some(e.to_string());
+                    }
+                    screen = Screen::enter()?;
+                },
+            }
+        }
+    }
+
+    fn handle(&mut self, key: Key) -> Action {
+        match key {
+            Key::Ctrl('c') => return Action::Quit,
+            Key::Up | Key::Ctrl('p' | 'k') => self.select_by(-1),
+            Key::Down | Key::Ctrl('n' | 'j') => self.select_by(1),
+            Key::PageUp => self.select_by(-page_size()),
+            Key::PageDown => self.select_by(page_size()),
+            Key::Enter if self.picking => return Action::Pick,
+            Key::Esc if self.picking => return Action::Quit,
+            _ if self.editing => self.edit(key),
+            Key::Char('q') | Key::Esc => return Action::Quit,
+            Key::Char('/') => self.editing = true,
+            Key::Char('k') => self.select_by(-1),
+            Key::Char('j') => self.select_by(1),
+            Key::Ctrl('b') => self.select_by(-page_size()),
+            Key::Ctrl('f') => self.select_by(page_size()),
+            Key::Char('g') | Key::Home => self.selected = 0,
+            Key::Char('G') | Key::End => self.selected = self.results.len().saturating_sub(1),
+            Key::Enter => return Action::Open,
+            Key::Char('p') => self.preview = !self.preview,
+            _ => {},
+        }
+
+        Action::Continue
+    }
+
+    // Edits the query in the search box, updating the results right away.
+    fn edit(&mut self, key: Key) {
+        match key {
+            Key::Char(c) => self.query.push(c),
+            Key::Backspace => {
+                self.query.pop();
+            },
+            Key::Ctrl('u') => self.query.clear(),
+            Key::Ctrl('w') => {
+                let end = self.query.trim_end().rfind(' ').map_or(0, |idx| idx + 1);
+                self.query.truncate(end);
+            },
+            Key::Enter | Key::Esc | Key::Tab => {
+                self.editing = false;
+                return;
+            },
+            _ => return,
+        }
+
+        self.update_results();
+    }
+
+    fn select_by(&mut self, delta: isize) {
+        let last = self.results.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    // Shows every page for an empty query.
+    fn update_results(&mut self) {
+        self.selected = 0;
+        self.scroll = 0;
+        self.error = None;
+
+        if self.picking {
+            self.update_picks();
+            return;
+        }
+
+        let query = self.query.trim();
+        if query.is_empty() {
+            self.results = self.db.pages.table.iter().map(Entry::new).collect();
+            return;
+        }
+
+        // Searching directly, rather than through `Database::search`, keeps
+        // every key press out of the logs.
+        match self.db.matchers(query, &self.opts) {
+            Ok(matchers) => {
+                self.results = self.db
+                    .search_matchers(&matchers, &self.opts)
+                    .into_iter()
+                    .map(Entry::new)
+                    .collect();
+            },
+            Err(e) => {
+                self.results.clear();
+                self.error = Some(e.to_string());
+            },
+        }
+    }
+
+    // Lists every name that fuzzy matches the query, best matches first.
+    // Ties go to shorter names, so "ls" comes before "lsof".
+    fn update_picks(&mut self) {
+        let query = self.query.trim();
+        let mut scored = self.db
+            .pages
+            .table
+            .iter()
+            .filter(|page| self.opts.filters_match(page))
+            .flat_map(|page| page.names.iter().map(move |name| Entry { page, name: name.value }))
+            .filter_map(|entry| {
+                let score = fuzzy_score(entry.name, query, self.opts.case_sensitive)?;
+                Some((score, entry))
+            })
+            .collect::<Vec<(i64, Entry<'db, 'a>)>>();
+
+        if !query.is_empty() {
+            scored.sort_by(|(a_score, a), (b_score, b)| {
+                b_score
+                    .cmp(a_score)
+                    .then(a.name.len().cmp(&b.name.len()))
+                    .then(a.name.cmp(b.name))
+            });
+        }
+
+        self.results = scored
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .take(self.opts.limit.unwrap_or(usize::MAX))
+            .collect();
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (cols, rows) = term::size().unwrap_or((80, 24));
+        let body_rows = rows.saturating_sub(2);
+        let side_by_side = cols >= SIDE_BY_SIDE_WIDTH;
+
+        let (list_rows, list_cols) = if side_by_side {
+            (body_rows, cols * 2 / 5)
+        } else {
+            (body_rows / 2, cols)
+        };
+        let details_cols = if side_by_side { cols - list_cols - 3 } else { cols };
+
+        // Keep the selection in view.
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if list_rows > 0 && self.selected >= self.scroll + list_rows {
+            self.scroll = self.selected + 1 - list_rows;
+        }
+
+        let details = self.details(details_cols);
+        let mut screen = Vec::new();
+        write!(screen, "\x1b[H")?;
+
+        // The search box and the number of results.
+        let total = if self.picking {
+            self.db.pages.table.iter().map(|page| page.names.len()).sum()
+        } else {
+            self.db.num_pages()
+        };
+        let count = format!("{} of {total}", self.results.len());
+        let cursor = if self.editing { "_" } else { "" };
+        let search = if self.picking {
+            format!("> {}{cursor}", self.query)
+        } else {
+            format!("Search ({}): {}{cursor}", self.opts.mode, self.query)
+        };
+        let gap = cols.saturating_sub(count.chars().count());
+        write_line(&mut screen, &format!("{}{count}", fit(&search, gap)), cols)?;
+
+        if side_by_side {
+            for row in 0..body_rows {
+                let item = self.list_item(self.scroll + row, list_cols);
+                let detail = details.get(row).map_or("", String::as_str);
+                write!(screen, "{item} \u{2502} ")?;
+                write_line(&mut screen, detail, details_cols)?;
+            }
+        } else {
+            for row in 0..list_rows {
+                let item = self.list_item(self.scroll + row, list_cols);
+                write!(screen, "{item}")?;
+                write_line(&mut screen, "", 0)?;
+            }
+            write_line(&mut screen, &"\u{2500}".repeat(cols), cols)?;
+            for row in 0..body_rows.saturating_sub(list_rows + 1) {
+                write_line(&mut screen, details.get(row).map_or("", String::as_str), cols)?;
+            }
+        }
+
+        // The status line shows an error or the key bindings.
+        let help = if self.picking {
+            "Type to filter  Up/Down move  Enter pick  Esc cancel"
+        } else if self.editing {
+            "Type to search  Up/Down move  Enter/Esc done  Ctrl-U clear"
+        } else {
+            "j/k move  Enter open  p preview  / search  g/G top/bottom  q quit"
+        };
+        let status = self.error.as_deref().unwrap_or(help);
+        write!(screen, "\x1b[7m{}\x1b[0m", fit(status, cols))?;
+
+        let mut out = io::stderr().lock();
+        out.write_all(&screen)?;
+        out.flush()
+    }
+
+    // Returns the list row for the result, highlighted when it is selected.
+    fn list_item(&self, idx: usize, width: usize) -> String {
+        let Some(entry) = self.results.get(idx) else {
+            return " ".repeat(width);
+        };
+
+        let label = if self.picking {
+            format!(" {}({})", entry.name, entry.page.sects.join(", "))
+        } else {
+            format!(" {}", entry.page.whatis())
+        };
+        let text = fit(&label, width);
+        if idx == self.selected {
+            format!("\x1b[7m{text}\x1b[0m")
+        } else {
+            text
+        }
+    }
+
+    // Returns the lines of the selected page's details.
+    fn details(&self, width: usize) -> Vec<String> {
+        let Some(Entry { page, .. }) = self.results.get(self.selected) else {
+            let message = if self.query.is_empty() { "" } else { "No results." };
+            return vec![message.to_string()];
+        };
+
+        if self.preview {
+            let man_dir = self.db_dir.as_deref().unwrap_or(Path::new("."));
+            return match source::preview_page(page, man_dir, width) {
+                Ok(text) => text.lines().map(str::to_string).collect(),
+                Err(e) => vec![format!("No preview: {e}")],
+            };
+        }
+
+        let mut text = Vec::new();
+        let query = self.query.trim();
+        // Writing to memory cannot fail.
+        let _ = page.write(&mut text, Painter::plain(), &[query], Some(width));
+
+        String::from_utf8_lossy(&text).lines().map(str::to_string).collect()
+    }
+}
+
+impl<'db, 'a> Entry<'db, 'a> {
+    fn new(page: &'db Page<'a>) -> Self {
+        Self { page, name: page.names.first().map_or("", |name| name.value) }
+    }
+}
+
+// Moves by a screenful in the list.
+fn page_size() -> isize {
+    let rows = term::size().map_or(24, |(_, rows)| rows);
+    isize::try_from(rows.saturating_sub(3).max(1)).unwrap_or(1)
+}
+
+// Truncates or pads `text` to exactly `width` columns.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted = text.chars().take(width).collect::<String>();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+// Writes `text` fitted to `width` columns, clears the rest of the row, and
+// moves to the next row.
+fn write_line(out: &mut Vec<u8>, text: &str, width: usize) -> io::Result<()> {
+    write!(out, "{}\x1b[K\r\n", fit(text, width))
+}
+
+// Switches to the terminal's alternate screen in raw mode, restoring the
+// normal screen when dropped.
+struct Screen {
+    _raw: RawMode,
+}
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        let raw = RawMode::enable()?;
+        let mut out = io::stderr().lock();
+        write!(out, "\x1b[?1049h\x1b[?25l")?;
+        out.flush()?;
+        Ok(Self { _raw: raw })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut out = io::stderr().lock();
+        let _ = write!(out, "\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+    }
+}
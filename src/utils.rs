@@ -1,58 +1,337 @@
-use std::num::TryFromIntError;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
 use std::str;
 
-pub fn print_list(list: &[&str]) {
-    if list.is_empty() {
-        println!();
-        return;
+use crate::cli::{ArgValue, Program, OPTIONS, SUBCOMMANDS};
+use crate::database::ParseOptions;
+use crate::error::{DbError, ErrorKind};
+use crate::memory::vec_size;
+
+// Writes the items as a comma-separated list followed by a newline.
+pub fn write_list(out: &mut dyn Write, list: &[&str]) -> io::Result<()> {
+    writeln!(out, "{}", list.join(", "))
+}
+
+// Writes a new file next to `path` and then renames it over the old one, so
+// that a database mapped from the old file, by this process or another, is
+// still read whole. The new file is removed when it cannot be written.
+pub fn replace_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>
+) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let written = File::create(&tmp).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        write(&mut out)?;
+        out.flush()
+    });
+    written.and_then(|()| fs::rename(&tmp, path)).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+// Removes the backspace sequences that preformatted pages use for bold
+// ("x\bx") and underlined ("_\bx") text.
+pub fn strip_overstrike(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\u{8}' {
+            out.pop();
+        } else {
+            out.push(c);
+        }
     }
+    out
+}
+
+// Writes `label` followed by the items joined with `sep`, wrapping lines so
+// they fit in `width` columns. Continuation lines are indented to line up
+// with the first item, and lines only break between items.
+pub fn write_wrapped(
+    out: &mut dyn Write,
+    label: &str,
+    items: &[&str],
+    sep: &str,
+    width: Option<usize>
+) -> io::Result<()> {
+    let width = width.unwrap_or(usize::MAX);
+    let indent = label.chars().count();
+
+    // A separator like ", " keeps its comma at the end of a wrapped line.
+    let sep_end = sep.trim_end();
+    let sep_gap = &sep[sep_end.len()..];
 
-    let last_idx = list.len() - 1;
+    let mut line = label.to_string();
+    let mut col = indent;
 
-    for (count, item) in list.iter().enumerate() {
-        if count == last_idx {
-            println!("{item}");
-            return;
+    for (idx, item) in items.iter().enumerate() {
+        let mut piece = (*item).to_string();
+        if idx + 1 < items.len() {
+            piece.push_str(sep_end);
         }
+        let piece_len = piece.chars().count();
 
-        print!("{item}, ");
+        if idx > 0 {
+            if col + sep_gap.len() + piece_len > width {
+                writeln!(out, "{line}")?;
+                line = " ".repeat(indent);
+                col = indent;
+            } else {
+                line.push_str(sep_gap);
+                col += sep_gap.len();
+            }
+        }
+
+        line.push_str(&piece);
+        col += piece_len;
     }
+
+    writeln!(out, "{line}")
 }
 
-pub fn print_help() {
-    let name = env!("CARGO_PKG_NAME");
-    println!("USAGE:\n  ./{name} [OPTIONS] <MANDOC_DB_FILE_PATH>\n");
+pub fn print_help(program: Program) {
+    let name = program.name();
+    println!("USAGE:\n  ./{name} [OPTIONS] [MANDOC_DB_FILE_PATH...] [NAME...] [-]");
+    println!("  ./{name} <COMMAND> [ARGS]\n");
+
     println!("OPTIONS:");
-    println!("  -h,--help     Print this help message.");
-    println!("  -s,--search   Search for a page entry by name.");
+    let opts = OPTIONS
+        .iter()
+        .map(|opt| {
+            let mut usage = opt.short
+                .map_or_else(String::new, |short| format!("-{short},"));
+            usage.push_str("--");
+            usage.push_str(opt.long);
+            if !matches!(opt.value, ArgValue::None) {
+                usage.push(' ');
+                usage.push_str(opt.value_name);
+            }
+            (usage, opt.help)
+        })
+        .collect::<Vec<(String, &str)>>();
+    let width = opts.iter().map(|(usage, _)| usage.len()).max().unwrap_or(0);
+    for (usage, help) in opts {
+        println!("  {usage:width$}   {help}");
+    }
+
+    println!("\nCOMMANDS:");
+    for (cmd, args, help) in SUBCOMMANDS {
+        println!("  {cmd} {args}   {help}");
+    }
+
+    println!();
+    println!("When NAMEs are given they are looked up without starting the search");
+    println!("prompt. Without a database path, $MANDOC_DB, the config file, or");
+    println!("/usr/share/man/mandoc.db is used, in that order.");
+    println!();
+    println!("Defaults are read from ~/.config/{}/config.toml if it exists.",
+        env!("CARGO_PKG_NAME"));
 }
 
-pub fn parse_num(bytes: &[u8], idx: usize) -> Result<usize, TryFromIntError> {
-    assert!(idx + 3 < bytes.len());
+// Reads the big endian number at `idx`.
+pub fn parse_num(bytes: &[u8], idx: usize) -> Result<usize, DbError> {
+    let num_bytes = idx
+        .checked_add(4)
+        .and_then(|end| bytes.get(idx..end))
+        .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, idx,
+            "Number is past the end of the file."))?;
+
     let mut int_bytes = [0u8; 4];
-    int_bytes.copy_from_slice(&bytes[idx..=idx + 3]);
+    int_bytes.copy_from_slice(num_bytes);
     usize::try_from(u32::from_be_bytes(int_bytes))
+        .map_err(|_| DbError::at(ErrorKind::OutOfBounds, idx, "Number is too large."))
+}
+
+// Reads the NUL-terminated string at `idx`.
+pub fn parse_str(bytes: &[u8], idx: usize) -> Result<&str, DbError> {
+    let rest = bytes
+        .get(idx..)
+        .ok_or_else(|| DbError::at(ErrorKind::OutOfBounds, idx,
+            "String is past the end of the file."))?;
+    let len = rest
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| DbError::at(ErrorKind::Encoding, idx, "Unterminated string."))?;
+
+    str::from_utf8(&rest[..len])
+        .map_err(|e| DbError::at(ErrorKind::Encoding, idx + e.valid_up_to(),
+            "String is not valid UTF-8."))
 }
 
 pub fn parse_list(
     bytes: &[u8],
     idx: usize
-) -> Result<Vec<&str>, &'static str> {
-    let mut list = Vec::with_capacity(20);
-    let split_iter = bytes[idx..].split_inclusive(|b| *b == 0);
-
-    for item_bytes in split_iter {
-        match item_bytes.len() {
-            0 => return Err("Encountered an unexpected NUL byte."),
-            // A NUL byte marks the end of a list.
-            1 if item_bytes[0] == 0 => break,
-            len => {
-                let item_str = str::from_utf8(&item_bytes[..(len - 1)])
-                    .map_err(|_| "str::from_utf8 failed while parsing a list.")?;
-                list.push(item_str);
+) -> Result<Vec<&str>, DbError> {
+    StrList::parse(bytes, idx).map(|list| list.to_vec())
+}
+
+// A strings list read where it is in the database, without copying its
+// strings into a Vec. Walking a list, or counting its strings, allocates
+// nothing; `to_vec` allocates exactly the strings there are. Lists that are
+// not in a database, such as those of a page loaded from a cache or built
+// from page files, are collected from their strings instead.
+#[derive(Clone, Debug)]
+pub struct StrList<'a>(StrItems<'a>);
+
+#[derive(Clone, Debug)]
+enum StrItems<'a> {
+    // The strings, each followed by its NUL byte, without the NUL byte that
+    // ends the list, and their number.
+    InPlace { items: &'a str, len: usize },
+    Collected(Vec<&'a str>),
+}
+
+impl<'a> StrList<'a> {
+    // Checks the strings of the list at `idx`, which ends with an empty
+    // string.
+    pub fn parse(bytes: &'a [u8], idx: usize) -> Result<Self, DbError> {
+        Self::parse_with(bytes, idx, &ParseOptions::default())
+    }
+
+    // Like `parse`, failing once the list has more strings, or a string more
+    // bytes, than `options` allow.
+    pub fn parse_with(
+        bytes: &'a [u8],
+        idx: usize,
+        options: &ParseOptions
+    ) -> Result<Self, DbError> {
+        let mut len = 0;
+        let mut end = idx;
+        loop {
+            let item = parse_str(bytes, end)?;
+
+            // An empty string (i.e. a second NUL byte) marks the end of a list.
+            if item.is_empty() {
+                break;
+            }
+
+            options.check_str_len(item.len(), end)?;
+            len += 1;
+            options.check_list_len(len, idx)?;
+            end += item.len() + 1;
+        }
+
+        // Every string is UTF-8 and NUL is ASCII, so the strings together
+        // are as well.
+        let items = str::from_utf8(&bytes[idx..end])
+            .map_err(|e| DbError::at(ErrorKind::Encoding, idx + e.valid_up_to(),
+                "String is not valid UTF-8."))?;
+        Ok(Self(StrItems::InPlace { items, len }))
+    }
+
+    pub fn iter(&self) -> StrListIter<'_, 'a> {
+        match &self.0 {
+            StrItems::InPlace { items, .. } => StrListIter::InPlace(items.split_terminator('\0')),
+            StrItems::Collected(items) => StrListIter::Collected(items.iter()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            StrItems::InPlace { len, .. } => *len,
+            StrItems::Collected(items) => items.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn first(&self) -> Option<&'a str> {
+        self.iter().next()
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.iter().any(|other| other == item)
+    }
+
+    // Joins the strings with `sep`, like `[&str]::join`.
+    pub fn join(&self, sep: &str) -> String {
+        self.to_vec().join(sep)
+    }
+
+    // The number of bytes the list takes in the database, including the NUL
+    // bytes that end each string and the list.
+    pub fn byte_len(&self) -> usize {
+        match &self.0 {
+            StrItems::InPlace { items, .. } => items.len() + 1,
+            StrItems::Collected(items) => {
+                items.iter().map(|item| item.len() + 1).sum::<usize>() + 1
             },
         }
     }
 
-    Ok(list)
+    // Returns the bytes of heap memory used by a collected list. A list read
+    // in place uses none.
+    pub fn heap_size(&self) -> usize {
+        match &self.0 {
+            StrItems::InPlace { .. } => 0,
+            StrItems::Collected(items) => vec_size(items),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<&'a str> {
+        let mut list = Vec::with_capacity(self.len());
+        list.extend(self.iter());
+        list
+    }
+}
+
+impl Default for StrList<'_> {
+    fn default() -> Self {
+        Self(StrItems::Collected(Vec::new()))
+    }
+}
+
+// Lists are equal when their strings are, however they were read.
+impl PartialEq for StrList<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl Eq for StrList<'_> {}
+
+impl<'a> From<Vec<&'a str>> for StrList<'a> {
+    fn from(items: Vec<&'a str>) -> Self {
+        Self(StrItems::Collected(items))
+    }
+}
+
+impl<'a> FromIterator<&'a str> for StrList<'a> {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        Self(StrItems::Collected(iter.into_iter().collect()))
+    }
+}
+
+impl<'l, 'a> IntoIterator for &'l StrList<'a> {
+    type Item = &'a str;
+    type IntoIter = StrListIter<'l, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// The strings of a `StrList`, in order.
+#[derive(Clone, Debug)]
+pub enum StrListIter<'l, 'a> {
+    InPlace(str::SplitTerminator<'a, char>),
+    Collected(slice::Iter<'l, &'a str>),
+}
+
+impl<'a> Iterator for StrListIter<'_, 'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            Self::InPlace(items) => items.next(),
+            Self::Collected(items) => items.next().copied(),
+        }
+    }
 }
@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::database::{Database, DB_MAGIC_NUMBER, DB_VERSION_NUMBER};
+use crate::error::{DbError, ErrorKind};
+use crate::macros;
+use crate::pages::PageFormat;
+use crate::swap;
+use crate::utils::{parse_num, parse_str, StrList};
+
+// The length of the header: the magic number, the version, the offsets of
+// the macros table and final magic number, and the number of pages.
+const HEADER_LEN: usize = 20;
+
+// The size of a page record and of a macro value record.
+const PAGE_LEN: usize = 20;
+const VALUE_LEN: usize = 8;
+
+// Checks a mandoc.db for damage without parsing it into a `Database`, so that
+// every problem is found instead of only the first one. Each problem is
+// reported with the offset where it was found.
+//
+// The header must have the magic number and version, and the final magic
+// number must end the file. Every offset in the page records and macro
+// tables must land inside the file, strings must be NUL-terminated UTF-8,
+// string lists must end with an empty string, and the page and macro table
+// counts must fit in the file. The pages of macro values must be page records
+// with readable names.
+pub fn validate(bytes: &[u8]) -> Vec<DbError> {
+    let mut validator = Validator {
+        bytes,
+        problems: Vec::new(),
+        page_count: 0,
+        broken_names: HashSet::new(),
+    };
+    validator.header();
+    validator.problems
+}
+
+struct Validator<'a> {
+    bytes: &'a [u8],
+    problems: Vec<DbError>,
+    // The number of page records, and the offsets of those whose names
+    // cannot be read, which macro values cannot refer to.
+    page_count: usize,
+    broken_names: HashSet<usize>,
+}
+
+impl<'a> Validator<'a> {
+    fn problem(&mut self, kind: ErrorKind, offset: usize, message: impl Into<String>) {
+        self.problems.push(DbError::at(kind, offset, message));
+    }
+
+    fn num(&mut self, idx: usize) -> Option<usize> {
+        parse_num(self.bytes, idx).map_err(|e| self.problems.push(e)).ok()
+    }
+
+    // Reads the offset at `idx`, which must land inside the file.
+    fn offset(&mut self, idx: usize, what: &str) -> Option<usize> {
+        let offset = self.num(idx)?;
+        if offset >= self.bytes.len() {
+            self.problem(ErrorKind::OutOfBounds, idx,
+                format!("{what} offset {offset} is past the end of the file."));
+            return None;
+        }
+        Some(offset)
+    }
+
+    fn str(&mut self, idx: usize) -> Option<&'a str> {
+        parse_str(self.bytes, idx).map_err(|e| self.problems.push(e)).ok()
+    }
+
+    // Reads a list of strings at `idx`, which ends with an empty string.
+    fn list(&mut self, idx: usize) -> Option<StrList<'a>> {
+        StrList::parse(self.bytes, idx).map_err(|e| self.problems.push(e)).ok()
+    }
+
+    fn header(&mut self) {
+        let len = self.bytes.len();
+        if len < HEADER_LEN {
+            self.problem(ErrorKind::Format, 0,
+                format!("The file is {len} bytes, shorter than the {HEADER_LEN} byte header."));
+            return;
+        }
+
+        // Every other number would be swapped as well.
+        if self.num(0) == Some(swap::SWAPPED_MAGIC_NUMBER) {
+            self.problem(ErrorKind::Format, 0,
+                "The database appears to be written with opposite endianness.");
+            return;
+        }
+        if self.num(0) != Some(DB_MAGIC_NUMBER) {
+            self.problem(ErrorKind::Format, 0, "Invalid magic number.");
+        }
+        if let Some(version) = self.num(4).filter(|version| *version != DB_VERSION_NUMBER) {
+            self.problem(ErrorKind::Version, 4, format!("Unsupported version {version}."));
+        }
+
+        if let Some(final_idx) = self.offset(12, "Final magic number") {
+            if self.num(final_idx).is_some_and(|magic| magic != DB_MAGIC_NUMBER) {
+                self.problem(ErrorKind::Format, final_idx, "Invalid final magic number.");
+            }
+            if final_idx + 4 != len {
+                self.problem(ErrorKind::Format, 12,
+                    format!("The final magic number at {final_idx} does not end the file."));
+            }
+        }
+
+        let macros_idx = self.offset(8, "Macros table");
+        self.pages(macros_idx.unwrap_or(len));
+        if let Some(macros_idx) = macros_idx {
+            self.macros(macros_idx);
+        }
+    }
+
+    // Checks the page records, which come before the macros table.
+    fn pages(&mut self, end: usize) {
+        let Some(count) = self.num(16) else {
+            return;
+        };
+        let table_end = count.checked_mul(PAGE_LEN).and_then(|len| len.checked_add(HEADER_LEN));
+        if table_end.is_none_or(|table_end| table_end > end) {
+            self.problem(ErrorKind::Count, 16,
+                format!("{count} page records do not fit before offset {end}."));
+            return;
+        }
+        self.page_count = count;
+
+        for idx in 0..count {
+            let start = HEADER_LEN + idx * PAGE_LEN;
+
+            let names = self.offset(start, "Names list").is_some_and(|names| self.names(names));
+            if !names {
+                self.broken_names.insert(start);
+            }
+            if let Some(sects) = self.offset(start + 4, "Sections list") {
+                if self.list(sects).is_some_and(|sects| sects.is_empty()) {
+                    self.problem(ErrorKind::Format, sects, "Page has no sections.");
+                }
+            }
+            // Machine-independent pages have no architectures list.
+            if self.num(start + 8).is_some_and(|archs| archs != 0) {
+                if let Some(archs) = self.offset(start + 8, "Architectures list") {
+                    self.list(archs);
+                }
+            }
+            if let Some(desc) = self.offset(start + 12, "Description") {
+                self.str(desc);
+            }
+            if let Some(files) = self.offset(start + 16, "Files list") {
+                if let Err(e) = PageFormat::try_from(self.bytes[files]) {
+                    self.problem(e.kind, files, e.message);
+                }
+                self.list(files + 1);
+            }
+        }
+    }
+
+    // Checks a names list, whose names start with a name sources byte, and
+    // returns whether it could be read.
+    fn names(&mut self, idx: usize) -> bool {
+        let Some(names) = self.list(idx) else {
+            return false;
+        };
+        if names.is_empty() {
+            self.problem(ErrorKind::Format, idx, "Page has no names.");
+        }
+
+        let mut name_idx = idx;
+        for name in names.iter() {
+            if !matches!(name.as_bytes()[0], 1..=31) {
+                self.problem(ErrorKind::Format, name_idx, "Invalid name sources byte.");
+            }
+            name_idx += name.len() + 1;
+        }
+        true
+    }
+
+    fn macros(&mut self, start: usize) {
+        let Some(count) = self.num(start) else {
+            return;
+        };
+        if count != macros::KEYS.len() {
+            self.problem(ErrorKind::Count, start,
+                format!("Expected {} macro tables but found {count}.", macros::KEYS.len()));
+        }
+        let end = count.checked_mul(4).and_then(|len| len.checked_add(start + 4));
+        if end.is_none_or(|end| end > self.bytes.len()) {
+            self.problem(ErrorKind::Count, start,
+                format!("{count} macro tables do not fit in the file."));
+            return;
+        }
+
+        for idx in 0..count {
+            if let Some(table) = self.offset(start + 4 + idx * 4, "Macro table") {
+                self.table(table);
+            }
+        }
+    }
+
+    fn table(&mut self, start: usize) {
+        let Some(count) = self.num(start) else {
+            return;
+        };
+        let end = count.checked_mul(VALUE_LEN).and_then(|len| len.checked_add(start + 4));
+        if end.is_none_or(|end| end > self.bytes.len()) {
+            self.problem(ErrorKind::Count, start,
+                format!("{count} macro values do not fit in the file."));
+            return;
+        }
+
+        for idx in 0..count {
+            let value = start + 4 + idx * VALUE_LEN;
+            if let Some(str) = self.offset(value, "Macro value string") {
+                self.str(str);
+            }
+            if let Some(pages) = self.offset(value + 4, "Macro value pages list") {
+                self.value_pages(pages);
+            }
+        }
+    }
+
+    // Checks a macro value's list of page record offsets, which ends with 0.
+    // Each offset must be the start of a page record whose names can be
+    // read, since that is how the value's pages are found.
+    fn value_pages(&mut self, start: usize) {
+        let mut idx = start;
+        loop {
+            let Ok(page) = parse_num(self.bytes, idx) else {
+                self.problem(ErrorKind::Encoding, start, "Unterminated pages list.");
+                return;
+            };
+            if page == 0 {
+                return;
+            }
+            if !macros::is_page_record(page, self.page_count) {
+                self.problem(ErrorKind::Format, idx,
+                    format!("Page offset {page} is not a page record."));
+            } else if self.broken_names.contains(&page) {
+                self.problem(ErrorKind::Format, idx,
+                    format!("The names of the page record at {page} cannot be read."));
+            }
+            idx += 4;
+        }
+    }
+}
+
+// Checks that parsing the database and writing it again gives back the same
+// bytes, as it does for a valid database written by makewhatis(8), and
+// reports where the output first differs. Tools that rewrite databases rely
+// on this to change nothing but what they mean to.
+pub fn verify_roundtrip(bytes: &[u8]) -> Option<DbError> {
+    let written = Database::parse(bytes)
+        .and_then(|db| {
+            let mut written = Vec::with_capacity(bytes.len());
+            db.write(&mut written)?;
+            Ok(written)
+        });
+    let written = match written {
+        Ok(written) => written,
+        Err(e) => return Some(match e.downcast::<DbError>() {
+            Ok(e) => *e,
+            Err(e) => DbError::new(ErrorKind::Format, e.to_string()),
+        }),
+    };
+
+    let Some(offset) = bytes.iter().zip(&written).position(|(a, b)| a != b) else {
+        return (bytes.len() != written.len()).then(|| {
+            DbError::at(ErrorKind::Format, bytes.len().min(written.len()),
+                format!("Writing the database again gives {} bytes instead of {}.",
+                    written.len(), bytes.len()))
+        });
+    };
+    Some(DbError::at(ErrorKind::Format, offset,
+        format!("Writing the database again gives byte {:#04x} instead of {:#04x}.",
+            written[offset], bytes[offset])))
+}
+
+// Writes each problem, or that none were found.
+pub fn write_problems(out: &mut dyn Write, problems: &[DbError]) -> io::Result<()> {
+    if problems.is_empty() {
+        return writeln!(out, "No problems found.");
+    }
+
+    for problem in problems {
+        writeln!(out, "{problem}")?;
+    }
+    Ok(())
+}
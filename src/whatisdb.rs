@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use log::{debug, warn};
+
+use crate::convert::parse_whatis_line;
+use crate::edit::OwnedDatabase;
+use crate::error::{DbError, ErrorKind};
+
+// The magic number and version of a btree(3) file of the 4.4BSD db(3)
+// library, in the metadata page.
+const BTREE_MAGIC: u32 = 0x0005_3162;
+const BTREE_VERSION: u32 = 3;
+
+// The page that every search starts from.
+const ROOT_PAGE: usize = 1;
+// The page number that ends a chain of pages.
+const INVALID_PAGE: usize = 0;
+
+// The length of a page header: its number, its siblings, its flags, and the
+// bounds of its free space.
+const PAGE_HEADER_LEN: usize = 20;
+
+// Page types, in the low bits of the page flags.
+const PAGE_TYPE: u32 = 0x1f;
+const INTERNAL_PAGE: u32 = 0x01;
+const LEAF_PAGE: u32 = 0x02;
+
+// Leaf entry flags for data and keys kept on overflow pages.
+const BIG_DATA: u8 = 0x01;
+const BIG_KEY: u8 = 0x02;
+
+// Returns whether `bytes` start with the metadata of a btree(3) file, as
+// the whatis.db of older BSD releases does.
+pub fn is_whatis_db(bytes: &[u8]) -> bool {
+    Btree::new(bytes).is_ok()
+}
+
+// A key and its value, which may have been read from several pages.
+type Pair = (Vec<u8>, Vec<u8>);
+
+// Reads a btree(3) file, written in the byte order of the machine that made
+// it.
+struct Btree<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+    page_len: usize,
+}
+
+impl<'a> Btree<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, DbError> {
+        let mut btree = Self { bytes, big_endian: false, page_len: 0 };
+        if btree.u32(0)? != BTREE_MAGIC {
+            btree.big_endian = true;
+            if btree.u32(0)? != BTREE_MAGIC {
+                return Err(DbError::at(ErrorKind::Format, 0, "The file is not a btree(3) file."));
+            }
+        }
+
+        let version = btree.u32(4)?;
+        if version != BTREE_VERSION {
+            return Err(DbError::at(ErrorKind::Version, 4,
+                format!("Unsupported btree(3) version {version}.")));
+        }
+        btree.page_len = btree.num(8)?;
+        if btree.page_len <= PAGE_HEADER_LEN {
+            return Err(DbError::at(ErrorKind::Format, 8, "The btree(3) page size is too small."));
+        }
+        Ok(btree)
+    }
+
+    fn get(&self, idx: usize, len: usize) -> Result<&'a [u8], DbError> {
+        idx.checked_add(len)
+            .and_then(|end| self.bytes.get(idx..end))
+            .ok_or_else(|| {
+                DbError::at(ErrorKind::OutOfBounds, idx, "btree(3) data is out of bounds.")
+            })
+    }
+
+    fn u32(&self, idx: usize) -> Result<u32, DbError> {
+        let bytes = self.get(idx, 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+
+    fn u16(&self, idx: usize) -> Result<usize, DbError> {
+        let bytes = self.get(idx, 2)?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(usize::from(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }))
+    }
+
+    fn num(&self, idx: usize) -> Result<usize, DbError> {
+        usize::try_from(self.u32(idx)?).map_err(|_| {
+            DbError::at(ErrorKind::OutOfBounds, idx, "A btree(3) number is too large.")
+        })
+    }
+
+    fn page(&self, pgno: usize) -> Result<usize, DbError> {
+        pgno.checked_mul(self.page_len)
+            .filter(|start| start + self.page_len <= self.bytes.len())
+            .ok_or_else(|| DbError::new(ErrorKind::OutOfBounds,
+                format!("btree(3) page {pgno} is out of bounds.")))
+    }
+
+    // Returns the offsets of the entries of the page starting at `start`,
+    // which are listed after its header up to the start of its free space.
+    fn entries(&self, start: usize) -> Result<Vec<usize>, DbError> {
+        let lower = self.u16(start + 16)?;
+        (PAGE_HEADER_LEN..lower.max(PAGE_HEADER_LEN))
+            .step_by(2)
+            .map(|idx| Ok(start + self.u16(start + idx)?))
+            .collect()
+    }
+
+    // Returns every key and value, in key order.
+    //
+    // The leftmost leaf is found by following the first entry of each
+    // internal page down from the root, and the leaves are then read from
+    // left to right.
+    fn pairs(&self) -> Result<Vec<Pair>, DbError> {
+        let mut pgno = ROOT_PAGE;
+        let mut seen = HashSet::new();
+        loop {
+            if !seen.insert(pgno) {
+                return Err(DbError::new(ErrorKind::Format, "The btree(3) pages form a loop."));
+            }
+            let start = self.page(pgno)?;
+            match self.u32(start + 12)? & PAGE_TYPE {
+                LEAF_PAGE => break,
+                // An internal entry is the key size, then the page number.
+                INTERNAL_PAGE => {
+                    let first = *self.entries(start)?.first().ok_or_else(|| {
+                        DbError::at(ErrorKind::Format, start, "An internal page is empty.")
+                    })?;
+                    pgno = self.num(first + 4)?;
+                },
+                _ => return Err(DbError::at(ErrorKind::Format, start,
+                    "A btree(3) page has an unknown type.")),
+            }
+        }
+
+        let mut pairs = Vec::new();
+        while pgno != INVALID_PAGE {
+            let start = self.page(pgno)?;
+            // A leaf entry is the key size, the data size, flags, the key,
+            // and the data.
+            for entry in self.entries(start)? {
+                let key_len = self.num(entry)?;
+                let data_len = self.num(entry + 4)?;
+                let flags = self.get(entry + 8, 1)?[0];
+                let key = self.item(entry + 9, key_len, flags & BIG_KEY != 0)?;
+                let data = self.item(entry + 9 + key_len, data_len, flags & BIG_DATA != 0)?;
+                pairs.push((key, data));
+            }
+
+            pgno = self.num(start + 8)?;
+            if pgno != INVALID_PAGE && !seen.insert(pgno) {
+                return Err(DbError::new(ErrorKind::Format, "The btree(3) pages form a loop."));
+            }
+        }
+        Ok(pairs)
+    }
+
+    // Reads a key or value, which is in the entry itself unless it was too
+    // big for it. Then the entry holds the number of the first of a chain of
+    // overflow pages and the length of the whole item.
+    fn item(&self, idx: usize, len: usize, big: bool) -> Result<Vec<u8>, DbError> {
+        if !big {
+            return Ok(self.get(idx, len)?.to_vec());
+        }
+
+        let mut pgno = self.num(idx)?;
+        let total = self.num(idx + 4)?;
+        let mut item = Vec::with_capacity(total.min(self.bytes.len()));
+        let mut seen = HashSet::new();
+        while item.len() < total {
+            if pgno == INVALID_PAGE || !seen.insert(pgno) {
+                return Err(DbError::at(ErrorKind::Format, idx,
+                    "A btree(3) overflow chain ends early."));
+            }
+            let start = self.page(pgno)?;
+            let len = (total - item.len()).min(self.page_len - PAGE_HEADER_LEN);
+            item.extend_from_slice(self.get(start + PAGE_HEADER_LEN, len)?);
+            pgno = self.num(start + 8)?;
+        }
+        Ok(item)
+    }
+}
+
+// Reads the whatis.db btree(3) file of an older BSD release into the pages
+// of a mandoc.db.
+//
+// Its records hold whatis lines such as "ls(1) - list directory contents",
+// either whole in the key or the value, or as a "name(sect)" key with the
+// description as its value. Records that are not whatis lines are skipped.
+// As with `convert --from-whatis`, each page gets the file
+// "manSECT/NAME.SECT".
+pub fn read_whatis_db(bytes: &[u8]) -> Result<OwnedDatabase, Box<dyn Error>> {
+    let mut db = OwnedDatabase::new();
+    // A database may list a page under each of its names, so pages are only
+    // added once, by their first name, sections, and description.
+    let mut seen = HashSet::new();
+    let mut skipped = 0;
+    for (key, value) in Btree::new(bytes)?.pairs()? {
+        let text = |bytes: &[u8]| {
+            let text = String::from_utf8_lossy(bytes);
+            text.trim_end_matches(['\0', '\n']).to_string()
+        };
+        let (key, value) = (text(&key), text(&value));
+
+        let page = parse_whatis_line(&value)
+            .or_else(|| parse_whatis_line(&key))
+            .or_else(|| parse_whatis_line(&format!("{key} - {value}")));
+        let Some(page) = page else {
+            skipped += 1;
+            continue;
+        };
+        if seen.insert((page.names[0].0.clone(), page.sects.join(","), page.desc.clone())) {
+            db.add_page(page);
+        }
+    }
+    if skipped > 0 {
+        warn!("Skipped {skipped} whatis.db records that are not whatis lines");
+    }
+    debug!("Read {} pages from whatis.db", db.pages().len());
+
+    Ok(db)
+}
+
+// Converts an old whatis.db into the bytes of a mandoc.db, so that it can be
+// parsed and searched like one.
+pub fn whatis_db_to_mandoc_db(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut converted = Vec::new();
+    read_whatis_db(bytes)?.write(&mut converted)?;
+    Ok(converted)
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use mandoc_db_search::build;
+use mandoc_db_search::database::Database;
+
+// A man directory for one test, removed when it is dropped.
+struct TempManDir(PathBuf);
+
+impl TempManDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("mandoc_db_search-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    // Writes a page file at `rel`, e.g. "man1/ls.1".
+    fn add(&self, rel: &str, contents: &[u8]) {
+        let path = self.0.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    // Builds the directory's mandoc.db and returns its bytes.
+    fn build(&self) -> Vec<u8> {
+        let db_path = self.0.join("mandoc.db");
+        build::build(self.0.to_str().unwrap(), Some(db_path.to_str().unwrap())).unwrap();
+        fs::read(db_path).unwrap()
+    }
+}
+
+impl Drop for TempManDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// Returns the names and description of each page of a database.
+fn pages(bytes: &[u8]) -> Vec<(Vec<String>, String)> {
+    let db = Database::parse(bytes).unwrap();
+    db.pages
+        .table
+        .iter()
+        .map(|page| {
+            let names = page.names.iter().map(|name| name.value.to_string()).collect();
+            (names, page.desc.to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn reads_the_name_section_of_a_man_page() {
+    let dir = TempManDir::new("man-page");
+    dir.add("man1/cat.1", b".TH CAT 1\n.SH NAME\ncat, tac \\- concatenate files\n.SH SYNOPSIS\n");
+
+    assert_eq!(pages(&dir.build()),
+        [(vec!["cat".to_string(), "tac".to_string()], "concatenate files".to_string())]);
+}
+
+#[test]
+fn leaves_comments_out_of_the_name_section() {
+    let dir = TempManDir::new("man-comment");
+    dir.add("man1/cat.1", b".TH CAT 1\n.SH NAME\n.\\\" The names of the page.\ncat, tac \
+        \\- concatenate files\n'\\\" The end of the NAME section.\n.SH SYNOPSIS\n");
+
+    assert_eq!(pages(&dir.build()),
+        [(vec!["cat".to_string(), "tac".to_string()], "concatenate files".to_string())]);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn reads_gzipped_pages() {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b".TH LS 1\n.SH NAME\nls \\- list directory contents\n").unwrap();
+    let dir = TempManDir::new("man-gzip");
+    dir.add("man1/ls.1.gz", &encoder.finish().unwrap());
+
+    let bytes = dir.build();
+    assert_eq!(pages(&bytes), [(vec!["ls".to_string()], "list directory contents".to_string())]);
+    let db = Database::parse(&bytes).unwrap();
+    assert_eq!(db.pages.table[0].files.to_vec(), ["man1/ls.1.gz"]);
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use mandoc_db_search::cache;
+use mandoc_db_search::database::Database;
+use mandoc_db_search::search::SearchOptions;
+use mandoc_db_search::testutil::DbBuilder;
+
+// A cache file for one test, removed when it is dropped.
+struct TempCache(PathBuf);
+
+impl TempCache {
+    fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("mandoc_db_search-{}-{name}.cache", process::id())))
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempCache {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Returns the "name(sect)" of each page a search for `query` finds.
+fn search(db: &Database<'_>, query: &str) -> Vec<String> {
+    db.search(&[query], &SearchOptions::default())
+        .iter()
+        .map(|page| format!("{}({})", page.names.first().unwrap().value, page.sects.join(",")))
+        .collect()
+}
+
+#[test]
+fn searches_the_macro_values_of_a_cache() {
+    // Pages that share their first name refer to different values.
+    let bytes = DbBuilder::new()
+        .with_page("intro", "1", "introduction to general commands")
+        .with_macro("Xr", "ls(1)")
+        .with_page("intro", "3", "introduction to library functions")
+        .with_macro("Xr", "printf(3)")
+        .with_page("ls", "1", "list directory contents")
+        .with_macro("Xr", "ls(1)")
+        .build();
+    let db = Database::parse(&bytes).unwrap();
+
+    let cache = TempCache::new("macros");
+    cache::write_cache(&db, "/usr/share/man/mandoc.db", cache.path()).unwrap();
+    let cache_bytes = fs::read(&cache.0).unwrap();
+    let (cached, db_path) = cache::load_cache(&cache_bytes).unwrap();
+
+    assert_eq!(db_path, "/usr/share/man/mandoc.db");
+    assert_eq!(search(&cached, "Xr=ls(1)"), ["intro(1)", "ls(1)"]);
+    assert_eq!(search(&cached, "Xr=printf(3)"), ["intro(3)"]);
+}
+
+#[test]
+fn writes_a_cached_database_like_the_original() {
+    let bytes = DbBuilder::new()
+        .with_page("intro", "1", "introduction to general commands")
+        .with_macro("Xr", "ls(1)")
+        .with_page("intro", "3", "introduction to library functions")
+        .with_macro("Xr", "printf(3)")
+        .with_pages(25)
+        .build();
+    let db = Database::parse(&bytes).unwrap();
+
+    let cache = TempCache::new("write");
+    cache::write_cache(&db, "mandoc.db", cache.path()).unwrap();
+    let cache_bytes = fs::read(&cache.0).unwrap();
+    let (cached, _) = cache::load_cache(&cache_bytes).unwrap();
+
+    let mut written = Vec::new();
+    cached.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
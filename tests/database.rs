@@ -0,0 +1,253 @@
+use std::io::Cursor;
+
+use mandoc_db_search::database::{Database, ParseOptions, DB_MAGIC_NUMBER, DB_VERSION_NUMBER};
+use mandoc_db_search::error::{DbError, ErrorKind};
+use mandoc_db_search::macros;
+use mandoc_db_search::pages::PageFormat;
+use mandoc_db_search::progress::Progress;
+use mandoc_db_search::search::SearchOptions;
+use mandoc_db_search::stream;
+use mandoc_db_search::swap;
+use mandoc_db_search::testutil::DbBuilder;
+use mandoc_db_search::utils::{parse_num, StrList};
+
+// Returns the "name(sect)" of each page a search for `query` finds.
+fn search(db: &Database<'_>, query: &str) -> Vec<String> {
+    db.search(&[query], &SearchOptions::default())
+        .iter()
+        .map(|page| format!("{}({})", page.names.first().unwrap().value, page.sects.join(",")))
+        .collect()
+}
+
+// A database with a page of every kind: several names and sections, an
+// architecture, several files, and macro values shared between pages.
+fn sample() -> DbBuilder {
+    DbBuilder::new()
+        .with_page("ls", "1", "list directory contents")
+        .with_macro("Xr", "cat(1)")
+        .with_macro("Fl", "l")
+        .with_page("pthread_create", "3", "create a new thread")
+        .with_names(&["pthread_attr"])
+        .with_sects(&["3p"])
+        .with_files(&["man3/pthread_create.3", "man3p/pthread_create.3p"])
+        .with_macro("Xr", "cat(1)")
+        .with_page("apm", "8", "power management")
+        .with_archs(&["amd64", "i386"])
+        .with_macro("Cd", "apm0")
+        .with_pages(40)
+}
+
+#[test]
+fn writes_a_parsed_database_byte_for_byte() {
+    let bytes = sample().build();
+    let db = Database::parse(&bytes).unwrap();
+
+    let mut written = Vec::new();
+    db.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn keeps_the_pages_and_macro_values_through_a_write() {
+    let bytes = sample().build();
+    let db = Database::parse(&bytes).unwrap();
+    let mut written = Vec::new();
+    db.write(&mut written).unwrap();
+    let reparsed = Database::parse(&written).unwrap();
+
+    assert_eq!(reparsed.num_pages(), 43);
+    let page = &reparsed.pages.table[1];
+    assert_eq!(page.names.iter().map(|name| name.value).collect::<Vec<&str>>(),
+        ["pthread_create", "pthread_attr"]);
+    assert_eq!(page.sects.to_vec(), ["3", "3p"]);
+    assert_eq!(page.files.to_vec(), ["man3/pthread_create.3", "man3p/pthread_create.3p"]);
+    assert_eq!(reparsed.pages.table[2].archs.as_ref().map(StrList::to_vec),
+        Some(vec!["amd64", "i386"]));
+
+    assert_eq!(search(&reparsed, "Xr=cat(1)"), ["ls(1)", "pthread_create(3,3p)"]);
+    assert_eq!(search(&reparsed, "Cd=apm0"), ["apm(8)"]);
+}
+
+#[test]
+fn finds_every_page_of_a_macro_value() {
+    // More than the 21 pages that used to be read of a pages list, so the
+    // list has to be followed to its end.
+    let mut builder = DbBuilder::new();
+    for num in 0..30 {
+        builder = builder
+            .with_page(&format!("tool{num}"), "1", "a tool")
+            .with_macro("Xr", "ssl(8)");
+    }
+    let bytes = builder.build();
+    let db = Database::parse(&bytes).unwrap();
+
+    let values = &db.macros.get().unwrap().tables[0].values;
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0].pages, (0..30).collect::<Vec<usize>>());
+    assert_eq!(search(&db, "Xr=ssl(8)").len(), 30);
+}
+
+#[test]
+fn tells_apart_pages_that_share_a_name() {
+    let bytes = DbBuilder::new()
+        .with_page("intro", "1", "introduction to general commands")
+        .with_macro("Xr", "ls(1)")
+        .with_page("intro", "3", "introduction to library functions")
+        .with_macro("Xr", "printf(3)")
+        .build();
+    let db = Database::parse(&bytes).unwrap();
+
+    assert_eq!(search(&db, "Xr=ls(1)"), ["intro(1)"]);
+    assert_eq!(search(&db, "Xr=printf(3)"), ["intro(3)"]);
+}
+
+// Returns the error of a failed parse.
+fn parse_error(bytes: &[u8], options: &ParseOptions) -> DbError {
+    let err = Database::parse_with_options(bytes, options, &Progress::hidden()).unwrap_err();
+    *err.downcast::<DbError>().unwrap()
+}
+
+#[test]
+fn writes_the_header_and_final_magic_number() {
+    let bytes = sample().build();
+
+    assert_eq!(parse_num(&bytes, 0).unwrap(), DB_MAGIC_NUMBER);
+    assert_eq!(parse_num(&bytes, 4).unwrap(), DB_VERSION_NUMBER);
+    assert_eq!(parse_num(&bytes, 12).unwrap(), bytes.len() - 4);
+    assert_eq!(parse_num(&bytes, 16).unwrap(), 43);
+    assert_eq!(parse_num(&bytes, bytes.len() - 4).unwrap(), DB_MAGIC_NUMBER);
+    // Every macro table starts on a number boundary.
+    let macros = parse_num(&bytes, 8).unwrap();
+    assert_eq!(macros % 4, 0);
+    assert_eq!(parse_num(&bytes, macros).unwrap(), macros::KEYS.len());
+}
+
+#[test]
+fn writes_the_macro_values_of_the_pages_that_are_left() {
+    let mut db = sample().database().clone();
+    db.remove_page(0);
+    let mut bytes = Vec::new();
+    db.write(&mut bytes).unwrap();
+    let db = Database::parse(&bytes).unwrap();
+
+    assert_eq!(search(&db, "Xr=cat(1)"), ["pthread_create(3,3p)"]);
+    assert!(search(&db, "Fl=l").is_empty());
+    assert_eq!(search(&db, "Cd=apm0"), ["apm(8)"]);
+}
+
+#[test]
+fn keeps_unknown_page_formats() {
+    let bytes = DbBuilder::new()
+        .with_page("ls", "1", "list directory contents")
+        .with_format(PageFormat::Unknown(3))
+        .build();
+    let db = Database::parse(&bytes).unwrap();
+
+    assert_eq!(db.pages.table[0].format, PageFormat::Unknown(3));
+    assert_eq!(search(&db, "ls"), ["ls(1)"]);
+    let mut written = Vec::new();
+    db.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn fails_on_more_pages_than_the_limit() {
+    let bytes = sample().build();
+    let options = ParseOptions { max_pages: 42, ..ParseOptions::default() };
+
+    let err = parse_error(&bytes, &options);
+    assert_eq!((err.kind, err.offset), (ErrorKind::Limit, Some(16)));
+    let options = ParseOptions { max_pages: 43, ..ParseOptions::default() };
+    assert!(Database::parse_with_options(&bytes, &options, &Progress::hidden()).is_ok());
+}
+
+#[test]
+fn fails_on_longer_lists_and_strings_than_the_limits() {
+    let bytes = sample().build();
+
+    let options = ParseOptions { max_list_len: 1, ..ParseOptions::default() };
+    assert_eq!(parse_error(&bytes, &options).kind, ErrorKind::Limit);
+
+    let options = ParseOptions { max_str_len: 10, ..ParseOptions::default() };
+    assert_eq!(parse_error(&bytes, &options).kind, ErrorKind::Limit);
+}
+
+#[test]
+fn fails_on_longer_macro_pages_lists_than_the_limit() {
+    let mut builder = DbBuilder::new();
+    for num in 0..3 {
+        builder = builder.with_page(&format!("tool{num}"), "1", "a tool").with_macro("Xr", "ssl(8)");
+    }
+    let bytes = builder.build();
+    let options = ParseOptions { max_list_len: 2, ..ParseOptions::default() };
+
+    // The pages lists are within the limit, but the macros are parsed when
+    // they are first needed.
+    let db = Database::parse_with_options(&bytes, &options, &Progress::hidden()).unwrap();
+    assert_eq!(db.macros.get().unwrap_err().kind, ErrorKind::Limit);
+}
+
+// Returns a copy of a database with every number in little endian byte
+// order, as a port that left out the conversion would write it.
+fn little_endian(bytes: &[u8]) -> Vec<u8> {
+    let mut swapped = bytes.to_vec();
+    let mut swap = |idx: usize| swapped[idx..idx + 4].reverse();
+
+    for idx in [0, 4, 8, 12, 16, bytes.len() - 4] {
+        swap(idx);
+    }
+    for idx in 0..parse_num(bytes, 16).unwrap() * 5 {
+        swap(20 + idx * 4);
+    }
+
+    let macros = parse_num(bytes, 8).unwrap();
+    swap(macros);
+    for key in 0..parse_num(bytes, macros).unwrap() {
+        let table = parse_num(bytes, macros + 4 + key * 4).unwrap();
+        swap(macros + 4 + key * 4);
+        swap(table);
+        for value in 0..parse_num(bytes, table).unwrap() {
+            let record = table + 4 + value * 8;
+            swap(record);
+            swap(record + 4);
+            let mut page = parse_num(bytes, record + 4).unwrap();
+            loop {
+                swap(page);
+                if parse_num(bytes, page).unwrap() == 0 {
+                    break;
+                }
+                page += 4;
+            }
+        }
+    }
+    swapped
+}
+
+#[test]
+fn detects_and_swaps_databases_in_little_endian_byte_order() {
+    let bytes = sample().build();
+    let swapped = little_endian(&bytes);
+
+    assert!(swap::is_swapped(&swapped));
+    assert!(!swap::is_swapped(&bytes));
+    let err = parse_error(&swapped, &ParseOptions::default());
+    assert_eq!((err.kind, err.offset), (ErrorKind::Format, Some(0)));
+    assert!(err.message.contains("endianness"));
+
+    assert_eq!(swap::swap_bytes(&swapped).unwrap(), bytes);
+}
+
+#[test]
+fn streams_databases_but_not_compressed_ones() {
+    let bytes = sample().build();
+    let db = stream::read_database(Cursor::new(&bytes)).unwrap();
+    let mut written = Vec::new();
+    db.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+
+    // The header of a gzip file.
+    let compressed = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 3];
+    let err = stream::read_database(Cursor::new(&compressed)).unwrap_err();
+    assert_eq!((err.kind, err.offset), (ErrorKind::Format, Some(0)));
+    assert!(err.message.contains("gzip"));
+}
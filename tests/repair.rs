@@ -0,0 +1,91 @@
+use mandoc_db_search::database::Database;
+use mandoc_db_search::error::ErrorKind;
+use mandoc_db_search::repair::repair;
+use mandoc_db_search::search::SearchOptions;
+use mandoc_db_search::testutil::DbBuilder;
+use mandoc_db_search::utils::parse_num;
+use mandoc_db_search::validate::validate;
+
+// Three pages, the first two sharing a macro value.
+fn sample() -> Vec<u8> {
+    DbBuilder::new()
+        .with_page("ls", "1", "list directory contents")
+        .with_macro("Xr", "cat(1)")
+        .with_macro("Fl", "l")
+        .with_page("cat", "1", "concatenate files")
+        .with_macro("Xr", "cat(1)")
+        .with_page("apm", "8", "power management")
+        .with_macro("Cd", "apm0")
+        .build()
+}
+
+// Overwrites the number at `idx`.
+fn set_num(bytes: &mut [u8], idx: usize, num: usize) {
+    bytes[idx..idx + 4].copy_from_slice(&u32::try_from(num).unwrap().to_be_bytes());
+}
+
+// Returns the "name(sect)" of each page a search for `query` finds.
+fn search(db: &Database<'_>, query: &str) -> Vec<String> {
+    db.search(&[query], &SearchOptions::default())
+        .iter()
+        .map(|page| format!("{}({})", page.names.first().unwrap().value, page.sects.join(",")))
+        .collect()
+}
+
+#[test]
+fn keeps_everything_of_an_undamaged_database() {
+    let bytes = sample();
+    let (db, problems) = repair(&bytes);
+    assert!(problems.is_empty());
+
+    let mut written = Vec::new();
+    db.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn drops_damaged_pages_and_their_macro_values() {
+    let mut bytes = sample();
+    let len = bytes.len();
+    // The names of the first page.
+    set_num(&mut bytes, 20, len);
+
+    let (db, problems) = repair(&bytes);
+    assert_eq!(db.num_pages(), 2);
+    assert!(problems.iter().any(|e| e.offset == Some(len)));
+
+    // The shared value keeps its other page, and the value only the dropped
+    // page had is gone.
+    assert_eq!(search(&db, "Xr=cat(1)"), ["cat(1)"]);
+    assert!(search(&db, "Fl=l").is_empty());
+    assert_eq!(search(&db, "Cd=apm0"), ["apm(8)"]);
+}
+
+#[test]
+fn drops_references_to_offsets_that_are_not_page_records() {
+    let mut bytes = sample();
+    let macros = parse_num(&bytes, 8).unwrap();
+    let table = parse_num(&bytes, macros + 4).unwrap();
+    let pages = parse_num(&bytes, table + 8).unwrap();
+    set_num(&mut bytes, pages, 24);
+
+    let (db, problems) = repair(&bytes);
+    assert_eq!(db.num_pages(), 3);
+    assert_eq!(problems.iter().map(|e| (e.kind, e.offset)).collect::<Vec<_>>(),
+        [(ErrorKind::Format, Some(pages))]);
+    assert_eq!(search(&db, "Xr=cat(1)"), ["cat(1)"]);
+}
+
+#[test]
+fn writes_a_database_that_validates() {
+    let mut bytes = sample();
+    let len = bytes.len();
+    set_num(&mut bytes, 40 + 12, len);
+    assert!(!validate(&bytes).is_empty());
+
+    let (db, _) = repair(&bytes);
+    let mut written = Vec::new();
+    db.write(&mut written).unwrap();
+    assert!(validate(&written).is_empty());
+    assert_eq!(Database::parse(&written).unwrap().num_pages(), 2);
+}
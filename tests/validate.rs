@@ -0,0 +1,103 @@
+use mandoc_db_search::error::ErrorKind;
+use mandoc_db_search::testutil::DbBuilder;
+use mandoc_db_search::utils::parse_num;
+use mandoc_db_search::validate::{validate, verify_roundtrip};
+
+// A database whose first page's macro value is shared with the second page.
+fn sample() -> Vec<u8> {
+    DbBuilder::new()
+        .with_page("ls", "1", "list directory contents")
+        .with_macro("Xr", "cat(1)")
+        .with_page("cat", "1", "concatenate files")
+        .with_macro("Xr", "cat(1)")
+        .with_archs(&["amd64"])
+        .build()
+}
+
+// Overwrites the number at `idx`.
+fn set_num(bytes: &mut [u8], idx: usize, num: usize) {
+    bytes[idx..idx + 4].copy_from_slice(&u32::try_from(num).unwrap().to_be_bytes());
+}
+
+// The offset of the pages list of the first value of the Xr table.
+fn xr_pages(bytes: &[u8]) -> usize {
+    let macros = parse_num(bytes, 8).unwrap();
+    let table = parse_num(bytes, macros + 4).unwrap();
+    parse_num(bytes, table + 8).unwrap()
+}
+
+// The kind and offset of each problem.
+fn problems(bytes: &[u8]) -> Vec<(ErrorKind, Option<usize>)> {
+    validate(bytes).into_iter().map(|e| (e.kind, e.offset)).collect()
+}
+
+#[test]
+fn finds_no_problems_in_a_written_database() {
+    let bytes = sample();
+    assert!(validate(&bytes).is_empty());
+    assert!(verify_roundtrip(&bytes).is_none());
+}
+
+#[test]
+fn reports_every_offset_past_the_end_of_the_file() {
+    let mut bytes = sample();
+    let len = bytes.len();
+    // The description of the first page and the files of the second.
+    set_num(&mut bytes, 20 + 12, len);
+    set_num(&mut bytes, 40 + 16, len + 100);
+
+    assert_eq!(problems(&bytes), [
+        (ErrorKind::OutOfBounds, Some(32)),
+        (ErrorKind::OutOfBounds, Some(56)),
+    ]);
+}
+
+#[test]
+fn reports_a_final_magic_number_that_does_not_end_the_file() {
+    let mut bytes = sample();
+    bytes.extend_from_slice(&[0; 4]);
+
+    assert_eq!(problems(&bytes), [(ErrorKind::Format, Some(12))]);
+}
+
+#[test]
+fn reports_macro_values_that_do_not_point_at_page_records() {
+    let mut bytes = sample();
+    let pages = xr_pages(&bytes);
+    // Inside the first page record, then past the last one.
+    set_num(&mut bytes, pages, 24);
+    set_num(&mut bytes, pages + 4, 60);
+
+    assert_eq!(problems(&bytes), [
+        (ErrorKind::Format, Some(pages)),
+        (ErrorKind::Format, Some(pages + 4)),
+    ]);
+}
+
+#[test]
+fn reports_macro_values_of_pages_whose_names_cannot_be_read() {
+    let mut bytes = sample();
+    let len = bytes.len();
+    set_num(&mut bytes, 20, len);
+    let pages = xr_pages(&bytes);
+
+    assert_eq!(problems(&bytes), [
+        (ErrorKind::OutOfBounds, Some(20)),
+        (ErrorKind::Format, Some(pages)),
+    ]);
+}
+
+#[test]
+fn reports_where_a_rewrite_first_differs() {
+    let mut bytes = sample();
+    // Padding after the first macro value string, which a rewrite zeroes.
+    let macros = parse_num(&bytes, 8).unwrap();
+    let table = parse_num(&bytes, macros + 4).unwrap();
+    let value = parse_num(&bytes, table + 4).unwrap();
+    let padding = value + "cat(1)".len() + 1;
+    bytes[padding] = b'x';
+
+    assert!(validate(&bytes).is_empty());
+    let problem = verify_roundtrip(&bytes).unwrap();
+    assert_eq!((problem.kind, problem.offset), (ErrorKind::Format, Some(padding)));
+}